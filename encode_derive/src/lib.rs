@@ -1,6 +1,6 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields, GenericParam};
+use syn::{Data, DeriveInput, Fields, GenericParam, parse_macro_input};
 
 #[proc_macro_derive(Size)]
 pub fn derive_size(input: TokenStream) -> TokenStream {
@@ -25,7 +25,7 @@ pub fn derive_size(input: TokenStream) -> TokenStream {
                             #name::#variant_name => 0,
                         }
                     }
-                    Fields::Unnamed(fields) => {
+                    Fields::Unnamed(_) => {
                         quote! {
                             #name::#variant_name(val) => val.size_in_bytes(),
                         }
@@ -43,8 +43,9 @@ pub fn derive_size(input: TokenStream) -> TokenStream {
                 }
             });
 
+            // One byte for the variant index written by the `Encode` derive.
             quote! {
-                match self {
+                1 + match self {
                     #(#variant_sizes)*
                 }
             }
@@ -84,28 +85,30 @@ pub fn derive_encode(input: TokenStream) -> TokenStream {
         Data::Struct(data) => {
             let field_encode = data.fields.iter().map(|f| {
                 let field_name = &f.ident;
-                quote! { encoded.extend(self.#field_name.encode()); }
+                quote! { self.#field_name.encode(buf); }
             });
 
             quote! {
-                let mut encoded = Vec::new();
                 #(#field_encode)*
-                encoded
             }
         }
         Data::Enum(data) => {
-            let variant_encodes = data.variants.iter().map(|variant| {
+            let variant_encodes = data.variants.iter().enumerate().map(|(index, variant)| {
                 let variant_name = &variant.ident;
+                let index = index as u8;
                 match &variant.fields {
                     Fields::Unit => {
                         quote! {
-                            #name::#variant_name => encoded.extend(Vec::new()),
+                            #name::#variant_name => {
+                                #index.encode(buf);
+                            }
                         }
                     }
-                    Fields::Unnamed(fields) => {
+                    Fields::Unnamed(_) => {
                         quote! {
                             #name::#variant_name(val) => {
-                                encoded.extend(val.encode());
+                                #index.encode(buf);
+                                val.encode(buf);
                             }
                         }
                     }
@@ -113,7 +116,7 @@ pub fn derive_encode(input: TokenStream) -> TokenStream {
                         let field_encodes = fields.named.iter().map(|field| {
                             let field_name = &field.ident;
                             quote! {
-                                encoded.extend( #field_name.encode());
+                                #field_name.encode(buf);
                             }
                         });
 
@@ -121,6 +124,7 @@ pub fn derive_encode(input: TokenStream) -> TokenStream {
 
                         quote! {
                             #name::#variant_name { #(#field_names),* } => {
+                                #index.encode(buf);
                                 #(#field_encodes)*
                             }
                         }
@@ -129,11 +133,9 @@ pub fn derive_encode(input: TokenStream) -> TokenStream {
             });
 
             quote! {
-                let mut encoded = Vec::new();
                 match self {
                     #(#variant_encodes)*
                 }
-                encoded
             }
         }
         _ => panic!("Encode derive is only intended for structs and enums"),
@@ -141,19 +143,31 @@ pub fn derive_encode(input: TokenStream) -> TokenStream {
 
     let expanded = quote! {
         impl<#(#generic_params,)*> Encode for #name<#(#generic_params,)*> #where_clause {
-            fn encode(&self) -> Vec<u8> {
+            fn encode(&self, buf: &mut ::bytes::BytesMut) {
                 #field_encode
             }
         }
+
+        impl<#(#generic_params,)*> crate::EncodeFrame for #name<#(#generic_params,)*> #where_clause {
+            fn encode_frame(&self, frame: &mut crate::frame::Frame) {
+                frame.put(self);
+            }
+        }
     };
 
     if generics.params.is_empty() {
         let expanded = quote! {
             impl Encode for #name {
-                fn encode(&self) -> Vec<u8> {
+                fn encode(&self, buf: &mut ::bytes::BytesMut) {
                     #field_encode
                 }
             }
+
+            impl crate::EncodeFrame for #name {
+                fn encode_frame(&self, frame: &mut crate::frame::Frame) {
+                    frame.put(self);
+                }
+            }
         };
         TokenStream::from(expanded)
     } else {
@@ -161,6 +175,38 @@ pub fn derive_encode(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Encodes a struct into a `Frame` field by field, for structs holding values
+/// (like file-backed records) that can only be written to a frame.
+#[proc_macro_derive(EncodeFrame)]
+pub fn derive_encode_frame(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    let field_encode = match &input.data {
+        Data::Struct(data) => {
+            let field_encode = data.fields.iter().map(|f| {
+                let field_name = &f.ident;
+                quote! { crate::EncodeFrame::encode_frame(&self.#field_name, frame); }
+            });
+
+            quote! {
+                #(#field_encode)*
+            }
+        }
+        _ => panic!("EncodeFrame derive is only intended for structs"),
+    };
+
+    let expanded = quote! {
+        impl crate::EncodeFrame for #name {
+            fn encode_frame(&self, frame: &mut crate::frame::Frame) {
+                #field_encode
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
 #[proc_macro_derive(Decode)]
 pub fn derive_decode(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -196,12 +242,13 @@ pub fn derive_decode(input: TokenStream) -> TokenStream {
             }
         }
         Data::Enum(data) => {
-            let variant_decodes = data.variants.iter().map(|variant| {
+            let variant_decodes = data.variants.iter().enumerate().map(|(index, variant)| {
                 let variant_name = &variant.ident;
+                let index = index as u8;
                 let decode_variant = if let Some(field) = variant.fields.iter().next() {
                     let field_type = &field.ty;
                     quote! {
-                        #variant_name => #name::#variant_name(<#field_type as Decode>::decode(bytes, offset)),
+                        #index => #name::#variant_name(<#field_type as Decode>::decode(bytes, offset)),
                     }
                 } else {
                     panic!("non variant enums are not supported")
@@ -223,7 +270,7 @@ pub fn derive_decode(input: TokenStream) -> TokenStream {
 
     let expanded = quote! {
         impl<#(#generic_params,)*> Decode for #name<#(#generic_params,)*> #where_clause {
-            fn decode(bytes: &[u8], offset: &mut usize) -> #name<#(#generic_params,)*> {
+            fn decode(bytes: &::bytes::Bytes, offset: &mut usize) -> #name<#(#generic_params,)*> {
                 #field_decode
             }
         }
@@ -232,7 +279,7 @@ pub fn derive_decode(input: TokenStream) -> TokenStream {
     if generics.params.is_empty() {
         let expanded = quote! {
             impl Decode for #name {
-                fn decode(bytes: &[u8], offset: &mut usize) -> #name {
+                fn decode(bytes: &::bytes::Bytes, offset: &mut usize) -> #name {
                     #field_decode
                 }
             }
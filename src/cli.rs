@@ -0,0 +1,78 @@
+//! Admin commands run from the broker binary instead of starting a broker.
+//! They read the log directory directly, without opening it for writing, so
+//! they can run alongside a broker using it.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Error};
+
+use crate::kafka::{
+    group::{lag::group_lag, offsets::OffsetStore},
+    log::{partition::LogManager, LOG_DIR},
+};
+
+/// The first argument selecting the consumer groups command.
+pub const CONSUMER_GROUPS: &str = "consumer-groups";
+
+const USAGE: &str =
+    "usage: consumer-groups [--log-dir <dir>] (--list | --describe --group <group>)";
+
+/// Runs the consumer groups command with the arguments following its name,
+/// printing to stdout.
+pub fn consumer_groups(args: &[String]) -> Result<(), Error> {
+    let mut log_dir = PathBuf::from(LOG_DIR);
+    let mut group = None;
+    let mut list = false;
+    let mut describe = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log-dir" => log_dir = args.next().ok_or_else(|| anyhow!(USAGE))?.into(),
+            "--group" => group = Some(args.next().ok_or_else(|| anyhow!(USAGE))?),
+            "--list" => list = true,
+            "--describe" => describe = true,
+            _ => bail!(USAGE),
+        }
+    }
+
+    let logs = LogManager::new(log_dir);
+    let offsets = OffsetStore::read(&logs)?;
+
+    if list {
+        let mut groups: Vec<&String> = offsets.keys().collect();
+        groups.sort();
+        for group in groups {
+            println!("{group}");
+        }
+        return Ok(());
+    }
+
+    let (true, Some(group)) = (describe, group) else {
+        bail!(USAGE);
+    };
+
+    let Some(committed) = offsets.get(group) else {
+        bail!("consumer group {group} has no committed offsets");
+    };
+
+    println!(
+        "{:<24} {:<24} {:>9} {:>15} {:>15} {:>8}",
+        "GROUP", "TOPIC", "PARTITION", "CURRENT-OFFSET", "LOG-END-OFFSET", "LAG"
+    );
+
+    let or_dash = |value: Option<i64>| value.map_or("-".to_string(), |v| v.to_string());
+    for lag in group_lag(committed, &logs)? {
+        println!(
+            "{:<24} {:<24} {:>9} {:>15} {:>15} {:>8}",
+            group,
+            lag.topic,
+            lag.partition,
+            lag.committed_offset,
+            or_dash(lag.log_end_offset),
+            or_dash(lag.lag),
+        );
+    }
+
+    Ok(())
+}
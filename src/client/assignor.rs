@@ -0,0 +1,527 @@
+//! The consumer protocol of classic groups: what members subscribe to, what
+//! they are assigned, and the assignors the group leader runs to share the
+//! partitions among the members.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use anyhow::{Context, Error};
+use bytes::{BufMut, Bytes, BytesMut};
+use encode_derive::{Decode, Size};
+
+use crate::{
+    types::{
+        array::Array32,
+        bytes::NullableBytes32,
+        check_decoded,
+        kafkastring::{NullableString, String16},
+    },
+    Decode, Encode, Size,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TopicPartition {
+    pub topic: String,
+    pub partition: i32,
+}
+
+impl TopicPartition {
+    pub fn new(topic: impl Into<String>, partition: i32) -> Self {
+        Self {
+            topic: topic.into(),
+            partition,
+        }
+    }
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+struct TopicPartitions {
+    topic: String16,
+    partitions: Array32<i32>,
+}
+
+fn grouped(partitions: &[TopicPartition]) -> Array32<TopicPartitions> {
+    let mut topics: BTreeMap<&str, Vec<i32>> = BTreeMap::new();
+    for tp in partitions {
+        topics.entry(&tp.topic).or_default().push(tp.partition);
+    }
+    Array32(
+        topics
+            .into_iter()
+            .map(|(topic, mut partitions)| {
+                partitions.sort_unstable();
+                TopicPartitions {
+                    topic: topic.into(),
+                    partitions: Array32(partitions),
+                }
+            })
+            .collect(),
+    )
+}
+
+fn flattened(topics: Array32<TopicPartitions>) -> Vec<TopicPartition> {
+    topics
+        .0
+        .into_iter()
+        .flat_map(|topic| {
+            let name = topic.topic.0;
+            topic
+                .partitions
+                .0
+                .into_iter()
+                .map(move |partition| TopicPartition::new(name.clone(), partition))
+        })
+        .collect()
+}
+
+/// Decodes consumer protocol bytes, which other members send, failing if
+/// they are truncated or malformed.
+fn decode_protocol<T>(
+    bytes: &Bytes,
+    decode: impl FnOnce(&Bytes, &mut usize) -> T,
+) -> Result<T, Error> {
+    let mut offset = 0;
+    let value = decode(bytes, &mut offset);
+    check_decoded(bytes, offset).context("Malformed consumer protocol metadata")?;
+    Ok(value)
+}
+
+/// The consumer protocol version members subscribe with.
+const SUBSCRIPTION_VERSION: i16 = 3;
+/// The consumer protocol version assignments are sent in.
+const ASSIGNMENT_VERSION: i16 = 3;
+
+/// The metadata a member joins with: the topics it subscribes to and, from
+/// v1 on, the partitions it owns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subscription {
+    pub topics: Vec<String>,
+    pub user_data: Option<Bytes>,
+    pub owned_partitions: Vec<TopicPartition>,
+    /// The generation the owned partitions were assigned in, from v2 on.
+    pub generation_id: i32,
+    pub rack_id: Option<String>,
+}
+
+impl Subscription {
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_i16(SUBSCRIPTION_VERSION);
+        Array32(
+            self.topics
+                .iter()
+                .map(|topic| String16(topic.clone()))
+                .collect(),
+        )
+        .encode(&mut buf);
+        NullableBytes32(self.user_data.clone()).encode(&mut buf);
+        grouped(&self.owned_partitions).encode(&mut buf);
+        buf.put_i32(self.generation_id);
+        NullableString(self.rack_id.clone()).encode(&mut buf);
+        buf.freeze()
+    }
+
+    /// Decodes any version; fields newer than it are left empty.
+    pub fn from_bytes(bytes: &Bytes) -> Result<Self, Error> {
+        decode_protocol(bytes, |bytes, offset| {
+            let version = i16::decode(bytes, offset);
+            let topics = Array32::<String16>::decode(bytes, offset);
+            let mut subscription = Self {
+                topics: topics.0.into_iter().map(|topic| topic.0).collect(),
+                user_data: NullableBytes32::decode(bytes, offset).0,
+                generation_id: -1,
+                ..Default::default()
+            };
+            if version >= 1 {
+                subscription.owned_partitions = flattened(Array32::decode(bytes, offset));
+            }
+            if version >= 2 {
+                subscription.generation_id = i32::decode(bytes, offset);
+            }
+            if version >= 3 {
+                subscription.rack_id = NullableString::decode(bytes, offset).0;
+            }
+            subscription
+        })
+    }
+}
+
+/// The partitions the leader assigned a member.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Assignment {
+    pub partitions: Vec<TopicPartition>,
+    pub user_data: Option<Bytes>,
+}
+
+impl Assignment {
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_i16(ASSIGNMENT_VERSION);
+        grouped(&self.partitions).encode(&mut buf);
+        NullableBytes32(self.user_data.clone()).encode(&mut buf);
+        buf.freeze()
+    }
+
+    /// Decodes any version. An empty assignment, as sent to members the
+    /// leader did not assign anything, has no partitions.
+    pub fn from_bytes(bytes: &Bytes) -> Result<Self, Error> {
+        if bytes.is_empty() {
+            return Ok(Self::default());
+        }
+        decode_protocol(bytes, |bytes, offset| {
+            i16::decode(bytes, offset);
+            Self {
+                partitions: flattened(Array32::decode(bytes, offset)),
+                user_data: NullableBytes32::decode(bytes, offset).0,
+            }
+        })
+    }
+}
+
+/// How members give up partitions in a rebalance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebalanceProtocol {
+    /// Every partition is revoked before joining.
+    Eager,
+    /// Only partitions assigned elsewhere are revoked, after which the
+    /// member joins again so that they can be handed out.
+    Cooperative,
+}
+
+/// Shares the partitions of the subscribed topics among the members of a
+/// group. Run by the leader.
+pub trait Assignor: std::fmt::Debug + Send + Sync {
+    /// The protocol name members join with.
+    fn name(&self) -> &'static str;
+
+    /// The rebalance protocols this assignor supports.
+    fn supports(&self, protocol: RebalanceProtocol) -> bool;
+
+    /// Assigns partitions to each member, given the partition count of each
+    /// subscribed topic. Topics missing from `partitions` are not assigned.
+    fn assign(
+        &self,
+        partitions: &HashMap<String, i32>,
+        members: &BTreeMap<String, Subscription>,
+    ) -> BTreeMap<String, Vec<TopicPartition>>;
+}
+
+/// Gives each member a contiguous range of each topic it subscribes to, the
+/// first members in member id order one more partition if they do not
+/// divide evenly. Java's `range`.
+#[derive(Debug, Default)]
+pub struct RangeAssignor;
+
+impl Assignor for RangeAssignor {
+    fn name(&self) -> &'static str {
+        "range"
+    }
+
+    fn supports(&self, protocol: RebalanceProtocol) -> bool {
+        protocol == RebalanceProtocol::Eager
+    }
+
+    fn assign(
+        &self,
+        partitions: &HashMap<String, i32>,
+        members: &BTreeMap<String, Subscription>,
+    ) -> BTreeMap<String, Vec<TopicPartition>> {
+        let mut assignment: BTreeMap<String, Vec<TopicPartition>> = members
+            .keys()
+            .map(|member_id| (member_id.clone(), vec![]))
+            .collect();
+
+        let topics: BTreeSet<&String> = members.values().flat_map(|s| &s.topics).collect();
+        for topic in topics {
+            let Some(&count) = partitions.get(topic) else {
+                continue;
+            };
+            let subscribed: Vec<&String> = members
+                .iter()
+                .filter(|(_, subscription)| subscription.topics.contains(topic))
+                .map(|(member_id, _)| member_id)
+                .collect();
+            let per_member = count / subscribed.len() as i32;
+            let extra = count % subscribed.len() as i32;
+
+            let mut next = 0;
+            for (i, member_id) in subscribed.into_iter().enumerate() {
+                let len = per_member + i32::from((i as i32) < extra);
+                let member = assignment.get_mut(member_id).expect("every member");
+                member.extend((next..next + len).map(|p| TopicPartition::new(topic.clone(), p)));
+                next += len;
+            }
+        }
+
+        assignment
+    }
+}
+
+/// Keeps partitions with the members that own them as far as the balance
+/// allows, and never hands a partition to a member while another still
+/// owns it: the owner revokes it first and the next rebalance assigns it.
+/// Java's `cooperative-sticky`.
+#[derive(Debug, Default)]
+pub struct CooperativeStickyAssignor;
+
+impl CooperativeStickyAssignor {
+    /// The owner of each partition still to be assigned. A partition
+    /// claimed by several members stays with the newest generation.
+    fn owners<'a>(
+        partitions: &HashMap<String, i32>,
+        members: &'a BTreeMap<String, Subscription>,
+    ) -> HashMap<TopicPartition, (&'a String, i32)> {
+        let mut owners: HashMap<TopicPartition, (&String, i32)> = HashMap::new();
+        for (member_id, subscription) in members {
+            for tp in &subscription.owned_partitions {
+                let exists = partitions
+                    .get(&tp.topic)
+                    .is_some_and(|&count| (0..count).contains(&tp.partition));
+                if !exists || !subscription.topics.contains(&tp.topic) {
+                    continue;
+                }
+                let generation = subscription.generation_id;
+                owners
+                    .entry(tp.clone())
+                    .and_modify(|owner| {
+                        if generation > owner.1 {
+                            *owner = (member_id, generation);
+                        }
+                    })
+                    .or_insert((member_id, generation));
+            }
+        }
+        owners
+    }
+}
+
+impl Assignor for CooperativeStickyAssignor {
+    fn name(&self) -> &'static str {
+        "cooperative-sticky"
+    }
+
+    fn supports(&self, _protocol: RebalanceProtocol) -> bool {
+        true
+    }
+
+    fn assign(
+        &self,
+        partitions: &HashMap<String, i32>,
+        members: &BTreeMap<String, Subscription>,
+    ) -> BTreeMap<String, Vec<TopicPartition>> {
+        let owners = Self::owners(partitions, members);
+        let mut assignment: BTreeMap<&String, BTreeSet<TopicPartition>> = members
+            .keys()
+            .map(|member_id| (member_id, BTreeSet::new()))
+            .collect();
+        for (tp, (owner, _)) in &owners {
+            assignment
+                .get_mut(owner)
+                .expect("every member")
+                .insert(tp.clone());
+        }
+
+        let eligible =
+            |member_id: &String, tp: &TopicPartition| members[member_id].topics.contains(&tp.topic);
+
+        // Partitions no one owns go to the eligible member with the fewest.
+        let mut topics: Vec<&String> = members.values().flat_map(|s| &s.topics).collect();
+        topics.sort();
+        topics.dedup();
+        for topic in topics {
+            let Some(&count) = partitions.get(topic) else {
+                continue;
+            };
+            for partition in 0..count {
+                let tp = TopicPartition::new(topic.clone(), partition);
+                if owners.contains_key(&tp) {
+                    continue;
+                }
+                let fewest = assignment
+                    .iter()
+                    .filter(|(member_id, _)| eligible(member_id, &tp))
+                    .min_by_key(|(member_id, assigned)| (assigned.len(), **member_id))
+                    .map(|(member_id, _)| *member_id);
+                if let Some(member_id) = fewest {
+                    assignment
+                        .get_mut(member_id)
+                        .expect("every member")
+                        .insert(tp);
+                }
+            }
+        }
+
+        // Then partitions move from the members with the most to eligible
+        // members with at least two fewer, until none can.
+        loop {
+            let mut by_count: Vec<(&String, usize)> = assignment
+                .iter()
+                .map(|(member_id, assigned)| (*member_id, assigned.len()))
+                .collect();
+            by_count.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+            let moved = by_count.iter().find_map(|&(from, from_count)| {
+                assignment[from].iter().rev().find_map(|tp| {
+                    assignment
+                        .iter()
+                        .filter(|(to, assigned)| {
+                            **to != from && assigned.len() + 1 < from_count && eligible(to, tp)
+                        })
+                        .min_by_key(|(to, assigned)| (assigned.len(), **to))
+                        .map(|(to, _)| (from, *to, tp.clone()))
+                })
+            });
+            let Some((from, to, tp)) = moved else {
+                break;
+            };
+            assignment.get_mut(from).expect("every member").remove(&tp);
+            assignment.get_mut(to).expect("every member").insert(tp);
+        }
+
+        // A partition moving to another member is held back until its owner
+        // has revoked it.
+        assignment
+            .into_iter()
+            .map(|(member_id, assigned)| {
+                let assigned = assigned
+                    .into_iter()
+                    .filter(|tp| {
+                        owners
+                            .get(tp)
+                            .map_or(true, |(owner, _)| *owner == member_id)
+                    })
+                    .collect();
+                (member_id.clone(), assigned)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(topics: &[&str], owned: &[(&str, i32)]) -> Subscription {
+        Subscription {
+            topics: topics.iter().map(|topic| topic.to_string()).collect(),
+            owned_partitions: owned
+                .iter()
+                .map(|&(topic, partition)| TopicPartition::new(topic, partition))
+                .collect(),
+            generation_id: 1,
+            ..Default::default()
+        }
+    }
+
+    fn partitions(topics: &[(&str, i32)]) -> HashMap<String, i32> {
+        topics
+            .iter()
+            .map(|&(topic, count)| (topic.to_string(), count))
+            .collect()
+    }
+
+    fn assigned(assignment: &BTreeMap<String, Vec<TopicPartition>>, member_id: &str) -> Vec<i32> {
+        assignment[member_id]
+            .iter()
+            .map(|tp| tp.partition)
+            .collect()
+    }
+
+    #[test]
+    fn test_protocol_round_trips() {
+        let subscription = Subscription {
+            user_data: Some(Bytes::from_static(b"data")),
+            rack_id: Some("rack".to_string()),
+            ..subscription(&["a", "b"], &[("b", 1), ("a", 0), ("b", 0)])
+        };
+        let decoded = Subscription::from_bytes(&subscription.to_bytes()).unwrap();
+        assert_eq!(decoded.topics, subscription.topics);
+        assert_eq!(decoded.owned_partitions.len(), 3);
+        assert_eq!(decoded.owned_partitions[0], TopicPartition::new("a", 0));
+        assert_eq!(decoded.rack_id, subscription.rack_id);
+
+        // v0 is the version and topics, then user data.
+        let v0 = Bytes::from_static(&[0, 0, 0, 0, 0, 1, 0, 1, b't', 255, 255, 255, 255]);
+        let decoded = Subscription::from_bytes(&v0).unwrap();
+        assert_eq!(decoded.topics, ["t"]);
+        assert_eq!(decoded.generation_id, -1);
+        assert!(Subscription::from_bytes(&v0.slice(..7)).is_err());
+        let huge = Bytes::from_static(&[0, 0, 0x7f, 0xff, 0xff, 0xff, 0, 1]);
+        assert!(Subscription::from_bytes(&huge).is_err());
+        assert!(Assignment::from_bytes(&huge).is_err());
+
+        let assignment = Assignment {
+            partitions: vec![TopicPartition::new("a", 2)],
+            user_data: None,
+        };
+        assert_eq!(
+            Assignment::from_bytes(&assignment.to_bytes()).unwrap(),
+            assignment
+        );
+        assert_eq!(
+            Assignment::from_bytes(&Bytes::new()).unwrap(),
+            Assignment::default()
+        );
+    }
+
+    #[test]
+    fn test_range_assignor() {
+        let members = BTreeMap::from([
+            ("m1".to_string(), subscription(&["a", "b"], &[])),
+            ("m2".to_string(), subscription(&["a"], &[])),
+        ]);
+        let assignment = RangeAssignor.assign(&partitions(&[("a", 3), ("b", 2)]), &members);
+
+        assert_eq!(
+            assignment["m1"],
+            [
+                TopicPartition::new("a", 0),
+                TopicPartition::new("a", 1),
+                TopicPartition::new("b", 0),
+                TopicPartition::new("b", 1),
+            ]
+        );
+        assert_eq!(assigned(&assignment, "m2"), [2]);
+    }
+
+    #[test]
+    fn test_cooperative_sticky_assignor() {
+        let counts = partitions(&[("a", 4)]);
+
+        // A first member gets every partition.
+        let members = BTreeMap::from([("m1".to_string(), subscription(&["a"], &[]))]);
+        let first = CooperativeStickyAssignor.assign(&counts, &members);
+        assert_eq!(assigned(&first, "m1"), [0, 1, 2, 3]);
+
+        // When a second joins, the first keeps what it does not give up, and
+        // what it gives up is only assigned once it was revoked.
+        let owned: Vec<(&str, i32)> = (0..4).map(|p| ("a", p)).collect();
+        let members = BTreeMap::from([
+            ("m1".to_string(), subscription(&["a"], &owned)),
+            ("m2".to_string(), subscription(&["a"], &[])),
+        ]);
+        let second = CooperativeStickyAssignor.assign(&counts, &members);
+        assert_eq!(assigned(&second, "m1"), [0, 1]);
+        assert!(second["m2"].is_empty());
+
+        let members = BTreeMap::from([
+            (
+                "m1".to_string(),
+                subscription(&["a"], &[("a", 0), ("a", 1)]),
+            ),
+            ("m2".to_string(), subscription(&["a"], &[])),
+        ]);
+        let third = CooperativeStickyAssignor.assign(&counts, &members);
+        assert_eq!(assigned(&third, "m1"), [0, 1]);
+        assert_eq!(assigned(&third, "m2"), [2, 3]);
+
+        // Members only get topics they subscribe to, even if unbalanced.
+        let members = BTreeMap::from([
+            ("m1".to_string(), subscription(&["a"], &[])),
+            ("m2".to_string(), subscription(&["b"], &[])),
+        ]);
+        let assignment =
+            CooperativeStickyAssignor.assign(&partitions(&[("a", 4), ("b", 0)]), &members);
+        assert_eq!(assigned(&assignment, "m1"), [0, 1, 2, 3]);
+        assert!(assignment["m2"].is_empty());
+    }
+}
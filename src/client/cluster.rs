@@ -0,0 +1,245 @@
+//! What a client knows of the cluster: its brokers, the partitions of the
+//! topics it uses and their leaders, and its connections to the brokers.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Error};
+
+use crate::{
+    kafka::{
+        errors::NONE,
+        group::findcoordinator::FindCoordinatorRequest,
+        topics::metadata::{MetadataRequest, MetadataRequestTopic},
+    },
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        cstring::CompactNullableString,
+        uuid::UUID,
+    },
+};
+
+use super::Connection;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionInfo {
+    pub partition: i32,
+    /// The node id of the leader, -1 if there is none.
+    pub leader: i32,
+    pub leader_epoch: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicInfo {
+    pub id: UUID,
+    /// Ordered by partition index.
+    pub partitions: Vec<PartitionInfo>,
+}
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    /// The address of each broker, by node id.
+    brokers: HashMap<i32, String>,
+    topics: HashMap<String, TopicInfo>,
+}
+
+/// The metadata of the topics a client asked for, refreshed on demand, and
+/// a connection to each broker, opened on first use.
+pub struct Cluster {
+    client_id: String,
+    bootstrap_servers: Vec<String>,
+    snapshot: Mutex<Snapshot>,
+    connections: tokio::sync::Mutex<HashMap<i32, Arc<Connection>>>,
+    /// The connection metadata is asked on before any broker is known.
+    bootstrap: tokio::sync::Mutex<Option<Arc<Connection>>>,
+}
+
+impl Cluster {
+    /// A cluster reached through `bootstrap_servers`, a comma separated list
+    /// of `host:port`, as the Java client's `bootstrap.servers`.
+    pub fn new(bootstrap_servers: &str, client_id: &str) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            bootstrap_servers: bootstrap_servers
+                .split(',')
+                .map(str::trim)
+                .filter(|server| !server.is_empty())
+                .map(str::to_string)
+                .collect(),
+            snapshot: Mutex::new(Snapshot::default()),
+            connections: tokio::sync::Mutex::new(HashMap::new()),
+            bootstrap: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Asks for the metadata of `topics` again. Topics that do not exist or
+    /// may not be described are forgotten.
+    pub async fn refresh(&self, topics: &[String]) -> Result<(), Error> {
+        let request = MetadataRequest {
+            topics: CompactNullableArray(Some(
+                topics
+                    .iter()
+                    .map(|name| MetadataRequestTopic {
+                        topic_id: UUID([0; 16]),
+                        name: CompactNullableString(Some(name.clone())),
+                        tagged_fields: 0,
+                    })
+                    .collect(),
+            )),
+            allow_auto_topic_creation: 0,
+            include_topic_authorized_operations: 0,
+            tagged_fields: 0,
+        };
+        let response = self.any_connection().await?.metadata(&request).await?;
+
+        let mut snapshot = self.snapshot.lock().unwrap_or_else(|e| e.into_inner());
+        snapshot.brokers = response
+            .brokers
+            .iter()
+            .map(|broker| {
+                (
+                    broker.node_id,
+                    format!("{}:{}", broker.host.as_str(), broker.port),
+                )
+            })
+            .collect();
+        for topic in response.topics.iter() {
+            let Some(name) = topic.name.0.clone() else {
+                continue;
+            };
+            if topic.error_code != NONE {
+                snapshot.topics.remove(&name);
+                continue;
+            }
+            let mut partitions: Vec<PartitionInfo> = topic
+                .partitions
+                .iter()
+                .map(|partition| PartitionInfo {
+                    partition: partition.partition_index,
+                    leader: partition.leader_id,
+                    leader_epoch: partition.leader_epoch,
+                })
+                .collect();
+            partitions.sort_by_key(|partition| partition.partition);
+            let info = TopicInfo {
+                id: topic.topic_id.clone(),
+                partitions,
+            };
+            snapshot.topics.insert(name, info);
+        }
+
+        Ok(())
+    }
+
+    pub fn topic(&self, name: &str) -> Option<TopicInfo> {
+        let snapshot = self.snapshot.lock().unwrap_or_else(|e| e.into_inner());
+        snapshot.topics.get(name).cloned()
+    }
+
+    /// The name of a known topic by its id.
+    pub fn topic_name(&self, id: &UUID) -> Option<String> {
+        let snapshot = self.snapshot.lock().unwrap_or_else(|e| e.into_inner());
+        snapshot
+            .topics
+            .iter()
+            .find(|(_, topic)| topic.id == *id)
+            .map(|(name, _)| name.clone())
+    }
+
+    /// The node id of the leader of a partition, if it is known.
+    pub fn leader(&self, topic: &str, partition: i32) -> Option<i32> {
+        let snapshot = self.snapshot.lock().unwrap_or_else(|e| e.into_inner());
+        snapshot
+            .topics
+            .get(topic)?
+            .partitions
+            .iter()
+            .find(|info| info.partition == partition)
+            .map(|info| info.leader)
+            .filter(|&leader| snapshot.brokers.contains_key(&leader))
+    }
+
+    /// The connection to a broker, opened again if it was closed.
+    pub async fn connection(&self, node_id: i32) -> Result<Arc<Connection>, Error> {
+        let mut connections = self.connections.lock().await;
+        if let Some(connection) = connections.get(&node_id).filter(|c| !c.is_closed()) {
+            return Ok(connection.clone());
+        }
+
+        let address = {
+            let snapshot = self.snapshot.lock().unwrap_or_else(|e| e.into_inner());
+            snapshot.brokers.get(&node_id).cloned()
+        }
+        .ok_or_else(|| anyhow!("Unknown broker {node_id}"))?;
+        let connection = Arc::new(Connection::connect(address, &self.client_id).await?);
+        connections.insert(node_id, connection.clone());
+        Ok(connection)
+    }
+
+    /// The connection to the coordinator of a group, for `key_type` 0, or of
+    /// a transactional id, for 1.
+    pub async fn coordinator(&self, key_type: i8, key: &str) -> Result<Arc<Connection>, Error> {
+        let request = FindCoordinatorRequest {
+            key_type,
+            coordinator_keys: CompactArray(vec![key.into()]),
+            tagged_fields: 0,
+        };
+        let response = self.any_connection().await?.send(&request).await?;
+        let Some(coordinator) = response.coordinators.iter().next() else {
+            bail!("No coordinator found for {key}");
+        };
+        if coordinator.error_code != NONE {
+            bail!(
+                "FindCoordinator for {key} failed with error code {}",
+                coordinator.error_code
+            );
+        }
+
+        {
+            let mut snapshot = self.snapshot.lock().unwrap_or_else(|e| e.into_inner());
+            snapshot.brokers.insert(
+                coordinator.node_id,
+                format!("{}:{}", coordinator.host.as_str(), coordinator.port),
+            );
+        }
+        self.connection(coordinator.node_id).await
+    }
+
+    /// A connection to any broker: one already open, or else one to the
+    /// first bootstrap server that accepts it.
+    pub async fn any_connection(&self) -> Result<Arc<Connection>, Error> {
+        let open = self
+            .connections
+            .lock()
+            .await
+            .values()
+            .find(|connection| !connection.is_closed())
+            .cloned();
+        if let Some(connection) = open {
+            return Ok(connection);
+        }
+
+        let mut bootstrap = self.bootstrap.lock().await;
+        if let Some(connection) = bootstrap.as_ref().filter(|c| !c.is_closed()) {
+            return Ok(connection.clone());
+        }
+        let mut last_error = anyhow!("No bootstrap servers");
+        for server in &self.bootstrap_servers {
+            match Connection::connect(server.as_str(), &self.client_id).await {
+                Ok(connection) => {
+                    let connection = Arc::new(connection);
+                    *bootstrap = Some(connection.clone());
+                    return Ok(connection);
+                }
+                Err(e) => last_error = e.context(format!("Failed to connect to {server}")),
+            }
+        }
+        Err(last_error)
+    }
+}
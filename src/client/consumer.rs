@@ -0,0 +1,1650 @@
+//! A consumer that joins a classic group, assigns the partitions with a
+//! client-side assignor when it leads the group, reads them in incremental
+//! fetch sessions and commits the offsets it consumed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Error};
+use bytes::Bytes;
+use tokio::{task::JoinHandle, task::JoinSet};
+
+use crate::{
+    kafka::{
+        errors::{
+            COORDINATOR_LOAD_IN_PROGRESS, COORDINATOR_NOT_AVAILABLE, FETCH_SESSION_ID_NOT_FOUND,
+            ILLEGAL_GENERATION, INVALID_FETCH_SESSION_EPOCH, LEADER_NOT_AVAILABLE,
+            MEMBER_ID_REQUIRED, NONE, NOT_COORDINATOR, NOT_LEADER_OR_FOLLOWER, OFFSET_OUT_OF_RANGE,
+            REBALANCE_IN_PROGRESS, UNKNOWN_MEMBER_ID, UNKNOWN_TOPIC_ID, UNKNOWN_TOPIC_OR_PARTITION,
+        },
+        fetch::{
+            AbortedTransactions, FetchPartitionsRequest, FetchRequest, FetchResponse,
+            ForgottenTopicsData, TopicFetch, READ_COMMITTED,
+        },
+        fetch_session::{FINAL_EPOCH, INITIAL_EPOCH},
+        group::{
+            heartbeat::HeartbeatRequest,
+            joingroup::{JoinGroupRequest, JoinGroupRequestProtocol, JoinGroupResponseMember},
+            leavegroup::{LeaveGroupRequest, MemberIdentity},
+            offsetcommit::{
+                OffsetCommitRequest, OffsetCommitRequestPartition, OffsetCommitRequestTopic,
+            },
+            offsetfetch::{OffsetFetchRequest, OffsetFetchRequestGroup, OffsetFetchRequestTopic},
+            syncgroup::{SyncGroupRequest, SyncGroupRequestAssignment},
+            CONSUMER_PROTOCOL_TYPE,
+        },
+        listoffsets::{
+            ListOffsetsPartition, ListOffsetsRequest, ListOffsetsTopic, EARLIEST_TIMESTAMP,
+            LATEST_TIMESTAMP,
+        },
+        log::{TopicRecordBatch, ABORT_MARKER},
+    },
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        bytes::CompactBytes,
+        cstring::{CompactNullableString, CompactString},
+        records::CompactRecords,
+        uuid::UUID,
+    },
+    Decode,
+};
+
+use super::{
+    assignor::{
+        Assignment, Assignor, CooperativeStickyAssignor, RangeAssignor, RebalanceProtocol,
+        Subscription, TopicPartition,
+    },
+    cluster::Cluster,
+    Connection,
+};
+
+/// The bits of a batch's attributes naming its compression codec.
+const COMPRESSION_MASK: i16 = 0x07;
+/// Set when the broker stamps records with the time it appended them.
+const LOG_APPEND_TIME_FLAG: i16 = 0x08;
+/// The length of a batch header up to and including its length field.
+const BATCH_LENGTH_END: usize = 12;
+
+/// Where a consumer starts reading a partition the group has no offset for,
+/// or whose offset is out of range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetReset {
+    Earliest,
+    Latest,
+    /// Fail the poll instead.
+    None,
+}
+
+/// How a consumer joins its group and reads, with the defaults of the Java
+/// client.
+#[derive(Debug, Clone)]
+pub struct ConsumerConfig {
+    pub client_id: String,
+    pub group_id: String,
+    /// `group.instance.id`: set for static members, which do not leave the
+    /// group when they close.
+    pub group_instance_id: Option<String>,
+    /// `partition.assignment.strategy`, in order of preference. The group
+    /// is cooperative if every assignor supports it, eager otherwise.
+    pub assignors: Vec<Arc<dyn Assignor>>,
+    /// `session.timeout.ms`: how long the coordinator waits for heartbeats.
+    pub session_timeout: Duration,
+    /// `max.poll.interval.ms`: how long the coordinator waits for members
+    /// to join a rebalance.
+    pub rebalance_timeout: Duration,
+    /// `heartbeat.interval.ms`
+    pub heartbeat_interval: Duration,
+    /// `enable.auto.commit`: whether the consumed offsets are committed on
+    /// poll every `auto_commit_interval`, when partitions are revoked and on
+    /// close.
+    pub enable_auto_commit: bool,
+    /// `auto.commit.interval.ms`
+    pub auto_commit_interval: Duration,
+    /// `auto.offset.reset`
+    pub auto_offset_reset: OffsetReset,
+    /// `isolation.level`: 1 to only read committed transactions.
+    pub isolation_level: i8,
+    /// `fetch.min.bytes`
+    pub fetch_min_bytes: i32,
+    /// `fetch.max.bytes`
+    pub fetch_max_bytes: i32,
+    /// `max.partition.fetch.bytes`
+    pub max_partition_fetch_bytes: i32,
+    /// `fetch.max.wait.ms`, also how long a poll waits before fetching again
+    /// when a fetch returned nothing.
+    pub fetch_max_wait: Duration,
+    /// `check.crcs`
+    pub check_crcs: bool,
+    /// `request.timeout.ms`
+    pub request_timeout: Duration,
+    /// `retry.backoff.ms`
+    pub retry_backoff: Duration,
+    /// `metadata.max.age.ms`: how often the group leader checks whether the
+    /// subscribed topics gained partitions.
+    pub metadata_max_age: Duration,
+}
+
+impl Default for ConsumerConfig {
+    fn default() -> Self {
+        Self {
+            client_id: "consumer".to_string(),
+            group_id: String::new(),
+            group_instance_id: None,
+            assignors: vec![Arc::new(RangeAssignor), Arc::new(CooperativeStickyAssignor)],
+            session_timeout: Duration::from_secs(45),
+            rebalance_timeout: Duration::from_secs(300),
+            heartbeat_interval: Duration::from_secs(3),
+            enable_auto_commit: true,
+            auto_commit_interval: Duration::from_secs(5),
+            auto_offset_reset: OffsetReset::Latest,
+            isolation_level: 0,
+            fetch_min_bytes: 1,
+            fetch_max_bytes: 52428800,
+            max_partition_fetch_bytes: 1048576,
+            fetch_max_wait: Duration::from_millis(500),
+            check_crcs: true,
+            request_timeout: Duration::from_secs(30),
+            retry_backoff: Duration::from_millis(100),
+            metadata_max_age: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumerRecord {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Milliseconds since the epoch.
+    pub timestamp: i64,
+    pub key: Option<Bytes>,
+    pub value: Option<Bytes>,
+    pub headers: Vec<(String, Option<Bytes>)>,
+}
+
+/// The member a consumer is in its group.
+#[derive(Debug)]
+struct Membership {
+    member_id: String,
+    /// -1 until the member joined.
+    generation_id: i32,
+    /// Set when the member must join the group again.
+    rejoin: bool,
+}
+
+/// What the consumer and its heartbeat task share.
+struct Group {
+    config: ConsumerConfig,
+    cluster: Cluster,
+    membership: Mutex<Membership>,
+    coordinator: tokio::sync::Mutex<Option<Arc<Connection>>>,
+}
+
+fn coordinator_error(error_code: i16) -> bool {
+    matches!(
+        error_code,
+        COORDINATOR_LOAD_IN_PROGRESS | COORDINATOR_NOT_AVAILABLE | NOT_COORDINATOR
+    )
+}
+
+impl Group {
+    fn membership(&self) -> MutexGuard<'_, Membership> {
+        self.membership.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The connection to the group coordinator, looked up again once it
+    /// failed or moved.
+    async fn coordinator(&self) -> Result<Arc<Connection>, Error> {
+        let mut coordinator = self.coordinator.lock().await;
+        if let Some(connection) = coordinator.as_ref().filter(|c| !c.is_closed()) {
+            return Ok(connection.clone());
+        }
+        let connection = self.cluster.coordinator(0, &self.config.group_id).await?;
+        *coordinator = Some(connection.clone());
+        Ok(connection)
+    }
+
+    async fn forget_coordinator(&self) {
+        *self.coordinator.lock().await = None;
+    }
+
+    /// Handles an error code every group request may get. Returns whether
+    /// it was one of them.
+    async fn handle_error(&self, error_code: i16) -> bool {
+        match error_code {
+            REBALANCE_IN_PROGRESS => self.membership().rejoin = true,
+            ILLEGAL_GENERATION => {
+                let mut membership = self.membership();
+                membership.generation_id = -1;
+                membership.rejoin = true;
+            }
+            UNKNOWN_MEMBER_ID => {
+                let mut membership = self.membership();
+                membership.member_id.clear();
+                membership.generation_id = -1;
+                membership.rejoin = true;
+            }
+            error_code if coordinator_error(error_code) => self.forget_coordinator().await,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Keeps the membership alive between polls, flagging when the member
+    /// must rejoin.
+    async fn heartbeat(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.config.heartbeat_interval);
+        loop {
+            interval.tick().await;
+            let request = {
+                let membership = self.membership();
+                if membership.rejoin || membership.generation_id < 0 {
+                    continue;
+                }
+                HeartbeatRequest {
+                    group_id: self.config.group_id.as_str().into(),
+                    generation_id: membership.generation_id,
+                    member_id: membership.member_id.as_str().into(),
+                    group_instance_id: CompactNullableString(self.config.group_instance_id.clone()),
+                    tagged_fields: 0,
+                }
+            };
+
+            let Ok(connection) = self.coordinator().await else {
+                continue;
+            };
+            let sent = tokio::time::timeout(self.config.request_timeout, connection.send(&request));
+            let error_code = match sent.await {
+                Ok(Ok(response)) => response.error_code,
+                _ => {
+                    self.forget_coordinator().await;
+                    continue;
+                }
+            };
+            // A heartbeat of a generation that already ended tells nothing.
+            if self.membership().generation_id == request.generation_id {
+                self.handle_error(error_code).await;
+            }
+        }
+    }
+}
+
+/// A partition assigned to the consumer.
+#[derive(Debug, Clone, Default)]
+struct PartitionState {
+    /// The offset of the next record to return, once known.
+    position: Option<i64>,
+    /// Set when the position must be reset by `auto.offset.reset` rather
+    /// than taken from the committed offset.
+    reset: bool,
+}
+
+/// The client side of a fetch session with a broker.
+#[derive(Debug, Default)]
+struct FetchSession {
+    /// 0 until the broker opened a session.
+    id: i32,
+    /// The epoch of the next fetch; 0 opens a new session.
+    epoch: i32,
+    /// The fetch offset the broker has for each partition of the session.
+    partitions: HashMap<(UUID, i32), i64>,
+}
+
+impl FetchSession {
+    /// A fetch of `wanted` partitions at their fetch offsets. Only what
+    /// changed since the last fetch is sent in an open session.
+    fn request(&self, config: &ConsumerConfig, wanted: &[(UUID, i32, i64)]) -> FetchRequest {
+        let full = self.epoch == INITIAL_EPOCH;
+
+        let mut topics: Vec<TopicFetch> = vec![];
+        for (topic_id, partition, fetch_offset) in wanted {
+            let key = (topic_id.clone(), *partition);
+            if !full && self.partitions.get(&key) == Some(fetch_offset) {
+                continue;
+            }
+            let request = FetchPartitionsRequest {
+                partition: *partition,
+                current_leader_epoch: -1,
+                fetch_offset: *fetch_offset,
+                last_fetched_epoch: -1,
+                log_start_offset: -1,
+                partition_max_bytes: config.max_partition_fetch_bytes,
+                tagged_field: 0,
+            };
+            match topics.last_mut() {
+                Some(topic) if topic.topic_id == *topic_id => topic.partitions.0.push(request),
+                _ => topics.push(TopicFetch {
+                    topic_id: topic_id.clone(),
+                    partitions: CompactArray(vec![request]),
+                    tagged_field: 0,
+                }),
+            }
+        }
+
+        let mut forgotten: BTreeMap<&UUID, Vec<i32>> = BTreeMap::new();
+        if !full {
+            for (topic_id, partition) in self.partitions.keys() {
+                if !wanted
+                    .iter()
+                    .any(|(id, p, _)| id == topic_id && p == partition)
+                {
+                    forgotten.entry(topic_id).or_default().push(*partition);
+                }
+            }
+        }
+
+        FetchRequest {
+            max_wait_ms: config.fetch_max_wait.as_millis() as i32,
+            min_bytes: config.fetch_min_bytes,
+            max_bytes: config.fetch_max_bytes,
+            isolation_level: config.isolation_level,
+            session_id: self.id,
+            session_epoch: self.epoch,
+            topics: CompactArray(topics),
+            forgotten_topics_data: CompactArray(
+                forgotten
+                    .into_iter()
+                    .map(|(topic_id, partitions)| ForgottenTopicsData {
+                        topic_id: topic_id.clone(),
+                        partitions: CompactArray(partitions),
+                        tagged_field: 0,
+                    })
+                    .collect(),
+            ),
+            rack_id: CompactString(String::new()),
+            tagged_field: 0,
+        }
+    }
+
+    /// Moves the session on after the broker answered a fetch of `wanted`.
+    /// A session the broker lost starts over with a full fetch.
+    fn complete(&mut self, wanted: &[(UUID, i32, i64)], response: &FetchResponse) {
+        if matches!(
+            response.error_code,
+            FETCH_SESSION_ID_NOT_FOUND | INVALID_FETCH_SESSION_EPOCH
+        ) {
+            *self = Self::default();
+            return;
+        }
+        if self.epoch == INITIAL_EPOCH {
+            // A broker that opened no session is sent full fetches.
+            self.id = response.session_id;
+            self.epoch = if self.id == 0 { INITIAL_EPOCH } else { 1 };
+        } else {
+            self.epoch = self.epoch.checked_add(1).unwrap_or(1);
+        }
+        self.partitions = wanted
+            .iter()
+            .map(|(topic_id, partition, offset)| ((topic_id.clone(), *partition), *offset))
+            .collect();
+    }
+}
+
+/// Decodes the records of a fetched partition from `position` on. Returns
+/// them with the position after the last complete batch. Control batches
+/// and, with `read_committed`, aborted transactions are skipped.
+fn decode_records(
+    tp: &TopicPartition,
+    records: &Bytes,
+    position: i64,
+    aborted_transactions: &[AbortedTransactions],
+    read_committed: bool,
+    check_crcs: bool,
+) -> Result<(Vec<ConsumerRecord>, i64), Error> {
+    let mut aborted: Vec<&AbortedTransactions> = aborted_transactions.iter().collect();
+    aborted.sort_by_key(|txn| std::cmp::Reverse(txn.first_offset));
+    let mut aborting: HashSet<i64> = HashSet::new();
+
+    let mut decoded = vec![];
+    let mut next_position = position;
+    let mut at = 0;
+    while at + BATCH_LENGTH_END <= records.len() {
+        let batch_length = i32::decode(records, &mut (at + 8)).max(0) as usize;
+        // The last batch may be cut short by the fetch size.
+        if at + BATCH_LENGTH_END + batch_length > records.len() {
+            break;
+        }
+        let start = at;
+        let malformed = || {
+            anyhow!(
+                "Malformed batch at position {start} of {}-{}",
+                tp.topic,
+                tp.partition
+            )
+        };
+        let batch = TopicRecordBatch::try_decode(records, &mut at).map_err(|_| malformed())?;
+        if batch.base_offset < 0
+            || batch.last_offset_delta < 0
+            || batch
+                .base_offset
+                .checked_add(batch.last_offset_delta.into())
+                .is_none()
+        {
+            return Err(malformed());
+        }
+
+        if check_crcs && !batch.crc_matches() {
+            bail!(
+                "Corrupt batch at offset {} of {}-{}",
+                batch.base_offset,
+                tp.topic,
+                tp.partition
+            );
+        }
+        if batch.attributes & COMPRESSION_MASK != 0 {
+            bail!("Compressed batches are not supported");
+        }
+        if batch.last_offset() < next_position {
+            continue;
+        }
+        next_position = batch.last_offset() + 1;
+
+        if read_committed {
+            while aborted
+                .last()
+                .is_some_and(|txn| txn.first_offset <= batch.last_offset())
+            {
+                aborting.insert(aborted.pop().expect("checked above").producer_id);
+            }
+            if batch.is_control() {
+                if batch.control_type() == Some(ABORT_MARKER) {
+                    aborting.remove(&batch.producer_id);
+                }
+                continue;
+            }
+            if batch.is_transactional() && aborting.contains(&batch.producer_id) {
+                continue;
+            }
+        }
+        if batch.is_control() {
+            continue;
+        }
+
+        let batch_records = batch.try_decode_records().map_err(|_| malformed())?;
+        for record in batch_records {
+            if !(0..=batch.last_offset_delta.into()).contains(&record.delta_offset.0) {
+                return Err(malformed());
+            }
+            let offset = batch.base_offset + record.delta_offset.0;
+            if offset < position {
+                continue;
+            }
+            decoded.push(ConsumerRecord {
+                topic: tp.topic.clone(),
+                partition: tp.partition,
+                offset,
+                timestamp: if batch.attributes & LOG_APPEND_TIME_FLAG != 0 {
+                    batch.max_timestamp
+                } else {
+                    batch.base_timestamp.saturating_add(record.timestamp.0)
+                },
+                key: record.key.0,
+                value: record.value.0,
+                headers: record
+                    .headers_array
+                    .0
+                    .into_iter()
+                    .map(|header| (header.header_key.0, header.value.0))
+                    .collect(),
+            });
+        }
+    }
+
+    Ok((decoded, next_position))
+}
+
+/// Groups partitions by topic, in order.
+fn by_topic<'a, T>(
+    partitions: impl IntoIterator<Item = (&'a TopicPartition, T)>,
+) -> BTreeMap<&'a str, Vec<(i32, T)>> {
+    let mut topics: BTreeMap<&str, Vec<(i32, T)>> = BTreeMap::new();
+    for (tp, value) in partitions {
+        topics
+            .entry(tp.topic.as_str())
+            .or_default()
+            .push((tp.partition, value));
+    }
+    topics
+}
+
+/// A member of a consumer group. Records are read by [`poll`](Self::poll),
+/// which also joins the group and rebalances when needed; heartbeats are
+/// sent in the background.
+pub struct Consumer {
+    group: Arc<Group>,
+    heartbeat: JoinHandle<()>,
+    subscription: Vec<String>,
+    assignment: BTreeMap<TopicPartition, PartitionState>,
+    /// The fetch session with each broker, by node id.
+    sessions: HashMap<i32, FetchSession>,
+    /// The offsets last committed, so that auto-commit skips partitions
+    /// without new records.
+    committed: HashMap<TopicPartition, i64>,
+    next_auto_commit: Instant,
+    /// The partition counts the leader last assigned, to rebalance when a
+    /// subscribed topic gains partitions. `None` unless leading.
+    assigned_partitions: Option<HashMap<String, i32>>,
+    next_metadata_check: Instant,
+}
+
+impl Consumer {
+    pub fn new(bootstrap_servers: &str, config: ConsumerConfig) -> Result<Self, Error> {
+        if config.group_id.is_empty() {
+            bail!("A consumer needs a group id");
+        }
+        if config.assignors.is_empty() {
+            bail!("A consumer needs an assignor");
+        }
+
+        let group = Arc::new(Group {
+            cluster: Cluster::new(bootstrap_servers, &config.client_id),
+            membership: Mutex::new(Membership {
+                member_id: String::new(),
+                generation_id: -1,
+                rejoin: true,
+            }),
+            coordinator: tokio::sync::Mutex::new(None),
+            config,
+        });
+
+        let now = Instant::now();
+        Ok(Self {
+            heartbeat: tokio::spawn(group.clone().heartbeat()),
+            next_auto_commit: now + group.config.auto_commit_interval,
+            next_metadata_check: now + group.config.metadata_max_age,
+            group,
+            subscription: vec![],
+            assignment: BTreeMap::new(),
+            sessions: HashMap::new(),
+            committed: HashMap::new(),
+            assigned_partitions: None,
+        })
+    }
+
+    pub fn cluster(&self) -> &Cluster {
+        &self.group.cluster
+    }
+
+    /// Subscribes to `topics`, replacing the previous subscription. The
+    /// group rebalances on the next poll.
+    pub fn subscribe(&mut self, topics: &[&str]) {
+        self.subscription = topics.iter().map(|topic| topic.to_string()).collect();
+        self.subscription.sort();
+        self.subscription.dedup();
+        self.group.membership().rejoin = true;
+    }
+
+    /// The partitions currently assigned to this consumer.
+    pub fn assignment(&self) -> Vec<TopicPartition> {
+        self.assignment.keys().cloned().collect()
+    }
+
+    /// The offset of the next record to be returned from a partition, if
+    /// it is assigned and known.
+    pub fn position(&self, tp: &TopicPartition) -> Option<i64> {
+        self.assignment.get(tp)?.position
+    }
+
+    /// Reads an assigned partition from `offset` on the next poll.
+    pub fn seek(&mut self, tp: &TopicPartition, offset: i64) -> Result<(), Error> {
+        let state = self
+            .assignment
+            .get_mut(tp)
+            .ok_or_else(|| anyhow!("{}-{} is not assigned", tp.topic, tp.partition))?;
+        state.position = Some(offset);
+        state.reset = false;
+        Ok(())
+    }
+
+    /// How partitions are given up in a rebalance: cooperatively only if
+    /// every assignor supports it, as all members must agree on it.
+    fn rebalance_protocol(&self) -> RebalanceProtocol {
+        let cooperative = self
+            .group
+            .config
+            .assignors
+            .iter()
+            .all(|assignor| assignor.supports(RebalanceProtocol::Cooperative));
+        if cooperative {
+            RebalanceProtocol::Cooperative
+        } else {
+            RebalanceProtocol::Eager
+        }
+    }
+
+    /// Returns the records fetched, waiting up to `timeout` for some. Joins
+    /// the group first if the member must, and commits the consumed offsets
+    /// when auto-commit is due.
+    pub async fn poll(&mut self, timeout: Duration) -> Result<Vec<ConsumerRecord>, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if !self.subscription.is_empty() {
+                self.ensure_active_group(deadline).await?;
+            }
+            self.maybe_auto_commit().await;
+            self.update_positions().await?;
+
+            let records = self.fetch().await?;
+            let now = Instant::now();
+            if !records.is_empty() || now >= deadline {
+                return Ok(records);
+            }
+            tokio::time::sleep(self.group.config.fetch_max_wait.min(deadline - now)).await;
+        }
+    }
+
+    /// Joins the group until the member is in a stable generation, or the
+    /// deadline passed.
+    async fn ensure_active_group(&mut self, deadline: Instant) -> Result<(), Error> {
+        self.check_metadata().await;
+        while self.group.membership().rejoin {
+            if !self.join().await? {
+                if Instant::now() >= deadline {
+                    break;
+                }
+                tokio::time::sleep(self.group.config.retry_backoff).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Has the leader rebalance when a subscribed topic gained partitions.
+    async fn check_metadata(&mut self) {
+        let Some(assigned) = &self.assigned_partitions else {
+            return;
+        };
+        let now = Instant::now();
+        if now < self.next_metadata_check {
+            return;
+        }
+        self.next_metadata_check = now + self.group.config.metadata_max_age;
+
+        let topics: Vec<String> = assigned.keys().cloned().collect();
+        if self.group.cluster.refresh(&topics).await.is_err() {
+            return;
+        }
+        let current = self.partition_counts(&topics);
+        if current != *assigned {
+            self.group.membership().rejoin = true;
+        }
+    }
+
+    fn partition_counts(&self, topics: &[String]) -> HashMap<String, i32> {
+        topics
+            .iter()
+            .filter_map(|topic| {
+                let info = self.group.cluster.topic(topic)?;
+                Some((topic.clone(), info.partitions.len() as i32))
+            })
+            .collect()
+    }
+
+    /// Gives up partitions, committing what was consumed of them first if
+    /// auto-commit is on.
+    async fn revoke(&mut self, revoked: &[TopicPartition]) {
+        if revoked.is_empty() {
+            return;
+        }
+        if self.group.config.enable_auto_commit {
+            let offsets = self.consumed(revoked.iter());
+            let _ = self.commit_offsets(&offsets).await;
+        }
+        for tp in revoked {
+            self.assignment.remove(tp);
+        }
+    }
+
+    /// Joins the group and syncs the assignment. Returns whether it
+    /// completed; a rebalance that must be retried does not.
+    async fn join(&mut self) -> Result<bool, Error> {
+        let protocol = self.rebalance_protocol();
+
+        let (member_id, generation_id) = {
+            let membership = self.group.membership();
+            (membership.member_id.clone(), membership.generation_id)
+        };
+        if generation_id < 0 && !self.assignment.is_empty() {
+            // Partitions of a generation that ended without this member are
+            // lost: other members may own them by now.
+            self.assignment.clear();
+        } else if protocol == RebalanceProtocol::Eager {
+            let revoked = self.assignment();
+            self.revoke(&revoked).await;
+        }
+
+        let subscription = Subscription {
+            topics: self.subscription.clone(),
+            owned_partitions: self.assignment(),
+            generation_id,
+            ..Default::default()
+        }
+        .to_bytes();
+        let group = self.group.clone();
+        let config = &group.config;
+        let request = JoinGroupRequest {
+            group_id: config.group_id.as_str().into(),
+            session_timeout_ms: config.session_timeout.as_millis() as i32,
+            rebalance_timeout_ms: config.rebalance_timeout.as_millis() as i32,
+            member_id: member_id.as_str().into(),
+            group_instance_id: CompactNullableString(config.group_instance_id.clone()),
+            protocol_type: CONSUMER_PROTOCOL_TYPE.into(),
+            protocols: CompactArray(
+                config
+                    .assignors
+                    .iter()
+                    .map(|assignor| JoinGroupRequestProtocol {
+                        name: assignor.name().into(),
+                        metadata: CompactBytes(subscription.clone()),
+                        tagged_fields: 0,
+                    })
+                    .collect(),
+            ),
+            reason: CompactNullableString(None),
+            tagged_fields: 0,
+        };
+        // The coordinator answers once every member joined.
+        let join_timeout = config
+            .request_timeout
+            .max(config.rebalance_timeout + Duration::from_secs(5));
+        let Some(response) = self.send_to_coordinator(&request, join_timeout).await else {
+            return Ok(false);
+        };
+        match response.error_code {
+            NONE => {}
+            MEMBER_ID_REQUIRED => {
+                self.group.membership().member_id = response.member_id.0;
+                return Ok(false);
+            }
+            error_code if self.group.handle_error(error_code).await => return Ok(false),
+            error_code => bail!("JoinGroup failed with error code {error_code}"),
+        }
+
+        let protocol_name = response.protocol_name.0.clone().unwrap_or_default();
+        let assignments = if response.leader == response.member_id {
+            let Some(assignor) = config
+                .assignors
+                .iter()
+                .find(|assignor| assignor.name() == protocol_name)
+                .cloned()
+            else {
+                bail!("The group chose unknown assignor {protocol_name}");
+            };
+            match self.lead(assignor.as_ref(), &response.members).await? {
+                Some(assignments) => assignments,
+                None => return Ok(false),
+            }
+        } else {
+            self.assigned_partitions = None;
+            vec![]
+        };
+
+        let request = SyncGroupRequest {
+            group_id: config.group_id.as_str().into(),
+            generation_id: response.generation_id,
+            member_id: response.member_id.clone(),
+            group_instance_id: CompactNullableString(config.group_instance_id.clone()),
+            protocol_type: CompactNullableString(Some(CONSUMER_PROTOCOL_TYPE.to_string())),
+            protocol_name: response.protocol_name.clone(),
+            assignments: CompactArray(assignments),
+            tagged_fields: 0,
+        };
+        {
+            let mut membership = self.group.membership();
+            membership.member_id = response.member_id.0.clone();
+            membership.generation_id = response.generation_id;
+        }
+        let Some(synced) = self.send_to_coordinator(&request, join_timeout).await else {
+            return Ok(false);
+        };
+        match synced.error_code {
+            NONE => {}
+            error_code if self.group.handle_error(error_code).await => return Ok(false),
+            error_code => bail!("SyncGroup failed with error code {error_code}"),
+        }
+
+        let assigned: BTreeSet<TopicPartition> = Assignment::from_bytes(&synced.assignment.0)?
+            .partitions
+            .into_iter()
+            .collect();
+        let revoked: Vec<TopicPartition> = self
+            .assignment
+            .keys()
+            .filter(|tp| !assigned.contains(tp))
+            .cloned()
+            .collect();
+        self.revoke(&revoked).await;
+        for tp in assigned {
+            self.assignment.entry(tp).or_default();
+        }
+
+        // Partitions revoked cooperatively are handed out in another
+        // rebalance.
+        self.group.membership().rejoin = !revoked.is_empty();
+        Ok(true)
+    }
+
+    /// Assigns the partitions as the leader. `None` if the metadata could
+    /// not be read, upon which the rebalance is retried.
+    async fn lead(
+        &mut self,
+        assignor: &dyn Assignor,
+        members: &[JoinGroupResponseMember],
+    ) -> Result<Option<Vec<SyncGroupRequestAssignment>>, Error> {
+        let mut subscriptions = BTreeMap::new();
+        for member in members {
+            let subscription = Subscription::from_bytes(&member.metadata.0)?;
+            subscriptions.insert(member.member_id.0.clone(), subscription);
+        }
+
+        let mut topics: Vec<String> = subscriptions
+            .values()
+            .flat_map(|subscription| subscription.topics.iter().cloned())
+            .collect();
+        topics.sort();
+        topics.dedup();
+        if self.group.cluster.refresh(&topics).await.is_err() {
+            return Ok(None);
+        }
+        let counts = self.partition_counts(&topics);
+
+        let assignment = assignor.assign(&counts, &subscriptions);
+        self.assigned_partitions = Some(counts);
+        self.next_metadata_check = Instant::now() + self.group.config.metadata_max_age;
+
+        Ok(Some(
+            subscriptions
+                .keys()
+                .map(|member_id| SyncGroupRequestAssignment {
+                    member_id: member_id.as_str().into(),
+                    assignment: CompactBytes(
+                        Assignment {
+                            partitions: assignment.get(member_id).cloned().unwrap_or_default(),
+                            user_data: None,
+                        }
+                        .to_bytes(),
+                    ),
+                    tagged_fields: 0,
+                })
+                .collect(),
+        ))
+    }
+
+    /// Sends a request to the group coordinator. `None` if it could not be
+    /// reached or did not answer in time, after which it is looked up again.
+    async fn send_to_coordinator<R: super::Request>(
+        &self,
+        request: &R,
+        timeout: Duration,
+    ) -> Option<R::Response> {
+        let connection = self.group.coordinator().await.ok()?;
+        match tokio::time::timeout(timeout, connection.send(request)).await {
+            Ok(Ok(response)) => Some(response),
+            _ => {
+                self.group.forget_coordinator().await;
+                None
+            }
+        }
+    }
+
+    /// The positions of the partitions among `partitions` with one.
+    fn consumed<'a>(
+        &self,
+        partitions: impl Iterator<Item = &'a TopicPartition>,
+    ) -> HashMap<TopicPartition, i64> {
+        partitions
+            .filter_map(|tp| Some((tp.clone(), self.assignment.get(tp)?.position?)))
+            .collect()
+    }
+
+    async fn maybe_auto_commit(&mut self) {
+        let now = Instant::now();
+        if !self.group.config.enable_auto_commit || now < self.next_auto_commit {
+            return;
+        }
+        self.next_auto_commit = now + self.group.config.auto_commit_interval;
+
+        let mut offsets = self.consumed(self.assignment.keys());
+        offsets.retain(|tp, offset| self.committed.get(tp) != Some(offset));
+        // A failed auto-commit is retried at the next interval.
+        let _ = self.commit_offsets(&offsets).await;
+    }
+
+    /// Commits the position of every assigned partition.
+    pub async fn commit(&mut self) -> Result<(), Error> {
+        let offsets = self.consumed(self.assignment.keys());
+        self.commit_offsets(&offsets).await
+    }
+
+    /// Commits the offsets of the next records to read from partitions.
+    /// Fails if the group rebalanced since they were assigned.
+    pub async fn commit_offsets(
+        &mut self,
+        offsets: &HashMap<TopicPartition, i64>,
+    ) -> Result<(), Error> {
+        if offsets.is_empty() {
+            return Ok(());
+        }
+        let (member_id, generation_id) = {
+            let membership = self.group.membership();
+            (membership.member_id.clone(), membership.generation_id)
+        };
+        let config = &self.group.config;
+        let request = OffsetCommitRequest {
+            group_id: config.group_id.as_str().into(),
+            generation_id_or_member_epoch: generation_id,
+            member_id: member_id.as_str().into(),
+            group_instance_id: CompactNullableString(config.group_instance_id.clone()),
+            topics: CompactArray(
+                by_topic(offsets.iter())
+                    .into_iter()
+                    .map(|(topic, partitions)| OffsetCommitRequestTopic {
+                        name: topic.into(),
+                        partitions: CompactArray(
+                            partitions
+                                .into_iter()
+                                .map(|(partition, &offset)| OffsetCommitRequestPartition {
+                                    partition_index: partition,
+                                    committed_offset: offset,
+                                    committed_leader_epoch: -1,
+                                    committed_metadata: CompactNullableString(None),
+                                    tagged_fields: 0,
+                                })
+                                .collect(),
+                        ),
+                        tagged_fields: 0,
+                    })
+                    .collect(),
+            ),
+            tagged_fields: 0,
+        };
+        let Some(response) = self
+            .send_to_coordinator(&request, config.request_timeout)
+            .await
+        else {
+            bail!("The group coordinator could not be reached");
+        };
+
+        for topic in response.topics.iter() {
+            for partition in topic.partitions.iter() {
+                let tp = TopicPartition::new(topic.name.as_str(), partition.partition_index);
+                match partition.error_code {
+                    NONE => {
+                        if let Some(&offset) = offsets.get(&tp) {
+                            self.committed.insert(tp, offset);
+                        }
+                    }
+                    error_code => {
+                        self.group.handle_error(error_code).await;
+                        bail!(
+                            "Committing {}-{} failed with error code {error_code}",
+                            tp.topic,
+                            tp.partition
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The offsets committed for partitions; partitions without one are
+    /// left out.
+    pub async fn committed(
+        &self,
+        partitions: &[TopicPartition],
+    ) -> Result<HashMap<TopicPartition, i64>, Error> {
+        let (committed, _) = self.fetch_committed(partitions).await?;
+        Ok(committed
+            .into_iter()
+            .filter_map(|(tp, offset)| Some((tp, offset?)))
+            .collect())
+    }
+
+    /// Asks the coordinator for committed offsets. Returns the offset, or
+    /// `None` if there is none, of each partition it could tell, and
+    /// whether it could tell every one.
+    async fn fetch_committed(
+        &self,
+        partitions: &[TopicPartition],
+    ) -> Result<(HashMap<TopicPartition, Option<i64>>, bool), Error> {
+        let config = &self.group.config;
+        let request = OffsetFetchRequest {
+            groups: vec![OffsetFetchRequestGroup {
+                group_id: config.group_id.as_str().into(),
+                member_id: CompactNullableString(None),
+                member_epoch: -1,
+                topics: CompactNullableArray(Some(
+                    by_topic(partitions.iter().map(|tp| (tp, ())))
+                        .into_iter()
+                        .map(|(topic, partitions)| OffsetFetchRequestTopic {
+                            name: topic.into(),
+                            partition_indexes: CompactArray(
+                                partitions.into_iter().map(|(p, _)| p).collect(),
+                            ),
+                            tagged_fields: 0,
+                        })
+                        .collect(),
+                )),
+            }],
+            require_stable: 1,
+        };
+        let Some(response) = self
+            .send_to_coordinator(&request, config.request_timeout)
+            .await
+        else {
+            return Ok((HashMap::new(), false));
+        };
+        let Some(group) = response.groups.iter().next() else {
+            bail!("OffsetFetch returned no group");
+        };
+        match group.error_code {
+            NONE => {}
+            error_code if self.group.handle_error(error_code).await => {
+                return Ok((HashMap::new(), false))
+            }
+            error_code => bail!("OffsetFetch failed with error code {error_code}"),
+        }
+
+        let mut committed = HashMap::new();
+        for topic in group.topics.iter() {
+            for partition in topic.partitions.iter() {
+                // Offsets of pending transactions are asked again later.
+                if partition.error_code != NONE {
+                    continue;
+                }
+                let tp = TopicPartition::new(topic.name.as_str(), partition.partition_index);
+                let offset = partition.committed_offset;
+                committed.insert(tp, (offset >= 0).then_some(offset));
+            }
+        }
+        let complete = committed.len() == partitions.len();
+        Ok((committed, complete))
+    }
+
+    /// Finds the position of the assigned partitions without one: the
+    /// committed offset, or else the one `auto.offset.reset` names.
+    async fn update_positions(&mut self) -> Result<(), Error> {
+        let unknown: Vec<TopicPartition> = self
+            .assignment
+            .iter()
+            .filter(|(_, state)| state.position.is_none() && !state.reset)
+            .map(|(tp, _)| tp.clone())
+            .collect();
+        if !unknown.is_empty() {
+            let (committed, _) = self.fetch_committed(&unknown).await?;
+            for (tp, offset) in committed {
+                if let Some(state) = self.assignment.get_mut(&tp) {
+                    state.position = offset;
+                    state.reset = offset.is_none();
+                }
+            }
+        }
+
+        let reset: Vec<TopicPartition> = self
+            .assignment
+            .iter()
+            .filter(|(_, state)| state.position.is_none() && state.reset)
+            .map(|(tp, _)| tp.clone())
+            .collect();
+        if reset.is_empty() {
+            return Ok(());
+        }
+        let timestamp = match self.group.config.auto_offset_reset {
+            OffsetReset::Earliest => EARLIEST_TIMESTAMP,
+            OffsetReset::Latest => LATEST_TIMESTAMP,
+            OffsetReset::None => {
+                let tp = &reset[0];
+                bail!("No offset to read {}-{} from", tp.topic, tp.partition);
+            }
+        };
+
+        let by_leader = self.by_leader(reset.iter()).await;
+        for (node_id, partitions) in by_leader {
+            let request = ListOffsetsRequest {
+                replica_id: -1,
+                isolation_level: self.group.config.isolation_level,
+                topics: CompactArray(
+                    by_topic(partitions.iter().map(|&tp| (tp, ())))
+                        .into_iter()
+                        .map(|(topic, partitions)| ListOffsetsTopic {
+                            name: topic.into(),
+                            partitions: CompactArray(
+                                partitions
+                                    .into_iter()
+                                    .map(|(partition, _)| ListOffsetsPartition {
+                                        partition_index: partition,
+                                        current_leader_epoch: -1,
+                                        timestamp,
+                                        tagged_fields: 0,
+                                    })
+                                    .collect(),
+                            ),
+                            tagged_fields: 0,
+                        })
+                        .collect(),
+                ),
+                tagged_fields: 0,
+            };
+            let Ok(connection) = self.group.cluster.connection(node_id).await else {
+                continue;
+            };
+            let Ok(response) = connection.send(&request).await else {
+                continue;
+            };
+            for topic in response.topics.iter() {
+                for partition in topic.partitions.iter() {
+                    let tp = TopicPartition::new(topic.name.as_str(), partition.partition_index);
+                    if partition.error_code != NONE {
+                        continue;
+                    }
+                    if let Some(state) = self.assignment.get_mut(&tp) {
+                        state.position = Some(partition.found_offset);
+                        state.reset = false;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Groups partitions by the node id of their leader. The metadata is
+    /// refreshed first if a leader is unknown; partitions whose leader is
+    /// still unknown are left out.
+    async fn by_leader<'a>(
+        &self,
+        partitions: impl Iterator<Item = &'a TopicPartition> + Clone,
+    ) -> BTreeMap<i32, Vec<&'a TopicPartition>> {
+        let cluster = &self.group.cluster;
+        let unknown: BTreeSet<String> = partitions
+            .clone()
+            .filter(|tp| cluster.leader(&tp.topic, tp.partition).is_none())
+            .map(|tp| tp.topic.clone())
+            .collect();
+        if !unknown.is_empty() {
+            let topics: Vec<String> = unknown.into_iter().collect();
+            let _ = cluster.refresh(&topics).await;
+        }
+
+        let mut by_leader: BTreeMap<i32, Vec<&TopicPartition>> = BTreeMap::new();
+        for tp in partitions {
+            if let Some(leader) = cluster.leader(&tp.topic, tp.partition) {
+                by_leader.entry(leader).or_default().push(tp);
+            }
+        }
+        by_leader
+    }
+
+    /// Fetches the assigned partitions with a position from their leaders,
+    /// one fetch session per broker.
+    async fn fetch(&mut self) -> Result<Vec<ConsumerRecord>, Error> {
+        let fetchable: Vec<TopicPartition> = self
+            .assignment
+            .iter()
+            .filter(|(_, state)| state.position.is_some())
+            .map(|(tp, _)| tp.clone())
+            .collect();
+        let by_leader = self.by_leader(fetchable.iter()).await;
+
+        let mut fetches = JoinSet::new();
+        for (node_id, partitions) in by_leader {
+            let wanted: Vec<(UUID, i32, i64)> = partitions
+                .iter()
+                .filter_map(|tp| {
+                    let topic_id = self.group.cluster.topic(&tp.topic)?.id;
+                    Some((topic_id, tp.partition, self.assignment[*tp].position?))
+                })
+                .collect();
+            let session = self.sessions.remove(&node_id).unwrap_or_default();
+            let request = session.request(&self.group.config, &wanted);
+            let group = self.group.clone();
+            fetches.spawn(async move {
+                let fetched = async {
+                    let connection = group.cluster.connection(node_id).await?;
+                    tokio::time::timeout(group.config.request_timeout, connection.fetch(&request))
+                        .await
+                        .map_err(|_| anyhow!("Fetch from broker {node_id} timed out"))?
+                }
+                .await;
+                (node_id, session, wanted, fetched)
+            });
+        }
+
+        let mut records = vec![];
+        let mut refresh = false;
+        while let Some(joined) = fetches.join_next().await {
+            let (node_id, mut session, wanted, fetched) = joined?;
+            let Ok(response) = fetched else {
+                // The session is opened again on the next fetch.
+                refresh = true;
+                continue;
+            };
+            session.complete(&wanted, &response);
+            self.sessions.insert(node_id, session);
+            refresh |= self.handle_fetch(response, &mut records)?;
+        }
+
+        if refresh {
+            let topics: Vec<String> = self.subscribed_or_assigned_topics();
+            let _ = self.group.cluster.refresh(&topics).await;
+        }
+        Ok(records)
+    }
+
+    fn subscribed_or_assigned_topics(&self) -> Vec<String> {
+        let mut topics: Vec<String> = self
+            .assignment
+            .keys()
+            .map(|tp| tp.topic.clone())
+            .chain(self.subscription.iter().cloned())
+            .collect();
+        topics.sort();
+        topics.dedup();
+        topics
+    }
+
+    /// Takes the records of a fetch response and moves the positions past
+    /// them. Returns whether the metadata is stale.
+    fn handle_fetch(
+        &mut self,
+        response: FetchResponse,
+        records: &mut Vec<ConsumerRecord>,
+    ) -> Result<bool, Error> {
+        let config = &self.group.config;
+        let mut refresh = false;
+        for topic in response.responses.0 {
+            let Some(name) = self.group.cluster.topic_name(&topic.topic_id) else {
+                refresh = true;
+                continue;
+            };
+            for partition in topic.partitions.0 {
+                let tp = TopicPartition::new(name.as_str(), partition.partition_idx);
+                let Some(state) = self.assignment.get_mut(&tp) else {
+                    continue;
+                };
+                let Some(position) = state.position else {
+                    continue;
+                };
+                match partition.error_code {
+                    NONE => {
+                        let CompactRecords::Memory(bytes) = &partition.records else {
+                            continue;
+                        };
+                        let aborted = partition.aborted_transactions.0.unwrap_or_default();
+                        let (decoded, next_position) = decode_records(
+                            &tp,
+                            bytes,
+                            position,
+                            &aborted,
+                            config.isolation_level == READ_COMMITTED,
+                            config.check_crcs,
+                        )?;
+                        state.position = Some(next_position);
+                        records.extend(decoded);
+                    }
+                    OFFSET_OUT_OF_RANGE => {
+                        state.position = None;
+                        state.reset = true;
+                    }
+                    NOT_LEADER_OR_FOLLOWER
+                    | LEADER_NOT_AVAILABLE
+                    | UNKNOWN_TOPIC_OR_PARTITION
+                    | UNKNOWN_TOPIC_ID => refresh = true,
+                    error_code => bail!(
+                        "Fetching {}-{} failed with error code {error_code}",
+                        tp.topic,
+                        tp.partition
+                    ),
+                }
+            }
+        }
+        Ok(refresh)
+    }
+
+    /// Commits the consumed offsets if auto-commit is on, leaves the group
+    /// unless the member is static, and closes the fetch sessions.
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.heartbeat.abort();
+
+        let committed = if self.group.config.enable_auto_commit {
+            let mut offsets = self.consumed(self.assignment.keys());
+            offsets.retain(|tp, offset| self.committed.get(tp) != Some(offset));
+            self.commit_offsets(&offsets).await
+        } else {
+            Ok(())
+        };
+
+        let member_id = self.group.membership().member_id.clone();
+        let config = &self.group.config;
+        if !member_id.is_empty() && config.group_instance_id.is_none() {
+            let request = LeaveGroupRequest {
+                group_id: config.group_id.as_str().into(),
+                members: vec![MemberIdentity {
+                    member_id: member_id.as_str().into(),
+                    group_instance_id: CompactNullableString(None),
+                    reason: CompactNullableString(Some("the consumer is closing".to_string())),
+                }],
+            };
+            let _ = self
+                .send_to_coordinator(&request, config.request_timeout)
+                .await;
+        }
+
+        for (node_id, session) in &self.sessions {
+            if session.id == 0 {
+                continue;
+            }
+            let request = FetchSession {
+                epoch: FINAL_EPOCH,
+                id: session.id,
+                partitions: HashMap::new(),
+            }
+            .request(config, &[]);
+            if let Ok(connection) = self.group.cluster.connection(*node_id).await {
+                let _ = connection.fetch(&request).await;
+            }
+        }
+
+        committed
+    }
+}
+
+impl Drop for Consumer {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+
+    use super::*;
+    use crate::{
+        client::{
+            producer::{Producer, ProducerConfig, ProducerRecord},
+            tests::TestBroker,
+        },
+        kafka::log::TopicRecordDisk,
+        Encode,
+    };
+
+    fn batch(base_offset: i64, values: &[&str]) -> TopicRecordBatch {
+        let records = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let value = Bytes::from(value.to_string());
+                TopicRecordDisk::new(i as i32, i as i64, None, Some(value))
+            })
+            .collect();
+        let mut batch = TopicRecordBatch::new(1000, records);
+        batch.base_offset = base_offset;
+        batch
+    }
+
+    fn transactional(mut batch: TopicRecordBatch, producer_id: i64) -> TopicRecordBatch {
+        batch.attributes |= crate::kafka::log::TRANSACTIONAL_FLAG;
+        batch.producer_id = producer_id;
+        batch.update_crc();
+        batch
+    }
+
+    fn values(records: &[ConsumerRecord]) -> Vec<(i64, &str)> {
+        records
+            .iter()
+            .map(|r| {
+                (
+                    r.offset,
+                    std::str::from_utf8(r.value.as_ref().unwrap()).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_records() {
+        let mut abort = TopicRecordBatch::control(7, 0, false, 0, 1000);
+        abort.base_offset = 5;
+        let mut commit = TopicRecordBatch::control(8, 0, true, 0, 1000);
+        commit.base_offset = 7;
+        let batches = [
+            batch(0, &["a", "b", "c"]),
+            transactional(batch(3, &["aborted", "aborted"]), 7),
+            abort,
+            transactional(batch(6, &["committed"]), 8),
+            commit,
+            batch(8, &["d"]),
+        ];
+        let mut buf = BytesMut::new();
+        for batch in &batches {
+            batch.encode(&mut buf);
+        }
+        // The last batch is cut short, as by the fetch size.
+        let complete = buf.len();
+        buf.put_slice(&batch(9, &["partial"]).to_bytes()[..20]);
+        let records = buf.freeze();
+
+        let tp = TopicPartition::new("topic", 0);
+        let aborted = [AbortedTransactions {
+            producer_id: 7,
+            first_offset: 3,
+            tagged_field: 0,
+        }];
+
+        // Records before the position are skipped; control batches too.
+        let (decoded, position) = decode_records(&tp, &records, 1, &aborted, false, true).unwrap();
+        assert_eq!(
+            values(&decoded),
+            [
+                (1, "b"),
+                (2, "c"),
+                (3, "aborted"),
+                (4, "aborted"),
+                (6, "committed"),
+                (8, "d")
+            ]
+        );
+        assert_eq!(position, 9);
+        assert_eq!(decoded[1].timestamp, 1002);
+
+        let (decoded, position) = decode_records(&tp, &records, 0, &aborted, true, true).unwrap();
+        assert_eq!(
+            values(&decoded),
+            [(0, "a"), (1, "b"), (2, "c"), (6, "committed"), (8, "d")]
+        );
+        assert_eq!(position, 9);
+
+        let mut corrupt = records[..complete].to_vec();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert!(decode_records(&tp, &Bytes::from(corrupt), 0, &[], false, true).is_err());
+    }
+
+    #[test]
+    fn test_malformed_batches_fail_without_crc_checks() {
+        let tp = TopicPartition::new("topic", 0);
+        let records = batch(0, &["a", "b"]).to_bytes();
+        let decode =
+            |bytes: Vec<u8>| decode_records(&tp, &Bytes::from(bytes), 0, &[], false, false);
+        assert_eq!(
+            values(&decode(records.to_vec()).unwrap().0),
+            [(0, "a"), (1, "b")]
+        );
+
+        // A record count far beyond the batch.
+        let mut huge_count = records.to_vec();
+        huge_count[57..61].copy_from_slice(&i32::MAX.to_be_bytes());
+        assert!(decode(huge_count).is_err());
+
+        // A batch length too short for the batch header.
+        let mut short = records.to_vec();
+        short[8..12].copy_from_slice(&10i32.to_be_bytes());
+        assert!(decode(short).is_err());
+
+        // A record claiming an offset past the end of its batch.
+        let mut past_end = records.to_vec();
+        past_end[23..27].copy_from_slice(&0i32.to_be_bytes());
+        assert!(decode(past_end).is_err());
+    }
+
+    #[test]
+    fn test_fetch_session_sends_changes() {
+        let config = ConsumerConfig::default();
+        let topic_id = UUID([1; 16]);
+        let sent = |request: &FetchRequest| -> Vec<(i32, i64)> {
+            request
+                .topics
+                .iter()
+                .flat_map(|topic| topic.partitions.iter())
+                .map(|p| (p.partition, p.fetch_offset))
+                .collect()
+        };
+        let response = |session_id| FetchResponse {
+            throttle_time: 0,
+            error_code: NONE,
+            session_id,
+            responses: CompactArray(vec![]),
+            tagged_field: 0,
+        };
+
+        let mut session = FetchSession::default();
+        let wanted = [(topic_id.clone(), 0, 0), (topic_id.clone(), 1, 0)];
+        let request = session.request(&config, &wanted);
+        assert_eq!(
+            (request.session_id, request.session_epoch),
+            (0, INITIAL_EPOCH)
+        );
+        assert_eq!(sent(&request), [(0, 0), (1, 0)]);
+        session.complete(&wanted, &response(9));
+
+        // Only moved partitions are sent, and dropped ones forgotten.
+        let wanted = [(topic_id.clone(), 0, 5)];
+        let request = session.request(&config, &wanted);
+        assert_eq!((request.session_id, request.session_epoch), (9, 1));
+        assert_eq!(sent(&request), [(0, 5)]);
+        assert_eq!(request.forgotten_topics_data[0].partitions.0, [1]);
+        session.complete(&wanted, &response(9));
+
+        let request = session.request(&config, &wanted);
+        assert_eq!((request.session_id, request.session_epoch), (9, 2));
+        assert!(sent(&request).is_empty());
+
+        // A session the broker lost starts over.
+        let mut lost = response(0);
+        lost.error_code = FETCH_SESSION_ID_NOT_FOUND;
+        session.complete(&wanted, &lost);
+        let request = session.request(&config, &wanted);
+        assert_eq!(
+            (request.session_id, request.session_epoch),
+            (0, INITIAL_EPOCH)
+        );
+        assert_eq!(sent(&request), [(0, 5)]);
+    }
+
+    async fn produce(test: &TestBroker, from: usize, to: usize) {
+        let producer = Producer::new(&test.addr.to_string(), ProducerConfig::default())
+            .await
+            .unwrap();
+        for i in from..to {
+            let value = Bytes::from(format!("value-{i}"));
+            let mut record = ProducerRecord::new("topic", None, Some(value));
+            record.partition = Some((i % 4) as i32);
+            producer.send(record).await.unwrap();
+        }
+        producer.close().await;
+    }
+
+    /// Polls until `done` or the deadline, returning the records read.
+    async fn poll_until(
+        mut consumer: Consumer,
+        deadline: Instant,
+        done: impl Fn(&Consumer, &[ConsumerRecord]) -> bool,
+    ) -> (Consumer, Vec<ConsumerRecord>) {
+        let mut records = vec![];
+        while !done(&consumer, &records) && Instant::now() < deadline {
+            records.extend(consumer.poll(Duration::from_millis(200)).await.unwrap());
+        }
+        (consumer, records)
+    }
+
+    #[tokio::test]
+    async fn test_group_shares_partitions_and_resumes_from_commits() {
+        let test = TestBroker::start("consumer-test").await;
+        test.create_topic("topic", 4);
+        produce(&test, 0, 20).await;
+
+        let config = ConsumerConfig {
+            group_id: "group".to_string(),
+            assignors: vec![Arc::new(CooperativeStickyAssignor)],
+            session_timeout: Duration::from_secs(10),
+            rebalance_timeout: Duration::from_secs(2),
+            heartbeat_interval: Duration::from_millis(100),
+            enable_auto_commit: false,
+            auto_offset_reset: OffsetReset::Earliest,
+            fetch_max_wait: Duration::from_millis(100),
+            ..ConsumerConfig::default()
+        };
+        let addr = test.addr.to_string();
+        let mut first = Consumer::new(&addr, config.clone()).unwrap();
+        first.subscribe(&["topic"]);
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let mut records = vec![];
+        while records.len() < 20 && Instant::now() < deadline {
+            records.extend(first.poll(Duration::from_millis(500)).await.unwrap());
+        }
+        assert_eq!(first.assignment().len(), 4);
+        let mut offsets: Vec<(i32, i64)> =
+            records.iter().map(|r| (r.partition, r.offset)).collect();
+        offsets.sort();
+        let expected: Vec<(i32, i64)> = (0..4).flat_map(|p| (0..5).map(move |o| (p, o))).collect();
+        assert_eq!(offsets, expected);
+        assert!(first.sessions.values().all(|session| session.id > 0));
+
+        first.commit().await.unwrap();
+        let all = first.assignment();
+        let committed = first.committed(&all).await.unwrap();
+        assert!(all.iter().all(|tp| committed[tp] == 5));
+
+        // The second member gets half the partitions, once the first gave
+        // them up in a second rebalance. Each member polls in its own task,
+        // as a rebalance waits for all of them to rejoin.
+        let mut second = Consumer::new(&addr, config).unwrap();
+        second.subscribe(&["topic"]);
+        let settled = |consumer: &Consumer, _: &[ConsumerRecord]| {
+            consumer.assignment().len() == 2 && !consumer.group.membership().rejoin
+        };
+        let first = tokio::spawn(poll_until(first, deadline, settled));
+        let second = tokio::spawn(poll_until(second, deadline, settled));
+        let (first, old) = first.await.unwrap();
+        let (second, more) = second.await.unwrap();
+        assert!(old.is_empty() && more.is_empty());
+        assert!(settled(&first, &[]) && settled(&second, &[]));
+
+        produce(&test, 20, 40).await;
+        let read_all = |_: &Consumer, records: &[ConsumerRecord]| records.len() == 10;
+        let first = tokio::spawn(poll_until(first, deadline, read_all));
+        let second = tokio::spawn(poll_until(second, deadline, read_all));
+        let (first, first_records) = first.await.unwrap();
+        let (second, second_records) = second.await.unwrap();
+
+        let owned = first.assignment();
+        assert!(first_records
+            .iter()
+            .all(|r| owned.contains(&TopicPartition::new(&r.topic, r.partition))));
+        let mut offsets: Vec<(i32, i64)> = first_records
+            .iter()
+            .chain(&second_records)
+            .map(|r| (r.partition, r.offset))
+            .collect();
+        offsets.sort();
+        let expected: Vec<(i32, i64)> = (0..4).flat_map(|p| (5..10).map(move |o| (p, o))).collect();
+        assert_eq!(offsets, expected);
+
+        first.close().await.unwrap();
+        second.close().await.unwrap();
+    }
+}
@@ -0,0 +1,765 @@
+//! A client for Kafka brokers, built on the same codecs the broker serves
+//! requests with. A [`Connection`] negotiates versions with ApiVersions and
+//! can have many requests in flight, matching responses by correlation id.
+
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{anyhow, bail, Context, Error};
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, ToSocketAddrs},
+    sync::oneshot,
+    task::JoinHandle,
+};
+
+use crate::{
+    kafka::{
+        apiversions::{ApiVersionsRequest, ApiVersionsResponse, ClientSoftware},
+        errors::{NONE, UNSUPPORTED_VERSION},
+        fetch::{FetchRequest, FetchResponse},
+        group::{
+            findcoordinator::{FindCoordinatorRequest, FindCoordinatorResponse},
+            heartbeat::{HeartbeatRequest, HeartbeatResponse},
+            joingroup::{JoinGroupRequest, JoinGroupResponse},
+            leavegroup::{LeaveGroupRequest, LeaveGroupResponse},
+            offsetcommit::{OffsetCommitRequest, OffsetCommitResponse},
+            offsetfetch::{OffsetFetchRequest, OffsetFetchResponse},
+            syncgroup::{SyncGroupRequest, SyncGroupResponse},
+        },
+        listoffsets::{ListOffsetsRequest, ListOffsetsResponse},
+        listpartitions::{DescribePartitionsRequest, DescribePartitionsResponse},
+        produce::{ProduceRequest, ProduceResponse},
+        producer::initproducerid::{InitProducerIdRequest, InitProducerIdResponse},
+        response_header_version, skip_tagged_fields,
+        topics::metadata::{MetadataRequest, MetadataResponse},
+        RequestHeader,
+    },
+    types::check_decoded,
+    Decode, Encode, Size,
+};
+
+pub mod assignor;
+pub mod cluster;
+pub mod consumer;
+pub mod producer;
+
+/// A request the client can send, with the versions of it the client can
+/// encode and whose responses it can decode.
+pub trait Request: Encode + Size {
+    const API_KEY: i16;
+    const VERSIONS: RangeInclusive<i16>;
+
+    type Response;
+
+    /// Encodes the body for `version`, which is in [`VERSIONS`](Self::VERSIONS).
+    fn encode_version(&self, buf: &mut BytesMut, _version: i16) {
+        self.encode(buf);
+    }
+
+    /// Decodes the body of the response to a request of `version`.
+    fn decode_response(bytes: &Bytes, offset: &mut usize, version: i16) -> Self::Response;
+}
+
+impl Request for ApiVersionsRequest {
+    const API_KEY: i16 = 18;
+    const VERSIONS: RangeInclusive<i16> = 0..=4;
+
+    type Response = ApiVersionsResponse;
+
+    /// Brokers that do not support the version tell so in v0.
+    fn decode_response(bytes: &Bytes, offset: &mut usize, version: i16) -> Self::Response {
+        let error_code = i16::decode(bytes, &mut offset.clone());
+        let version = if error_code == UNSUPPORTED_VERSION {
+            0
+        } else {
+            version
+        };
+        ApiVersionsResponse::decode_version(bytes, offset, version)
+    }
+}
+
+impl Request for ProduceRequest {
+    const API_KEY: i16 = 0;
+    const VERSIONS: RangeInclusive<i16> = 9..=11;
+
+    type Response = ProduceResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        ProduceResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for FetchRequest {
+    const API_KEY: i16 = 1;
+    const VERSIONS: RangeInclusive<i16> = 13..=16;
+
+    type Response = FetchResponse;
+
+    /// Before v15 the body starts with the replica id, -1 for consumers.
+    fn encode_version(&self, buf: &mut BytesMut, version: i16) {
+        if version < 15 {
+            buf.put_i32(-1);
+        }
+        self.encode(buf);
+    }
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        FetchResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for MetadataRequest {
+    const API_KEY: i16 = 3;
+    const VERSIONS: RangeInclusive<i16> = 12..=12;
+
+    type Response = MetadataResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        MetadataResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for DescribePartitionsRequest {
+    const API_KEY: i16 = 75;
+    const VERSIONS: RangeInclusive<i16> = 0..=0;
+
+    type Response = DescribePartitionsResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        DescribePartitionsResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for InitProducerIdRequest {
+    const API_KEY: i16 = 22;
+    const VERSIONS: RangeInclusive<i16> = 3..=5;
+
+    type Response = InitProducerIdResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        InitProducerIdResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for ListOffsetsRequest {
+    const API_KEY: i16 = 2;
+    const VERSIONS: RangeInclusive<i16> = 6..=8;
+
+    type Response = ListOffsetsResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        ListOffsetsResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for OffsetCommitRequest {
+    const API_KEY: i16 = 8;
+    const VERSIONS: RangeInclusive<i16> = 8..=9;
+
+    type Response = OffsetCommitResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        OffsetCommitResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for OffsetFetchRequest {
+    const API_KEY: i16 = 9;
+    const VERSIONS: RangeInclusive<i16> = 9..=9;
+
+    type Response = OffsetFetchResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        OffsetFetchResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for FindCoordinatorRequest {
+    const API_KEY: i16 = 10;
+    const VERSIONS: RangeInclusive<i16> = 4..=5;
+
+    type Response = FindCoordinatorResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        FindCoordinatorResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for JoinGroupRequest {
+    const API_KEY: i16 = 11;
+    const VERSIONS: RangeInclusive<i16> = 9..=9;
+
+    type Response = JoinGroupResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        JoinGroupResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for HeartbeatRequest {
+    const API_KEY: i16 = 12;
+    const VERSIONS: RangeInclusive<i16> = 4..=4;
+
+    type Response = HeartbeatResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        HeartbeatResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for LeaveGroupRequest {
+    const API_KEY: i16 = 13;
+    const VERSIONS: RangeInclusive<i16> = 5..=5;
+
+    type Response = LeaveGroupResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        LeaveGroupResponse::decode(bytes, offset)
+    }
+}
+
+impl Request for SyncGroupRequest {
+    const API_KEY: i16 = 14;
+    const VERSIONS: RangeInclusive<i16> = 5..=5;
+
+    type Response = SyncGroupResponse;
+
+    fn decode_response(bytes: &Bytes, offset: &mut usize, _version: i16) -> Self::Response {
+        SyncGroupResponse::decode(bytes, offset)
+    }
+}
+
+/// The requests waiting for a response, by correlation id.
+#[derive(Debug, Default)]
+struct Pending {
+    waiters: HashMap<i32, oneshot::Sender<Bytes>>,
+    /// Set once no more responses can be read.
+    closed: bool,
+}
+
+/// A connection to a broker. Requests may be sent concurrently from many
+/// tasks; each waits for its own response.
+pub struct Connection {
+    client_id: Option<String>,
+    writer: tokio::sync::Mutex<Box<dyn AsyncWrite + Send + Unpin>>,
+    pending: Arc<Mutex<Pending>>,
+    next_correlation_id: AtomicI32,
+    /// The versions of each API the broker supports.
+    versions: HashMap<i16, RangeInclusive<i16>>,
+    reader: JoinHandle<()>,
+}
+
+impl Connection {
+    /// Connects to a broker over TCP.
+    pub async fn connect(addr: impl ToSocketAddrs, client_id: &str) -> Result<Self, Error> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Self::new(stream, client_id).await
+    }
+
+    /// Starts a connection over an established stream, asking the broker
+    /// which versions it supports.
+    pub async fn new<S>(stream: S, client_id: &str) -> Result<Self, Error>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let pending = Arc::new(Mutex::new(Pending::default()));
+
+        let mut connection = Self {
+            client_id: Some(client_id.to_string()),
+            writer: tokio::sync::Mutex::new(Box::new(writer)),
+            pending: pending.clone(),
+            next_correlation_id: AtomicI32::new(0),
+            versions: HashMap::new(),
+            reader: tokio::spawn(read_responses(reader, pending)),
+        };
+        connection.versions = connection.api_versions().await?;
+
+        Ok(connection)
+    }
+
+    /// Asks for the versions the broker supports, starting from the newest
+    /// ApiVersions the client knows and retrying with the broker's newest
+    /// if it does not know it.
+    async fn api_versions(&self) -> Result<HashMap<i16, RangeInclusive<i16>>, Error> {
+        let mut version = *ApiVersionsRequest::VERSIONS.end();
+        loop {
+            let request = ApiVersionsRequest {
+                client_software: (version >= 3).then(|| ClientSoftware {
+                    name: env!("CARGO_PKG_NAME").into(),
+                    version: env!("CARGO_PKG_VERSION").into(),
+                    tagged_fields: 0,
+                }),
+            };
+            let response = self.send_version(&request, version).await?;
+
+            let versions: HashMap<i16, RangeInclusive<i16>> = response
+                .api_keys
+                .iter()
+                .map(|api| (api.key, api.min..=api.max))
+                .collect();
+            match response.error_code {
+                NONE => return Ok(versions),
+                UNSUPPORTED_VERSION => {
+                    let supported = versions
+                        .get(&ApiVersionsRequest::API_KEY)
+                        .map(|versions| *versions.end())
+                        .filter(|&max| {
+                            max < version && ApiVersionsRequest::VERSIONS.contains(&max)
+                        });
+                    match supported {
+                        Some(max) => version = max,
+                        None => bail!("The broker supports no ApiVersions version the client does"),
+                    }
+                }
+                error_code => bail!("ApiVersions failed with error code {error_code}"),
+            }
+        }
+    }
+
+    /// Whether the broker closed the connection or it failed, after which
+    /// every request fails.
+    pub fn is_closed(&self) -> bool {
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.closed
+    }
+
+    /// The newest version of `R` both the client and the broker support.
+    pub fn version<R: Request>(&self) -> Result<i16, Error> {
+        self.versions
+            .get(&R::API_KEY)
+            .and_then(|broker| {
+                let version = (*R::VERSIONS.end()).min(*broker.end());
+                (R::VERSIONS.contains(&version) && broker.contains(&version)).then_some(version)
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "The broker supports no version of api key {} the client does",
+                    R::API_KEY
+                )
+            })
+    }
+
+    /// Sends a request in the negotiated version and waits for its response.
+    pub async fn send<R: Request>(&self, request: &R) -> Result<R::Response, Error> {
+        self.send_version(request, self.version::<R>()?).await
+    }
+
+    async fn send_version<R: Request>(
+        &self,
+        request: &R,
+        version: i16,
+    ) -> Result<R::Response, Error> {
+        let correlation_id = self.next_correlation_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            if pending.closed {
+                bail!("The connection is closed");
+            }
+            pending.waiters.insert(correlation_id, sender);
+        }
+
+        if let Err(e) = self.write(request, version, correlation_id).await {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            pending.waiters.remove(&correlation_id);
+            return Err(e);
+        }
+
+        let response = receiver
+            .await
+            .map_err(|_| anyhow!("The connection was closed before a response"))?;
+
+        // The response header is the correlation id, then tagged fields if
+        // it is flexible.
+        let mut offset = 4;
+        if response_header_version(R::API_KEY, version) >= 1 {
+            skip_tagged_fields(&response, &mut offset);
+        }
+        let decoded = R::decode_response(&response, &mut offset, version);
+        check_decoded(&response, offset)
+            .with_context(|| format!("Malformed response to api key {} v{version}", R::API_KEY))?;
+        Ok(decoded)
+    }
+
+    /// Sends a request the broker does not answer.
+    pub async fn send_without_response<R: Request>(&self, request: &R) -> Result<(), Error> {
+        let correlation_id = self.next_correlation_id.fetch_add(1, Ordering::Relaxed);
+        self.write(request, self.version::<R>()?, correlation_id)
+            .await
+    }
+
+    async fn write<R: Request>(
+        &self,
+        request: &R,
+        version: i16,
+        correlation_id: i32,
+    ) -> Result<(), Error> {
+        let header = RequestHeader {
+            api_key: R::API_KEY,
+            api_version: version,
+            correlation_id,
+            client_id: self.client_id.clone(),
+        };
+
+        // The size prefix is filled in once the body is encoded.
+        let mut buf = BytesMut::with_capacity(4 + 64 + request.size_in_bytes());
+        buf.put_i32(0);
+        header.encode(&mut buf);
+        request.encode_version(&mut buf, version);
+        let size = (buf.len() - 4) as i32;
+        buf[..4].copy_from_slice(&size.to_be_bytes());
+
+        let mut writer = self.writer.lock().await;
+        writer.write_all(&buf).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    pub async fn fetch(&self, request: &FetchRequest) -> Result<FetchResponse, Error> {
+        self.send(request).await
+    }
+
+    /// Produces records, with no response if the request does not ask for
+    /// acknowledgements.
+    pub async fn produce(
+        &self,
+        request: &ProduceRequest,
+    ) -> Result<Option<ProduceResponse>, Error> {
+        if request.acks == 0 {
+            self.send_without_response(request).await?;
+            return Ok(None);
+        }
+        self.send(request).await.map(Some)
+    }
+
+    pub async fn metadata(&self, request: &MetadataRequest) -> Result<MetadataResponse, Error> {
+        self.send(request).await
+    }
+
+    pub async fn describe_topic_partitions(
+        &self,
+        request: &DescribePartitionsRequest,
+    ) -> Result<DescribePartitionsResponse, Error> {
+        self.send(request).await
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Hands each response to the request it answers, until the connection
+/// closes, upon which the requests still waiting fail.
+async fn read_responses<R: AsyncRead + Unpin>(mut reader: R, pending: Arc<Mutex<Pending>>) {
+    while let Ok(response) = read_response(&mut reader).await {
+        let Some(correlation_id) = response.get(..4) else {
+            break;
+        };
+        let correlation_id = i32::from_be_bytes(correlation_id.try_into().unwrap());
+        let waiter = pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .waiters
+            .remove(&correlation_id);
+        // A request whose caller went away is not waited on anymore.
+        if let Some(waiter) = waiter {
+            let _ = waiter.send(response);
+        }
+    }
+
+    let mut pending = pending.lock().unwrap_or_else(|e| e.into_inner());
+    pending.closed = true;
+    pending.waiters.clear();
+}
+
+/// Reads one response, which is an INT32 size followed by that many bytes.
+async fn read_response<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Bytes, Error> {
+    let size = reader.read_i32().await?;
+    if size < 0 {
+        bail!("Invalid response size {size}");
+    }
+    let mut buf = BytesMut::zeroed(size as usize);
+    reader.read_exact(&mut buf).await?;
+    Ok(buf.freeze())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{fs, net::SocketAddr, path::PathBuf};
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        handle_client,
+        kafka::{
+            broker::{tests::create_topic, Broker, BrokerConfig},
+            errors::UNKNOWN_TOPIC_OR_PARTITION,
+            fetch::{FetchPartitionsRequest, TopicFetch},
+            listpartitions::TopicsRequest,
+            produce::{PartitionProduceData, TopicProduceData},
+            sasl::Session,
+            topics::metadata::MetadataRequestTopic,
+        },
+        types::{
+            array::{CompactArray, CompactNullableArray},
+            bytes::CompactNullableBytes,
+            cstring::{CompactNullableString, CompactString},
+            records::CompactRecords,
+            uuid::UUID,
+        },
+    };
+
+    /// A broker on a temporary log directory, serving connections on an
+    /// ephemeral port until the test ends.
+    pub(crate) struct TestBroker {
+        pub addr: SocketAddr,
+        pub broker: Arc<Broker>,
+        dir: PathBuf,
+    }
+
+    impl TestBroker {
+        pub async fn start(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            // Metadata sends clients to the address the broker advertises.
+            let broker = Arc::new(
+                Broker::open(BrokerConfig {
+                    host: addr.ip().to_string(),
+                    port: addr.port() as i32,
+                    log_dir: dir.clone(),
+                    ..BrokerConfig::default()
+                })
+                .unwrap(),
+            );
+
+            let serving = broker.clone();
+            tokio::spawn(async move {
+                while let Ok((socket, peer_addr)) = listener.accept().await {
+                    tokio::spawn(serve(socket, peer_addr, serving.clone()));
+                }
+            });
+
+            Self { addr, broker, dir }
+        }
+
+        /// Creates a topic with one partition per id.
+        pub fn create_topic(&self, name: &str, partitions: i32) -> UUID {
+            create_topic(&self.broker, name, partitions)
+        }
+    }
+
+    impl Drop for TestBroker {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn serve(mut socket: TcpStream, peer_addr: SocketAddr, broker: Arc<Broker>) {
+        let session = Arc::new(Mutex::new(Session::new(false)));
+        while let Ok(request) = read_response(&mut socket).await {
+            if handle_client(&request, peer_addr, &broker, &session, &mut socket)
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    /// A batch of one record without a key, at base offset 0.
+    fn batch(value: &[u8]) -> Bytes {
+        let mut record = BytesMut::new();
+        record.put_u8(0); // attributes
+        record.put_u8(0); // timestamp delta
+        record.put_u8(0); // offset delta
+        record.put_u8(1); // null key
+        record.put_u8(value.len() as u8 * 2);
+        record.put_slice(value);
+        record.put_u8(0); // headers
+
+        let mut body = BytesMut::new();
+        body.put_i16(0); // attributes
+        body.put_i32(0); // last offset delta
+        body.put_i64(0);
+        body.put_i64(0);
+        body.put_i64(-1); // producer id
+        body.put_i16(-1);
+        body.put_i32(-1);
+        body.put_i32(1);
+        body.put_u8(record.len() as u8 * 2);
+        body.put_slice(&record);
+
+        let mut batch = BytesMut::new();
+        batch.put_i64(0);
+        batch.put_i32(4 + 1 + 4 + body.len() as i32);
+        batch.put_i32(0); // partition leader epoch
+        batch.put_u8(2);
+        batch.put_u32(crc32c::crc32c(&body));
+        batch.put_slice(&body);
+        batch.freeze()
+    }
+
+    #[tokio::test]
+    async fn test_malformed_response_fails_the_request() {
+        use crate::kafka::apiversions::get_supported_versions;
+        use tokio::io::AsyncWriteExt;
+
+        let (client, mut server) = tokio::io::duplex(1 << 16);
+        let broker = tokio::spawn(async move {
+            let request = read_response(&mut server).await.unwrap();
+            let correlation_id = i32::decode(&request, &mut 4);
+            let versions = ApiVersionsResponse {
+                version: 4,
+                error_code: NONE,
+                api_keys: get_supported_versions("supported_versions.json").unwrap(),
+                throttle_time_ms: 0,
+            };
+            let frame = crate::encode_response(correlation_id, 0, &versions);
+            server
+                .write_all(&frame.into_bytes().unwrap())
+                .await
+                .unwrap();
+
+            // A Metadata response claiming four brokers but ending after the
+            // throttle time.
+            let request = read_response(&mut server).await.unwrap();
+            let correlation_id = i32::decode(&request, &mut 4);
+            let mut response = BytesMut::new();
+            response.put_i32(10);
+            response.put_i32(correlation_id);
+            response.put_slice(&[0, 0, 0, 0, 0, 5]);
+            server.write_all(&response).await.unwrap();
+            server
+        });
+
+        let connection = Connection::new(client, "client-test").await.unwrap();
+        let metadata = MetadataRequest {
+            topics: CompactNullableArray(None),
+            allow_auto_topic_creation: 0,
+            include_topic_authorized_operations: 0,
+            tagged_fields: 0,
+        };
+        let error = connection.metadata(&metadata).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Malformed response to api key 3"));
+        assert!(!connection.is_closed());
+        broker.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connection_round_trips() {
+        let test = TestBroker::start("client-test").await;
+        let topic_id = test.create_topic("topic", 2);
+
+        let connection = Connection::connect(test.addr, "client-test").await.unwrap();
+        assert_eq!(connection.version::<FetchRequest>().unwrap(), 16);
+        assert_eq!(connection.version::<ProduceRequest>().unwrap(), 11);
+
+        let metadata = MetadataRequest {
+            topics: CompactNullableArray(Some(vec![
+                MetadataRequestTopic {
+                    topic_id: UUID([0; 16]),
+                    name: CompactNullableString(Some("topic".to_string())),
+                    tagged_fields: 0,
+                },
+                MetadataRequestTopic {
+                    topic_id: UUID([0; 16]),
+                    name: CompactNullableString(Some("missing".to_string())),
+                    tagged_fields: 0,
+                },
+            ])),
+            allow_auto_topic_creation: 0,
+            include_topic_authorized_operations: 0,
+            tagged_fields: 0,
+        };
+        // Requests in flight together get their own responses.
+        let describe = DescribePartitionsRequest {
+            topics_array: CompactArray(vec![TopicsRequest {
+                name: CompactString("topic".to_string()),
+                tag_buffer: 0,
+            }]),
+            response_partition_limit: 100,
+            cursor: 0xff,
+            tag_buffer: 0,
+        };
+        let (metadata, described) = tokio::join!(
+            connection.metadata(&metadata),
+            connection.describe_topic_partitions(&describe)
+        );
+        let metadata = metadata.unwrap();
+        assert_eq!(metadata.brokers.len(), 1);
+        assert_eq!(metadata.topics[0].topic_id, topic_id);
+        assert_eq!(metadata.topics[0].partitions.len(), 2);
+        assert_eq!(metadata.topics[1].error_code, UNKNOWN_TOPIC_OR_PARTITION);
+        assert_eq!(described.unwrap().topics_array[0].partitions_array.len(), 2);
+
+        let produce = ProduceRequest {
+            transactional_id: CompactNullableString(None),
+            acks: -1,
+            timeout_ms: 1000,
+            topic_data: CompactArray(vec![TopicProduceData {
+                name: CompactString("topic".to_string()),
+                partition_data: CompactArray(vec![PartitionProduceData {
+                    index: 1,
+                    records: CompactNullableBytes(Some(batch(b"hello"))),
+                    tagged_fields: 0,
+                }]),
+                tagged_fields: 0,
+            }]),
+            tagged_fields: 0,
+        };
+        let produced = connection.produce(&produce).await.unwrap().unwrap();
+        assert_eq!(
+            produced.responses[0].partition_responses[0].error_code,
+            NONE
+        );
+
+        let fetch = FetchRequest {
+            max_wait_ms: 0,
+            min_bytes: 0,
+            max_bytes: 1 << 20,
+            isolation_level: 0,
+            session_id: 0,
+            session_epoch: -1,
+            topics: CompactArray(vec![TopicFetch {
+                topic_id: topic_id.clone(),
+                partitions: CompactArray(vec![FetchPartitionsRequest {
+                    partition: 1,
+                    current_leader_epoch: -1,
+                    fetch_offset: 0,
+                    last_fetched_epoch: -1,
+                    log_start_offset: -1,
+                    partition_max_bytes: 1 << 20,
+                    tagged_field: 0,
+                }]),
+                tagged_field: 0,
+            }]),
+            forgotten_topics_data: CompactArray(vec![]),
+            rack_id: CompactString(String::new()),
+            tagged_field: 0,
+        };
+        let fetched = connection.fetch(&fetch).await.unwrap();
+        let partition = &fetched.responses[0].partitions[0];
+        assert_eq!(partition.high_watermark, 1);
+        let CompactRecords::Memory(records) = &partition.records else {
+            panic!("fetched records are decoded into memory");
+        };
+        assert!(records.ends_with(b"hello\x00"));
+    }
+}
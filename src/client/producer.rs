@@ -0,0 +1,872 @@
+//! A producer that batches records per partition, partitions them as the
+//! Java client does, and retries failed batches without writing them twice.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Error};
+use bytes::Bytes;
+use rand::Rng;
+use tokio::{sync::oneshot, sync::Notify, task::JoinHandle};
+
+use crate::{
+    kafka::{
+        errors::{
+            CORRUPT_MESSAGE, DUPLICATE_SEQUENCE_NUMBER, LEADER_NOT_AVAILABLE, NONE,
+            NOT_ENOUGH_REPLICAS, NOT_ENOUGH_REPLICAS_AFTER_APPEND, NOT_LEADER_OR_FOLLOWER,
+            REQUEST_TIMED_OUT, UNKNOWN_TOPIC_OR_PARTITION,
+        },
+        log::{TopicHeaders, TopicRecordBatch, TopicRecordDisk},
+        produce::{PartitionProduceData, ProduceRequest, ProduceResponse, TopicProduceData},
+        producer::initproducerid::InitProducerIdRequest,
+    },
+    types::{
+        array::CompactArray,
+        bytes::CompactNullableBytes,
+        cstring::{CompactNullableString, VarintString},
+    },
+    Encode, Size,
+};
+
+use super::cluster::Cluster;
+
+/// Bytes of a batch besides its records.
+const BATCH_OVERHEAD: usize = 61;
+
+/// How a producer batches and sends records, with the defaults of the Java
+/// client.
+#[derive(Debug, Clone)]
+pub struct ProducerConfig {
+    pub client_id: String,
+    /// `linger.ms`: how long a batch waits for more records before it is
+    /// sent, unless it is full.
+    pub linger: Duration,
+    /// `batch.size`: the size a batch is sent at without waiting. A record
+    /// larger than that gets a batch of its own.
+    pub batch_size: usize,
+    /// `acks`: 0 not to wait for the broker, 1 or -1 to wait for the records
+    /// to be written.
+    pub acks: i16,
+    /// `enable.idempotence`: whether batches carry a producer id and
+    /// sequence numbers, so that the broker does not write a retried batch
+    /// twice. Needs acks -1.
+    pub idempotent: bool,
+    /// `retries`: how many times a batch is sent again after a retriable
+    /// error.
+    pub retries: u32,
+    /// `retry.backoff.ms`
+    pub retry_backoff: Duration,
+    /// `request.timeout.ms`, also the time the broker may take to write.
+    pub request_timeout: Duration,
+    /// `delivery.timeout.ms`: how long a record may wait to be written,
+    /// retries included, before it fails.
+    pub delivery_timeout: Duration,
+}
+
+impl Default for ProducerConfig {
+    fn default() -> Self {
+        Self {
+            client_id: "producer".to_string(),
+            linger: Duration::from_millis(5),
+            batch_size: 16384,
+            acks: -1,
+            idempotent: true,
+            retries: i32::MAX as u32,
+            retry_backoff: Duration::from_millis(100),
+            request_timeout: Duration::from_secs(30),
+            delivery_timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProducerRecord {
+    pub topic: String,
+    /// The partition to write to, or `None` to pick it from the key.
+    pub partition: Option<i32>,
+    pub key: Option<Bytes>,
+    pub value: Option<Bytes>,
+    pub headers: Vec<(String, Option<Bytes>)>,
+    /// Milliseconds since the epoch, or `None` for the time it is sent.
+    pub timestamp: Option<i64>,
+}
+
+impl ProducerRecord {
+    pub fn new(topic: &str, key: Option<Bytes>, value: Option<Bytes>) -> Self {
+        Self {
+            topic: topic.to_string(),
+            key,
+            value,
+            ..Self::default()
+        }
+    }
+}
+
+/// Where a record was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordMetadata {
+    pub topic: String,
+    pub partition: i32,
+    /// -1 with acks 0, as the broker does not tell.
+    pub offset: i64,
+    pub timestamp: i64,
+}
+
+type DeliverySender = oneshot::Sender<Result<RecordMetadata, Error>>;
+
+/// Resolves once a record is written, or failed to be.
+#[derive(Debug)]
+pub struct Delivery(oneshot::Receiver<Result<RecordMetadata, Error>>);
+
+impl Future for Delivery {
+    type Output = Result<RecordMetadata, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|result| result.unwrap_or_else(|_| Err(anyhow!("The producer was closed"))))
+    }
+}
+
+/// The murmur2 hash of the Java client, which partitions keyed records.
+pub fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747b28c;
+    const M: u32 = 0x5bd1e995;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, &byte) in rest.iter().enumerate().rev() {
+            h ^= (byte as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h as i32
+}
+
+/// The partition of a keyed record, as the Java client picks it.
+pub fn key_partition(key: &[u8], partitions: usize) -> i32 {
+    ((murmur2(key) & 0x7fffffff) as usize % partitions) as i32
+}
+
+type TopicPartition = (String, i32);
+
+/// Records gathered for one partition.
+struct Batch {
+    base_timestamp: i64,
+    records: Vec<TopicRecordDisk>,
+    deliveries: Vec<(DeliverySender, i64)>,
+    size: usize,
+    created: Instant,
+    /// The batch as first sent, so that a retry sends the same sequence
+    /// numbers.
+    encoded: Option<Bytes>,
+    attempts: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Batch {
+    fn new(base_timestamp: i64) -> Self {
+        Self {
+            base_timestamp,
+            records: Vec::new(),
+            deliveries: Vec::new(),
+            size: BATCH_OVERHEAD,
+            created: Instant::now(),
+            encoded: None,
+            attempts: 0,
+            retry_at: None,
+        }
+    }
+
+    /// Fails every record of the batch.
+    fn fail(self, error: &Error) {
+        for (delivery, _) in self.deliveries {
+            let _ = delivery.send(Err(anyhow!("{error:#}")));
+        }
+    }
+
+    fn complete(self, topic: &str, partition: i32, base_offset: i64) {
+        for (i, (delivery, timestamp)) in self.deliveries.into_iter().enumerate() {
+            let offset = if base_offset < 0 {
+                -1
+            } else {
+                base_offset + i as i64
+            };
+            let _ = delivery.send(Ok(RecordMetadata {
+                topic: topic.to_string(),
+                partition,
+                offset,
+                timestamp,
+            }));
+        }
+    }
+}
+
+/// The batches waiting to be sent, and the partitions that have one in
+/// flight. A partition has at most one batch in flight, so that retries
+/// keep the records in order.
+#[derive(Default)]
+struct Accumulator {
+    batches: BTreeMap<TopicPartition, VecDeque<Batch>>,
+    in_flight: HashSet<TopicPartition>,
+    /// The partition records without a key go to, until its batch is sent.
+    sticky: HashMap<String, i32>,
+    /// The next sequence number of each partition.
+    sequences: HashMap<TopicPartition, i32>,
+    /// The producer id and epoch of an idempotent producer.
+    producer: Option<(i64, i16)>,
+    /// Set when a batch failed after its sequence numbers were used, so
+    /// that the producer must start over with a new producer id.
+    reset_producer: bool,
+    /// How many callers wait for every batch to be sent.
+    flushing: usize,
+    closed: bool,
+}
+
+impl Accumulator {
+    fn is_empty(&self) -> bool {
+        self.in_flight.is_empty() && self.batches.values().all(VecDeque::is_empty)
+    }
+
+    fn append(
+        &mut self,
+        topic_partition: TopicPartition,
+        record: &ProducerRecord,
+        timestamp: i64,
+        batch_size: usize,
+        delivery: DeliverySender,
+    ) -> bool {
+        let batches = self.batches.entry(topic_partition).or_default();
+        let fits = |batch: &Batch, size: usize| {
+            batch.encoded.is_none() && (batch.records.is_empty() || batch.size + size <= batch_size)
+        };
+
+        let estimate = record_size(record);
+        if !batches.back().is_some_and(|batch| fits(batch, estimate)) {
+            batches.push_back(Batch::new(timestamp));
+        }
+        let batch = batches.back_mut().unwrap();
+
+        let headers = record
+            .headers
+            .iter()
+            .map(|(key, value)| TopicHeaders {
+                header_key: VarintString(key.clone()),
+                value: value.clone().into(),
+            })
+            .collect();
+        let disk = TopicRecordDisk::new(
+            batch.records.len() as i32,
+            timestamp - batch.base_timestamp,
+            record.key.clone(),
+            record.value.clone(),
+        )
+        .with_headers(headers);
+        batch.size += disk.size_in_bytes();
+        batch.records.push(disk);
+        batch.deliveries.push((delivery, timestamp));
+        batch.size >= batch_size
+    }
+
+    /// Takes the batches that are due, encoded.
+    fn drain(&mut self, config: &ProducerConfig, cluster: &Cluster, now: Instant) -> Drained {
+        let mut ready: HashMap<i32, Vec<(TopicPartition, Batch)>> = HashMap::new();
+        let mut unknown = HashSet::new();
+        let mut next = None::<Instant>;
+        let hurry = self.flushing > 0 || self.closed;
+
+        for (topic_partition, batches) in &mut self.batches {
+            if self.in_flight.contains(topic_partition) {
+                continue;
+            }
+            let Some(batch) = batches.front() else {
+                continue;
+            };
+
+            if batch.created + config.delivery_timeout <= now {
+                let error = anyhow!(
+                    "Expired {} records for {}-{} after {:?}",
+                    batch.deliveries.len(),
+                    topic_partition.0,
+                    topic_partition.1,
+                    config.delivery_timeout
+                );
+                let batch = batches.pop_front().unwrap();
+                self.reset_producer |= batch.encoded.is_some() && self.producer.is_some();
+                batch.fail(&error);
+                continue;
+            }
+
+            let due = match batch.retry_at {
+                Some(retry_at) => retry_at,
+                None if hurry || batches.len() > 1 || batch.size >= config.batch_size => now,
+                None => batch.created + config.linger,
+            };
+            if due > now {
+                next = Some(next.map_or(due, |next| next.min(due)));
+                continue;
+            }
+            let Some(leader) = cluster.leader(&topic_partition.0, topic_partition.1) else {
+                unknown.insert(topic_partition.0.clone());
+                continue;
+            };
+
+            let mut batch = batches.pop_front().unwrap();
+            if batch.encoded.is_none() {
+                let sequence = self.sequences.entry(topic_partition.clone()).or_insert(0);
+                batch.encoded = Some(encode(&mut batch, self.producer, *sequence));
+                *sequence = sequence.wrapping_add(batch.deliveries.len() as i32) & i32::MAX;
+            }
+            if self.sticky.get(&topic_partition.0) == Some(&topic_partition.1) {
+                self.sticky.remove(&topic_partition.0);
+            }
+            self.in_flight.insert(topic_partition.clone());
+            ready
+                .entry(leader)
+                .or_default()
+                .push((topic_partition.clone(), batch));
+        }
+
+        Drained {
+            ready,
+            unknown,
+            next,
+        }
+    }
+}
+
+struct Drained {
+    /// The batches due, by the node that leads their partition.
+    ready: HashMap<i32, Vec<(TopicPartition, Batch)>>,
+    /// The topics with batches due whose leaders are unknown.
+    unknown: HashSet<String>,
+    /// When the next batch will be due.
+    next: Option<Instant>,
+}
+
+/// Encodes the records of a batch with its CRC, and the producer id and
+/// first sequence number of an idempotent producer.
+fn encode(batch: &mut Batch, producer: Option<(i64, i16)>, sequence: i32) -> Bytes {
+    let records = std::mem::take(&mut batch.records);
+    let mut encoded = TopicRecordBatch::new(batch.base_timestamp, records);
+    if let Some((producer_id, producer_epoch)) = producer {
+        encoded.producer_id = producer_id;
+        encoded.producer_epoch = producer_epoch;
+        encoded.base_sequence = sequence;
+        encoded.update_crc();
+    }
+    encoded.to_bytes()
+}
+
+/// At least the size of a record in a batch, for deciding whether it fits:
+/// its attributes and varints at their widest, and its data.
+fn record_size(record: &ProducerRecord) -> usize {
+    const RECORD_OVERHEAD: usize = 1 + 5 + 10 + 5 + 5 + 5 + 5;
+    const HEADER_OVERHEAD: usize = 5 + 5;
+
+    let len = |bytes: &Option<Bytes>| bytes.as_ref().map_or(0, Bytes::len);
+    let headers: usize = record
+        .headers
+        .iter()
+        .map(|(key, value)| HEADER_OVERHEAD + key.len() + len(value))
+        .sum();
+    RECORD_OVERHEAD + len(&record.key) + len(&record.value) + headers
+}
+
+/// What became of a batch that was sent.
+enum Outcome {
+    Written(i64),
+    Retry(Error),
+    Failed(Error),
+}
+
+struct Inner {
+    config: ProducerConfig,
+    cluster: Cluster,
+    accumulator: Mutex<Accumulator>,
+    /// Wakes the sender when batches may be due.
+    wake: Notify,
+    /// Tells flushing callers that batches were sent.
+    sent: Notify,
+}
+
+/// Sends records to the leaders of their partitions in batches, from a
+/// background task.
+pub struct Producer {
+    inner: Arc<Inner>,
+    sender: JoinHandle<()>,
+}
+
+impl Producer {
+    /// Starts a producer for the cluster at `bootstrap_servers`, getting a
+    /// producer id first if it is idempotent.
+    pub async fn new(bootstrap_servers: &str, config: ProducerConfig) -> Result<Self, Error> {
+        if config.idempotent && config.acks != -1 {
+            bail!("An idempotent producer must use acks -1");
+        }
+        let inner = Arc::new(Inner {
+            cluster: Cluster::new(bootstrap_servers, &config.client_id),
+            config,
+            accumulator: Mutex::new(Accumulator::default()),
+            wake: Notify::new(),
+            sent: Notify::new(),
+        });
+        if inner.config.idempotent {
+            inner.init_producer_id().await?;
+        }
+
+        Ok(Self {
+            sender: tokio::spawn(inner.clone().run()),
+            inner,
+        })
+    }
+
+    pub fn cluster(&self) -> &Cluster {
+        &self.inner.cluster
+    }
+
+    /// Adds a record to the batch of its partition. The returned delivery
+    /// resolves once the batch is written.
+    pub async fn send(&self, record: ProducerRecord) -> Result<Delivery, Error> {
+        let cluster = &self.inner.cluster;
+        let topic = match cluster.topic(&record.topic) {
+            Some(topic) => topic,
+            None => {
+                cluster.refresh(std::slice::from_ref(&record.topic)).await?;
+                cluster
+                    .topic(&record.topic)
+                    .ok_or_else(|| anyhow!("Unknown topic {}", record.topic))?
+            }
+        };
+        let partitions = topic.partitions.len();
+        if partitions == 0 {
+            bail!("Topic {} has no partitions", record.topic);
+        }
+
+        let timestamp = record.timestamp.unwrap_or_else(now_ms);
+        let (sender, receiver) = oneshot::channel();
+        let mut accumulator = self.lock();
+        if accumulator.closed {
+            bail!("The producer is closed");
+        }
+        let partition = match (record.partition, &record.key) {
+            (Some(partition), _) if (0..partitions as i32).contains(&partition) => partition,
+            (Some(partition), _) => {
+                bail!("Topic {} has no partition {partition}", record.topic)
+            }
+            (None, Some(key)) => key_partition(key, partitions),
+            (None, None) => *accumulator
+                .sticky
+                .entry(record.topic.clone())
+                .or_insert_with(|| rand::thread_rng().gen_range(0..partitions as i32)),
+        };
+
+        let full = accumulator.append(
+            (record.topic.clone(), partition),
+            &record,
+            timestamp,
+            self.inner.config.batch_size,
+            sender,
+        );
+        if full && record.key.is_none() {
+            accumulator.sticky.remove(&record.topic);
+        }
+        drop(accumulator);
+
+        self.inner.wake.notify_one();
+        Ok(Delivery(receiver))
+    }
+
+    /// Sends every batch without waiting for linger, and waits until they
+    /// are written or failed.
+    pub async fn flush(&self) {
+        self.lock().flushing += 1;
+        self.inner.wake.notify_one();
+        loop {
+            let sent = self.inner.sent.notified();
+            if self.lock().is_empty() {
+                break;
+            }
+            sent.await;
+        }
+        self.lock().flushing -= 1;
+    }
+
+    /// Sends the records still batched and stops the producer.
+    pub async fn close(mut self) {
+        self.lock().closed = true;
+        self.inner.wake.notify_one();
+        let _ = (&mut self.sender).await;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Accumulator> {
+        self.inner.lock()
+    }
+}
+
+impl Drop for Producer {
+    fn drop(&mut self) {
+        self.sender.abort();
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Accumulator> {
+        self.accumulator.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Gets a new producer id, with which sequence numbers start over.
+    async fn init_producer_id(&self) -> Result<(), Error> {
+        let request = InitProducerIdRequest {
+            transactional_id: CompactNullableString(None),
+            transaction_timeout_ms: -1,
+            producer_id: -1,
+            producer_epoch: -1,
+            tagged_fields: 0,
+        };
+        let connection = self.cluster.any_connection().await?;
+        let response = connection.send(&request).await?;
+        if response.error_code != NONE {
+            bail!(
+                "InitProducerId failed with error code {}",
+                response.error_code
+            );
+        }
+
+        let mut accumulator = self.lock();
+        accumulator.producer = Some((response.producer_id, response.producer_epoch));
+        accumulator.sequences.clear();
+        accumulator.reset_producer = false;
+        Ok(())
+    }
+
+    /// Sends batches as they are due until the producer is closed and every
+    /// batch is sent.
+    async fn run(self: Arc<Self>) {
+        loop {
+            let reset = self.lock().reset_producer;
+            if reset {
+                if let Err(e) = self.init_producer_id().await {
+                    eprintln!("failed to reset the producer id: {e:#}");
+                    tokio::time::sleep(self.config.retry_backoff).await;
+                    continue;
+                }
+            }
+
+            let Drained {
+                ready,
+                unknown,
+                mut next,
+            } = {
+                let mut accumulator = self.lock();
+                if accumulator.closed && accumulator.is_empty() {
+                    return;
+                }
+                accumulator.drain(&self.config, &self.cluster, Instant::now())
+            };
+            self.sent.notify_waiters();
+
+            if !unknown.is_empty() {
+                let topics: Vec<String> = unknown.into_iter().collect();
+                let _ = self.cluster.refresh(&topics).await;
+                let retry_at = Instant::now() + self.config.retry_backoff;
+                next = Some(next.map_or(retry_at, |next| next.min(retry_at)));
+            }
+            for (node, batches) in ready {
+                tokio::spawn(self.clone().send_batches(node, batches));
+            }
+
+            let wake = self.wake.notified();
+            match next {
+                Some(next) => {
+                    tokio::select! {
+                        _ = wake => {}
+                        _ = tokio::time::sleep_until(next.into()) => {}
+                    }
+                }
+                None => wake.await,
+            }
+        }
+    }
+
+    /// Sends the batches due on one node in a single request, and settles
+    /// each by its partition's response.
+    async fn send_batches(self: Arc<Self>, node: i32, batches: Vec<(TopicPartition, Batch)>) {
+        let mut topics: BTreeMap<&str, Vec<PartitionProduceData>> = BTreeMap::new();
+        for ((topic, partition), batch) in &batches {
+            topics.entry(topic).or_default().push(PartitionProduceData {
+                index: *partition,
+                records: CompactNullableBytes(batch.encoded.clone()),
+                tagged_fields: 0,
+            });
+        }
+        let request = ProduceRequest {
+            transactional_id: CompactNullableString(None),
+            acks: self.config.acks,
+            timeout_ms: self.config.request_timeout.as_millis() as i32,
+            topic_data: CompactArray(
+                topics
+                    .into_iter()
+                    .map(|(name, partitions)| TopicProduceData {
+                        name: name.into(),
+                        partition_data: CompactArray(partitions),
+                        tagged_fields: 0,
+                    })
+                    .collect(),
+            ),
+            tagged_fields: 0,
+        };
+
+        let response = async {
+            let connection = self.cluster.connection(node).await?;
+            tokio::time::timeout(self.config.request_timeout, connection.produce(&request))
+                .await
+                .map_err(|_| anyhow!("Produce request to broker {node} timed out"))?
+        }
+        .await;
+
+        let mut stale = HashSet::new();
+        let outcomes: Vec<Outcome> = batches
+            .iter()
+            .map(|((topic, partition), _)| {
+                let outcome = outcome(&response, topic, *partition);
+                if matches!(outcome, Outcome::Retry(_)) {
+                    stale.insert(topic.clone());
+                }
+                outcome
+            })
+            .collect();
+        if !stale.is_empty() {
+            let topics: Vec<String> = stale.into_iter().collect();
+            let _ = self.cluster.refresh(&topics).await;
+        }
+
+        let mut accumulator = self.lock();
+        for (((topic, partition), mut batch), outcome) in batches.into_iter().zip(outcomes) {
+            let topic_partition = (topic, partition);
+            accumulator.in_flight.remove(&topic_partition);
+            let (topic, partition) = &topic_partition;
+            match outcome {
+                Outcome::Written(base_offset) => batch.complete(topic, *partition, base_offset),
+                Outcome::Retry(_)
+                    if batch.attempts < self.config.retries
+                        && batch.created.elapsed() < self.config.delivery_timeout =>
+                {
+                    batch.attempts += 1;
+                    batch.retry_at = Some(Instant::now() + self.config.retry_backoff);
+                    accumulator
+                        .batches
+                        .entry(topic_partition)
+                        .or_default()
+                        .push_front(batch);
+                }
+                Outcome::Retry(error) | Outcome::Failed(error) => {
+                    // Whether the broker wrote the batch is unknown, so its
+                    // sequence numbers cannot be reused.
+                    accumulator.reset_producer |= accumulator.producer.is_some();
+                    batch.fail(&error);
+                }
+            }
+        }
+        drop(accumulator);
+
+        self.wake.notify_one();
+        self.sent.notify_waiters();
+    }
+}
+
+/// What the response to a produce request says of one partition's batch.
+fn outcome(
+    response: &Result<Option<ProduceResponse>, Error>,
+    topic: &str,
+    partition: i32,
+) -> Outcome {
+    let response = match response {
+        Ok(None) => return Outcome::Written(-1),
+        Ok(Some(response)) => response,
+        Err(e) => return Outcome::Retry(anyhow!("{e:#}")),
+    };
+    let Some(partition_response) = response
+        .responses
+        .iter()
+        .filter(|response| response.name.as_str() == topic)
+        .flat_map(|response| response.partition_responses.iter())
+        .find(|response| response.index == partition)
+    else {
+        return Outcome::Failed(anyhow!("No response for {topic}-{partition}"));
+    };
+
+    let error = || {
+        anyhow!(
+            "Producing to {topic}-{partition} failed with error code {}{}",
+            partition_response.error_code,
+            partition_response
+                .error_message
+                .0
+                .as_ref()
+                .map_or(String::new(), |message| format!(": {message}"))
+        )
+    };
+    match partition_response.error_code {
+        // A duplicate was written by an earlier attempt.
+        NONE | DUPLICATE_SEQUENCE_NUMBER => Outcome::Written(partition_response.base_offset),
+        CORRUPT_MESSAGE
+        | UNKNOWN_TOPIC_OR_PARTITION
+        | LEADER_NOT_AVAILABLE
+        | NOT_LEADER_OR_FOLLOWER
+        | REQUEST_TIMED_OUT
+        | NOT_ENOUGH_REPLICAS
+        | NOT_ENOUGH_REPLICAS_AFTER_APPEND => Outcome::Retry(error()),
+        _ => Outcome::Failed(error()),
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::tests::TestBroker,
+        kafka::produce::{PartitionProduceResponse, TopicProduceResponse},
+    };
+
+    #[test]
+    fn test_murmur2_matches_java() {
+        assert_eq!(murmur2(b"21"), -973932308);
+        assert_eq!(murmur2(b"foobar"), -790332482);
+        assert_eq!(murmur2(b"a-little-bit-long-string"), -985981536);
+        assert_eq!(murmur2(b"a-little-bit-longer-string"), -1486304829);
+        assert_eq!(
+            murmur2(b"lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8"),
+            -58897971
+        );
+        assert_eq!(murmur2(b"abc"), 479470107);
+        assert_eq!(key_partition(b"foobar", 10), 6);
+    }
+
+    #[test]
+    fn test_outcome_by_error_code() {
+        let response = |error_code| {
+            Ok(Some(ProduceResponse {
+                responses: CompactArray(vec![TopicProduceResponse {
+                    name: "topic".into(),
+                    partition_responses: CompactArray(vec![PartitionProduceResponse {
+                        index: 0,
+                        error_code,
+                        base_offset: 7,
+                        log_append_time_ms: -1,
+                        log_start_offset: 0,
+                        record_errors: CompactArray(vec![]),
+                        error_message: CompactNullableString(None),
+                        tagged_fields: 0,
+                    }]),
+                    tagged_fields: 0,
+                }]),
+                throttle_time_ms: 0,
+                tagged_fields: 0,
+            }))
+        };
+
+        assert!(matches!(
+            outcome(&response(NONE), "topic", 0),
+            Outcome::Written(7)
+        ));
+        assert!(matches!(
+            outcome(&response(DUPLICATE_SEQUENCE_NUMBER), "topic", 0),
+            Outcome::Written(7)
+        ));
+        assert!(matches!(
+            outcome(&response(NOT_LEADER_OR_FOLLOWER), "topic", 0),
+            Outcome::Retry(_)
+        ));
+        assert!(matches!(
+            outcome(
+                &response(crate::kafka::errors::MESSAGE_TOO_LARGE),
+                "topic",
+                0
+            ),
+            Outcome::Failed(_)
+        ));
+        assert!(matches!(
+            outcome(&response(NONE), "topic", 1),
+            Outcome::Failed(_)
+        ));
+        assert!(matches!(
+            outcome(&Err(anyhow!("connection reset")), "topic", 0),
+            Outcome::Retry(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_records_are_batched_per_partition() {
+        let test = TestBroker::start("producer-test").await;
+        test.create_topic("topic", 3);
+
+        let config = ProducerConfig {
+            linger: Duration::from_secs(10),
+            ..ProducerConfig::default()
+        };
+        let producer = Producer::new(&test.addr.to_string(), config).await.unwrap();
+
+        let mut deliveries = Vec::new();
+        for i in 0..10 {
+            let key = Bytes::from(format!("key-{}", i % 3));
+            let value = Bytes::from(format!("value-{i}"));
+            let record = ProducerRecord::new("topic", Some(key), Some(value));
+            deliveries.push(producer.send(record).await.unwrap());
+        }
+        let unknown = ProducerRecord::new("missing", None, None);
+        assert!(producer.send(unknown).await.is_err());
+        // Flushing sends the batches before they linger for long.
+        producer.flush().await;
+
+        let mut next_offsets = HashMap::new();
+        for (i, delivery) in deliveries.into_iter().enumerate() {
+            let written = delivery.await.unwrap();
+            let key = format!("key-{}", i % 3);
+            assert_eq!(written.partition, key_partition(key.as_bytes(), 3));
+            let next = next_offsets.entry(written.partition).or_insert(0);
+            assert_eq!(written.offset, *next);
+            *next += 1;
+        }
+
+        for (&partition, &records) in &next_offsets {
+            let log = test.broker.logs.partition("topic", partition).unwrap();
+            let batches = log.lock().unwrap().read_batches().unwrap();
+            assert_eq!(batches.len(), 1);
+            assert!(batches[0].crc_matches());
+            assert!(batches[0].producer_id >= 0);
+            assert_eq!(batches[0].base_sequence, 0);
+            assert_eq!(batches[0].last_offset_delta as i64 + 1, records);
+        }
+
+        producer.close().await;
+    }
+}
@@ -0,0 +1,292 @@
+use std::{fs::File, io, sync::Arc};
+
+use bytes::{Bytes, BytesMut};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_rustls::server::TlsStream;
+
+use crate::{
+    types::{
+        bytes::{
+            ByteBuf, Bytes32, CompactBytes, CompactNullableBytes, NullableBytes32,
+            NullableVarintBytes,
+        },
+        cstring::{CompactNullableString, CompactString, VarintString},
+        kafkastring::{NullableString, String16},
+        record::GenericRecord,
+        uuid::UUID,
+        uvarint::UVarint,
+        varint::Varint,
+    },
+    Encode, EncodeFrame,
+};
+
+/// A byte range of an on-disk file that is sent to the client as-is.
+#[derive(Debug, Clone)]
+pub struct FileRegion {
+    pub file: Arc<File>,
+    pub position: u64,
+    pub len: u64,
+}
+
+impl FileRegion {
+    /// Reads the region into memory, for transports that cannot splice files.
+    pub fn read(&self) -> io::Result<Bytes> {
+        use std::os::unix::fs::FileExt;
+
+        let mut buf = vec![0; self.len as usize];
+        self.file.read_exact_at(&mut buf, self.position)?;
+        Ok(Bytes::from(buf))
+    }
+}
+
+/// Returned when a response could not be written whole. The client can no
+/// longer tell where the next response starts, so the connection is closed.
+#[derive(Debug, thiserror::Error)]
+#[error("failed to write a response: {0}")]
+pub struct WriteFailed(pub io::Error);
+
+/// A connection responses are written to.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {
+    /// The socket file regions can be sent to directly, if the connection
+    /// does not transform what is written to it.
+    fn as_tcp(&mut self) -> Option<&mut TcpStream> {
+        None
+    }
+}
+
+impl Transport for TcpStream {
+    fn as_tcp(&mut self) -> Option<&mut TcpStream> {
+        Some(self)
+    }
+}
+
+/// Records have to be encrypted, so file regions are read into memory.
+impl Transport for TlsStream<TcpStream> {}
+
+#[derive(Debug)]
+enum Chunk {
+    Bytes(Bytes),
+    File(FileRegion),
+}
+
+/// An encoded response made of in-memory chunks interleaved with file
+/// regions, so record data never has to be copied into user space.
+#[derive(Debug, Default)]
+pub struct Frame {
+    chunks: Vec<Chunk>,
+    buf: BytesMut,
+}
+
+impl Frame {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            chunks: Vec::new(),
+            buf: BytesMut::with_capacity(capacity),
+        }
+    }
+
+    pub fn put<T: Encode>(&mut self, value: &T) {
+        value.encode(&mut self.buf);
+    }
+
+    /// Appends `bytes` as its own chunk instead of copying it into the buffer.
+    pub fn put_bytes(&mut self, bytes: Bytes) {
+        self.flush_buf();
+        self.chunks.push(Chunk::Bytes(bytes));
+    }
+
+    pub fn put_region(&mut self, region: FileRegion) {
+        self.flush_buf();
+        self.chunks.push(Chunk::File(region));
+    }
+
+    fn flush_buf(&mut self) {
+        if !self.buf.is_empty() {
+            let bytes = self.buf.split().freeze();
+            self.chunks.push(Chunk::Bytes(bytes));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let chunks: usize = self
+            .chunks
+            .iter()
+            .map(|chunk| match chunk {
+                Chunk::Bytes(bytes) => bytes.len(),
+                Chunk::File(region) => region.len as usize,
+            })
+            .sum();
+
+        chunks + self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Collects the frame into memory, reading file regions from disk.
+    pub fn into_bytes(mut self) -> io::Result<Bytes> {
+        self.flush_buf();
+
+        let mut buf = BytesMut::with_capacity(self.len());
+        for chunk in &self.chunks {
+            match chunk {
+                Chunk::Bytes(bytes) => buf.extend_from_slice(bytes),
+                Chunk::File(region) => buf.extend_from_slice(&region.read()?),
+            }
+        }
+
+        Ok(buf.freeze())
+    }
+
+    pub async fn write_to<S: Transport>(mut self, socket: &mut S) -> io::Result<()> {
+        self.flush_buf();
+
+        for chunk in &self.chunks {
+            match chunk {
+                Chunk::Bytes(bytes) => socket.write_all(bytes).await?,
+                Chunk::File(region) => match socket.as_tcp() {
+                    Some(tcp) => send_region(tcp, region).await?,
+                    None => socket.write_all(&read_region(region).await?).await?,
+                },
+            }
+        }
+
+        socket.flush().await
+    }
+}
+
+macro_rules! impl_encode_frame {
+    ($($name:ty),*) => {
+        $(
+            impl EncodeFrame for $name {
+                fn encode_frame(&self, frame: &mut Frame) {
+                    frame.put(self);
+                }
+            }
+        )*
+    };
+}
+
+impl_encode_frame!(
+    i8,
+    u8,
+    i16,
+    i32,
+    i64,
+    u32,
+    UUID,
+    UVarint,
+    Varint,
+    String16,
+    NullableString,
+    CompactString,
+    CompactNullableString,
+    VarintString,
+    ByteBuf,
+    Bytes32,
+    NullableBytes32,
+    CompactBytes,
+    CompactNullableBytes,
+    NullableVarintBytes,
+    GenericRecord
+);
+
+#[cfg(target_os = "linux")]
+async fn send_region(socket: &mut TcpStream, region: &FileRegion) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    use tokio::io::Interest;
+
+    let mut offset = region.position as libc::off_t;
+    let end = offset + region.len as libc::off_t;
+
+    while offset < end {
+        socket.writable().await?;
+
+        let result = socket.try_io(Interest::WRITABLE, || {
+            let remaining = (end - offset) as usize;
+            // SAFETY: both descriptors stay open for the duration of the call
+            // and `offset` is a valid pointer to an `off_t`.
+            let sent = unsafe {
+                libc::sendfile(
+                    socket.as_raw_fd(),
+                    region.file.as_raw_fd(),
+                    &mut offset,
+                    remaining,
+                )
+            };
+
+            if sent < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(sent as usize)
+            }
+        });
+
+        match result {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a region on the blocking pool, for writing it from memory.
+async fn read_region(region: &FileRegion) -> io::Result<Bytes> {
+    let region = region.clone();
+    tokio::task::spawn_blocking(move || region.read()).await?
+}
+
+/// Other unix targets have no compatible `sendfile`.
+#[cfg(not(target_os = "linux"))]
+async fn send_region(socket: &mut TcpStream, region: &FileRegion) -> io::Result<()> {
+    socket.write_all(&read_region(region).await?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_truncated_region_fails_the_write() {
+        let path = std::env::temp_dir().join(format!("frame-{}.log", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&[9u8; 4096]).unwrap();
+
+        let mut frame = Frame::default();
+        frame.put(&4096i32);
+        frame.put_region(FileRegion {
+            file: Arc::new(File::open(&path).unwrap()),
+            position: 0,
+            len: 4096,
+        });
+        // The segment is cut short after the response was built.
+        file.set_len(100).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+
+        let error = frame.write_to(&mut server).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        drop(server);
+
+        // What was sent is less than the size prefix promised.
+        let mut received = vec![];
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received.len(), 4 + 100);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{
+    kafka::{
+        errors::{CLUSTER_AUTHORIZATION_FAILED, INVALID_REQUEST, NONE, SECURITY_DISABLED},
+        log::{acl_record::AccessControlEntryRecord, RecordValue},
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+        uuid::UUID,
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+use uuid::Uuid;
+
+use super::{invalid_binding, operation, resource, same_binding, CLUSTER_NAME};
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AclCreation {
+    pub resource_type: i8,
+    pub resource_name: CompactString,
+    pub resource_pattern_type: i8,
+    pub principal: CompactString,
+    pub host: CompactString,
+    pub operation: i8,
+    pub permission_type: i8,
+    pub tagged_fields: u8,
+}
+
+/// CreateAcls v2-3, which only differ in allowing USER resources.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct CreateAclsRequest {
+    pub creations: CompactArray<AclCreation>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AclCreationResult {
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct CreateAclsResponse {
+    pub throttle_time_ms: i32,
+    pub results: CompactArray<AclCreationResult>,
+    pub tagged_fields: u8,
+}
+
+impl AclCreationResult {
+    fn new(error_code: i16, error_message: Option<&str>) -> Self {
+        Self {
+            error_code,
+            error_message: CompactNullableString(error_message.map(str::to_string)),
+            tagged_fields: 0,
+        }
+    }
+}
+
+impl AclCreation {
+    fn record(&self) -> AccessControlEntryRecord {
+        AccessControlEntryRecord {
+            id: UUID(*Uuid::new_v4().as_bytes()),
+            resource_type: self.resource_type,
+            resource_name: self.resource_name.clone(),
+            pattern_type: self.resource_pattern_type,
+            principal: self.principal.clone(),
+            host: self.host.clone(),
+            operation: self.operation,
+            permission_type: self.permission_type,
+            tagged_fields: 0,
+        }
+    }
+}
+
+impl CreateAclsRequest {
+    /// Creates the valid bindings of the request. A binding that already
+    /// exists is left as is.
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<CreateAclsResponse, Error> {
+        let failed = |error_code, error_message| {
+            let results = self
+                .creations
+                .iter()
+                .map(|_| AclCreationResult::new(error_code, Some(error_message)))
+                .collect();
+            Ok(CreateAclsResponse {
+                throttle_time_ms: 0,
+                results: CompactArray(results),
+                tagged_fields: 0,
+            })
+        };
+        if !ctx.broker.config.authorizer.enabled {
+            return failed(SECURITY_DISABLED, "No Authorizer is configured");
+        }
+        if !ctx.authorize(operation::ALTER, resource::CLUSTER, CLUSTER_NAME) {
+            return failed(CLUSTER_AUTHORIZATION_FAILED, "Cluster authorization failed");
+        }
+
+        let bindings: Vec<AccessControlEntryRecord> =
+            self.creations.iter().map(AclCreation::record).collect();
+        let broker = ctx.broker.clone();
+        let results = tokio::task::spawn_blocking(move || {
+            broker.metadata.update(|image| {
+                let mut created: Vec<AccessControlEntryRecord> = Vec::new();
+                let results = bindings
+                    .into_iter()
+                    .map(|binding| {
+                        if let Some(message) = invalid_binding(&binding) {
+                            return AclCreationResult::new(INVALID_REQUEST, Some(message));
+                        }
+                        let exists = image
+                            .acls()
+                            .chain(&created)
+                            .any(|acl| same_binding(acl, &binding));
+                        if !exists {
+                            created.push(binding);
+                        }
+                        AclCreationResult::new(NONE, None)
+                    })
+                    .collect();
+
+                let records = created
+                    .into_iter()
+                    .map(RecordValue::AccessControlEntry)
+                    .collect();
+                (records, results)
+            })
+        })
+        .await??;
+
+        Ok(CreateAclsResponse {
+            throttle_time_ms: 0,
+            results: CompactArray(results),
+            tagged_fields: 0,
+        })
+    }
+}
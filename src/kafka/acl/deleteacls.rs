@@ -0,0 +1,183 @@
+use crate::{
+    kafka::{
+        errors::{CLUSTER_AUTHORIZATION_FAILED, INVALID_REQUEST, NONE, SECURITY_DISABLED},
+        log::{
+            acl_record::{AccessControlEntryRecord, RemoveAccessControlEntryRecord},
+            RecordValue,
+        },
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{operation, resource, AclFilter, CLUSTER_NAME};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct DeleteAclsFilter {
+    pub resource_type_filter: i8,
+    pub resource_name_filter: CompactNullableString,
+    pub pattern_type_filter: i8,
+    pub principal_filter: CompactNullableString,
+    pub host_filter: CompactNullableString,
+    pub operation: i8,
+    pub permission_type: i8,
+    pub tagged_fields: u8,
+}
+
+/// DeleteAcls v2-3, which only differ in allowing USER resources.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteAclsRequest {
+    pub filters: CompactArray<DeleteAclsFilter>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteAclsMatchingAcl {
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub resource_type: i8,
+    pub resource_name: CompactString,
+    pub pattern_type: i8,
+    pub principal: CompactString,
+    pub host: CompactString,
+    pub operation: i8,
+    pub permission_type: i8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteAclsFilterResult {
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub matching_acls: CompactArray<DeleteAclsMatchingAcl>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteAclsResponse {
+    pub throttle_time_ms: i32,
+    pub filter_results: CompactArray<DeleteAclsFilterResult>,
+    pub tagged_fields: u8,
+}
+
+impl DeleteAclsFilterResult {
+    fn new(
+        error_code: i16,
+        error_message: Option<&str>,
+        deleted: Vec<DeleteAclsMatchingAcl>,
+    ) -> Self {
+        Self {
+            error_code,
+            error_message: CompactNullableString(error_message.map(str::to_string)),
+            matching_acls: CompactArray(deleted),
+            tagged_fields: 0,
+        }
+    }
+}
+
+impl From<&DeleteAclsFilter> for AclFilter {
+    fn from(filter: &DeleteAclsFilter) -> Self {
+        Self {
+            resource_type: filter.resource_type_filter,
+            resource_name: filter.resource_name_filter.0.clone(),
+            pattern_type: filter.pattern_type_filter,
+            principal: filter.principal_filter.0.clone(),
+            host: filter.host_filter.0.clone(),
+            operation: filter.operation,
+            permission_type: filter.permission_type,
+        }
+    }
+}
+
+impl From<AccessControlEntryRecord> for DeleteAclsMatchingAcl {
+    fn from(acl: AccessControlEntryRecord) -> Self {
+        Self {
+            error_code: NONE,
+            error_message: CompactNullableString(None),
+            resource_type: acl.resource_type,
+            resource_name: acl.resource_name,
+            pattern_type: acl.pattern_type,
+            principal: acl.principal,
+            host: acl.host,
+            operation: acl.operation,
+            permission_type: acl.permission_type,
+            tagged_fields: 0,
+        }
+    }
+}
+
+impl DeleteAclsRequest {
+    /// Deletes the bindings each filter matches. A binding matched by
+    /// several filters is listed under the first.
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<DeleteAclsResponse, Error> {
+        let failed = |error_code, error_message| {
+            let results = self
+                .filters
+                .iter()
+                .map(|_| DeleteAclsFilterResult::new(error_code, Some(error_message), vec![]))
+                .collect();
+            Ok(DeleteAclsResponse {
+                throttle_time_ms: 0,
+                filter_results: CompactArray(results),
+                tagged_fields: 0,
+            })
+        };
+        if !ctx.broker.config.authorizer.enabled {
+            return failed(SECURITY_DISABLED, "No Authorizer is configured");
+        }
+        if !ctx.authorize(operation::ALTER, resource::CLUSTER, CLUSTER_NAME) {
+            return failed(CLUSTER_AUTHORIZATION_FAILED, "Cluster authorization failed");
+        }
+
+        let filters: Vec<AclFilter> = self.filters.iter().map(AclFilter::from).collect();
+        let broker = ctx.broker.clone();
+        let results = tokio::task::spawn_blocking(move || {
+            broker.metadata.update(|image| {
+                let mut records = Vec::new();
+                let mut deleted = Vec::new();
+                let results = filters
+                    .iter()
+                    .map(|filter| {
+                        if let Some(message) = filter.invalid() {
+                            return DeleteAclsFilterResult::new(
+                                INVALID_REQUEST,
+                                Some(message),
+                                vec![],
+                            );
+                        }
+                        let matching: Vec<&AccessControlEntryRecord> = image
+                            .acls()
+                            .filter(|acl| filter.matches(acl) && !deleted.contains(&acl.id))
+                            .collect();
+                        for acl in &matching {
+                            deleted.push(acl.id.clone());
+                            records.push(RecordValue::RemoveAccessControlEntry(
+                                RemoveAccessControlEntryRecord {
+                                    id: acl.id.clone(),
+                                    tagged_fields: 0,
+                                },
+                            ));
+                        }
+                        let matching = matching.into_iter().cloned().map(Into::into).collect();
+                        DeleteAclsFilterResult::new(NONE, None, matching)
+                    })
+                    .collect();
+
+                (records, results)
+            })
+        })
+        .await??;
+
+        Ok(DeleteAclsResponse {
+            throttle_time_ms: 0,
+            filter_results: CompactArray(results),
+            tagged_fields: 0,
+        })
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{
+    kafka::{
+        errors::{CLUSTER_AUTHORIZATION_FAILED, INVALID_REQUEST, NONE, SECURITY_DISABLED},
+        log::acl_record::AccessControlEntryRecord,
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{operation, resource, AclFilter, CLUSTER_NAME};
+
+/// DescribeAcls v2-3, which only differ in allowing USER resources.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeAclsRequest {
+    pub resource_type_filter: i8,
+    pub resource_name_filter: CompactNullableString,
+    pub pattern_type_filter: i8,
+    pub principal_filter: CompactNullableString,
+    pub host_filter: CompactNullableString,
+    pub operation: i8,
+    pub permission_type: i8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AclDescription {
+    pub principal: CompactString,
+    pub host: CompactString,
+    pub operation: i8,
+    pub permission_type: i8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeAclsResource {
+    pub resource_type: i8,
+    pub resource_name: CompactString,
+    pub pattern_type: i8,
+    pub acls: CompactArray<AclDescription>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeAclsResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub resources: CompactArray<DescribeAclsResource>,
+    pub tagged_fields: u8,
+}
+
+impl DescribeAclsResponse {
+    fn error(error_code: i16, error_message: &str) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code,
+            error_message: CompactNullableString(Some(error_message.to_string())),
+            resources: CompactArray(vec![]),
+            tagged_fields: 0,
+        }
+    }
+}
+
+/// Groups bindings by their resource pattern, in the order first seen.
+fn by_resource(acls: Vec<AccessControlEntryRecord>) -> Vec<DescribeAclsResource> {
+    let mut resources: Vec<DescribeAclsResource> = Vec::new();
+
+    for acl in acls {
+        let description = AclDescription {
+            principal: acl.principal,
+            host: acl.host,
+            operation: acl.operation,
+            permission_type: acl.permission_type,
+            tagged_fields: 0,
+        };
+        let existing = resources.iter_mut().find(|resource| {
+            resource.resource_type == acl.resource_type
+                && resource.resource_name == acl.resource_name
+                && resource.pattern_type == acl.pattern_type
+        });
+        match existing {
+            Some(resource) => resource.acls.0.push(description),
+            None => resources.push(DescribeAclsResource {
+                resource_type: acl.resource_type,
+                resource_name: acl.resource_name,
+                pattern_type: acl.pattern_type,
+                acls: CompactArray(vec![description]),
+                tagged_fields: 0,
+            }),
+        }
+    }
+
+    resources
+}
+
+impl DescribeAclsRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<DescribeAclsResponse, Error> {
+        if !ctx.broker.config.authorizer.enabled {
+            return Ok(DescribeAclsResponse::error(
+                SECURITY_DISABLED,
+                "No Authorizer is configured",
+            ));
+        }
+
+        let filter = AclFilter {
+            resource_type: self.resource_type_filter,
+            resource_name: self.resource_name_filter.0.clone(),
+            pattern_type: self.pattern_type_filter,
+            principal: self.principal_filter.0.clone(),
+            host: self.host_filter.0.clone(),
+            operation: self.operation,
+            permission_type: self.permission_type,
+        };
+        if let Some(message) = filter.invalid() {
+            return Ok(DescribeAclsResponse::error(INVALID_REQUEST, message));
+        }
+
+        let acls = ctx.broker.metadata.read(|image| {
+            ctx.authorized(image, operation::DESCRIBE, resource::CLUSTER, CLUSTER_NAME)
+                .then(|| {
+                    image
+                        .acls()
+                        .filter(|acl| filter.matches(acl))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+        });
+        let Some(acls) = acls else {
+            return Ok(DescribeAclsResponse::error(
+                CLUSTER_AUTHORIZATION_FAILED,
+                "Cluster authorization failed",
+            ));
+        };
+
+        Ok(DescribeAclsResponse {
+            throttle_time_ms: 0,
+            error_code: NONE,
+            error_message: CompactNullableString(None),
+            resources: CompactArray(by_resource(acls)),
+            tagged_fields: 0,
+        })
+    }
+}
@@ -0,0 +1,547 @@
+//! ACL authorization. Bindings in the metadata log allow or deny a
+//! principal connecting from a host an operation on the resources a pattern
+//! matches; each API checks the operations it performs against them, as
+//! Kafka's `StandardAuthorizer` does.
+
+use super::log::acl_record::AccessControlEntryRecord;
+
+pub mod createacls;
+pub mod deleteacls;
+pub mod describeacls;
+
+/// Resource types, numbered as in the protocol.
+pub mod resource {
+    pub const ANY: i8 = 1;
+    pub const TOPIC: i8 = 2;
+    pub const GROUP: i8 = 3;
+    pub const CLUSTER: i8 = 4;
+    pub const TRANSACTIONAL_ID: i8 = 5;
+    pub const DELEGATION_TOKEN: i8 = 6;
+    pub const USER: i8 = 7;
+}
+
+/// Operations, numbered as in the protocol and in authorized operations
+/// bit fields.
+pub mod operation {
+    pub const ANY: i8 = 1;
+    pub const ALL: i8 = 2;
+    pub const READ: i8 = 3;
+    pub const WRITE: i8 = 4;
+    pub const CREATE: i8 = 5;
+    pub const DELETE: i8 = 6;
+    pub const ALTER: i8 = 7;
+    pub const DESCRIBE: i8 = 8;
+    pub const CLUSTER_ACTION: i8 = 9;
+    pub const DESCRIBE_CONFIGS: i8 = 10;
+    pub const ALTER_CONFIGS: i8 = 11;
+    pub const IDEMPOTENT_WRITE: i8 = 12;
+}
+
+/// Whether a binding allows or denies, numbered as in the protocol.
+pub mod permission {
+    pub const ANY: i8 = 1;
+    pub const DENY: i8 = 2;
+    pub const ALLOW: i8 = 3;
+}
+
+/// How a binding's resource name matches resources, numbered as in the
+/// protocol. ANY and MATCH only appear in filters.
+pub mod pattern {
+    pub const ANY: i8 = 1;
+    pub const MATCH: i8 = 2;
+    pub const LITERAL: i8 = 3;
+    pub const PREFIXED: i8 = 4;
+}
+
+/// Reported as authorized operations when the client did not ask for them.
+pub const OPERATIONS_NOT_REQUESTED: i32 = i32::MIN;
+
+/// The name of the only cluster resource.
+pub const CLUSTER_NAME: &str = "kafka-cluster";
+
+/// A literal resource name or host matching every one.
+pub const WILDCARD: &str = "*";
+
+/// The principal of bindings applying to every user.
+const WILDCARD_PRINCIPAL: &str = "User:*";
+
+/// The operations that apply to a resource type, which its authorized
+/// operations are made of.
+fn operations(resource_type: i8) -> &'static [i8] {
+    use operation::*;
+
+    match resource_type {
+        resource::TOPIC => &[
+            READ,
+            WRITE,
+            CREATE,
+            DELETE,
+            ALTER,
+            DESCRIBE,
+            DESCRIBE_CONFIGS,
+            ALTER_CONFIGS,
+        ],
+        resource::GROUP => &[READ, DELETE, DESCRIBE],
+        resource::CLUSTER => &[
+            CREATE,
+            ALTER,
+            DESCRIBE,
+            CLUSTER_ACTION,
+            DESCRIBE_CONFIGS,
+            ALTER_CONFIGS,
+            IDEMPOTENT_WRITE,
+        ],
+        resource::TRANSACTIONAL_ID => &[WRITE, DESCRIBE],
+        resource::DELEGATION_TOKEN => &[DESCRIBE],
+        resource::USER => &[CREATE, DESCRIBE],
+        _ => &[],
+    }
+}
+
+/// The bit field of `operations`, as in authorized operations fields.
+pub fn operation_bits(operations: impl IntoIterator<Item = i8>) -> i32 {
+    operations.into_iter().fold(0, |bits, op| bits | 1 << op)
+}
+
+/// Whether a binding allowing `granted` allows `requested`: ALL allows
+/// anything, and the operations that change or read a resource allow
+/// describing it.
+fn implies(granted: i8, requested: i8) -> bool {
+    use operation::*;
+
+    granted == requested
+        || granted == ALL
+        || match requested {
+            DESCRIBE => matches!(granted, READ | WRITE | DELETE | ALTER),
+            DESCRIBE_CONFIGS => granted == ALTER_CONFIGS,
+            _ => false,
+        }
+}
+
+/// Whether the pattern of a binding matches the resource `name`.
+fn pattern_matches(acl: &AccessControlEntryRecord, name: &str) -> bool {
+    match acl.pattern_type {
+        pattern::LITERAL => *acl.resource_name == name || *acl.resource_name == WILDCARD,
+        pattern::PREFIXED => name.starts_with(acl.resource_name.as_str()),
+        _ => false,
+    }
+}
+
+/// Who a request is authorized for: the principal of its connection and
+/// the address it comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requester {
+    pub principal: String,
+    pub host: String,
+}
+
+impl Requester {
+    fn is_bound_by(&self, acl: &AccessControlEntryRecord) -> bool {
+        (*acl.principal == self.principal || *acl.principal == WILDCARD_PRINCIPAL)
+            && (*acl.host == self.host || *acl.host == WILDCARD)
+    }
+}
+
+/// Decides requests against ACL bindings, as configured by
+/// `authorizer.class.name`, `super.users` and
+/// `allow.everyone.if.no.acl.found`.
+#[derive(Debug, Clone, Default)]
+pub struct Authorizer {
+    /// Without an authorizer, every request is allowed and the ACL APIs
+    /// fail with SECURITY_DISABLED.
+    pub enabled: bool,
+    /// Principals allowed everything.
+    pub super_users: Vec<String>,
+    /// Whether operations on resources no binding matches are allowed.
+    pub allow_everyone_if_no_acl_found: bool,
+}
+
+impl Authorizer {
+    /// Whether `requester` may perform `operation` on a resource. A binding
+    /// denying it wins over any allowing it.
+    pub fn authorize<'a>(
+        &self,
+        acls: impl IntoIterator<Item = &'a AccessControlEntryRecord>,
+        requester: &Requester,
+        operation: i8,
+        resource_type: i8,
+        name: &str,
+    ) -> bool {
+        if self.allows_all(requester) {
+            return true;
+        }
+        let matching = matching(acls, resource_type, name);
+        self.decide(&matching, requester, operation)
+    }
+
+    /// The bit field of the operations `requester` may perform on a
+    /// resource.
+    pub fn authorized_operations<'a>(
+        &self,
+        acls: impl IntoIterator<Item = &'a AccessControlEntryRecord>,
+        requester: &Requester,
+        resource_type: i8,
+        name: &str,
+    ) -> i32 {
+        let operations = operations(resource_type).iter().copied();
+        if self.allows_all(requester) {
+            return operation_bits(operations);
+        }
+        let matching = matching(acls, resource_type, name);
+        operation_bits(operations.filter(|&op| self.decide(&matching, requester, op)))
+    }
+
+    /// Whether `requester` may perform `operation` on some resource of a
+    /// type: a binding allows it and no binding denies it on every such
+    /// resource or on the same pattern.
+    pub fn authorize_any<'a>(
+        &self,
+        acls: impl IntoIterator<Item = &'a AccessControlEntryRecord>,
+        requester: &Requester,
+        operation: i8,
+        resource_type: i8,
+    ) -> bool {
+        if self.allows_all(requester) {
+            return true;
+        }
+        let bindings: Vec<&AccessControlEntryRecord> = acls
+            .into_iter()
+            .filter(|acl| acl.resource_type == resource_type)
+            .collect();
+        if bindings.is_empty() {
+            return self.allow_everyone_if_no_acl_found;
+        }
+
+        let applying: Vec<&AccessControlEntryRecord> = bindings
+            .into_iter()
+            .filter(|acl| requester.is_bound_by(acl))
+            .collect();
+        let denied = |allowed: &AccessControlEntryRecord| {
+            applying.iter().any(|acl| {
+                acl.permission_type == permission::DENY
+                    && (acl.operation == operation || acl.operation == operation::ALL)
+                    && ((acl.pattern_type == pattern::LITERAL && *acl.resource_name == WILDCARD)
+                        || (acl.pattern_type == allowed.pattern_type
+                            && acl.resource_name == allowed.resource_name))
+            })
+        };
+        applying.iter().any(|acl| {
+            acl.permission_type == permission::ALLOW
+                && implies(acl.operation, operation)
+                && !denied(acl)
+        })
+    }
+
+    fn allows_all(&self, requester: &Requester) -> bool {
+        !self.enabled || self.super_users.contains(&requester.principal)
+    }
+
+    /// Decides an operation from the bindings matching its resource.
+    fn decide(
+        &self,
+        matching: &[&AccessControlEntryRecord],
+        requester: &Requester,
+        operation: i8,
+    ) -> bool {
+        if matching.is_empty() {
+            return self.allow_everyone_if_no_acl_found;
+        }
+
+        let applying = || matching.iter().filter(|acl| requester.is_bound_by(acl));
+        let denied = applying().any(|acl| {
+            acl.permission_type == permission::DENY
+                && (acl.operation == operation || acl.operation == operation::ALL)
+        });
+        !denied
+            && applying().any(|acl| {
+                acl.permission_type == permission::ALLOW && implies(acl.operation, operation)
+            })
+    }
+}
+
+/// The bindings whose pattern matches a resource, whoever they apply to.
+fn matching<'a>(
+    acls: impl IntoIterator<Item = &'a AccessControlEntryRecord>,
+    resource_type: i8,
+    name: &str,
+) -> Vec<&'a AccessControlEntryRecord> {
+    acls.into_iter()
+        .filter(|acl| acl.resource_type == resource_type && pattern_matches(acl, name))
+        .collect()
+}
+
+/// Selects bindings for DescribeAcls and DeleteAcls. Unset names, principals
+/// and hosts, and ANY types, patterns, operations and permissions match
+/// every binding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclFilter {
+    pub resource_type: i8,
+    pub resource_name: Option<String>,
+    pub pattern_type: i8,
+    pub principal: Option<String>,
+    pub host: Option<String>,
+    pub operation: i8,
+    pub permission_type: i8,
+}
+
+impl AclFilter {
+    /// Why the filter cannot match anything, if it has an unknown value.
+    pub fn invalid(&self) -> Option<&'static str> {
+        if !(resource::ANY..=resource::USER).contains(&self.resource_type) {
+            Some("Unknown resource type")
+        } else if !(pattern::ANY..=pattern::PREFIXED).contains(&self.pattern_type) {
+            Some("Unknown pattern type")
+        } else if !(operation::ANY..=operation::IDEMPOTENT_WRITE).contains(&self.operation) {
+            Some("Unknown operation")
+        } else if !(permission::ANY..=permission::ALLOW).contains(&self.permission_type) {
+            Some("Unknown permission type")
+        } else {
+            None
+        }
+    }
+
+    pub fn matches(&self, acl: &AccessControlEntryRecord) -> bool {
+        let name = self.resource_name.as_deref();
+        let pattern_matches = match self.pattern_type {
+            pattern::ANY => name.map_or(true, |name| *acl.resource_name == name),
+            // The bindings that apply to the named resource.
+            pattern::MATCH => name.map_or(true, |name| pattern_matches(acl, name)),
+            pattern_type => {
+                acl.pattern_type == pattern_type
+                    && name.map_or(true, |name| *acl.resource_name == name)
+            }
+        };
+
+        (self.resource_type == resource::ANY || acl.resource_type == self.resource_type)
+            && pattern_matches
+            && self
+                .principal
+                .as_ref()
+                .map_or(true, |principal| *principal == *acl.principal)
+            && self.host.as_ref().map_or(true, |host| *host == *acl.host)
+            && (self.operation == operation::ANY || acl.operation == self.operation)
+            && (self.permission_type == permission::ANY
+                || acl.permission_type == self.permission_type)
+    }
+}
+
+/// Why a binding cannot be created, if it is not fully specified.
+pub fn invalid_binding(acl: &AccessControlEntryRecord) -> Option<&'static str> {
+    let principal_valid = acl
+        .principal
+        .split_once(':')
+        .is_some_and(|(kind, name)| !kind.is_empty() && !name.is_empty());
+
+    if !(resource::TOPIC..=resource::USER).contains(&acl.resource_type) {
+        Some("Invalid resource type")
+    } else if !matches!(acl.pattern_type, pattern::LITERAL | pattern::PREFIXED) {
+        Some("Invalid pattern type")
+    } else if !(operation::ALL..=operation::IDEMPOTENT_WRITE).contains(&acl.operation) {
+        Some("Invalid operation")
+    } else if !matches!(acl.permission_type, permission::DENY | permission::ALLOW) {
+        Some("Invalid permission type")
+    } else if acl.resource_name.is_empty() {
+        Some("Resource name must not be empty")
+    } else if acl.resource_type == resource::CLUSTER && *acl.resource_name != CLUSTER_NAME {
+        Some("The only valid name for the CLUSTER resource is kafka-cluster")
+    } else if !principal_valid {
+        Some("Principals must be of the form type:name")
+    } else if acl.host.is_empty() {
+        Some("Host must not be empty")
+    } else {
+        None
+    }
+}
+
+/// Whether two bindings allow or deny the same thing.
+pub fn same_binding(a: &AccessControlEntryRecord, b: &AccessControlEntryRecord) -> bool {
+    let key = |acl: &AccessControlEntryRecord| {
+        (
+            acl.resource_type,
+            acl.resource_name.clone(),
+            acl.pattern_type,
+            acl.principal.clone(),
+            acl.host.clone(),
+            acl.operation,
+            acl.permission_type,
+        )
+    };
+    key(a) == key(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{cstring::CompactString, uuid::UUID};
+
+    fn acl(
+        resource_type: i8,
+        name: &str,
+        pattern_type: i8,
+        principal: &str,
+        operation: i8,
+        permission_type: i8,
+    ) -> AccessControlEntryRecord {
+        AccessControlEntryRecord {
+            id: UUID([0; 16]),
+            resource_type,
+            resource_name: CompactString(name.to_string()),
+            pattern_type,
+            principal: CompactString(principal.to_string()),
+            host: CompactString(WILDCARD.to_string()),
+            operation,
+            permission_type,
+            tagged_fields: 0,
+        }
+    }
+
+    fn requester(user: &str) -> Requester {
+        Requester {
+            principal: format!("User:{user}"),
+            host: "10.0.0.1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_authorize() {
+        use operation::*;
+
+        let authorizer = Authorizer {
+            enabled: true,
+            super_users: vec!["User:admin".to_string()],
+            allow_everyone_if_no_acl_found: false,
+        };
+        let acls = [
+            acl(
+                resource::TOPIC,
+                "orders-",
+                pattern::PREFIXED,
+                "User:alice",
+                READ,
+                permission::ALLOW,
+            ),
+            acl(
+                resource::TOPIC,
+                "orders-eu",
+                pattern::LITERAL,
+                "User:alice",
+                ALL,
+                permission::DENY,
+            ),
+            acl(
+                resource::TOPIC,
+                WILDCARD,
+                pattern::LITERAL,
+                "User:*",
+                WRITE,
+                permission::ALLOW,
+            ),
+        ];
+        let alice = requester("alice");
+        let bob = requester("bob");
+
+        assert!(authorizer.authorize(&acls, &alice, READ, resource::TOPIC, "orders-us"));
+        // Reading implies describing.
+        assert!(authorizer.authorize(&acls, &alice, DESCRIBE, resource::TOPIC, "orders-us"));
+        assert!(!authorizer.authorize(&acls, &alice, READ, resource::TOPIC, "orders-eu"));
+        assert!(!authorizer.authorize(&acls, &bob, READ, resource::TOPIC, "orders-us"));
+        assert!(authorizer.authorize(&acls, &bob, WRITE, resource::TOPIC, "orders-us"));
+        // No binding matches groups.
+        assert!(!authorizer.authorize(&acls, &alice, READ, resource::GROUP, "group"));
+        assert!(authorizer.authorize(&acls, &requester("admin"), DELETE, resource::TOPIC, "x"));
+
+        assert_eq!(
+            authorizer.authorized_operations(&acls, &alice, resource::TOPIC, "orders-us"),
+            operation_bits([READ, WRITE, DESCRIBE])
+        );
+        assert_eq!(
+            authorizer.authorized_operations(&acls, &alice, resource::TOPIC, "orders-eu"),
+            0
+        );
+
+        assert!(authorizer.authorize_any(&acls, &alice, READ, resource::TOPIC));
+        assert!(!authorizer.authorize_any(&acls, &bob, READ, resource::TOPIC));
+
+        let permissive = Authorizer {
+            allow_everyone_if_no_acl_found: true,
+            ..authorizer
+        };
+        assert!(permissive.authorize(&acls, &bob, READ, resource::GROUP, "group"));
+        assert!(!permissive.authorize(&acls, &bob, READ, resource::TOPIC, "orders-us"));
+
+        // Without an authorizer, topics have the operations clients expect.
+        let disabled = Authorizer::default();
+        assert_eq!(
+            disabled.authorized_operations(&acls, &bob, resource::TOPIC, "t"),
+            0x00000df8
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let prefixed = acl(
+            resource::TOPIC,
+            "orders-",
+            pattern::PREFIXED,
+            "User:alice",
+            operation::READ,
+            permission::ALLOW,
+        );
+        let filter = AclFilter {
+            resource_type: resource::ANY,
+            resource_name: None,
+            pattern_type: pattern::ANY,
+            principal: None,
+            host: None,
+            operation: operation::ANY,
+            permission_type: permission::ANY,
+        };
+        assert!(filter.matches(&prefixed));
+        assert_eq!(filter.invalid(), None);
+
+        let matching = AclFilter {
+            resource_type: resource::TOPIC,
+            resource_name: Some("orders-us".to_string()),
+            pattern_type: pattern::MATCH,
+            ..filter.clone()
+        };
+        assert!(matching.matches(&prefixed));
+        let literal = AclFilter {
+            pattern_type: pattern::LITERAL,
+            ..matching.clone()
+        };
+        assert!(!literal.matches(&prefixed));
+        let other_user = AclFilter {
+            principal: Some("User:bob".to_string()),
+            ..filter.clone()
+        };
+        assert!(!other_user.matches(&prefixed));
+
+        assert!(AclFilter {
+            operation: 0,
+            ..filter
+        }
+        .invalid()
+        .is_some());
+
+        assert_eq!(invalid_binding(&prefixed), None);
+        let cluster = acl(
+            resource::CLUSTER,
+            "other",
+            pattern::LITERAL,
+            "User:alice",
+            operation::ALTER,
+            permission::ALLOW,
+        );
+        assert!(invalid_binding(&cluster).is_some());
+        let anonymous = acl(
+            resource::TOPIC,
+            "t",
+            pattern::LITERAL,
+            "alice",
+            operation::READ,
+            permission::ALLOW,
+        );
+        assert!(invalid_binding(&anonymous).is_some());
+    }
+}
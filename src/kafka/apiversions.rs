@@ -1,18 +1,61 @@
 use std::{fs::File, io::BufReader, path::Path};
 
-use crate::{types::array::CVec, Decode, Encode, Size};
+use crate::{
+    types::{
+        checked_count, cstring::CompactString, decode_unsigned_varint, encode_unsigned_varint,
+        length_prefix,
+    },
+    Decode, Encode, Size,
+};
 use anyhow::Error;
+use bytes::{BufMut, Bytes, BytesMut};
 use encode_derive::{Decode, Size};
 use serde::Deserialize as Serde_Deserialize;
 
-use super::{BaseRequest, BaseResponse};
+use super::{errors::UNSUPPORTED_VERSION, RequestContext};
 
-#[derive(Debug, Encode, Decode)]
+/// ApiVersions requests only carry a body from v3 on.
+#[derive(Debug, Default)]
 pub struct ApiVersionsRequest {
-    pub base: BaseRequest,
+    pub client_software: Option<ClientSoftware>,
 }
 
-#[derive(Serde_Deserialize, Debug, Encode, Decode, Size)]
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ClientSoftware {
+    pub name: CompactString,
+    pub version: CompactString,
+    pub tagged_fields: u8,
+}
+
+impl ApiVersionsRequest {
+    /// Decodes the body for `version`. Bodies of versions newer than we
+    /// understand are left alone; they are answered with a v0 error.
+    pub fn decode_version(bytes: &Bytes, offset: &mut usize, version: i16) -> Self {
+        let client_software = (3..=4)
+            .contains(&version)
+            .then(|| ClientSoftware::decode(bytes, offset));
+
+        Self { client_software }
+    }
+}
+
+/// The client software is only sent from v3 on, so it must be left out of
+/// earlier requests.
+impl Encode for ApiVersionsRequest {
+    fn encode(&self, buf: &mut BytesMut) {
+        if let Some(client_software) = &self.client_software {
+            client_software.encode(buf);
+        }
+    }
+}
+
+impl Size for ApiVersionsRequest {
+    fn size_in_bytes(&self) -> usize {
+        self.client_software.as_ref().map_or(0, Size::size_in_bytes)
+    }
+}
+
+#[derive(Serde_Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct SupportedVersionsKey {
     pub key: i16,
     pub min: i16,
@@ -20,24 +63,117 @@ pub struct SupportedVersionsKey {
     pub tagged_fields: u8,
 }
 
-#[derive(Debug, Encode, Decode, Size)]
+/// The response layout depends on the version: v1 adds the throttle time and
+/// v3 switches to compact arrays with tagged fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ApiVersionsResponse {
-    pub base: BaseResponse,
+    pub version: i16,
     pub error_code: i16,
-    pub api_keys: CVec<SupportedVersionsKey>,
+    pub api_keys: Vec<SupportedVersionsKey>,
     pub throttle_time_ms: i32,
-    pub tagged_fields: u8,
 }
 
-fn get_supported_versions<P: AsRef<Path>>(path: P) -> Result<CVec<SupportedVersionsKey>, Error> {
-    let f = File::open(path)?;
-    let reader = BufReader::new(f);
+impl Encode for ApiVersionsResponse {
+    fn encode(&self, buf: &mut BytesMut) {
+        let flexible = self.version >= 3;
+
+        buf.put_i16(self.error_code);
+
+        if flexible {
+            encode_unsigned_varint(self.api_keys.len() as u64 + 1, buf);
+        } else {
+            buf.put_i32(length_prefix(self.api_keys.len()));
+        }
+
+        for api in &self.api_keys {
+            buf.put_i16(api.key);
+            buf.put_i16(api.min);
+            buf.put_i16(api.max);
+            if flexible {
+                buf.put_u8(api.tagged_fields);
+            }
+        }
+
+        if self.version >= 1 {
+            buf.put_i32(self.throttle_time_ms);
+        }
+
+        if flexible {
+            buf.put_u8(0);
+        }
+    }
+}
+
+impl crate::EncodeFrame for ApiVersionsResponse {
+    fn encode_frame(&self, frame: &mut crate::frame::Frame) {
+        frame.put(self);
+    }
+}
+
+impl Size for ApiVersionsResponse {
+    fn size_in_bytes(&self) -> usize {
+        let flexible = self.version >= 3;
+
+        let array_prefix = if flexible {
+            crate::types::unsigned_varint_bytes_wide(self.api_keys.len() + 1)
+        } else {
+            4
+        };
+        let entry = if flexible { 7 } else { 6 };
+        let throttle = if self.version >= 1 { 4 } else { 0 };
+        let tagged_fields = if flexible { 1 } else { 0 };
 
-    let data_vec: Vec<SupportedVersionsKey> = serde_json::from_reader(reader)?;
+        2 + array_prefix + entry * self.api_keys.len() + throttle + tagged_fields
+    }
+}
+
+impl ApiVersionsResponse {
+    /// Decodes a response sent for a request of `version`.
+    pub fn decode_version(bytes: &Bytes, offset: &mut usize, version: i16) -> Self {
+        let flexible = version >= 3;
+
+        let error_code = i16::decode(bytes, offset);
+
+        let count = if flexible {
+            decode_unsigned_varint(bytes, offset).0.saturating_sub(1) as usize
+        } else {
+            i32::decode(bytes, offset).max(0) as usize
+        };
+        let count = checked_count(bytes, offset, count);
+
+        let api_keys = (0..count)
+            .map(|_| SupportedVersionsKey {
+                key: i16::decode(bytes, offset),
+                min: i16::decode(bytes, offset),
+                max: i16::decode(bytes, offset),
+                tagged_fields: if flexible {
+                    u8::decode(bytes, offset)
+                } else {
+                    0
+                },
+            })
+            .collect();
+
+        let throttle_time_ms = if version >= 1 {
+            i32::decode(bytes, offset)
+        } else {
+            0
+        };
+
+        Self {
+            version,
+            error_code,
+            api_keys,
+            throttle_time_ms,
+        }
+    }
+}
 
-    let data = CVec { data: data_vec };
+pub fn get_supported_versions<P: AsRef<Path>>(path: P) -> Result<Vec<SupportedVersionsKey>, Error> {
+    let f = File::open(path)?;
+    let reader = BufReader::new(f);
 
-    Ok(data)
+    Ok(serde_json::from_reader(reader)?)
 }
 
 pub fn is_version_supported<P: AsRef<Path>>(
@@ -45,54 +181,72 @@ pub fn is_version_supported<P: AsRef<Path>>(
     key: i16,
     version: i16,
 ) -> Result<bool, Error> {
-    let f = File::open(path)?;
-    let reader = BufReader::new(f);
-
-    let data: Vec<SupportedVersionsKey> = serde_json::from_reader(reader)?;
+    let data = get_supported_versions(path)?;
 
     Ok(data
         .iter()
-        .filter(|val| val.key == key && (version >= val.min && version <= val.max))
-        .last()
-        .is_some())
+        .any(|val| val.key == key && (version >= val.min && version <= val.max)))
 }
 
 impl ApiVersionsRequest {
-    pub async fn handle_request(&self) -> Result<ApiVersionsResponse, Error> {
-        let base = BaseResponse {
-            correlation_id: self.base.correlation_id,
-            size: 0,
-        };
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<ApiVersionsResponse, Error> {
+        let api_keys = get_supported_versions("supported_versions.json")?;
 
-        let error_code = if is_version_supported(
-            "supported_versions.json",
-            self.base.api_key,
-            self.base.api_versions,
-        )
-        .unwrap_or(false)
-        {
-            0
+        let supported = api_keys
+            .iter()
+            .any(|api| api.key == ctx.api_key && (api.min..=api.max).contains(&ctx.api_version));
+
+        // Clients that sent a version we don't know are told so in v0, the
+        // one layout every client can read.
+        let (version, error_code) = if supported {
+            (ctx.api_version, 0)
         } else {
-            35
-        };
-        let api_keys = match get_supported_versions("supported_versions.json") {
-            Ok(value) => value,
-            Err(err) => return Err(err),
+            (0, UNSUPPORTED_VERSION)
         };
-        let throttle_time_ms = 0;
-        let tagged_fields = 0;
 
-        let mut response = ApiVersionsResponse {
-            base,
+        Ok(ApiVersionsResponse {
+            version,
             error_code,
             api_keys,
-            throttle_time_ms,
-            tagged_fields,
-        };
+            throttle_time_ms: 0,
+        })
+    }
+}
 
-        let res_size = response.size_in_bytes() - 4;
-        response.base.size = res_size as i32;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(version: i16) -> ApiVersionsResponse {
+        ApiVersionsResponse {
+            version,
+            error_code: 0,
+            api_keys: vec![SupportedVersionsKey {
+                key: 18,
+                min: 0,
+                max: 4,
+                tagged_fields: 0,
+            }],
+            throttle_time_ms: 0,
+        }
+    }
 
-        Ok(response)
+    #[test]
+    fn test_response_layout_per_version() {
+        let v0 = response(0).to_bytes();
+        assert_eq!(&v0[..], &[0, 0, 0, 0, 0, 1, 0, 18, 0, 0, 0, 4]);
+
+        let v3 = response(3).to_bytes();
+        assert_eq!(&v3[..], &[0, 0, 2, 0, 18, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0]);
+
+        for version in 0..=4 {
+            let value = response(version);
+            let encoded = value.to_bytes();
+            assert_eq!(encoded.len(), value.size_in_bytes());
+            assert_eq!(
+                ApiVersionsResponse::decode_version(&encoded, &mut 0, version),
+                value
+            );
+        }
     }
 }
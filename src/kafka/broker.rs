@@ -0,0 +1,461 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    acl::Authorizer,
+    configs::LogConfigs,
+    fetch_session::FetchSessions,
+    group::{
+        lag::{group_lag, PartitionLag},
+        offsets::OffsetStore,
+        GroupConfig, GroupCoordinator,
+    },
+    listener::{Listener, SecurityProtocol, TlsConfig},
+    log::{
+        partition::{remove_dirs, LogManager},
+        LOG_DIR,
+    },
+    metadata::{ClusterMetadata, MetadataImage},
+    producer::ProducerIds,
+    quota::{QuotaManager, QuotaWindow},
+    sasl::{load_credentials, Mechanism},
+    txn::TransactionCoordinator,
+};
+
+/// How often logs are checked for segments past their retention.
+const RETENTION_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How this broker identifies and advertises itself, and where it keeps data.
+#[derive(Debug, Clone)]
+pub struct BrokerConfig {
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+    pub log_dir: PathBuf,
+    /// Where connections are accepted. Without a `listeners` property, a
+    /// single listener that is SASL_PLAINTEXT if SASL mechanisms are
+    /// enabled, PLAINTEXT otherwise.
+    pub listeners: Vec<Listener>,
+    /// The SASL mechanisms clients of SASL listeners may authenticate with.
+    pub sasl_enabled_mechanisms: Vec<String>,
+    /// A file of SCRAM credentials added to the metadata log on startup.
+    pub scram_credentials_file: Option<PathBuf>,
+    /// The certificates of SSL listeners.
+    pub tls: TlsConfig,
+    /// How requests are authorized against ACLs.
+    pub authorizer: Authorizer,
+    /// How client usage is sampled for quotas.
+    pub quota_window: QuotaWindow,
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        Self {
+            node_id: 1,
+            host: "localhost".to_string(),
+            port: 9092,
+            log_dir: PathBuf::from(LOG_DIR),
+            listeners: vec![Listener {
+                name: "PLAINTEXT".to_string(),
+                protocol: SecurityProtocol::Plaintext,
+                host: "127.0.0.1".to_string(),
+                port: 9092,
+            }],
+            sasl_enabled_mechanisms: Vec::new(),
+            scram_credentials_file: None,
+            tls: TlsConfig::default(),
+            authorizer: Authorizer::default(),
+            quota_window: QuotaWindow::default(),
+        }
+    }
+}
+
+impl BrokerConfig {
+    /// Reads the settings this broker knows of from a server.properties
+    /// file, keeping the defaults for the rest.
+    pub fn from_properties(path: &Path) -> io::Result<Self> {
+        let mut config = Self::default();
+        let mut listeners = None;
+        let mut protocols = HashMap::new();
+        let mut controllers = Vec::new();
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid property in {}: {line}", path.display()),
+            )
+        };
+
+        for line in fs::read_to_string(path)?.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| invalid(line))?;
+            let value = value.trim();
+            match key.trim() {
+                "node.id" => config.node_id = value.parse().map_err(|_| invalid(line))?,
+                // Only one log directory is supported.
+                "log.dirs" | "log.dir" => {
+                    config.log_dir = value.split(',').next().unwrap_or_default().into()
+                }
+                "sasl.enabled.mechanisms" => {
+                    config.sasl_enabled_mechanisms = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    if let Some(name) = config
+                        .sasl_enabled_mechanisms
+                        .iter()
+                        .find(|name| Mechanism::from_name(name).is_none())
+                    {
+                        return Err(invalid(&format!("unsupported SASL mechanism {name}")));
+                    }
+                }
+                "sasl.scram.credentials.file" => config.scram_credentials_file = Some(value.into()),
+                "listeners" => listeners = Some(value.to_string()),
+                "listener.security.protocol.map" => {
+                    for entry in value.split(',').filter(|entry| !entry.trim().is_empty()) {
+                        let (name, protocol) =
+                            entry.split_once(':').ok_or_else(|| invalid(line))?;
+                        let protocol = protocol.trim().parse().map_err(|e: String| invalid(&e))?;
+                        protocols.insert(name.trim().to_string(), protocol);
+                    }
+                }
+                "controller.listener.names" => {
+                    controllers = value
+                        .split(',')
+                        .map(|name| name.trim().to_string())
+                        .collect()
+                }
+                "ssl.certificate.location" => config.tls.certificate = value.into(),
+                "ssl.key.location" => config.tls.key = value.into(),
+                "ssl.ca.location" => config.tls.ca = Some(value.into()),
+                "ssl.client.auth" => {
+                    config.tls.client_auth = value.parse().map_err(|e: String| invalid(&e))?
+                }
+                // ACLs are always evaluated by the built-in authorizer.
+                "authorizer.class.name" => config.authorizer.enabled = !value.is_empty(),
+                "super.users" => {
+                    config.authorizer.super_users = value
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|user| !user.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                "allow.everyone.if.no.acl.found" => {
+                    config.authorizer.allow_everyone_if_no_acl_found =
+                        value.parse().map_err(|_| invalid(line))?
+                }
+                "quota.window.num" => {
+                    config.quota_window.samples = match value.parse() {
+                        Ok(0) | Err(_) => return Err(invalid(line)),
+                        Ok(samples) => samples,
+                    }
+                }
+                "quota.window.size.seconds" => {
+                    config.quota_window.size = match value.parse() {
+                        Ok(0) | Err(_) => return Err(invalid(line)),
+                        Ok(seconds) => Duration::from_secs(seconds),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match listeners {
+            // There is no controller to listen for.
+            Some(listeners) => {
+                config.listeners = listeners
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|listener| !listener.is_empty())
+                    .map(|listener| Listener::parse(listener, &protocols))
+                    .filter(|listener| {
+                        listener
+                            .as_ref()
+                            .map_or(true, |listener| !controllers.contains(&listener.name))
+                    })
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| invalid(&e))?
+            }
+            None if !config.sasl_enabled_mechanisms.is_empty() => {
+                config.listeners[0] = Listener {
+                    name: "SASL_PLAINTEXT".to_string(),
+                    protocol: SecurityProtocol::SaslPlaintext,
+                    ..config.listeners[0].clone()
+                }
+            }
+            None => {}
+        }
+
+        Ok(config)
+    }
+}
+
+/// State shared by every connection the broker serves.
+#[derive(Debug)]
+pub struct Broker {
+    pub config: BrokerConfig,
+    pub logs: LogManager,
+    pub metadata: ClusterMetadata,
+    pub groups: GroupCoordinator,
+    pub fetch_sessions: FetchSessions,
+    pub offsets: OffsetStore,
+    pub producer_ids: ProducerIds,
+    pub transactions: TransactionCoordinator,
+    pub quotas: QuotaManager,
+}
+
+impl Broker {
+    /// Opens the broker's logs, replaying the state they hold.
+    pub fn open(config: BrokerConfig) -> io::Result<Self> {
+        let logs = LogManager::new(&config.log_dir);
+        let metadata = ClusterMetadata::open(&logs)?;
+        if let Some(path) = &config.scram_credentials_file {
+            load_credentials(&metadata, path)?;
+        }
+        logs.set_configs(metadata.read(|image| LogConfigs::from_image(image, config.node_id)));
+        let offsets = OffsetStore::open(&logs)?;
+        let transactions = TransactionCoordinator::open(&logs)?;
+
+        Ok(Self {
+            quotas: QuotaManager::new(config.quota_window),
+            config,
+            logs,
+            metadata,
+            groups: GroupCoordinator::new(GroupConfig::default()),
+            fetch_sessions: FetchSessions::default(),
+            offsets,
+            producer_ids: ProducerIds::default(),
+            transactions,
+        })
+    }
+
+    /// Applies the topic configs of `image` to the logs. Called with the
+    /// image of every update that may change them.
+    pub fn sync_log_configs(&self, image: &MetadataImage) {
+        self.logs
+            .set_configs(LogConfigs::from_image(image, self.config.node_id));
+    }
+
+    /// Deletes the segments of every topic past their retention, in the
+    /// background.
+    pub fn spawn_retention(self: &Arc<Self>) {
+        let broker = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RETENTION_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let broker = broker.clone();
+                let result = tokio::task::spawn_blocking(move || broker.apply_retention()).await;
+                if let Err(e) = result.map_err(io::Error::other).and_then(|r| r) {
+                    eprintln!("failed to apply retention: {e:?}");
+                }
+            }
+        });
+    }
+
+    /// Holds the image throughout, so that no topic is deleted meanwhile.
+    fn apply_retention(&self) -> io::Result<()> {
+        let now = SystemTime::now();
+
+        self.metadata.read(|image| {
+            for topic in image.topics() {
+                for &partition in topic.partitions.keys() {
+                    let log = self.logs.partition(&topic.name, partition)?;
+                    let mut log = log.lock().unwrap_or_else(|e| e.into_inner());
+                    log.apply_retention(now)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Removes, in the background, the directories of partitions deleted
+    /// before the broker last stopped.
+    pub fn spawn_log_cleanup(&self) -> io::Result<()> {
+        let dirs = self.logs.deleted_dirs()?;
+        if !dirs.is_empty() {
+            tokio::task::spawn_blocking(move || remove_dirs(dirs));
+        }
+
+        Ok(())
+    }
+
+    /// The lag of every partition `group` committed an offset for.
+    pub fn group_lag(&self, group: &str) -> io::Result<Vec<PartitionLag>> {
+        group_lag(&self.offsets.group_offsets(group), &self.logs)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::Mutex,
+    };
+
+    use super::*;
+    use crate::{
+        kafka::{
+            listener::ClientAuth,
+            log::{partition_record::PartitionRecord, topic_log::TopicRecord, RecordValue},
+            sasl::Session,
+            RequestContext, RequestHeader,
+        },
+        types::{array::CompactArray, uuid::UUID},
+    };
+
+    /// Creates a topic led by `broker`, with one partition per id.
+    pub(crate) fn create_topic(broker: &Broker, name: &str, partitions: i32) -> UUID {
+        let id = UUID(*uuid::Uuid::new_v4().as_bytes());
+        let node_id = broker.config.node_id;
+        broker
+            .metadata
+            .update(|_| {
+                let mut records = vec![RecordValue::Topic(TopicRecord {
+                    name: name.into(),
+                    id: id.clone(),
+                    tagged_fields: 0,
+                })];
+                records.extend((0..partitions).map(|partition| {
+                    RecordValue::Partition(PartitionRecord {
+                        id: partition,
+                        topic_id: id.clone(),
+                        replicas: CompactArray(vec![node_id]),
+                        sync_replicas: CompactArray(vec![node_id]),
+                        removing_replicas: CompactArray(vec![]),
+                        adding_replicas: CompactArray(vec![]),
+                        leader: node_id,
+                        leader_epoch: 0,
+                        partition_epoch: 0,
+                        directories: CompactArray(vec![UUID([0; 16])]),
+                        tagged_fields: 0,
+                    })
+                }));
+                (records, ())
+            })
+            .unwrap();
+        id
+    }
+
+    /// A broker on a temporary log directory, for calling request handlers
+    /// directly.
+    pub(crate) struct TempBroker {
+        pub broker: Arc<Broker>,
+        dir: PathBuf,
+    }
+
+    impl TempBroker {
+        pub fn open(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            let broker = Broker::open(BrokerConfig {
+                log_dir: dir.clone(),
+                ..BrokerConfig::default()
+            })
+            .unwrap();
+            Self {
+                broker: Arc::new(broker),
+                dir,
+            }
+        }
+
+        /// Creates a topic with one partition per id.
+        pub fn create_topic(&self, name: &str, partitions: i32) -> UUID {
+            create_topic(&self.broker, name, partitions)
+        }
+
+        /// The context of a request from an unauthenticated client.
+        pub fn context(&self, api_key: i16, api_version: i16) -> RequestContext {
+            let header = RequestHeader {
+                api_key,
+                api_version,
+                correlation_id: 1,
+                client_id: Some("test".to_string()),
+            };
+            RequestContext::new(
+                header,
+                SocketAddr::from((Ipv4Addr::LOCALHOST, 9092)),
+                0,
+                self.broker.clone(),
+                Arc::new(Mutex::new(Session::new(false))),
+            )
+        }
+    }
+
+    impl Drop for TempBroker {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn test_config_from_properties() {
+        let dir = std::env::temp_dir().join(format!("broker-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("server.properties");
+
+        fs::write(
+            &path,
+            "# KRaft combined mode\n\
+             process.roles=broker,controller\n\
+             node.id=3\n\
+             listeners=PLAINTEXT://:9092,CONTROLLER://:9093,INTERNAL://localhost:9094\n\
+             controller.listener.names=CONTROLLER\n\
+             listener.security.protocol.map=CONTROLLER:PLAINTEXT,INTERNAL:SASL_SSL\n\
+             log.dirs=/var/kafka-logs,/var/other-logs\n\
+             sasl.enabled.mechanisms=SCRAM-SHA-512\n\
+             ssl.client.auth=requested\n\
+             authorizer.class.name=org.apache.kafka.metadata.authorizer.StandardAuthorizer\n\
+             super.users=User:admin;User:CN=broker\n\
+             quota.window.num=5\n",
+        )
+        .unwrap();
+        let config = BrokerConfig::from_properties(&path).unwrap();
+        assert_eq!(config.node_id, 3);
+        assert_eq!(config.log_dir, PathBuf::from("/var/kafka-logs"));
+        let listeners: Vec<(&str, SecurityProtocol)> = config
+            .listeners
+            .iter()
+            .map(|listener| (listener.name.as_str(), listener.protocol))
+            .collect();
+        assert_eq!(
+            listeners,
+            [
+                ("PLAINTEXT", SecurityProtocol::Plaintext),
+                ("INTERNAL", SecurityProtocol::SaslSsl)
+            ]
+        );
+        assert_eq!(config.tls.client_auth, ClientAuth::Requested);
+        assert!(config.authorizer.enabled);
+        assert_eq!(
+            config.authorizer.super_users,
+            ["User:admin", "User:CN=broker"]
+        );
+        assert!(!config.authorizer.allow_everyone_if_no_acl_found);
+        assert_eq!(config.quota_window.samples, 5);
+        assert_eq!(config.quota_window.size, Duration::from_secs(1));
+
+        // Without listeners, enabling SASL makes the default listener need it.
+        fs::write(&path, "sasl.enabled.mechanisms=PLAIN\n").unwrap();
+        let config = BrokerConfig::from_properties(&path).unwrap();
+        assert_eq!(
+            config.listeners[0].protocol,
+            SecurityProtocol::SaslPlaintext
+        );
+
+        fs::write(&path, "sasl.enabled.mechanisms=GSSAPI\n").unwrap();
+        assert!(BrokerConfig::from_properties(&path).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
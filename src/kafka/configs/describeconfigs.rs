@@ -0,0 +1,210 @@
+use crate::{
+    kafka::{
+        acl::operation,
+        errors::{INVALID_REQUEST, NONE, UNKNOWN_TOPIC_OR_PARTITION},
+        metadata::MetadataImage,
+        RequestContext,
+    },
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{ConfigLayers, BROKER_RESOURCE, CLUSTER_DEFAULT, CONFIGS, TOPIC_RESOURCE};
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeConfigsResource {
+    pub resource_type: i8,
+    pub resource_name: CompactString,
+    /// Null for every config.
+    pub configuration_keys: CompactNullableArray<CompactString>,
+    pub tagged_fields: u8,
+}
+
+/// DescribeConfigs v4.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeConfigsRequest {
+    pub resources: CompactArray<DescribeConfigsResource>,
+    pub include_synonyms: u8,
+    pub include_documentation: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeConfigsSynonym {
+    pub name: CompactString,
+    pub value: CompactNullableString,
+    pub source: i8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeConfigsResourceResult {
+    pub name: CompactString,
+    pub value: CompactNullableString,
+    pub read_only: u8,
+    pub config_source: i8,
+    pub is_sensitive: u8,
+    pub synonyms: CompactArray<DescribeConfigsSynonym>,
+    pub config_type: i8,
+    pub documentation: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeConfigsResult {
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub resource_type: i8,
+    pub resource_name: CompactString,
+    pub configs: CompactArray<DescribeConfigsResourceResult>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeConfigsResponse {
+    pub throttle_time_ms: i32,
+    pub results: CompactArray<DescribeConfigsResult>,
+    pub tagged_fields: u8,
+}
+
+/// Finds the levels of the configs of a resource, checking it exists.
+pub(crate) fn layers<'a>(
+    image: &'a MetadataImage,
+    resource_type: i8,
+    resource_name: &str,
+    node_id: i32,
+) -> Result<ConfigLayers<'a>, (i16, String)> {
+    match resource_type {
+        TOPIC_RESOURCE => {
+            if image.topic(resource_name).is_none() {
+                return Err((
+                    UNKNOWN_TOPIC_OR_PARTITION,
+                    format!("Topic {resource_name} does not exist."),
+                ));
+            }
+            let topic = image.configs(TOPIC_RESOURCE, resource_name);
+            Ok(ConfigLayers::topic(image, topic, node_id))
+        }
+        BROKER_RESOURCE => {
+            if resource_name != CLUSTER_DEFAULT && resource_name != node_id.to_string() {
+                return Err((
+                    INVALID_REQUEST,
+                    format!(
+                        "Unexpected broker id, expected {node_id} or empty string, but \
+                         received {resource_name}"
+                    ),
+                ));
+            }
+            Ok(ConfigLayers::broker(image, resource_name))
+        }
+        _ => Err((
+            INVALID_REQUEST,
+            format!("Unsupported resource type {resource_type}"),
+        )),
+    }
+}
+
+impl DescribeConfigsRequest {
+    fn describe(
+        &self,
+        resource: &DescribeConfigsResource,
+        image: &MetadataImage,
+        node_id: i32,
+    ) -> Result<Vec<DescribeConfigsResourceResult>, (i16, String)> {
+        let resource_type = resource.resource_type;
+        let layers = layers(image, resource_type, &resource.resource_name, node_id)?;
+
+        let configs = CONFIGS
+            .iter()
+            .filter(|def| {
+                let name = def.name_for(resource_type);
+                resource
+                    .configuration_keys
+                    .0
+                    .as_ref()
+                    .map_or(true, |keys| keys.iter().any(|key| key.0 == name))
+            })
+            .map(|def| {
+                let synonyms = layers.synonyms(def);
+                let effective = &synonyms[0];
+
+                DescribeConfigsResourceResult {
+                    name: def.name_for(resource_type).into(),
+                    value: CompactNullableString(Some(effective.value.clone())),
+                    read_only: 0,
+                    config_source: effective.source,
+                    is_sensitive: 0,
+                    synonyms: if self.include_synonyms != 0 {
+                        CompactArray(
+                            synonyms
+                                .iter()
+                                .map(|synonym| DescribeConfigsSynonym {
+                                    name: synonym.name.into(),
+                                    value: CompactNullableString(Some(synonym.value.clone())),
+                                    source: synonym.source,
+                                    tagged_fields: 0,
+                                })
+                                .collect(),
+                        )
+                    } else {
+                        CompactArray::default()
+                    },
+                    config_type: def.config_type as i8,
+                    documentation: CompactNullableString(
+                        (self.include_documentation != 0).then(|| def.documentation.to_string()),
+                    ),
+                    tagged_fields: 0,
+                }
+            })
+            .collect();
+
+        Ok(configs)
+    }
+
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<DescribeConfigsResponse, Error> {
+        let node_id = ctx.broker.config.node_id;
+
+        let results = ctx.broker.metadata.read(|image| {
+            self.resources
+                .iter()
+                .map(|resource| {
+                    let described = super::authorize(
+                        ctx,
+                        image,
+                        operation::DESCRIBE_CONFIGS,
+                        resource.resource_type,
+                        &resource.resource_name,
+                    )
+                    .and_then(|()| self.describe(resource, image, node_id));
+                    let (error_code, error_message, configs) = match described {
+                        Ok(configs) => (NONE, None, configs),
+                        Err((code, message)) => (code, Some(message), vec![]),
+                    };
+
+                    DescribeConfigsResult {
+                        error_code,
+                        error_message: CompactNullableString(error_message),
+                        resource_type: resource.resource_type,
+                        resource_name: resource.resource_name.clone(),
+                        configs: CompactArray(configs),
+                        tagged_fields: 0,
+                    }
+                })
+                .collect()
+        });
+
+        Ok(DescribeConfigsResponse {
+            throttle_time_ms: 0,
+            results: CompactArray(results),
+            tagged_fields: 0,
+        })
+    }
+}
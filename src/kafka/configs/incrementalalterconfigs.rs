@@ -0,0 +1,378 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    kafka::{
+        acl::operation,
+        errors::{INVALID_CONFIG, INVALID_REQUEST, NONE, UNKNOWN_SERVER_ERROR},
+        log::{config_record::ConfigRecord, RecordValue},
+        metadata::MetadataImage,
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{describeconfigs::layers, find, list, ConfigType};
+
+/// Operations on a config.
+const SET: i8 = 0;
+const DELETE: i8 = 1;
+const APPEND: i8 = 2;
+const SUBTRACT: i8 = 3;
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct AlterableConfig {
+    pub name: CompactString,
+    pub config_operation: i8,
+    pub value: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct AlterConfigsResource {
+    pub resource_type: i8,
+    pub resource_name: CompactString,
+    pub configs: CompactArray<AlterableConfig>,
+    pub tagged_fields: u8,
+}
+
+/// IncrementalAlterConfigs v1.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct IncrementalAlterConfigsRequest {
+    pub resources: CompactArray<AlterConfigsResource>,
+    pub validate_only: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AlterConfigsResourceResponse {
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub resource_type: i8,
+    pub resource_name: CompactString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct IncrementalAlterConfigsResponse {
+    pub throttle_time_ms: i32,
+    pub responses: CompactArray<AlterConfigsResourceResponse>,
+    pub tagged_fields: u8,
+}
+
+type Outcome = Result<Vec<RecordValue>, (i16, String)>;
+
+/// Applies the operations on `resource` to its configs, returning the
+/// records of the configs that change.
+fn alter(resource: &AlterConfigsResource, image: &MetadataImage, node_id: i32) -> Outcome {
+    let resource_type = resource.resource_type;
+    let name = &resource.resource_name.0;
+    let layers = layers(image, resource_type, name, node_id)?;
+
+    let mut seen = BTreeSet::new();
+    if !resource.configs.iter().all(|c| seen.insert(&c.name.0)) {
+        return Err((
+            INVALID_REQUEST,
+            "Error due to duplicate config keys".to_string(),
+        ));
+    }
+
+    let current = image
+        .configs(resource_type, name)
+        .cloned()
+        .unwrap_or_default();
+    let mut values = current.clone();
+
+    for config in &resource.configs {
+        let key = &config.name.0;
+        let def = find(resource_type, key)
+            .ok_or_else(|| (INVALID_CONFIG, format!("Unknown config name: {key}")))?;
+        let value = || {
+            config.value.0.clone().ok_or_else(|| {
+                (
+                    INVALID_REQUEST,
+                    format!("Null value not supported for: {key}"),
+                )
+            })
+        };
+
+        let new = match config.config_operation {
+            SET => value()?,
+            DELETE => {
+                values.remove(key);
+                continue;
+            }
+            op @ (APPEND | SUBTRACT) => {
+                if def.config_type != ConfigType::List {
+                    let verb = if op == APPEND { "append" } else { "subtract" };
+                    return Err((
+                        INVALID_CONFIG,
+                        format!("Config value {verb} is not allowed for config key: {key}"),
+                    ));
+                }
+
+                let base = values
+                    .get(key)
+                    .cloned()
+                    .unwrap_or_else(|| layers.value(def));
+                let mut items: Vec<String> = list(&base).map(str::to_string).collect();
+                let value = value()?;
+                for item in list(&value) {
+                    if op == SUBTRACT {
+                        items.retain(|i| i != item);
+                    } else if !items.iter().any(|i| i == item) {
+                        items.push(item.to_string());
+                    }
+                }
+                items.join(",")
+            }
+            op => {
+                return Err((
+                    INVALID_REQUEST,
+                    format!("Unknown alter config operation {op} for: {key}"),
+                ))
+            }
+        };
+
+        def.validate(resource_type, &new)
+            .map_err(|message| (INVALID_CONFIG, message))?;
+        values.insert(key.clone(), new);
+    }
+
+    Ok(changes(resource_type, name, &current, &values))
+}
+
+/// The records turning the configs `old` of a resource into `new`.
+pub(crate) fn changes(
+    resource_type: i8,
+    resource_name: &str,
+    old: &BTreeMap<String, String>,
+    new: &BTreeMap<String, String>,
+) -> Vec<RecordValue> {
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    names
+        .into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| {
+            RecordValue::Config(ConfigRecord {
+                resource_type,
+                resource_name: resource_name.into(),
+                name: name.as_str().into(),
+                value: CompactNullableString(new.get(name).cloned()),
+                tagged_fields: 0,
+            })
+        })
+        .collect()
+}
+
+impl IncrementalAlterConfigsRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<IncrementalAlterConfigsResponse, Error> {
+        let ctx = ctx.clone();
+        let broker = ctx.broker.clone();
+        let resources = self.resources.0.clone();
+        let validate_only = self.validate_only != 0;
+
+        let mut counts: HashMap<(i8, &str), usize> = HashMap::new();
+        for resource in &self.resources {
+            *counts
+                .entry((resource.resource_type, &resource.resource_name))
+                .or_default() += 1;
+        }
+        let duplicated: Vec<bool> = self
+            .resources
+            .iter()
+            .map(|r| counts[&(r.resource_type, r.resource_name.as_str())] > 1)
+            .collect();
+
+        let outcomes = tokio::task::spawn_blocking(move || {
+            broker.metadata.update_then(
+                |image| {
+                    let mut records = Vec::new();
+                    let outcomes: Vec<Result<(), (i16, String)>> = resources
+                        .iter()
+                        .zip(&duplicated)
+                        .map(|(resource, &duplicated)| {
+                            if duplicated {
+                                return Err((
+                                    INVALID_REQUEST,
+                                    "Duplicate resource in the request.".to_string(),
+                                ));
+                            }
+                            super::authorize(
+                                &ctx,
+                                image,
+                                operation::ALTER_CONFIGS,
+                                resource.resource_type,
+                                &resource.resource_name,
+                            )?;
+                            let changes = alter(resource, image, broker.config.node_id)?;
+                            if !validate_only {
+                                records.extend(changes);
+                            }
+                            Ok(())
+                        })
+                        .collect();
+
+                    (records, outcomes)
+                },
+                |image, _| {
+                    broker.sync_log_configs(image);
+                    Ok(())
+                },
+            )
+        })
+        .await?;
+
+        let outcomes = match outcomes {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                eprintln!("failed to alter configs: {e:?}");
+                self.resources
+                    .iter()
+                    .map(|_| Err((UNKNOWN_SERVER_ERROR, e.to_string())))
+                    .collect()
+            }
+        };
+
+        let responses = self
+            .resources
+            .iter()
+            .zip(outcomes)
+            .map(|(resource, outcome)| {
+                let (error_code, message) =
+                    outcome.map_or_else(|e| (e.0, Some(e.1)), |_| (NONE, None));
+                AlterConfigsResourceResponse {
+                    error_code,
+                    error_message: CompactNullableString(message),
+                    resource_type: resource.resource_type,
+                    resource_name: resource.resource_name.clone(),
+                    tagged_fields: 0,
+                }
+            })
+            .collect();
+
+        Ok(IncrementalAlterConfigsResponse {
+            throttle_time_ms: 0,
+            responses: CompactArray(responses),
+            tagged_fields: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::configs::{BROKER_RESOURCE, CLUSTER_DEFAULT};
+
+    fn resource(configs: &[(&str, i8, Option<&str>)]) -> AlterConfigsResource {
+        AlterConfigsResource {
+            resource_type: BROKER_RESOURCE,
+            resource_name: CLUSTER_DEFAULT.into(),
+            configs: CompactArray(
+                configs
+                    .iter()
+                    .map(|&(name, config_operation, value)| AlterableConfig {
+                        name: name.into(),
+                        config_operation,
+                        value: CompactNullableString(value.map(str::to_string)),
+                        tagged_fields: 0,
+                    })
+                    .collect(),
+            ),
+            tagged_fields: 0,
+        }
+    }
+
+    fn values(outcome: Outcome) -> Vec<(String, Option<String>)> {
+        outcome
+            .unwrap()
+            .into_iter()
+            .map(|record| match record {
+                RecordValue::Config(c) => (c.name.0, c.value.0),
+                other => panic!("unexpected record {other:?}"),
+            })
+            .collect()
+    }
+
+    fn error(outcome: Outcome) -> i16 {
+        outcome.map_or_else(|(code, _)| code, |_| NONE)
+    }
+
+    #[test]
+    fn test_alter_operations() {
+        let image = MetadataImage::default();
+
+        assert_eq!(
+            values(alter(
+                &resource(&[
+                    ("log.retention.ms", SET, Some("1000")),
+                    ("log.cleanup.policy", APPEND, Some("compact")),
+                ]),
+                &image,
+                1
+            )),
+            vec![
+                (
+                    "log.cleanup.policy".to_string(),
+                    Some("delete,compact".to_string())
+                ),
+                ("log.retention.ms".to_string(), Some("1000".to_string())),
+            ]
+        );
+        assert_eq!(
+            values(alter(
+                &resource(&[("log.cleanup.policy", SUBTRACT, Some("delete"))]),
+                &image,
+                1
+            )),
+            vec![("log.cleanup.policy".to_string(), Some("".to_string()))]
+        );
+        assert!(values(alter(
+            &resource(&[("log.retention.ms", DELETE, None)]),
+            &image,
+            1
+        ))
+        .is_empty());
+
+        assert_eq!(
+            error(alter(
+                &resource(&[("log.retention.ms", APPEND, Some("1"))]),
+                &image,
+                1
+            )),
+            INVALID_CONFIG
+        );
+        assert_eq!(
+            error(alter(&resource(&[("no.such", SET, Some("1"))]), &image, 1)),
+            INVALID_CONFIG
+        );
+        assert_eq!(
+            error(alter(
+                &resource(&[("log.retention.ms", SET, Some("soon"))]),
+                &image,
+                1
+            )),
+            INVALID_CONFIG
+        );
+        assert_eq!(
+            error(alter(
+                &resource(&[
+                    ("log.retention.ms", SET, Some("1")),
+                    ("log.retention.ms", DELETE, None),
+                ]),
+                &image,
+                1
+            )),
+            INVALID_REQUEST
+        );
+    }
+}
@@ -0,0 +1,392 @@
+//! Topic and broker configs: their definitions, and the values in effect once
+//! topic, broker and cluster-wide settings are layered over the defaults.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+use super::{
+    acl::{resource, CLUSTER_NAME},
+    errors::{CLUSTER_AUTHORIZATION_FAILED, TOPIC_AUTHORIZATION_FAILED},
+    metadata::MetadataImage,
+    RequestContext,
+};
+
+pub mod describeconfigs;
+pub mod incrementalalterconfigs;
+
+/// Resource types configs are attached to.
+pub const TOPIC_RESOURCE: i8 = 2;
+pub const BROKER_RESOURCE: i8 = 4;
+
+/// Where a config value comes from.
+pub const TOPIC_CONFIG: i8 = 1;
+pub const DYNAMIC_BROKER_CONFIG: i8 = 2;
+pub const DYNAMIC_DEFAULT_BROKER_CONFIG: i8 = 3;
+pub const DEFAULT_CONFIG: i8 = 5;
+
+/// Resource name of the broker configs shared by every broker.
+pub const CLUSTER_DEFAULT: &str = "";
+
+/// Checks that the client may perform `operation` on the configs of a
+/// resource: those of a topic are the topic's, and those of brokers the
+/// cluster's. Other resource types are left to be rejected as unknown.
+pub(crate) fn authorize(
+    ctx: &RequestContext,
+    image: &MetadataImage,
+    operation: i8,
+    resource_type: i8,
+    name: &str,
+) -> Result<(), (i16, String)> {
+    let (resource, name, error_code) = match resource_type {
+        TOPIC_RESOURCE => (resource::TOPIC, name, TOPIC_AUTHORIZATION_FAILED),
+        BROKER_RESOURCE => (
+            resource::CLUSTER,
+            CLUSTER_NAME,
+            CLUSTER_AUTHORIZATION_FAILED,
+        ),
+        _ => return Ok(()),
+    };
+    if ctx.authorized(image, operation, resource, name) {
+        Ok(())
+    } else {
+        Err((error_code, "Authorization failed.".to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigType {
+    Int = 3,
+    Long = 5,
+    List = 7,
+}
+
+/// A topic config, with the broker config that provides its default.
+#[derive(Debug)]
+pub struct ConfigDef {
+    pub name: &'static str,
+    pub broker_name: &'static str,
+    pub config_type: ConfigType,
+    pub default: &'static str,
+    /// Smallest value of a number.
+    pub min: i64,
+    /// Values the elements of a list may take.
+    pub valid_values: &'static [&'static str],
+    pub documentation: &'static str,
+}
+
+/// Every config this broker knows of, by topic config name.
+pub const CONFIGS: &[ConfigDef] = &[
+    ConfigDef {
+        name: "cleanup.policy",
+        broker_name: "log.cleanup.policy",
+        config_type: ConfigType::List,
+        default: "delete",
+        min: 0,
+        valid_values: &["compact", "delete"],
+        documentation: "The retention policy of old log segments; only \"delete\" removes them.",
+    },
+    ConfigDef {
+        name: "index.interval.bytes",
+        broker_name: "log.index.interval.bytes",
+        config_type: ConfigType::Int,
+        default: "4096",
+        min: 0,
+        valid_values: &[],
+        documentation: "How many bytes of records are appended between offset index entries.",
+    },
+    ConfigDef {
+        name: "max.message.bytes",
+        broker_name: "message.max.bytes",
+        config_type: ConfigType::Int,
+        default: "1048588",
+        min: 0,
+        valid_values: &[],
+        documentation: "The largest record batch size allowed.",
+    },
+    ConfigDef {
+        name: "retention.bytes",
+        broker_name: "log.retention.bytes",
+        config_type: ConfigType::Long,
+        default: "-1",
+        min: -1,
+        valid_values: &[],
+        documentation: "The size a partition may grow to before old segments are deleted, \
+                        or -1 for no limit.",
+    },
+    ConfigDef {
+        name: "retention.ms",
+        broker_name: "log.retention.ms",
+        config_type: ConfigType::Long,
+        default: "604800000",
+        min: -1,
+        valid_values: &[],
+        documentation: "How long a segment is kept after its last write, or -1 for no limit.",
+    },
+    ConfigDef {
+        name: "segment.bytes",
+        broker_name: "log.segment.bytes",
+        config_type: ConfigType::Int,
+        default: "1073741824",
+        min: 14,
+        valid_values: &[],
+        documentation: "The size at which the active segment is rolled.",
+    },
+    ConfigDef {
+        name: "segment.ms",
+        broker_name: "log.roll.ms",
+        config_type: ConfigType::Long,
+        default: "604800000",
+        min: 1,
+        valid_values: &[],
+        documentation: "The time after which the active segment is rolled even if not full.",
+    },
+];
+
+/// Looks up a config by the name it has on resources of `resource_type`.
+pub fn find(resource_type: i8, name: &str) -> Option<&'static ConfigDef> {
+    CONFIGS
+        .iter()
+        .find(|def| def.name_for(resource_type) == name)
+}
+
+impl ConfigDef {
+    pub fn name_for(&self, resource_type: i8) -> &'static str {
+        if resource_type == TOPIC_RESOURCE {
+            self.name
+        } else {
+            self.broker_name
+        }
+    }
+
+    /// Checks `value`, returning why it is invalid.
+    pub fn validate(&self, resource_type: i8, value: &str) -> Result<(), String> {
+        let name = self.name_for(resource_type);
+        let invalid =
+            |reason: String| format!("Invalid value {value} for configuration {name}: {reason}");
+
+        match self.config_type {
+            ConfigType::Int | ConfigType::Long => {
+                let number = match self.config_type {
+                    ConfigType::Int => value.trim().parse::<i32>().map(i64::from),
+                    _ => value.trim().parse::<i64>(),
+                }
+                .map_err(|_| invalid("Not a number of the expected type".to_string()))?;
+
+                if number < self.min {
+                    return Err(invalid(format!("Value must be at least {}", self.min)));
+                }
+            }
+            ConfigType::List => {
+                if let Some(item) = list(value).find(|item| !self.valid_values.contains(item)) {
+                    return Err(invalid(format!(
+                        "{item} is not one of {}",
+                        self.valid_values.join(", ")
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The elements of a list config.
+pub fn list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// One of the values a config takes at some level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Synonym {
+    pub name: &'static str,
+    pub value: String,
+    pub source: i8,
+}
+
+/// The levels a config value is looked up at, most specific first.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConfigLayers<'a> {
+    pub topic: Option<&'a BTreeMap<String, String>>,
+    pub broker: Option<&'a BTreeMap<String, String>>,
+    pub cluster: Option<&'a BTreeMap<String, String>>,
+}
+
+impl<'a> ConfigLayers<'a> {
+    /// The levels of the topic configs of a topic whose own configs are
+    /// `topic`.
+    pub fn topic(
+        image: &'a MetadataImage,
+        topic: Option<&'a BTreeMap<String, String>>,
+        node_id: i32,
+    ) -> Self {
+        Self {
+            topic,
+            ..Self::broker(image, &node_id.to_string())
+        }
+    }
+
+    /// The levels of the configs of `broker`, either a node id or
+    /// [`CLUSTER_DEFAULT`].
+    pub fn broker(image: &'a MetadataImage, broker: &str) -> Self {
+        Self {
+            topic: None,
+            broker: (broker != CLUSTER_DEFAULT)
+                .then(|| image.configs(BROKER_RESOURCE, broker))
+                .flatten(),
+            cluster: image.configs(BROKER_RESOURCE, CLUSTER_DEFAULT),
+        }
+    }
+
+    /// The values `def` takes at each level it is set at, ending with its
+    /// default. The first one is in effect.
+    pub fn synonyms(&self, def: &ConfigDef) -> Vec<Synonym> {
+        let levels = [
+            (self.topic, def.name, TOPIC_CONFIG),
+            (self.broker, def.broker_name, DYNAMIC_BROKER_CONFIG),
+            (self.cluster, def.broker_name, DYNAMIC_DEFAULT_BROKER_CONFIG),
+        ];
+
+        let mut synonyms: Vec<Synonym> = levels
+            .into_iter()
+            .filter_map(|(configs, name, source)| {
+                Some(Synonym {
+                    name,
+                    value: configs?.get(name)?.clone(),
+                    source,
+                })
+            })
+            .collect();
+        synonyms.push(Synonym {
+            name: def.broker_name,
+            value: def.default.to_string(),
+            source: DEFAULT_CONFIG,
+        });
+
+        synonyms
+    }
+
+    pub fn value(&self, def: &ConfigDef) -> String {
+        self.synonyms(def).swap_remove(0).value
+    }
+
+    fn parse<T: FromStr>(&self, name: &str) -> T {
+        let def = find(TOPIC_RESOURCE, name).expect("a known config");
+        // Values are validated before they are stored.
+        self.value(def)
+            .trim()
+            .parse()
+            .or_else(|_| def.default.parse())
+            .unwrap_or_else(|_| panic!("invalid default for {name}"))
+    }
+
+    pub fn log_config(&self) -> LogConfig {
+        LogConfig {
+            segment_bytes: self.parse::<i32>("segment.bytes") as u64,
+            segment_ms: self.parse("segment.ms"),
+            retention_bytes: self.parse("retention.bytes"),
+            retention_ms: self.parse("retention.ms"),
+            max_message_bytes: self.parse::<i32>("max.message.bytes").max(0) as u64,
+            index_interval_bytes: self.parse::<i32>("index.interval.bytes").max(0) as u64,
+            delete: list(&self.value(find(TOPIC_RESOURCE, "cleanup.policy").unwrap()))
+                .any(|policy| policy == "delete"),
+        }
+    }
+}
+
+/// The topic configs the storage layer acts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    pub segment_bytes: u64,
+    pub segment_ms: i64,
+    /// -1 for no limit.
+    pub retention_bytes: i64,
+    /// -1 for no limit.
+    pub retention_ms: i64,
+    pub max_message_bytes: u64,
+    pub index_interval_bytes: u64,
+    /// Whether old segments are deleted.
+    pub delete: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        ConfigLayers::default().log_config()
+    }
+}
+
+/// The log configs of every topic, and of topics without any of their own.
+#[derive(Debug, Default, Clone)]
+pub struct LogConfigs {
+    pub default: LogConfig,
+    pub topics: HashMap<String, LogConfig>,
+}
+
+impl LogConfigs {
+    pub fn from_image(image: &MetadataImage, node_id: i32) -> Self {
+        Self {
+            default: ConfigLayers::topic(image, None, node_id).log_config(),
+            topics: image
+                .topics()
+                .map(|topic| {
+                    let topic_configs = image.configs(TOPIC_RESOURCE, &topic.name);
+                    let layers = ConfigLayers::topic(image, topic_configs, node_id);
+                    (topic.name.clone(), layers.log_config())
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, topic: &str) -> &LogConfig {
+        self.topics.get(topic).unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_and_validation() {
+        let topic = BTreeMap::from([("retention.ms".to_string(), "1000".to_string())]);
+        let cluster = BTreeMap::from([
+            ("log.retention.ms".to_string(), "2000".to_string()),
+            ("log.segment.bytes".to_string(), "4096".to_string()),
+        ]);
+        let layers = ConfigLayers {
+            topic: Some(&topic),
+            broker: None,
+            cluster: Some(&cluster),
+        };
+
+        let retention = find(TOPIC_RESOURCE, "retention.ms").unwrap();
+        let sources: Vec<i8> = layers
+            .synonyms(retention)
+            .iter()
+            .map(|s| s.source)
+            .collect();
+        assert_eq!(
+            sources,
+            vec![TOPIC_CONFIG, DYNAMIC_DEFAULT_BROKER_CONFIG, DEFAULT_CONFIG]
+        );
+
+        let config = layers.log_config();
+        assert_eq!(config.retention_ms, 1000);
+        assert_eq!(config.segment_bytes, 4096);
+        assert!(config.delete);
+        assert_eq!(LogConfig::default().index_interval_bytes, 4096);
+
+        let policy = find(BROKER_RESOURCE, "log.cleanup.policy").unwrap();
+        assert!(policy.validate(TOPIC_RESOURCE, "compact, delete").is_ok());
+        assert!(policy.validate(TOPIC_RESOURCE, "delete,archive").is_err());
+        assert!(retention.validate(TOPIC_RESOURCE, "-2").is_err());
+        assert!(find(TOPIC_RESOURCE, "segment.bytes")
+            .unwrap()
+            .validate(TOPIC_RESOURCE, "3000000000")
+            .is_err());
+    }
+}
@@ -1,53 +1,78 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
-    types::{array::CVec, bytes::ByteBuf, cstring::CString, uuid::UUID},
-    Decode, Encode, Size,
+    frame::Frame,
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        checked_count,
+        cstring::CompactString,
+        decode_unsigned_varint,
+        kafkastring::String16,
+        length_prefix,
+        records::CompactRecords,
+        unsigned_varint_bytes_wide,
+        uuid::UUID,
+        uvarint::UVarint,
+    },
+    Decode, Encode, EncodeFrame, Size,
 };
 use anyhow::Error;
-use encode_derive::{Decode, Size};
+use bytes::Bytes;
+use encode_derive::{Decode, EncodeFrame, Size};
 
 use super::{
-    log::{get_topic_records_from_disk, get_topics, TopicRecordBatch},
-    BaseRequestV2, BaseResponse, BaseResponseV1,
+    acl::{operation, resource},
+    errors::{
+        OFFSET_OUT_OF_RANGE, TOPIC_AUTHORIZATION_FAILED, UNKNOWN_TOPIC_ID,
+        UNKNOWN_TOPIC_OR_PARTITION,
+    },
+    fetch_session::FINAL_EPOCH,
+    log::{get_topic_records_from_disk, partition::LogManager, txn_index::collect_aborted},
+    quota::Throttle,
+    RequestContext,
 };
 
-#[derive(Debug, Encode, Decode, Size)]
+/// The `isolation_level` of consumers that only read committed records.
+pub const READ_COMMITTED: i8 = 1;
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
 pub struct FetchPartitionsRequest {
     pub partition: i32,
     pub current_leader_epoch: i32,
     pub fetch_offset: i64,
+    pub last_fetched_epoch: i32,
     pub log_start_offset: i64,
-    pub pub_partition_max_bytes: i32,
+    pub partition_max_bytes: i32,
     pub tagged_field: u8,
 }
 
-#[derive(Debug, Encode, Decode, Size)]
+#[derive(Debug, Clone, Encode, Decode, Size)]
 pub struct TopicFetch {
     pub topic_id: UUID,
-    pub partitions: CVec<FetchPartitionsRequest>,
+    pub partitions: CompactArray<FetchPartitionsRequest>,
     pub tagged_field: u8,
 }
 
 #[derive(Debug, Encode, Decode, Size)]
 pub struct ForgottenTopicsData {
     pub topic_id: UUID,
-    pub partitions: i32,
+    pub partitions: CompactArray<i32>,
     pub tagged_field: u8,
 }
 
+/// A Fetch request body as sent from v15 on; earlier versions also carry a
+/// replica id, see [`FetchRequest::decode_version`].
 #[derive(Debug, Encode, Decode, Size)]
 pub struct FetchRequest {
-    pub basev2: BaseRequestV2,
     pub max_wait_ms: i32,
     pub min_bytes: i32,
     pub max_bytes: i32,
     pub isolation_level: i8,
     pub session_id: i32,
     pub session_epoch: i32,
-    pub topics: CVec<TopicFetch>,
-    pub forgotten_topics_data: CVec<ForgottenTopicsData>,
-    pub rack_id: CString,
+    pub topics: CompactArray<TopicFetch>,
+    pub forgotten_topics_data: CompactArray<ForgottenTopicsData>,
+    pub rack_id: CompactString,
     pub tagged_field: u8,
 }
 
@@ -58,10 +83,10 @@ pub struct AbortedTransactions {
     pub tagged_field: u8,
 }
 
-#[derive(Debug, Encode, Decode, Size)]
+#[derive(Debug, EncodeFrame, Decode, Size)]
 pub struct FetchTopicResponse {
     pub topic_id: UUID,
-    pub partitions: CVec<FetchPartitionsResponse>,
+    pub partitions: CompactArray<FetchPartitionsResponse>,
     pub tagged_field: u8,
 }
 
@@ -69,37 +94,70 @@ impl FetchTopicResponse {
     pub fn unknown_topic(topic_id: UUID) -> Self {
         Self {
             topic_id,
-            partitions: CVec {
-                data: vec![FetchPartitionsResponse::unknown_topic()],
-            },
+            partitions: CompactArray(vec![FetchPartitionsResponse::unknown_topic()]),
+            tagged_field: 0,
+        }
+    }
+
+    /// A topic the client may not read.
+    pub fn unauthorized(topic_id: UUID, partitions: &[FetchPartitionsRequest]) -> Self {
+        Self::failed(topic_id, partitions, TOPIC_AUTHORIZATION_FAILED)
+    }
+
+    /// Answers every requested partition with `error_code`.
+    pub fn failed(topic_id: UUID, partitions: &[FetchPartitionsRequest], error_code: i16) -> Self {
+        let partitions = partitions
+            .iter()
+            .map(|partition| FetchPartitionsResponse {
+                partition_idx: partition.partition,
+                error_code,
+                high_watermark: -1,
+                last_stable_offset: -1,
+                log_start_offset: -1,
+                ..FetchPartitionsResponse::unknown_topic()
+            })
+            .collect();
+        Self {
+            topic_id,
+            partitions: CompactArray(partitions),
             tagged_field: 0,
         }
     }
+
     pub async fn known_topic(
         topic_id: UUID,
-        idx: i64,
         topic_name: &str,
-        partition: i32,
+        partitions: &[FetchPartitionsRequest],
+        isolation_level: i8,
+        logs: &LogManager,
     ) -> Result<Self, Error> {
-        let data = FetchPartitionsResponse::known_topic(topic_name, idx, partition).await?;
+        let mut data = Vec::with_capacity(partitions.len());
+        for partition in partitions {
+            data.push(
+                FetchPartitionsResponse::known_topic(topic_name, partition, isolation_level, logs)
+                    .await?,
+            );
+        }
         Ok(Self {
             topic_id,
-            partitions: CVec { data: vec![data] },
+            partitions: CompactArray(data),
             tagged_field: 0,
         })
     }
 }
 
-#[derive(Debug, Encode, Decode, Size)]
+#[derive(Debug, EncodeFrame, Decode, Size)]
 pub struct FetchPartitionsResponse {
     pub partition_idx: i32,
     pub error_code: i16,
     pub high_watermark: i64,
     pub last_stable_offset: i64,
     pub log_start_offset: i64,
-    pub aborted_transactions: CVec<FetchPartitionsResponse>,
+    /// The aborted transactions with records in a read_committed fetch;
+    /// null for read_uncommitted.
+    pub aborted_transactions: CompactNullableArray<AbortedTransactions>,
     pub preferred_read_replica: i32,
-    pub records: ByteBuf,
+    pub records: CompactRecords,
     pub tagged_field: u8,
 }
 
@@ -107,90 +165,160 @@ impl FetchPartitionsResponse {
     pub fn unknown_topic() -> Self {
         Self {
             partition_idx: 0,
-            error_code: 100,
+            error_code: UNKNOWN_TOPIC_ID,
             high_watermark: 0,
             last_stable_offset: 0,
             log_start_offset: 0,
-            aborted_transactions: CVec { data: vec![] },
+            aborted_transactions: CompactNullableArray(None),
             preferred_read_replica: 0,
-            records: ByteBuf::empty(),
+            records: CompactRecords::empty(),
             tagged_field: 0,
         }
     }
-    pub async fn known_topic(name: &str, idx: i64, partition: i32) -> Result<Self, Error> {
-        let data = get_topic_records_from_disk(name, partition, idx).await?;
+    /// Reads a partition of a known topic. Offsets outside the log, such as
+    /// those of records deleted by DeleteRecords, are out of range.
+    /// read_committed fetches stop at the last stable offset and list the
+    /// aborted transactions they may return records of.
+    pub async fn known_topic(
+        name: &str,
+        request: &FetchPartitionsRequest,
+        isolation_level: i8,
+        logs: &LogManager,
+    ) -> Result<Self, Error> {
+        let partition = request.partition;
+        let Some(offsets) = logs.offsets(name, partition)? else {
+            return Ok(Self {
+                partition_idx: partition,
+                error_code: UNKNOWN_TOPIC_OR_PARTITION,
+                high_watermark: -1,
+                last_stable_offset: -1,
+                log_start_offset: -1,
+                ..Self::unknown_topic()
+            });
+        };
+
+        let read_committed = isolation_level == READ_COMMITTED;
+        let max_offset = if read_committed {
+            offsets.last_stable_offset
+        } else {
+            offsets.log_end_offset
+        };
+
+        let fetch_offset = request.fetch_offset;
+        let mut aborted_transactions = None;
+        let (error_code, records) =
+            if fetch_offset < offsets.log_start_offset || fetch_offset > offsets.log_end_offset {
+                (OFFSET_OUT_OF_RANGE, CompactRecords::empty())
+            } else {
+                let dir = logs.partition_dir(name, partition);
+                if read_committed {
+                    let dir = dir.clone();
+                    let aborted = tokio::task::spawn_blocking(move || {
+                        collect_aborted(&dir, fetch_offset, max_offset)
+                    })
+                    .await??;
+                    aborted_transactions = Some(
+                        aborted
+                            .into_iter()
+                            .map(|txn| AbortedTransactions {
+                                producer_id: txn.producer_id,
+                                first_offset: txn.first_offset,
+                                tagged_field: 0,
+                            })
+                            .collect(),
+                    );
+                }
+                let records = get_topic_records_from_disk(
+                    dir,
+                    fetch_offset,
+                    max_offset,
+                    request.partition_max_bytes,
+                )
+                .await?;
+                (0, records)
+            };
+
         Ok(Self {
-            partition_idx: 0,
-            error_code: 0,
-            high_watermark: 0,
-            last_stable_offset: 0,
-            log_start_offset: 0,
-            aborted_transactions: CVec { data: vec![] },
+            partition_idx: partition,
+            error_code,
+            high_watermark: offsets.log_end_offset,
+            last_stable_offset: offsets.last_stable_offset,
+            log_start_offset: offsets.log_start_offset,
+            aborted_transactions: CompactNullableArray(aborted_transactions),
             preferred_read_replica: -1,
-            records: data,
+            records,
             tagged_field: 0,
         })
     }
 }
 
-#[derive(Debug, Encode, Decode, Size)]
+#[derive(Debug, EncodeFrame, Decode, Size)]
 pub struct FetchResponse {
-    pub basev1: BaseResponseV1,
     pub throttle_time: i32,
     pub error_code: i16,
     pub session_id: i32,
-    pub responses: CVec<FetchTopicResponse>,
+    pub responses: CompactArray<FetchTopicResponse>,
     pub tagged_field: u8,
 }
 
+impl Throttle for FetchResponse {
+    fn set_throttle_time_ms(&mut self, throttle_time_ms: i32) {
+        self.throttle_time = throttle_time_ms;
+    }
+
+    fn hold_back(&mut self) {
+        self.responses = CompactArray(vec![]);
+    }
+}
+
 impl FetchResponse {
+    /// Reads the requested partitions of the topics in `names`, which maps
+    /// topic ids to names. Topics in `unauthorized` are not read.
     pub async fn get_topics(
-        correlation_id: i32,
         session_id: i32,
-        topics: &Vec<TopicFetch>,
+        topics: &[TopicFetch],
+        isolation_level: i8,
+        names: &HashMap<UUID, String>,
+        unauthorized: &HashSet<UUID>,
+        logs: &LogManager,
     ) -> Result<Self, Error> {
-        let base = BaseResponse::new_base(correlation_id);
         let tag_buffer = 0;
-        let basev1 = BaseResponseV1 { base, tag_buffer };
         if topics.is_empty() {
             Ok(FetchResponse {
-                basev1,
                 throttle_time: 0,
                 error_code: 0,
                 session_id,
-                responses: CVec { data: vec![] },
+                responses: CompactArray(vec![]),
                 tagged_field: tag_buffer,
             })
         } else {
             let mut ts: Vec<FetchTopicResponse> = vec![];
-            let topics_from_disk = get_topics().await?;
-            let mut topics_by_uuid: HashMap<_, _> = HashMap::new();
-            for (name, value) in &topics_from_disk {
-                topics_by_uuid.insert(value.id.clone(), name.clone());
-            }
             for topic in topics {
-                if let Some(topic_name) = topics_by_uuid.get(&topic.topic_id) {
-                    for partition in &topic.partitions.data {
-                        ts.push(
-                            FetchTopicResponse::known_topic(
-                                topic.topic_id.clone(),
-                                partition.fetch_offset,
-                                topic_name,
-                                partition.partition,
-                            )
-                            .await?,
-                        );
-                    }
+                if unauthorized.contains(&topic.topic_id) {
+                    ts.push(FetchTopicResponse::unauthorized(
+                        topic.topic_id.clone(),
+                        &topic.partitions,
+                    ));
+                } else if let Some(topic_name) = names.get(&topic.topic_id) {
+                    ts.push(
+                        FetchTopicResponse::known_topic(
+                            topic.topic_id.clone(),
+                            topic_name,
+                            &topic.partitions,
+                            isolation_level,
+                            logs,
+                        )
+                        .await?,
+                    );
                 } else {
                     ts.push(FetchTopicResponse::unknown_topic(topic.topic_id.clone()));
                 }
             }
             Ok(FetchResponse {
-                basev1,
                 throttle_time: 0,
                 error_code: 0,
                 session_id,
-                responses: CVec { data: ts },
+                responses: CompactArray(ts),
                 tagged_field: tag_buffer,
             })
         }
@@ -198,18 +326,822 @@ impl FetchResponse {
 }
 
 impl FetchRequest {
-    pub async fn handle_request(&self) -> Result<FetchResponse, Error> {
+    /// Decodes a v13+ body, skipping the replica id that v15 moved out of it.
+    /// Earlier bodies name their topics, see [`LegacyFetchRequest`].
+    pub fn decode_version(bytes: &Bytes, offset: &mut usize, version: i16) -> Self {
+        if version < 15 {
+            i32::decode(bytes, offset);
+        }
+
+        Self::decode(bytes, offset)
+    }
+
+    /// Reads the partitions of the fetch's session, or of the request
+    /// alone outside a session.
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<FetchResponse, Error> {
+        let fetch = match ctx.broker.fetch_sessions.begin(self) {
+            Ok(fetch) => fetch,
+            Err(error_code) => {
+                return Ok(FetchResponse {
+                    throttle_time: 0,
+                    error_code,
+                    session_id: 0,
+                    responses: CompactArray(vec![]),
+                    tagged_field: 0,
+                })
+            }
+        };
+
+        let mut names = HashMap::new();
+        let mut unauthorized = HashSet::new();
+        ctx.broker.metadata.read(|image| {
+            for topic in &fetch.topics {
+                let Some(known) = image.topic_by_id(&topic.topic_id) else {
+                    continue;
+                };
+                if ctx.authorized(image, operation::READ, resource::TOPIC, &known.name) {
+                    names.insert(known.id.clone(), known.name.clone());
+                } else {
+                    unauthorized.insert(known.id.clone());
+                }
+            }
+        });
+
         let mut response = FetchResponse::get_topics(
-            self.basev2.correlation_id,
-            self.session_id,
-            &self.topics.data,
+            fetch.session_id,
+            &fetch.topics,
+            self.isolation_level,
+            &names,
+            &unauthorized,
+            &ctx.broker.logs,
         )
         .await?;
+        ctx.broker
+            .fetch_sessions
+            .complete(&fetch, &mut response.responses.0);
+        Ok(response)
+    }
+}
 
-        let res_size = response.size_in_bytes() - 4;
+/// Decodes an array length: a compact varint from v12 on, an `INT32` before.
+fn decode_count(bytes: &Bytes, offset: &mut usize, flexible: bool) -> usize {
+    let count = if flexible {
+        decode_unsigned_varint(bytes, offset).0.saturating_sub(1) as usize
+    } else {
+        i32::decode(bytes, offset).max(0) as usize
+    };
+    checked_count(bytes, offset, count)
+}
 
-        response.basev1.base.size = res_size as i32;
+fn decode_name(bytes: &Bytes, offset: &mut usize, flexible: bool) -> String {
+    if flexible {
+        CompactString::decode(bytes, offset).0
+    } else {
+        String16::decode(bytes, offset).0
+    }
+}
 
-        Ok(response)
+fn put_count(frame: &mut Frame, len: usize, flexible: bool) {
+    if flexible {
+        frame.put(&UVarint(len as u64 + 1));
+    } else {
+        frame.put(&length_prefix::<i32>(len));
+    }
+}
+
+fn count_size(len: usize, flexible: bool) -> usize {
+    if flexible {
+        unsigned_varint_bytes_wide(len + 1)
+    } else {
+        4
+    }
+}
+
+fn put_name(frame: &mut Frame, name: &str, flexible: bool) {
+    if flexible {
+        frame.put(&CompactString(name.to_string()));
+    } else {
+        frame.put(&String16(name.to_string()));
+    }
+}
+
+fn name_size(name: &str, flexible: bool) -> usize {
+    if flexible {
+        unsigned_varint_bytes_wide(name.len() + 1) + name.len()
+    } else {
+        2 + name.len()
+    }
+}
+
+/// A Fetch request from v4 to v12, which names its topics rather than
+/// giving their ids. v5 adds the log start offset, v7 fetch sessions, v9 the
+/// leader epoch, v11 the rack id and v12 the flexible encoding.
+#[derive(Debug)]
+pub struct LegacyFetchRequest {
+    pub version: i16,
+    pub max_wait_ms: i32,
+    pub min_bytes: i32,
+    pub max_bytes: i32,
+    pub isolation_level: i8,
+    pub session_id: i32,
+    pub session_epoch: i32,
+    pub topics: Vec<(String, Vec<FetchPartitionsRequest>)>,
+    pub forgotten_topics_data: Vec<(String, Vec<i32>)>,
+    pub rack_id: String,
+}
+
+impl LegacyFetchRequest {
+    /// Decodes a v4 to v12 body. Fields a version lacks take the value that
+    /// means "not given", and fetches before v7 are outside any session.
+    pub fn decode_version(bytes: &Bytes, offset: &mut usize, version: i16) -> Self {
+        let flexible = version >= 12;
+
+        // The replica id: only other brokers send one.
+        i32::decode(bytes, offset);
+        let max_wait_ms = i32::decode(bytes, offset);
+        let min_bytes = i32::decode(bytes, offset);
+        let max_bytes = i32::decode(bytes, offset);
+        let isolation_level = i8::decode(bytes, offset);
+        let (session_id, session_epoch) = if version >= 7 {
+            (i32::decode(bytes, offset), i32::decode(bytes, offset))
+        } else {
+            (0, FINAL_EPOCH)
+        };
+
+        let topics = (0..decode_count(bytes, offset, flexible))
+            .map(|_| {
+                let name = decode_name(bytes, offset, flexible);
+                let partitions = (0..decode_count(bytes, offset, flexible))
+                    .map(|_| {
+                        let partition = i32::decode(bytes, offset);
+                        let current_leader_epoch = if version >= 9 {
+                            i32::decode(bytes, offset)
+                        } else {
+                            -1
+                        };
+                        let fetch_offset = i64::decode(bytes, offset);
+                        let last_fetched_epoch = if flexible {
+                            i32::decode(bytes, offset)
+                        } else {
+                            -1
+                        };
+                        let log_start_offset = if version >= 5 {
+                            i64::decode(bytes, offset)
+                        } else {
+                            -1
+                        };
+                        let partition_max_bytes = i32::decode(bytes, offset);
+                        if flexible {
+                            u8::decode(bytes, offset);
+                        }
+                        FetchPartitionsRequest {
+                            partition,
+                            current_leader_epoch,
+                            fetch_offset,
+                            last_fetched_epoch,
+                            log_start_offset,
+                            partition_max_bytes,
+                            tagged_field: 0,
+                        }
+                    })
+                    .collect();
+                if flexible {
+                    u8::decode(bytes, offset);
+                }
+                (name, partitions)
+            })
+            .collect();
+
+        let mut forgotten_topics_data = vec![];
+        if version >= 7 {
+            for _ in 0..decode_count(bytes, offset, flexible) {
+                let name = decode_name(bytes, offset, flexible);
+                let partitions = (0..decode_count(bytes, offset, flexible))
+                    .map(|_| i32::decode(bytes, offset))
+                    .collect();
+                if flexible {
+                    u8::decode(bytes, offset);
+                }
+                forgotten_topics_data.push((name, partitions));
+            }
+        }
+
+        let rack_id = if version >= 11 {
+            decode_name(bytes, offset, flexible)
+        } else {
+            String::new()
+        };
+        if flexible {
+            u8::decode(bytes, offset);
+        }
+
+        Self {
+            version,
+            max_wait_ms,
+            min_bytes,
+            max_bytes,
+            isolation_level,
+            session_id,
+            session_epoch,
+            topics,
+            forgotten_topics_data,
+            rack_id,
+        }
+    }
+
+    /// Looks the named topics up and serves the fetch as a [`FetchRequest`]
+    /// of their ids, answering by name. Topics that don't exist are reported
+    /// as such only to clients that may read them.
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<LegacyFetchResponse, Error> {
+        let mut ids = HashMap::new();
+        let mut missing = vec![];
+        ctx.broker.metadata.read(|image| {
+            for (name, partitions) in &self.topics {
+                if let Some(topic) = image.topic(name) {
+                    ids.insert(name.clone(), topic.id.clone());
+                } else if ctx.authorized(image, operation::READ, resource::TOPIC, name) {
+                    missing.push((name, partitions, UNKNOWN_TOPIC_OR_PARTITION));
+                } else {
+                    missing.push((name, partitions, TOPIC_AUTHORIZATION_FAILED));
+                }
+            }
+            for (name, _) in &self.forgotten_topics_data {
+                if let Some(topic) = image.topic(name) {
+                    ids.insert(name.clone(), topic.id.clone());
+                }
+            }
+        });
+
+        let request = FetchRequest {
+            max_wait_ms: self.max_wait_ms,
+            min_bytes: self.min_bytes,
+            max_bytes: self.max_bytes,
+            isolation_level: self.isolation_level,
+            session_id: self.session_id,
+            session_epoch: self.session_epoch,
+            topics: CompactArray(
+                self.topics
+                    .iter()
+                    .filter_map(|(name, partitions)| {
+                        Some(TopicFetch {
+                            topic_id: ids.get(name)?.clone(),
+                            partitions: CompactArray(partitions.clone()),
+                            tagged_field: 0,
+                        })
+                    })
+                    .collect(),
+            ),
+            forgotten_topics_data: CompactArray(
+                self.forgotten_topics_data
+                    .iter()
+                    .filter_map(|(name, partitions)| {
+                        Some(ForgottenTopicsData {
+                            topic_id: ids.get(name)?.clone(),
+                            partitions: CompactArray(partitions.clone()),
+                            tagged_field: 0,
+                        })
+                    })
+                    .collect(),
+            ),
+            rack_id: CompactString(self.rack_id.clone()),
+            tagged_field: 0,
+        };
+        let response = request.handle_request(ctx).await?;
+
+        // Session partitions may belong to topics named in earlier requests.
+        // Those deleted since can't be named, so they are left out.
+        let mut names: HashMap<UUID, String> =
+            ids.into_iter().map(|(name, id)| (id, name)).collect();
+        ctx.broker.metadata.read(|image| {
+            for topic in response.responses.iter() {
+                if let Some(known) = image.topic_by_id(&topic.topic_id) {
+                    names
+                        .entry(known.id.clone())
+                        .or_insert_with(|| known.name.clone());
+                }
+            }
+        });
+
+        let mut responses: Vec<_> = response
+            .responses
+            .0
+            .into_iter()
+            .filter_map(|topic| Some((names.get(&topic.topic_id)?.clone(), topic.partitions.0)))
+            .collect();
+        for (name, partitions, error_code) in missing {
+            let topic = FetchTopicResponse::failed(UUID([0; 16]), partitions, error_code);
+            responses.push((name.clone(), topic.partitions.0));
+        }
+
+        Ok(LegacyFetchResponse {
+            version: self.version,
+            throttle_time: response.throttle_time,
+            error_code: response.error_code,
+            session_id: response.session_id,
+            responses,
+        })
+    }
+}
+
+/// A Fetch response from v4 to v12, laid out for `version` like
+/// [`LegacyFetchRequest`]. Records are `NULLABLE_BYTES` before v12.
+#[derive(Debug)]
+pub struct LegacyFetchResponse {
+    pub version: i16,
+    pub throttle_time: i32,
+    pub error_code: i16,
+    pub session_id: i32,
+    pub responses: Vec<(String, Vec<FetchPartitionsResponse>)>,
+}
+
+impl Throttle for LegacyFetchResponse {
+    fn set_throttle_time_ms(&mut self, throttle_time_ms: i32) {
+        self.throttle_time = throttle_time_ms;
+    }
+
+    fn hold_back(&mut self) {
+        self.responses.clear();
+    }
+}
+
+impl EncodeFrame for LegacyFetchResponse {
+    fn encode_frame(&self, frame: &mut Frame) {
+        let flexible = self.version >= 12;
+
+        frame.put(&self.throttle_time);
+        if self.version >= 7 {
+            frame.put(&self.error_code);
+            frame.put(&self.session_id);
+        }
+
+        put_count(frame, self.responses.len(), flexible);
+        for (name, partitions) in &self.responses {
+            put_name(frame, name, flexible);
+            put_count(frame, partitions.len(), flexible);
+            for partition in partitions {
+                frame.put(&partition.partition_idx);
+                frame.put(&partition.error_code);
+                frame.put(&partition.high_watermark);
+                frame.put(&partition.last_stable_offset);
+                if self.version >= 5 {
+                    frame.put(&partition.log_start_offset);
+                }
+
+                match &partition.aborted_transactions.0 {
+                    Some(aborted) => {
+                        put_count(frame, aborted.len(), flexible);
+                        for txn in aborted {
+                            frame.put(&txn.producer_id);
+                            frame.put(&txn.first_offset);
+                            if flexible {
+                                frame.put(&0u8);
+                            }
+                        }
+                    }
+                    None if flexible => frame.put(&UVarint(0)),
+                    None => frame.put(&-1i32),
+                }
+
+                if self.version >= 11 {
+                    frame.put(&partition.preferred_read_replica);
+                }
+
+                match &partition.records {
+                    records if flexible => records.encode_frame(frame),
+                    CompactRecords::Null => frame.put(&-1i32),
+                    CompactRecords::Memory(bytes) => {
+                        frame.put(&length_prefix::<i32>(bytes.len()));
+                        frame.put_bytes(bytes.clone());
+                    }
+                    CompactRecords::File(region) => {
+                        frame.put(&length_prefix::<i32>(region.len as usize));
+                        frame.put_region(region.clone());
+                    }
+                }
+
+                if flexible {
+                    frame.put(&0u8);
+                }
+            }
+            if flexible {
+                frame.put(&0u8);
+            }
+        }
+        if flexible {
+            frame.put(&0u8);
+        }
+    }
+}
+
+impl Size for LegacyFetchResponse {
+    fn size_in_bytes(&self) -> usize {
+        let flexible = self.version >= 12;
+        let tagged_fields = if flexible { 1 } else { 0 };
+
+        let mut size = 4 + if self.version >= 7 { 6 } else { 0 };
+        size += count_size(self.responses.len(), flexible);
+        for (name, partitions) in &self.responses {
+            size += name_size(name, flexible) + count_size(partitions.len(), flexible);
+            for partition in partitions {
+                size += 4 + 2 + 8 + 8;
+                if self.version >= 5 {
+                    size += 8;
+                }
+                size += match &partition.aborted_transactions.0 {
+                    Some(aborted) => {
+                        count_size(aborted.len(), flexible) + aborted.len() * (16 + tagged_fields)
+                    }
+                    None if flexible => 1,
+                    None => 4,
+                };
+                if self.version >= 11 {
+                    size += 4;
+                }
+                size += if flexible {
+                    partition.records.size_in_bytes()
+                } else {
+                    4 + partition.records.len()
+                };
+                size += tagged_fields;
+            }
+            size += tagged_fields;
+        }
+        size + tagged_fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{frame::Frame, EncodeFrame};
+
+    #[test]
+    fn test_fetch_response_encodes_to_exact_size() {
+        let records = Bytes::from(vec![7u8; 300]);
+
+        let response = FetchResponse {
+            throttle_time: 0,
+            error_code: 0,
+            session_id: 0,
+            responses: CompactArray(vec![FetchTopicResponse {
+                topic_id: UUID([1; 16]),
+                partitions: CompactArray(vec![FetchPartitionsResponse {
+                    records: CompactRecords::Memory(records.clone()),
+                    ..FetchPartitionsResponse::unknown_topic()
+                }]),
+                tagged_field: 0,
+            }]),
+            tagged_field: 0,
+        };
+
+        let mut frame = Frame::default();
+        response.encode_frame(&mut frame);
+        let encoded = frame.into_bytes().unwrap();
+
+        assert_eq!(encoded.len(), response.size_in_bytes());
+        assert!(encoded.ends_with(&[&records[..], &[0, 0, 0]].concat()));
+
+        let decoded = FetchResponse::decode(&encoded, &mut 0);
+        let partition = &decoded.responses[0].partitions[0];
+        assert!(matches!(&partition.records, CompactRecords::Memory(r) if *r == records));
+    }
+
+    #[tokio::test]
+    async fn test_encode_frame_splices_file_records() {
+        use std::{io::Write, sync::Arc};
+        use tokio::{
+            io::AsyncReadExt,
+            net::{TcpListener, TcpStream},
+        };
+
+        let path = std::env::temp_dir().join(format!("fetch-frame-{}.log", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(&[1u8; 64]).unwrap();
+        file.write_all(&[9u8; 4096]).unwrap();
+
+        let region = crate::frame::FileRegion {
+            file: Arc::new(std::fs::File::open(&path).unwrap()),
+            position: 64,
+            len: 4096,
+        };
+
+        let mut partition = FetchPartitionsResponse::unknown_topic();
+        partition.records = CompactRecords::File(region);
+
+        let response = FetchResponse {
+            throttle_time: 0,
+            error_code: 0,
+            session_id: 0,
+            responses: CompactArray(vec![FetchTopicResponse {
+                topic_id: UUID([2; 16]),
+                partitions: CompactArray(vec![partition]),
+                tagged_field: 0,
+            }]),
+            tagged_field: 0,
+        };
+
+        let mut body = Frame::default();
+        response.encode_frame(&mut body);
+        let body = body.into_bytes().unwrap();
+        assert!(body.ends_with(&[&[9u8; 4096][..], &[0, 0, 0]].concat()));
+
+        let mut expected = ((body.len() + 5) as i32).to_be_bytes().to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 3, 0]);
+        expected.extend_from_slice(&body);
+
+        let mut frame = crate::response_frame(3, 1, response.size_in_bytes());
+        response.encode_frame(&mut frame);
+        assert_eq!(frame.len(), expected.len());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let writer = tokio::spawn(async move {
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            frame.write_to(&mut socket).await.unwrap();
+        });
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut received = vec![0; expected.len()];
+        socket.read_exact(&mut received).await.unwrap();
+        writer.await.unwrap();
+
+        assert_eq!(received, expected);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn fetch(
+        topic_id: &UUID,
+        session_id: i32,
+        session_epoch: i32,
+        partitions: &[(i32, i64)],
+    ) -> FetchRequest {
+        FetchRequest {
+            max_wait_ms: 0,
+            min_bytes: 0,
+            max_bytes: i32::MAX,
+            isolation_level: 0,
+            session_id,
+            session_epoch,
+            topics: CompactArray(vec![TopicFetch {
+                topic_id: topic_id.clone(),
+                partitions: CompactArray(
+                    partitions
+                        .iter()
+                        .map(|&(partition, fetch_offset)| FetchPartitionsRequest {
+                            partition,
+                            current_leader_epoch: -1,
+                            fetch_offset,
+                            last_fetched_epoch: -1,
+                            log_start_offset: -1,
+                            partition_max_bytes: 1024 * 1024,
+                            tagged_field: 0,
+                        })
+                        .collect(),
+                ),
+                tagged_field: 0,
+            }]),
+            forgotten_topics_data: CompactArray(vec![]),
+            rack_id: CompactString(String::new()),
+            tagged_field: 0,
+        }
+    }
+
+    fn answered(response: &FetchResponse) -> Vec<(i32, i64, bool)> {
+        response
+            .responses
+            .iter()
+            .flat_map(|topic| topic.partitions.iter())
+            .map(|p| (p.partition_idx, p.high_watermark, p.records.is_empty()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_only_session_fetches_leave_out_unchanged_partitions() {
+        use crate::kafka::{
+            broker::tests::TempBroker,
+            fetch_session::{FINAL_EPOCH, INITIAL_EPOCH},
+            log::{TopicRecordBatch, TopicRecordDisk},
+        };
+
+        let test = TempBroker::open("fetch-session-test");
+        let topic_id = test.create_topic("topic", 2);
+        let record = TopicRecordDisk::new(0, 0, None, Some(Bytes::from_static(b"value")));
+        let mut batch = TopicRecordBatch::new(0, vec![record]);
+        let log = test.broker.logs.partition("topic", 0).unwrap();
+        log.lock().unwrap().append(&mut batch).unwrap();
+        test.broker.logs.partition("topic", 1).unwrap();
+        let ctx = test.context(1, 16);
+
+        // Outside a session every partition is answered, every time.
+        for _ in 0..2 {
+            let request = fetch(&topic_id, 0, FINAL_EPOCH, &[(0, 1), (1, 0)]);
+            let response = request.handle_request(&ctx).await.unwrap();
+            assert_eq!(response.session_id, 0);
+            assert_eq!(answered(&response), [(0, 1, true), (1, 0, true)]);
+        }
+
+        let request = fetch(&topic_id, 0, INITIAL_EPOCH, &[(0, 0), (1, 0)]);
+        let response = request.handle_request(&ctx).await.unwrap();
+        assert!(response.session_id > 0);
+        assert_eq!(answered(&response), [(0, 1, false), (1, 0, true)]);
+
+        // In the session, only the partition that moved is sent and nothing
+        // changed since, so nothing is answered.
+        let request = fetch(&topic_id, response.session_id, 1, &[(0, 1)]);
+        let response = request.handle_request(&ctx).await.unwrap();
+        assert!(answered(&response).is_empty());
+
+        let record = TopicRecordDisk::new(0, 0, None, Some(Bytes::from_static(b"value")));
+        let mut batch = TopicRecordBatch::new(0, vec![record]);
+        log.lock().unwrap().append(&mut batch).unwrap();
+        let request = fetch(&topic_id, response.session_id, 2, &[]);
+        let response = request.handle_request(&ctx).await.unwrap();
+        assert_eq!(answered(&response), [(0, 2, false)]);
+    }
+
+    fn legacy_request(version: i16) -> Bytes {
+        use bytes::{BufMut, BytesMut};
+
+        let flexible = version >= 12;
+        let mut buf = BytesMut::new();
+        let put_count = |buf: &mut BytesMut, count: usize| {
+            if flexible {
+                buf.put_u8(count as u8 + 1);
+            } else {
+                buf.put_i32(count as i32);
+            }
+        };
+        let put_name = |buf: &mut BytesMut, name: &str| {
+            if flexible {
+                buf.put_u8(name.len() as u8 + 1);
+            } else {
+                buf.put_i16(name.len() as i16);
+            }
+            buf.put_slice(name.as_bytes());
+        };
+
+        buf.put_i32(-1);
+        buf.put_i32(500);
+        buf.put_i32(1);
+        buf.put_i32(1024);
+        buf.put_i8(READ_COMMITTED);
+        buf.put_i32(9);
+        buf.put_i32(3);
+        put_count(&mut buf, 1);
+        put_name(&mut buf, "topic");
+        put_count(&mut buf, 1);
+        buf.put_i32(2);
+        buf.put_i32(7);
+        buf.put_i64(42);
+        if flexible {
+            buf.put_i32(6);
+        }
+        buf.put_i64(10);
+        buf.put_i32(1000);
+        if flexible {
+            buf.put_u8(0);
+            buf.put_u8(0);
+        }
+        put_count(&mut buf, 1);
+        put_name(&mut buf, "gone");
+        put_count(&mut buf, 1);
+        buf.put_i32(3);
+        if flexible {
+            buf.put_u8(0);
+        }
+        put_name(&mut buf, "rack-a");
+        if flexible {
+            buf.put_u8(0);
+        }
+        buf.freeze()
+    }
+
+    #[test]
+    fn test_get_handler_decodes_requests_naming_topics() {
+        use crate::{get_handler, kafka::broker::tests::TempBroker, Handler};
+
+        let test = TempBroker::open("fetch-legacy-decode-test");
+        for version in [11, 12] {
+            let bytes = legacy_request(version);
+            let mut offset = 0;
+            let handler = get_handler(&test.context(1, version), &bytes, &mut offset);
+            let Some(Handler::LegacyFetch(request)) = handler else {
+                panic!("v{version} is not a legacy fetch");
+            };
+            assert_eq!(offset, bytes.len());
+
+            assert_eq!(request.version, version);
+            assert_eq!(request.max_wait_ms, 500);
+            assert_eq!(request.isolation_level, READ_COMMITTED);
+            assert_eq!((request.session_id, request.session_epoch), (9, 3));
+            let (name, partitions) = &request.topics[0];
+            assert_eq!(name, "topic");
+            let partition = &partitions[0];
+            assert_eq!(partition.partition, 2);
+            assert_eq!(partition.current_leader_epoch, 7);
+            assert_eq!(partition.fetch_offset, 42);
+            let last_fetched_epoch = if version >= 12 { 6 } else { -1 };
+            assert_eq!(partition.last_fetched_epoch, last_fetched_epoch);
+            assert_eq!(partition.log_start_offset, 10);
+            assert_eq!(partition.partition_max_bytes, 1000);
+            assert_eq!(
+                request.forgotten_topics_data,
+                [("gone".to_string(), vec![3])]
+            );
+            assert_eq!(request.rack_id, "rack-a");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_legacy_fetch_answers_by_name() {
+        use crate::kafka::{
+            broker::tests::TempBroker,
+            log::{TopicRecordBatch, TopicRecordDisk},
+        };
+
+        let test = TempBroker::open("fetch-legacy-test");
+        test.create_topic("topic", 1);
+        let record = TopicRecordDisk::new(0, 0, None, Some(Bytes::from_static(b"value")));
+        let mut batch = TopicRecordBatch::new(0, vec![record]);
+        let log = test.broker.logs.partition("topic", 0).unwrap();
+        log.lock().unwrap().append(&mut batch).unwrap();
+
+        for version in [4, 11, 12] {
+            let partition = |partition| FetchPartitionsRequest {
+                partition,
+                current_leader_epoch: -1,
+                fetch_offset: 0,
+                last_fetched_epoch: -1,
+                log_start_offset: -1,
+                partition_max_bytes: 1024 * 1024,
+                tagged_field: 0,
+            };
+            let request = LegacyFetchRequest {
+                version,
+                max_wait_ms: 0,
+                min_bytes: 0,
+                max_bytes: i32::MAX,
+                isolation_level: 0,
+                session_id: 0,
+                session_epoch: FINAL_EPOCH,
+                topics: vec![
+                    ("topic".to_string(), vec![partition(0)]),
+                    ("missing".to_string(), vec![partition(0), partition(1)]),
+                ],
+                forgotten_topics_data: vec![],
+                rack_id: String::new(),
+            };
+            let response = request
+                .handle_request(&test.context(1, version))
+                .await
+                .unwrap();
+
+            let answered: Vec<_> = response
+                .responses
+                .iter()
+                .flat_map(|(name, partitions)| {
+                    partitions
+                        .iter()
+                        .map(move |p| (name.as_str(), p.partition_idx, p.error_code))
+                })
+                .collect();
+            assert_eq!(
+                answered,
+                [
+                    ("topic", 0, 0),
+                    ("missing", 0, UNKNOWN_TOPIC_OR_PARTITION),
+                    ("missing", 1, UNKNOWN_TOPIC_OR_PARTITION)
+                ]
+            );
+            let records = match &response.responses[0].1[0].records {
+                CompactRecords::File(region) => region.len as usize,
+                records => records.len(),
+            };
+            assert!(records > 0);
+
+            let mut frame = Frame::default();
+            response.encode_frame(&mut frame);
+            let encoded = frame.into_bytes().unwrap();
+            assert_eq!(encoded.len(), response.size_in_bytes(), "v{version}");
+
+            if version == 4 {
+                // throttle_time_ms, then the first topic and its partition up
+                // to the records, which are prefixed by an INT32 length.
+                let offset = &mut 0;
+                assert_eq!(i32::decode(&encoded, offset), 0);
+                assert_eq!(i32::decode(&encoded, offset), 2);
+                assert_eq!(String16::decode(&encoded, offset).0, "topic");
+                assert_eq!(i32::decode(&encoded, offset), 1);
+                assert_eq!(i32::decode(&encoded, offset), 0);
+                assert_eq!(i16::decode(&encoded, offset), 0);
+                assert_eq!(i64::decode(&encoded, offset), 1);
+                assert_eq!(i64::decode(&encoded, offset), 1);
+                assert_eq!(i32::decode(&encoded, offset), -1);
+                assert_eq!(i32::decode(&encoded, offset), records as i32);
+                *offset += records;
+                assert_eq!(String16::decode(&encoded, offset).0, "missing");
+            }
+        }
     }
 }
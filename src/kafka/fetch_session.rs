@@ -0,0 +1,382 @@
+//! Incremental fetch sessions (KIP-227). A consumer opens a session with a
+//! full fetch; later fetches in the session only list the partitions whose
+//! fetch offset changed, and are only answered with the partitions that
+//! have records or whose offsets or errors changed.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::Instant,
+};
+
+use crate::types::{array::CompactArray, uuid::UUID};
+
+use super::{
+    errors::{FETCH_SESSION_ID_NOT_FOUND, INVALID_FETCH_SESSION_EPOCH, NONE},
+    fetch::{FetchPartitionsRequest, FetchRequest, FetchTopicResponse, TopicFetch},
+};
+
+/// The epoch of a fetch that opens a session.
+pub const INITIAL_EPOCH: i32 = 0;
+/// The epoch of a fetch outside any session, closing the one given.
+pub const FINAL_EPOCH: i32 = -1;
+
+/// How many sessions are kept before the least recently used is evicted.
+const MAX_SESSIONS: usize = 1000;
+
+/// What a partition was last fetched at and answered with.
+#[derive(Debug, Clone)]
+struct CachedPartition {
+    fetch_offset: i64,
+    log_start_offset: i64,
+    partition_max_bytes: i32,
+    current_leader_epoch: i32,
+    last_fetched_epoch: i32,
+    high_watermark: i64,
+    last_stable_offset: i64,
+    broker_log_start_offset: i64,
+}
+
+impl CachedPartition {
+    fn new(request: &FetchPartitionsRequest) -> Self {
+        Self {
+            fetch_offset: request.fetch_offset,
+            log_start_offset: request.log_start_offset,
+            partition_max_bytes: request.partition_max_bytes,
+            current_leader_epoch: request.current_leader_epoch,
+            last_fetched_epoch: request.last_fetched_epoch,
+            high_watermark: -1,
+            last_stable_offset: -1,
+            broker_log_start_offset: -1,
+        }
+    }
+
+    fn update(&mut self, request: &FetchPartitionsRequest) {
+        self.fetch_offset = request.fetch_offset;
+        self.log_start_offset = request.log_start_offset;
+        self.partition_max_bytes = request.partition_max_bytes;
+        self.current_leader_epoch = request.current_leader_epoch;
+        self.last_fetched_epoch = request.last_fetched_epoch;
+    }
+}
+
+#[derive(Debug)]
+struct Session {
+    /// The epoch of the next fetch in the session.
+    epoch: i32,
+    partitions: BTreeMap<(UUID, i32), CachedPartition>,
+    last_used: Instant,
+}
+
+impl Session {
+    fn topics(&self) -> Vec<TopicFetch> {
+        let mut topics: Vec<TopicFetch> = vec![];
+        for ((topic_id, partition), cached) in &self.partitions {
+            let request = FetchPartitionsRequest {
+                partition: *partition,
+                current_leader_epoch: cached.current_leader_epoch,
+                fetch_offset: cached.fetch_offset,
+                last_fetched_epoch: cached.last_fetched_epoch,
+                log_start_offset: cached.log_start_offset,
+                partition_max_bytes: cached.partition_max_bytes,
+                tagged_field: 0,
+            };
+            match topics.last_mut() {
+                Some(topic) if topic.topic_id == *topic_id => topic.partitions.0.push(request),
+                _ => topics.push(TopicFetch {
+                    topic_id: topic_id.clone(),
+                    partitions: CompactArray(vec![request]),
+                    tagged_field: 0,
+                }),
+            }
+        }
+        topics
+    }
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+    sessions: HashMap<i32, Session>,
+    last_id: i32,
+}
+
+/// The partitions a fetch reads and the session it belongs to.
+#[derive(Debug)]
+pub struct SessionFetch {
+    /// 0 outside any session.
+    pub session_id: i32,
+    /// Whether only changed partitions are answered.
+    pub incremental: bool,
+    pub topics: Vec<TopicFetch>,
+}
+
+/// The fetch sessions of this broker.
+#[derive(Debug, Default)]
+pub struct FetchSessions {
+    sessions: Mutex<Sessions>,
+}
+
+impl FetchSessions {
+    /// Opens, updates or closes the session of a fetch. Returns the
+    /// partitions to read, or the error code of an unknown session or an
+    /// out of order epoch.
+    pub fn begin(&self, request: &FetchRequest) -> Result<SessionFetch, i16> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+
+        match request.session_epoch {
+            FINAL_EPOCH | INITIAL_EPOCH => {
+                if request.session_id != 0 {
+                    sessions.sessions.remove(&request.session_id);
+                }
+                let topics = request.topics.iter().map(TopicFetch::clone).collect();
+                if request.session_epoch == FINAL_EPOCH {
+                    return Ok(SessionFetch {
+                        session_id: 0,
+                        incremental: false,
+                        topics,
+                    });
+                }
+
+                let mut partitions = BTreeMap::new();
+                for topic in request.topics.iter() {
+                    for partition in topic.partitions.iter() {
+                        partitions.insert(
+                            (topic.topic_id.clone(), partition.partition),
+                            CachedPartition::new(partition),
+                        );
+                    }
+                }
+                let session_id = sessions.open(Session {
+                    epoch: 1,
+                    partitions,
+                    last_used: Instant::now(),
+                });
+                Ok(SessionFetch {
+                    session_id,
+                    incremental: false,
+                    topics,
+                })
+            }
+            epoch => {
+                let session = sessions
+                    .sessions
+                    .get_mut(&request.session_id)
+                    .ok_or(FETCH_SESSION_ID_NOT_FOUND)?;
+                if session.epoch != epoch {
+                    return Err(INVALID_FETCH_SESSION_EPOCH);
+                }
+                session.epoch = session.epoch.checked_add(1).unwrap_or(1);
+                session.last_used = Instant::now();
+
+                for topic in request.topics.iter() {
+                    for partition in topic.partitions.iter() {
+                        session
+                            .partitions
+                            .entry((topic.topic_id.clone(), partition.partition))
+                            .and_modify(|cached| cached.update(partition))
+                            .or_insert_with(|| CachedPartition::new(partition));
+                    }
+                }
+                for forgotten in request.forgotten_topics_data.iter() {
+                    for partition in forgotten.partitions.iter() {
+                        session
+                            .partitions
+                            .remove(&(forgotten.topic_id.clone(), *partition));
+                    }
+                }
+
+                Ok(SessionFetch {
+                    session_id: request.session_id,
+                    incremental: true,
+                    topics: session.topics(),
+                })
+            }
+        }
+    }
+
+    /// Records what the partitions of a session were answered with. The
+    /// answer to an incremental fetch is cut down to the partitions with
+    /// records, errors or changed offsets.
+    pub fn complete(&self, fetch: &SessionFetch, responses: &mut Vec<FetchTopicResponse>) {
+        if fetch.session_id == 0 {
+            return;
+        }
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(session) = sessions.sessions.get_mut(&fetch.session_id) else {
+            return;
+        };
+
+        for topic in responses.iter_mut() {
+            topic.partitions.0.retain(|partition| {
+                let key = (topic.topic_id.clone(), partition.partition_idx);
+                let Some(cached) = session.partitions.get_mut(&key) else {
+                    return true;
+                };
+                let changed = cached.high_watermark != partition.high_watermark
+                    || cached.last_stable_offset != partition.last_stable_offset
+                    || cached.broker_log_start_offset != partition.log_start_offset;
+                cached.high_watermark = partition.high_watermark;
+                cached.last_stable_offset = partition.last_stable_offset;
+                cached.broker_log_start_offset = partition.log_start_offset;
+
+                !fetch.incremental
+                    || changed
+                    || partition.error_code != NONE
+                    || !partition.records.is_empty()
+            });
+        }
+        if fetch.incremental {
+            responses.retain(|topic| !topic.partitions.is_empty());
+        }
+    }
+}
+
+impl Sessions {
+    /// Adds a session under a new id, evicting the least recently used one
+    /// if there are too many.
+    fn open(&mut self, session: Session) -> i32 {
+        if self.sessions.len() >= MAX_SESSIONS {
+            let oldest = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_used)
+                .map(|(id, _)| *id);
+            if let Some(id) = oldest {
+                self.sessions.remove(&id);
+            }
+        }
+
+        loop {
+            self.last_id = self.last_id.checked_add(1).unwrap_or(1);
+            if !self.sessions.contains_key(&self.last_id) {
+                break;
+            }
+        }
+        self.sessions.insert(self.last_id, session);
+        self.last_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kafka::fetch::{FetchPartitionsResponse, ForgottenTopicsData},
+        types::cstring::CompactString,
+    };
+
+    fn request(session_id: i32, session_epoch: i32, partitions: &[(i32, i64)]) -> FetchRequest {
+        FetchRequest {
+            max_wait_ms: 0,
+            min_bytes: 0,
+            max_bytes: i32::MAX,
+            isolation_level: 0,
+            session_id,
+            session_epoch,
+            topics: CompactArray(if partitions.is_empty() {
+                vec![]
+            } else {
+                vec![TopicFetch {
+                    topic_id: UUID([1; 16]),
+                    partitions: CompactArray(
+                        partitions
+                            .iter()
+                            .map(|&(partition, fetch_offset)| FetchPartitionsRequest {
+                                partition,
+                                current_leader_epoch: -1,
+                                fetch_offset,
+                                last_fetched_epoch: -1,
+                                log_start_offset: -1,
+                                partition_max_bytes: 1024,
+                                tagged_field: 0,
+                            })
+                            .collect(),
+                    ),
+                    tagged_field: 0,
+                }]
+            }),
+            forgotten_topics_data: CompactArray(vec![]),
+            rack_id: CompactString(String::new()),
+            tagged_field: 0,
+        }
+    }
+
+    fn responses(fetch: &SessionFetch, high_watermark: i64) -> Vec<FetchTopicResponse> {
+        fetch
+            .topics
+            .iter()
+            .map(|topic| FetchTopicResponse {
+                topic_id: topic.topic_id.clone(),
+                partitions: CompactArray(
+                    topic
+                        .partitions
+                        .iter()
+                        .map(|partition| FetchPartitionsResponse {
+                            partition_idx: partition.partition,
+                            error_code: NONE,
+                            high_watermark,
+                            ..FetchPartitionsResponse::unknown_topic()
+                        })
+                        .collect(),
+                ),
+                tagged_field: 0,
+            })
+            .collect()
+    }
+
+    fn fetched(responses: &[FetchTopicResponse]) -> Vec<i32> {
+        responses
+            .iter()
+            .flat_map(|topic| topic.partitions.iter().map(|p| p.partition_idx))
+            .collect()
+    }
+
+    #[test]
+    fn test_incremental_fetches_only_answer_changes() {
+        let sessions = FetchSessions::default();
+
+        let full = sessions.begin(&request(0, 0, &[(0, 0), (1, 0)])).unwrap();
+        assert!(full.session_id > 0 && !full.incremental);
+        let mut answered = responses(&full, 5);
+        sessions.complete(&full, &mut answered);
+        assert_eq!(fetched(&answered), [0, 1]);
+
+        // Nothing changed: the session still reads both partitions, but
+        // answers neither.
+        let next = sessions.begin(&request(full.session_id, 1, &[])).unwrap();
+        assert_eq!(fetched(&responses(&next, 5)), [0, 1]);
+        let mut answered = responses(&next, 5);
+        sessions.complete(&next, &mut answered);
+        assert!(answered.is_empty());
+
+        // A new fetch offset is remembered; a forgotten partition is dropped.
+        let mut update = request(full.session_id, 2, &[(0, 3)]);
+        update.forgotten_topics_data = CompactArray(vec![ForgottenTopicsData {
+            topic_id: UUID([1; 16]),
+            partitions: CompactArray(vec![1]),
+            tagged_field: 0,
+        }]);
+        let next = sessions.begin(&update).unwrap();
+        assert_eq!(next.topics[0].partitions.len(), 1);
+        assert_eq!(next.topics[0].partitions[0].fetch_offset, 3);
+        let mut answered = responses(&next, 6);
+        sessions.complete(&next, &mut answered);
+        assert_eq!(fetched(&answered), [0]);
+
+        assert_eq!(
+            sessions
+                .begin(&request(full.session_id, 2, &[]))
+                .unwrap_err(),
+            INVALID_FETCH_SESSION_EPOCH
+        );
+
+        let last = sessions.begin(&request(full.session_id, FINAL_EPOCH, &[]));
+        assert_eq!(last.unwrap().session_id, 0);
+        assert_eq!(
+            sessions
+                .begin(&request(full.session_id, 4, &[]))
+                .unwrap_err(),
+            FETCH_SESSION_ID_NOT_FOUND
+        );
+    }
+}
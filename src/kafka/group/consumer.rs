@@ -0,0 +1,762 @@
+//! The consumer group protocol (KIP-848). The broker computes a target
+//! assignment whenever the group epoch moves, and members converge to it
+//! through their heartbeats: partitions still owned by another member are
+//! only handed over once that member has revoked them.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+use crate::{
+    kafka::{
+        errors::{
+            FENCED_MEMBER_EPOCH, GROUP_ID_NOT_FOUND, INVALID_REQUEST, NONE, UNKNOWN_MEMBER_ID,
+            UNSUPPORTED_ASSIGNOR,
+        },
+        metadata::ClusterMetadata,
+    },
+    types::uuid::UUID,
+};
+
+use super::{lock, GroupCoordinator};
+
+/// Partitions by topic id.
+pub type Assignment = BTreeMap<UUID, BTreeSet<i32>>;
+
+pub const UNIFORM_ASSIGNOR: &str = "uniform";
+pub const RANGE_ASSIGNOR: &str = "range";
+
+/// A topic as the assignors see it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicMetadata {
+    pub id: UUID,
+    pub name: String,
+    pub partitions: i32,
+}
+
+/// The topics in the cluster metadata, by name.
+pub fn topic_metadata(metadata: &ClusterMetadata) -> HashMap<String, TopicMetadata> {
+    metadata.read(|image| {
+        image
+            .topics()
+            .map(|topic| {
+                let metadata = TopicMetadata {
+                    id: topic.id.clone(),
+                    name: topic.name.clone(),
+                    partitions: topic.partitions.len() as i32,
+                };
+                (topic.name.clone(), metadata)
+            })
+            .collect()
+    })
+}
+
+/// Where a member is in converging to its target assignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberState {
+    Stable,
+    /// Waiting for the member to give up partitions that are not in its
+    /// target.
+    UnrevokedPartitions,
+    /// Waiting for other members to give up partitions in its target.
+    UnreleasedPartitions,
+}
+
+#[derive(Debug)]
+pub struct ConsumerMember {
+    pub member_id: String,
+    pub instance_id: Option<String>,
+    pub rack_id: Option<String>,
+    pub client_id: String,
+    pub client_host: String,
+    pub subscribed_topics: Vec<String>,
+    pub server_assignor: Option<String>,
+    pub rebalance_timeout: Duration,
+    pub member_epoch: i32,
+    pub previous_member_epoch: i32,
+    pub state: MemberState,
+    pub assigned: Assignment,
+    pub pending_revocation: Assignment,
+    last_heartbeat: Instant,
+    revocation_deadline: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub struct ConsumerGroup {
+    pub group_id: String,
+    /// Bumped whenever membership, subscriptions or subscribed topics change.
+    pub group_epoch: i32,
+    /// The group epoch the target assignment was computed for.
+    pub assignment_epoch: i32,
+    pub members: BTreeMap<String, ConsumerMember>,
+    pub target_assignment: HashMap<String, Assignment>,
+    /// The subscribed topics the target assignment was computed from.
+    subscribed_metadata: Vec<TopicMetadata>,
+}
+
+impl ConsumerGroup {
+    fn new(group_id: &str) -> Self {
+        Self {
+            group_id: group_id.to_string(),
+            group_epoch: 0,
+            assignment_epoch: 0,
+            members: BTreeMap::new(),
+            target_assignment: HashMap::new(),
+            subscribed_metadata: vec![],
+        }
+    }
+
+    /// The state reported by ConsumerGroupDescribe and ListGroups.
+    pub fn state(&self) -> &'static str {
+        if self.members.is_empty() {
+            "Empty"
+        } else if self.assignment_epoch < self.group_epoch {
+            "Assigning"
+        } else if self
+            .members
+            .values()
+            .any(|m| m.member_epoch != self.assignment_epoch || m.state != MemberState::Stable)
+        {
+            "Reconciling"
+        } else {
+            "Stable"
+        }
+    }
+
+    /// The assignor most members asked for.
+    pub fn assignor(&self) -> &str {
+        let mut votes: BTreeMap<&str, usize> = BTreeMap::new();
+        for member in self.members.values() {
+            if let Some(assignor) = &member.server_assignor {
+                *votes.entry(assignor).or_default() += 1;
+            }
+        }
+
+        votes
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map_or(UNIFORM_ASSIGNOR, |(assignor, _)| assignor)
+    }
+
+    fn subscribed(&self, topics: &HashMap<String, TopicMetadata>) -> Vec<TopicMetadata> {
+        let names: BTreeSet<&String> = self
+            .members
+            .values()
+            .flat_map(|m| &m.subscribed_topics)
+            .collect();
+
+        names
+            .into_iter()
+            .filter_map(|name| topics.get(name).cloned())
+            .collect()
+    }
+
+    fn remove_member(&mut self, member_id: &str) -> Option<ConsumerMember> {
+        let member = self.members.remove(member_id)?;
+        self.target_assignment.remove(member_id);
+        Some(member)
+    }
+
+    /// Partitions other members still hold, assigned or being revoked.
+    fn owned_by_others(&self, member_id: &str) -> Assignment {
+        let mut owned = Assignment::new();
+
+        for member in self.members.values().filter(|m| m.member_id != member_id) {
+            for assignment in [&member.assigned, &member.pending_revocation] {
+                for (topic, partitions) in assignment {
+                    owned
+                        .entry(topic.clone())
+                        .or_default()
+                        .extend(partitions.iter().copied());
+                }
+            }
+        }
+
+        owned
+    }
+
+    /// Moves a member one step towards its target assignment. `owned` is what
+    /// the member reported owning, if it did.
+    fn reconcile(&mut self, member_id: &str, owned: Option<&Assignment>) {
+        let target = self
+            .target_assignment
+            .get(member_id)
+            .cloned()
+            .unwrap_or_default();
+        let owned_by_others = self.owned_by_others(member_id);
+        let assignment_epoch = self.assignment_epoch;
+
+        let Some(member) = self.members.get_mut(member_id) else {
+            return;
+        };
+
+        if member.state == MemberState::UnrevokedPartitions {
+            let revoked =
+                owned.is_some_and(|owned| intersect(owned, &member.pending_revocation).is_empty());
+            if !revoked {
+                return;
+            }
+            member.pending_revocation.clear();
+            member.revocation_deadline = None;
+            member.state = MemberState::Stable;
+        }
+
+        if member.member_epoch == assignment_epoch && member.state == MemberState::Stable {
+            return;
+        }
+
+        let to_revoke = subtract(&member.assigned, &target);
+        if !to_revoke.is_empty() {
+            member.assigned = intersect(&member.assigned, &target);
+            member.pending_revocation = to_revoke;
+            member.state = MemberState::UnrevokedPartitions;
+            member.revocation_deadline = Some(Instant::now() + member.rebalance_timeout);
+            return;
+        }
+
+        let unreleased = intersect(&target, &owned_by_others);
+        member.assigned = subtract(&target, &unreleased);
+
+        if member.member_epoch != assignment_epoch {
+            member.previous_member_epoch = member.member_epoch;
+            member.member_epoch = assignment_epoch;
+        }
+
+        member.state = if unreleased.is_empty() {
+            MemberState::Stable
+        } else {
+            MemberState::UnreleasedPartitions
+        };
+    }
+
+    /// Bumps the group epoch if the group `changed` or its subscribed topics
+    /// did, then recomputes the target assignment if the epoch moved.
+    fn update_target(&mut self, topics: &HashMap<String, TopicMetadata>, changed: bool) {
+        let subscribed = self.subscribed(topics);
+        if changed || subscribed != self.subscribed_metadata {
+            self.subscribed_metadata = subscribed;
+            self.group_epoch += 1;
+        }
+
+        if self.assignment_epoch >= self.group_epoch {
+            return;
+        }
+
+        self.target_assignment = match self.assignor() {
+            RANGE_ASSIGNOR => range_assign(&self.members, &self.subscribed_metadata),
+            _ => uniform_assign(
+                &self.members,
+                &self.subscribed_metadata,
+                &self.target_assignment,
+            ),
+        };
+        self.assignment_epoch = self.group_epoch;
+    }
+}
+
+fn intersect(a: &Assignment, b: &Assignment) -> Assignment {
+    a.iter()
+        .filter_map(|(topic, partitions)| {
+            let other = b.get(topic)?;
+            let common: BTreeSet<i32> = partitions.intersection(other).copied().collect();
+            (!common.is_empty()).then(|| (topic.clone(), common))
+        })
+        .collect()
+}
+
+fn subtract(a: &Assignment, b: &Assignment) -> Assignment {
+    a.iter()
+        .filter_map(|(topic, partitions)| {
+            let rest: BTreeSet<i32> = match b.get(topic) {
+                Some(other) => partitions.difference(other).copied().collect(),
+                None => partitions.clone(),
+            };
+            (!rest.is_empty()).then(|| (topic.clone(), rest))
+        })
+        .collect()
+}
+
+fn subscribers<'a>(
+    members: &'a BTreeMap<String, ConsumerMember>,
+    topic: &TopicMetadata,
+) -> Vec<&'a String> {
+    members
+        .values()
+        .filter(|m| m.subscribed_topics.contains(&topic.name))
+        .map(|m| &m.member_id)
+        .collect()
+}
+
+/// Splits each topic's partitions into contiguous ranges over the members
+/// subscribed to it, ordered by member id.
+pub fn range_assign(
+    members: &BTreeMap<String, ConsumerMember>,
+    topics: &[TopicMetadata],
+) -> HashMap<String, Assignment> {
+    let mut result: HashMap<String, Assignment> = HashMap::new();
+
+    for topic in topics {
+        let subscribers = subscribers(members, topic);
+        if subscribers.is_empty() {
+            continue;
+        }
+
+        let per_member = topic.partitions as usize / subscribers.len();
+        let extra = topic.partitions as usize % subscribers.len();
+        let mut start = 0;
+
+        for (i, member_id) in subscribers.into_iter().enumerate() {
+            let count = per_member + usize::from(i < extra);
+            if count > 0 {
+                result
+                    .entry(member_id.clone())
+                    .or_default()
+                    .insert(topic.id.clone(), (start..start + count as i32).collect());
+            }
+            start += count as i32;
+        }
+    }
+
+    result
+}
+
+/// Spreads partitions evenly over the members, keeping each partition with
+/// its previous owner while that owner stays within its share.
+pub fn uniform_assign(
+    members: &BTreeMap<String, ConsumerMember>,
+    topics: &[TopicMetadata],
+    previous: &HashMap<String, Assignment>,
+) -> HashMap<String, Assignment> {
+    let total: usize = topics.iter().map(|t| t.partitions.max(0) as usize).sum();
+    let mut result: HashMap<String, Assignment> = HashMap::new();
+    let mut counts: BTreeMap<&String, usize> = members.keys().map(|id| (id, 0)).collect();
+    let mut taken: BTreeSet<(UUID, i32)> = BTreeSet::new();
+
+    if members.is_empty() {
+        return result;
+    }
+    let quota = total.div_ceil(members.len());
+
+    for (member_id, member) in members {
+        let Some(assignment) = previous.get(member_id) else {
+            continue;
+        };
+
+        for topic in topics
+            .iter()
+            .filter(|t| member.subscribed_topics.contains(&t.name))
+        {
+            for &partition in assignment.get(&topic.id).into_iter().flatten() {
+                if partition < topic.partitions && counts[member_id] < quota {
+                    taken.insert((topic.id.clone(), partition));
+                    *counts.get_mut(member_id).unwrap() += 1;
+                    result
+                        .entry(member_id.clone())
+                        .or_default()
+                        .entry(topic.id.clone())
+                        .or_default()
+                        .insert(partition);
+                }
+            }
+        }
+    }
+
+    for topic in topics {
+        let subscribers = subscribers(members, topic);
+
+        for partition in 0..topic.partitions {
+            if taken.contains(&(topic.id.clone(), partition)) {
+                continue;
+            }
+
+            let Some(member_id) = subscribers.iter().min_by_key(|id| (counts[*id], **id)) else {
+                break;
+            };
+
+            *counts.get_mut(member_id).unwrap() += 1;
+            result
+                .entry((*member_id).clone())
+                .or_default()
+                .entry(topic.id.clone())
+                .or_default()
+                .insert(partition);
+        }
+    }
+
+    result
+}
+
+/// A ConsumerGroupHeartbeat as seen by the coordinator. Fields left `None`
+/// did not change since the member's previous heartbeat.
+#[derive(Debug, Clone, Default)]
+pub struct ConsumerHeartbeat {
+    pub group_id: String,
+    pub member_id: String,
+    pub member_epoch: i32,
+    pub instance_id: Option<String>,
+    pub rack_id: Option<String>,
+    pub client_id: String,
+    pub client_host: String,
+    pub rebalance_timeout: Option<Duration>,
+    pub subscribed_topics: Option<Vec<String>>,
+    pub server_assignor: Option<String>,
+    pub owned: Option<Assignment>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsumerHeartbeatResult {
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub member_id: String,
+    pub member_epoch: i32,
+    pub heartbeat_interval: Duration,
+    /// Sent only when it changed.
+    pub assignment: Option<Assignment>,
+}
+
+impl ConsumerHeartbeatResult {
+    fn error(error_code: i16, message: &str) -> Self {
+        Self {
+            error_code,
+            error_message: Some(message.to_string()),
+            member_epoch: -1,
+            ..Default::default()
+        }
+    }
+}
+
+/// Member epoch sent to leave the group.
+const LEAVE_EPOCH: i32 = -1;
+/// Member epoch sent by static members leaving temporarily.
+const STATIC_LEAVE_EPOCH: i32 = -2;
+
+impl GroupCoordinator {
+    /// Records a consumer protocol heartbeat and answers with the member's
+    /// next assignment.
+    pub fn consumer_heartbeat(
+        &self,
+        request: ConsumerHeartbeat,
+        topics: &HashMap<String, TopicMetadata>,
+    ) -> ConsumerHeartbeatResult {
+        let classic = lock(&self.groups)
+            .get(&request.group_id)
+            .is_some_and(|group| !group.members.is_empty());
+        if classic {
+            return ConsumerHeartbeatResult::error(
+                GROUP_ID_NOT_FOUND,
+                "the group uses the classic protocol",
+            );
+        }
+
+        if request
+            .server_assignor
+            .as_deref()
+            .is_some_and(|a| a != UNIFORM_ASSIGNOR && a != RANGE_ASSIGNOR)
+        {
+            return ConsumerHeartbeatResult::error(UNSUPPORTED_ASSIGNOR, "unknown assignor");
+        }
+
+        let joining = request.member_epoch == 0;
+        if joining
+            && (request.subscribed_topics.is_none()
+                || request.rebalance_timeout.is_none()
+                || request.owned.as_ref().is_some_and(|o| !o.is_empty()))
+        {
+            return ConsumerHeartbeatResult::error(
+                INVALID_REQUEST,
+                "joining members must send their subscription and rebalance timeout",
+            );
+        }
+
+        let mut groups = lock(&self.consumer_groups);
+
+        if !joining && !groups.contains_key(&request.group_id) {
+            return ConsumerHeartbeatResult::error(UNKNOWN_MEMBER_ID, "unknown group");
+        }
+
+        let group = groups
+            .entry(request.group_id.clone())
+            .or_insert_with(|| ConsumerGroup::new(&request.group_id));
+
+        if matches!(request.member_epoch, LEAVE_EPOCH | STATIC_LEAVE_EPOCH) {
+            let removed = group.remove_member(&request.member_id).is_some();
+            group.update_target(topics, removed);
+
+            return ConsumerHeartbeatResult {
+                member_id: request.member_id,
+                member_epoch: request.member_epoch,
+                ..Default::default()
+            };
+        }
+
+        let member_id = if joining {
+            let member_id = if request.member_id.is_empty() {
+                Uuid::new_v4().to_string()
+            } else {
+                request.member_id.clone()
+            };
+
+            // A member rejoining gives up whatever it had.
+            group.remove_member(&member_id);
+            group.members.insert(
+                member_id.clone(),
+                ConsumerMember {
+                    member_id: member_id.clone(),
+                    instance_id: request.instance_id.clone(),
+                    rack_id: request.rack_id.clone(),
+                    client_id: request.client_id.clone(),
+                    client_host: request.client_host.clone(),
+                    subscribed_topics: vec![],
+                    server_assignor: None,
+                    rebalance_timeout: Duration::ZERO,
+                    member_epoch: 0,
+                    previous_member_epoch: 0,
+                    state: MemberState::Stable,
+                    assigned: Assignment::new(),
+                    pending_revocation: Assignment::new(),
+                    last_heartbeat: Instant::now(),
+                    revocation_deadline: None,
+                },
+            );
+            member_id
+        } else {
+            let Some(member) = group.members.get(&request.member_id) else {
+                return ConsumerHeartbeatResult::error(UNKNOWN_MEMBER_ID, "unknown member");
+            };
+
+            let lagging = request.member_epoch == member.previous_member_epoch
+                && request
+                    .owned
+                    .as_ref()
+                    .is_some_and(|owned| subtract(owned, &member.assigned).is_empty());
+
+            if request.member_epoch != member.member_epoch && !lagging {
+                return ConsumerHeartbeatResult::error(FENCED_MEMBER_EPOCH, "stale member epoch");
+            }
+
+            request.member_id.clone()
+        };
+
+        let member = group
+            .members
+            .get_mut(&member_id)
+            .expect("member added above");
+        let before = member.assigned.clone();
+        member.last_heartbeat = Instant::now();
+
+        if let Some(timeout) = request.rebalance_timeout {
+            member.rebalance_timeout = timeout;
+        }
+        if request.rack_id.is_some() {
+            member.rack_id = request.rack_id;
+        }
+
+        let mut changed = joining;
+        if let Some(topics) = request.subscribed_topics {
+            changed |= member.subscribed_topics != topics;
+            member.subscribed_topics = topics;
+        }
+        if request.server_assignor.is_some() {
+            changed |= member.server_assignor != request.server_assignor;
+            member.server_assignor = request.server_assignor;
+        }
+        group.update_target(topics, changed);
+        group.reconcile(&member_id, request.owned.as_ref());
+
+        let member = &group.members[&member_id];
+
+        ConsumerHeartbeatResult {
+            error_code: NONE,
+            error_message: None,
+            member_id: member_id.clone(),
+            member_epoch: member.member_epoch,
+            heartbeat_interval: self.config.consumer_heartbeat_interval,
+            assignment: (joining || member.assigned != before).then(|| member.assigned.clone()),
+        }
+    }
+
+    /// Removes consumer protocol members whose session expired or that did
+    /// not revoke partitions within their rebalance timeout.
+    pub(super) fn expire_consumer_members(&self) {
+        let now = Instant::now();
+        let session_timeout = self.config.consumer_session_timeout;
+
+        for group in lock(&self.consumer_groups).values_mut() {
+            let expired: Vec<String> = group
+                .members
+                .values()
+                .filter(|m| {
+                    now.duration_since(m.last_heartbeat) > session_timeout
+                        || m.revocation_deadline.is_some_and(|deadline| now > deadline)
+                })
+                .map(|m| m.member_id.clone())
+                .collect();
+
+            if expired.is_empty() {
+                continue;
+            }
+
+            for member_id in expired {
+                group.remove_member(&member_id);
+            }
+            group.group_epoch += 1;
+        }
+    }
+
+    /// Runs `f` against a consumer protocol group, if it exists.
+    pub fn with_consumer_group<T>(
+        &self,
+        group_id: &str,
+        f: impl FnOnce(&ConsumerGroup) -> T,
+    ) -> Option<T> {
+        lock(&self.consumer_groups).get(group_id).map(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::group::GroupConfig;
+
+    fn topics(partitions: i32) -> HashMap<String, TopicMetadata> {
+        let topic = TopicMetadata {
+            id: UUID([7; 16]),
+            name: "t".to_string(),
+            partitions,
+        };
+        HashMap::from([("t".to_string(), topic)])
+    }
+
+    fn join(member_id: &str, assignor: &str) -> ConsumerHeartbeat {
+        ConsumerHeartbeat {
+            group_id: "g".to_string(),
+            member_id: member_id.to_string(),
+            member_epoch: 0,
+            rebalance_timeout: Some(Duration::from_secs(30)),
+            subscribed_topics: Some(vec!["t".to_string()]),
+            server_assignor: Some(assignor.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn heartbeat(member_id: &str, epoch: i32, owned: &[i32]) -> ConsumerHeartbeat {
+        ConsumerHeartbeat {
+            group_id: "g".to_string(),
+            member_id: member_id.to_string(),
+            member_epoch: epoch,
+            owned: Some(Assignment::from([(
+                UUID([7; 16]),
+                owned.iter().copied().collect(),
+            )])),
+            ..Default::default()
+        }
+    }
+
+    fn partitions(assignment: &Option<Assignment>) -> Vec<i32> {
+        assignment
+            .as_ref()
+            .and_then(|a| a.get(&UUID([7; 16])))
+            .map(|p| p.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_incremental_reconciliation() {
+        let coordinator = GroupCoordinator::new(GroupConfig::default());
+        let topics = topics(4);
+
+        let a = coordinator.consumer_heartbeat(join("a", RANGE_ASSIGNOR), &topics);
+        assert_eq!(a.error_code, NONE);
+        assert_eq!(a.member_epoch, 1);
+        assert_eq!(partitions(&a.assignment), vec![0, 1, 2, 3]);
+
+        // b joins: its partitions are still owned by a, so it gets none yet.
+        let b = coordinator.consumer_heartbeat(join("b", RANGE_ASSIGNOR), &topics);
+        assert_eq!(b.member_epoch, 2);
+        assert_eq!(partitions(&b.assignment), Vec::<i32>::new());
+
+        // a must revoke 2 and 3 before moving to the new epoch.
+        let a = coordinator.consumer_heartbeat(heartbeat("a", 1, &[0, 1, 2, 3]), &topics);
+        assert_eq!(a.member_epoch, 1);
+        assert_eq!(partitions(&a.assignment), vec![0, 1]);
+
+        let a = coordinator.consumer_heartbeat(heartbeat("a", 1, &[0, 1]), &topics);
+        assert_eq!(a.member_epoch, 2);
+
+        let b = coordinator.consumer_heartbeat(heartbeat("b", 2, &[]), &topics);
+        assert_eq!(partitions(&b.assignment), vec![2, 3]);
+
+        let state = coordinator.with_consumer_group("g", |g| g.state());
+        assert_eq!(state, Some("Stable"));
+
+        let fenced = coordinator.consumer_heartbeat(heartbeat("b", 1, &[]), &topics);
+        assert_eq!(fenced.error_code, FENCED_MEMBER_EPOCH);
+
+        let left = coordinator.consumer_heartbeat(heartbeat("b", LEAVE_EPOCH, &[]), &topics);
+        assert_eq!(left.member_epoch, LEAVE_EPOCH);
+        let a = coordinator.consumer_heartbeat(heartbeat("a", 2, &[0, 1]), &topics);
+        assert_eq!(partitions(&a.assignment), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_uniform_assignor_is_sticky_and_balanced() {
+        let coordinator = GroupCoordinator::new(GroupConfig::default());
+        let topics = topics(6);
+
+        coordinator.consumer_heartbeat(join("a", UNIFORM_ASSIGNOR), &topics);
+        coordinator.consumer_heartbeat(join("b", UNIFORM_ASSIGNOR), &topics);
+
+        let targets = coordinator
+            .with_consumer_group("g", |g| g.target_assignment.clone())
+            .unwrap();
+        let a: Vec<i32> = targets["a"][&UUID([7; 16])].iter().copied().collect();
+        let b: Vec<i32> = targets["b"][&UUID([7; 16])].iter().copied().collect();
+        assert_eq!(a.len(), 3);
+        assert_eq!(b.len(), 3);
+
+        // a third member takes one partition from each without moving others.
+        coordinator.consumer_heartbeat(join("c", UNIFORM_ASSIGNOR), &topics);
+        let targets = coordinator
+            .with_consumer_group("g", |g| g.target_assignment.clone())
+            .unwrap();
+        assert_eq!(targets["c"][&UUID([7; 16])].len(), 2);
+        assert!(targets["a"][&UUID([7; 16])].iter().all(|p| a.contains(p)));
+        assert!(targets["b"][&UUID([7; 16])].iter().all(|p| b.contains(p)));
+    }
+
+    #[test]
+    fn test_join_validation() {
+        let coordinator = GroupCoordinator::new(GroupConfig::default());
+        let topics = topics(1);
+
+        let mut no_subscription = join("", UNIFORM_ASSIGNOR);
+        no_subscription.subscribed_topics = None;
+        assert_eq!(
+            coordinator
+                .consumer_heartbeat(no_subscription, &topics)
+                .error_code,
+            INVALID_REQUEST
+        );
+
+        assert_eq!(
+            coordinator
+                .consumer_heartbeat(join("", "sticky"), &topics)
+                .error_code,
+            UNSUPPORTED_ASSIGNOR
+        );
+
+        assert_eq!(
+            coordinator
+                .consumer_heartbeat(heartbeat("x", 3, &[]), &topics)
+                .error_code,
+            UNKNOWN_MEMBER_ID
+        );
+
+        let joined = coordinator.consumer_heartbeat(join("", UNIFORM_ASSIGNOR), &topics);
+        assert!(!joined.member_id.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use crate::{
+    kafka::{
+        acl::{operation, operation_bits, resource, OPERATIONS_NOT_REQUESTED},
+        errors::{GROUP_AUTHORIZATION_FAILED, GROUP_ID_NOT_FOUND, NONE},
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+        uuid::UUID,
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::consumer::{topic_metadata, Assignment, ConsumerGroup};
+
+/// ConsumerGroupDescribe v0.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConsumerGroupDescribeRequest {
+    pub group_ids: CompactArray<CompactString>,
+    pub include_authorized_operations: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConsumerGroupDescribeTopicPartitions {
+    pub topic_id: UUID,
+    pub topic_name: CompactString,
+    pub partitions: CompactArray<i32>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConsumerGroupDescribeAssignment {
+    pub topic_partitions: CompactArray<ConsumerGroupDescribeTopicPartitions>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConsumerGroupDescribeMember {
+    pub member_id: CompactString,
+    pub instance_id: CompactNullableString,
+    pub rack_id: CompactNullableString,
+    pub member_epoch: i32,
+    pub client_id: CompactString,
+    pub client_host: CompactString,
+    pub subscribed_topic_names: CompactArray<CompactString>,
+    pub subscribed_topic_regex: CompactNullableString,
+    pub assignment: ConsumerGroupDescribeAssignment,
+    pub target_assignment: ConsumerGroupDescribeAssignment,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConsumerGroupDescribeGroup {
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub group_id: CompactString,
+    pub group_state: CompactString,
+    pub group_epoch: i32,
+    pub assignment_epoch: i32,
+    pub assignor_name: CompactString,
+    pub members: CompactArray<ConsumerGroupDescribeMember>,
+    pub authorized_operations: i32,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConsumerGroupDescribeResponse {
+    pub throttle_time_ms: i32,
+    pub groups: CompactArray<ConsumerGroupDescribeGroup>,
+    pub tagged_fields: u8,
+}
+
+fn describe_assignment(
+    assignment: Option<&Assignment>,
+    names: &HashMap<UUID, String>,
+) -> ConsumerGroupDescribeAssignment {
+    let topic_partitions = assignment
+        .into_iter()
+        .flatten()
+        .map(
+            |(topic_id, partitions)| ConsumerGroupDescribeTopicPartitions {
+                topic_id: topic_id.clone(),
+                topic_name: names.get(topic_id).cloned().unwrap_or_default().into(),
+                partitions: CompactArray(partitions.iter().copied().collect()),
+                tagged_fields: 0,
+            },
+        )
+        .collect();
+
+    ConsumerGroupDescribeAssignment {
+        topic_partitions: CompactArray(topic_partitions),
+        tagged_fields: 0,
+    }
+}
+
+fn describe_group(
+    group: &ConsumerGroup,
+    names: &HashMap<UUID, String>,
+    authorized_operations: i32,
+) -> ConsumerGroupDescribeGroup {
+    let members = group
+        .members
+        .values()
+        .map(|member| ConsumerGroupDescribeMember {
+            member_id: member.member_id.clone().into(),
+            instance_id: CompactNullableString(member.instance_id.clone()),
+            rack_id: CompactNullableString(member.rack_id.clone()),
+            member_epoch: member.member_epoch,
+            client_id: member.client_id.clone().into(),
+            client_host: member.client_host.clone().into(),
+            subscribed_topic_names: CompactArray(
+                member
+                    .subscribed_topics
+                    .iter()
+                    .map(|t| t.clone().into())
+                    .collect(),
+            ),
+            subscribed_topic_regex: CompactNullableString(None),
+            assignment: describe_assignment(Some(&member.assigned), names),
+            target_assignment: describe_assignment(
+                group.target_assignment.get(&member.member_id),
+                names,
+            ),
+            tagged_fields: 0,
+        })
+        .collect();
+
+    ConsumerGroupDescribeGroup {
+        error_code: NONE,
+        error_message: CompactNullableString(None),
+        group_id: group.group_id.clone().into(),
+        group_state: group.state().into(),
+        group_epoch: group.group_epoch,
+        assignment_epoch: group.assignment_epoch,
+        assignor_name: group.assignor().into(),
+        members: CompactArray(members),
+        authorized_operations,
+        tagged_fields: 0,
+    }
+}
+
+impl ConsumerGroupDescribeRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<ConsumerGroupDescribeResponse, Error> {
+        let names: HashMap<UUID, String> = topic_metadata(&ctx.broker.metadata)
+            .into_values()
+            .map(|topic| (topic.id, topic.name))
+            .collect();
+
+        let groups = self
+            .group_ids
+            .iter()
+            .map(|group_id| {
+                let ops = ctx
+                    .broker
+                    .metadata
+                    .read(|image| ctx.authorized_operations(image, resource::GROUP, group_id));
+                let authorized_operations = if self.include_authorized_operations != 0 {
+                    ops
+                } else {
+                    OPERATIONS_NOT_REQUESTED
+                };
+                let (error_code, error_message) =
+                    if ops & operation_bits([operation::DESCRIBE]) == 0 {
+                        (
+                            GROUP_AUTHORIZATION_FAILED,
+                            "Group authorization failed".to_string(),
+                        )
+                    } else {
+                        let described = ctx.broker.groups.with_consumer_group(group_id, |group| {
+                            describe_group(group, &names, authorized_operations)
+                        });
+                        if let Some(described) = described {
+                            return described;
+                        }
+                        (
+                            GROUP_ID_NOT_FOUND,
+                            format!("group {} is not a consumer group", group_id.0),
+                        )
+                    };
+
+                ConsumerGroupDescribeGroup {
+                    error_code,
+                    error_message: CompactNullableString(Some(error_message)),
+                    group_id: group_id.clone(),
+                    group_state: CompactString::default(),
+                    group_epoch: -1,
+                    assignment_epoch: -1,
+                    assignor_name: CompactString::default(),
+                    members: CompactArray::default(),
+                    authorized_operations,
+                    tagged_fields: 0,
+                }
+            })
+            .collect();
+
+        Ok(ConsumerGroupDescribeResponse {
+            throttle_time_ms: 0,
+            groups: CompactArray(groups),
+            tagged_fields: 0,
+        })
+    }
+}
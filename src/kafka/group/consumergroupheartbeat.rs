@@ -0,0 +1,152 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::GROUP_AUTHORIZATION_FAILED,
+        RequestContext,
+    },
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        cstring::{CompactNullableString, CompactString},
+        nullable::NullableStruct,
+        uuid::UUID,
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{
+    consumer::{topic_metadata, Assignment, ConsumerHeartbeat},
+    joingroup::millis,
+};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct ConsumerGroupHeartbeatTopicPartitions {
+    pub topic_id: UUID,
+    pub partitions: CompactArray<i32>,
+    pub tagged_fields: u8,
+}
+
+/// ConsumerGroupHeartbeat v0. Nullable fields are null when unchanged since
+/// the member's previous heartbeat.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConsumerGroupHeartbeatRequest {
+    pub group_id: CompactString,
+    pub member_id: CompactString,
+    pub member_epoch: i32,
+    pub instance_id: CompactNullableString,
+    pub rack_id: CompactNullableString,
+    /// -1 when unchanged.
+    pub rebalance_timeout_ms: i32,
+    pub subscribed_topic_names: CompactNullableArray<CompactString>,
+    pub server_assignor: CompactNullableString,
+    /// The partitions the member owns.
+    pub topic_partitions: CompactNullableArray<ConsumerGroupHeartbeatTopicPartitions>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConsumerGroupHeartbeatAssignment {
+    pub topic_partitions: CompactArray<ConsumerGroupHeartbeatTopicPartitions>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConsumerGroupHeartbeatResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub member_id: CompactNullableString,
+    pub member_epoch: i32,
+    pub heartbeat_interval_ms: i32,
+    /// Null when the assignment did not change.
+    pub assignment: NullableStruct<ConsumerGroupHeartbeatAssignment>,
+    pub tagged_fields: u8,
+}
+
+pub(crate) fn to_assignment(topics: &[ConsumerGroupHeartbeatTopicPartitions]) -> Assignment {
+    topics
+        .iter()
+        .map(|t| (t.topic_id.clone(), t.partitions.iter().copied().collect()))
+        .collect()
+}
+
+pub(crate) fn from_assignment(
+    assignment: &Assignment,
+) -> CompactArray<ConsumerGroupHeartbeatTopicPartitions> {
+    CompactArray(
+        assignment
+            .iter()
+            .map(
+                |(topic_id, partitions)| ConsumerGroupHeartbeatTopicPartitions {
+                    topic_id: topic_id.clone(),
+                    partitions: CompactArray(partitions.iter().copied().collect()),
+                    tagged_fields: 0,
+                },
+            )
+            .collect(),
+    )
+}
+
+impl ConsumerGroupHeartbeatRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<ConsumerGroupHeartbeatResponse, Error> {
+        if !ctx.authorize(operation::READ, resource::GROUP, &self.group_id) {
+            return Ok(ConsumerGroupHeartbeatResponse {
+                throttle_time_ms: 0,
+                error_code: GROUP_AUTHORIZATION_FAILED,
+                error_message: CompactNullableString(Some(
+                    "Group authorization failed".to_string(),
+                )),
+                member_id: CompactNullableString(None),
+                member_epoch: -1,
+                heartbeat_interval_ms: 0,
+                assignment: NullableStruct(None),
+                tagged_fields: 0,
+            });
+        }
+
+        let topics = topic_metadata(&ctx.broker.metadata);
+
+        let heartbeat = ConsumerHeartbeat {
+            group_id: self.group_id.0.clone(),
+            member_id: self.member_id.0.clone(),
+            member_epoch: self.member_epoch,
+            instance_id: self.instance_id.0.clone(),
+            rack_id: self.rack_id.0.clone(),
+            client_id: ctx.client_id.clone().unwrap_or_default(),
+            client_host: format!("/{}", ctx.peer_addr.ip()),
+            rebalance_timeout: (self.rebalance_timeout_ms >= 0)
+                .then(|| millis(self.rebalance_timeout_ms)),
+            subscribed_topics: self
+                .subscribed_topic_names
+                .0
+                .as_ref()
+                .map(|names| names.iter().map(|n| n.0.clone()).collect()),
+            server_assignor: self.server_assignor.0.clone(),
+            owned: self.topic_partitions.0.as_deref().map(to_assignment),
+        };
+
+        let result = ctx.broker.groups.consumer_heartbeat(heartbeat, &topics);
+
+        Ok(ConsumerGroupHeartbeatResponse {
+            throttle_time_ms: 0,
+            error_code: result.error_code,
+            error_message: CompactNullableString(result.error_message),
+            member_id: CompactNullableString(
+                (!result.member_id.is_empty()).then_some(result.member_id),
+            ),
+            member_epoch: result.member_epoch,
+            heartbeat_interval_ms: result.heartbeat_interval.as_millis() as i32,
+            assignment: NullableStruct(result.assignment.map(|assignment| {
+                ConsumerGroupHeartbeatAssignment {
+                    topic_partitions: from_assignment(&assignment),
+                    tagged_fields: 0,
+                }
+            })),
+            tagged_fields: 0,
+        })
+    }
+}
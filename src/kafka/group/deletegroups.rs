@@ -0,0 +1,88 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{GROUP_AUTHORIZATION_FAILED, GROUP_ID_NOT_FOUND, NONE, UNKNOWN_SERVER_ERROR},
+        RequestContext,
+    },
+    types::{array::CompactArray, cstring::CompactString},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+/// DeleteGroups v2.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteGroupsRequest {
+    pub groups_names: CompactArray<CompactString>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteGroupsResult {
+    pub group_id: CompactString,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteGroupsResponse {
+    pub throttle_time_ms: i32,
+    pub results: CompactArray<DeleteGroupsResult>,
+    pub tagged_fields: u8,
+}
+
+impl DeleteGroupsRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<DeleteGroupsResponse, Error> {
+        let mut results = vec![];
+
+        for group_id in self.groups_names.iter() {
+            let broker = ctx.broker.clone();
+            let group = group_id.0.clone();
+            if !ctx.authorize(operation::DELETE, resource::GROUP, &group) {
+                results.push(DeleteGroupsResult {
+                    group_id: group_id.clone(),
+                    error_code: GROUP_AUTHORIZATION_FAILED,
+                    tagged_fields: 0,
+                });
+                continue;
+            }
+
+            // A group only known from its committed offsets is empty.
+            let error_code = match broker.groups.delete(&group) {
+                GROUP_ID_NOT_FOUND if broker.offsets.groups().contains(&group) => NONE,
+                error_code => error_code,
+            };
+
+            let error_code = if error_code == NONE {
+                let deleted =
+                    tokio::task::spawn_blocking(move || broker.offsets.delete_group(&group))
+                        .await?;
+
+                match deleted {
+                    Ok(()) => NONE,
+                    Err(e) => {
+                        eprintln!("failed to delete offsets of group {}: {e:?}", group_id.0);
+                        UNKNOWN_SERVER_ERROR
+                    }
+                }
+            } else {
+                error_code
+            };
+
+            results.push(DeleteGroupsResult {
+                group_id: group_id.clone(),
+                error_code,
+                tagged_fields: 0,
+            });
+        }
+
+        Ok(DeleteGroupsResponse {
+            throttle_time_ms: 0,
+            results: CompactArray(results),
+            tagged_fields: 0,
+        })
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    kafka::{
+        acl::{operation, operation_bits, resource, OPERATIONS_NOT_REQUESTED},
+        errors::{GROUP_AUTHORIZATION_FAILED, GROUP_ID_NOT_FOUND, NONE},
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        bytes::CompactBytes,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{Group, GroupState};
+
+/// DescribeGroups v5.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeGroupsRequest {
+    pub groups: CompactArray<CompactString>,
+    pub include_authorized_operations: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeGroupsResponseMember {
+    pub member_id: CompactString,
+    pub group_instance_id: CompactNullableString,
+    pub client_id: CompactString,
+    pub client_host: CompactString,
+    /// The member's metadata for the group's selected protocol.
+    pub member_metadata: CompactBytes,
+    pub member_assignment: CompactBytes,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeGroupsResponseGroup {
+    pub error_code: i16,
+    pub group_id: CompactString,
+    pub group_state: CompactString,
+    pub protocol_type: CompactString,
+    /// The selected protocol, once the group is past its first rebalance.
+    pub protocol_data: CompactString,
+    pub members: CompactArray<DescribeGroupsResponseMember>,
+    pub authorized_operations: i32,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeGroupsResponse {
+    pub throttle_time_ms: i32,
+    pub groups: CompactArray<DescribeGroupsResponseGroup>,
+    pub tagged_fields: u8,
+}
+
+fn describe_group(group: &Group, authorized_operations: i32) -> DescribeGroupsResponseGroup {
+    let protocol = group.protocol_name.clone().unwrap_or_default();
+
+    // Metadata and assignments are only meaningful once the group settled.
+    let settled = group.state == GroupState::Stable;
+
+    let members = group
+        .members
+        .iter()
+        .map(|member| DescribeGroupsResponseMember {
+            member_id: member.member_id.clone().into(),
+            group_instance_id: CompactNullableString(member.group_instance_id.clone()),
+            client_id: member.client_id.clone().into(),
+            client_host: member.client_host.clone().into(),
+            member_metadata: CompactBytes(if settled {
+                member.metadata(&protocol)
+            } else {
+                Default::default()
+            }),
+            member_assignment: CompactBytes(if settled {
+                member.assignment.clone()
+            } else {
+                Default::default()
+            }),
+            tagged_fields: 0,
+        })
+        .collect();
+
+    DescribeGroupsResponseGroup {
+        error_code: NONE,
+        group_id: group.group_id.clone().into(),
+        group_state: group.state.name().into(),
+        protocol_type: group.protocol_type.clone().unwrap_or_default().into(),
+        protocol_data: protocol.into(),
+        members: CompactArray(members),
+        authorized_operations,
+        tagged_fields: 0,
+    }
+}
+
+/// A group without members, or one that cannot be described.
+fn empty_group(
+    group_id: &CompactString,
+    error_code: i16,
+    group_state: &str,
+    authorized_operations: i32,
+) -> DescribeGroupsResponseGroup {
+    DescribeGroupsResponseGroup {
+        error_code,
+        group_id: group_id.clone(),
+        group_state: group_state.into(),
+        protocol_type: CompactString::default(),
+        protocol_data: CompactString::default(),
+        members: CompactArray::default(),
+        authorized_operations,
+        tagged_fields: 0,
+    }
+}
+
+impl DescribeGroupsRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<DescribeGroupsResponse, Error> {
+        let coordinator = &ctx.broker.groups;
+        let offset_groups = ctx.broker.offsets.groups();
+
+        let groups = self
+            .groups
+            .iter()
+            .map(|group_id| {
+                let ops = ctx
+                    .broker
+                    .metadata
+                    .read(|image| ctx.authorized_operations(image, resource::GROUP, group_id));
+                let authorized_operations = if self.include_authorized_operations != 0 {
+                    ops
+                } else {
+                    OPERATIONS_NOT_REQUESTED
+                };
+                if ops & operation_bits([operation::DESCRIBE]) == 0 {
+                    return empty_group(
+                        group_id,
+                        GROUP_AUTHORIZATION_FAILED,
+                        "",
+                        authorized_operations,
+                    );
+                }
+
+                if let Some(group) = coordinator.with_group(group_id, |group| {
+                    describe_group(group, authorized_operations)
+                }) {
+                    return group;
+                }
+
+                // Consumer protocol groups are described by
+                // ConsumerGroupDescribe. Groups only known from their
+                // committed offsets are empty, and unknown groups dead.
+                let (error_code, group_state) =
+                    if coordinator.with_consumer_group(group_id, |_| ()).is_some() {
+                        (GROUP_ID_NOT_FOUND, "")
+                    } else if offset_groups.contains(&group_id.0) {
+                        (NONE, GroupState::Empty.name())
+                    } else {
+                        (NONE, GroupState::Dead.name())
+                    };
+
+                empty_group(group_id, error_code, group_state, authorized_operations)
+            })
+            .collect();
+
+        Ok(DescribeGroupsResponse {
+            throttle_time_ms: 0,
+            groups: CompactArray(groups),
+            tagged_fields: 0,
+        })
+    }
+}
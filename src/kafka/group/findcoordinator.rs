@@ -0,0 +1,88 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{
+            GROUP_AUTHORIZATION_FAILED, INVALID_REQUEST, NONE,
+            TRANSACTIONAL_ID_AUTHORIZATION_FAILED,
+        },
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+/// FindCoordinator v4+, which batches the keys to look up.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct FindCoordinatorRequest {
+    /// 0 for consumer groups, 1 for transactions.
+    pub key_type: i8,
+    pub coordinator_keys: CompactArray<CompactString>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct Coordinator {
+    pub key: CompactString,
+    pub node_id: i32,
+    pub host: CompactString,
+    pub port: i32,
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct FindCoordinatorResponse {
+    pub throttle_time_ms: i32,
+    pub coordinators: CompactArray<Coordinator>,
+    pub tagged_fields: u8,
+}
+
+impl FindCoordinatorRequest {
+    /// This broker coordinates every group and transaction.
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<FindCoordinatorResponse, Error> {
+        let config = &ctx.broker.config;
+
+        let coordinators = self
+            .coordinator_keys
+            .iter()
+            .map(|key| {
+                let error_code = match self.key_type {
+                    0 if !ctx.authorize(operation::DESCRIBE, resource::GROUP, key) => {
+                        GROUP_AUTHORIZATION_FAILED
+                    }
+                    1 if !ctx.authorize(operation::DESCRIBE, resource::TRANSACTIONAL_ID, key) => {
+                        TRANSACTIONAL_ID_AUTHORIZATION_FAILED
+                    }
+                    0 | 1 => NONE,
+                    _ => INVALID_REQUEST,
+                };
+                let found = error_code == NONE;
+
+                Coordinator {
+                    key: key.clone(),
+                    node_id: if found { config.node_id } else { -1 },
+                    host: if found { config.host.as_str() } else { "" }.into(),
+                    port: if found { config.port } else { -1 },
+                    error_code,
+                    error_message: CompactNullableString(None),
+                    tagged_fields: 0,
+                }
+            })
+            .collect();
+
+        Ok(FindCoordinatorResponse {
+            throttle_time_ms: 0,
+            coordinators: CompactArray(coordinators),
+            tagged_fields: 0,
+        })
+    }
+}
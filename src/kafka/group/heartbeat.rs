@@ -0,0 +1,46 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::GROUP_AUTHORIZATION_FAILED,
+        RequestContext,
+    },
+    types::cstring::{CompactNullableString, CompactString},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+/// Heartbeat v4.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct HeartbeatRequest {
+    pub group_id: CompactString,
+    pub generation_id: i32,
+    pub member_id: CompactString,
+    pub group_instance_id: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct HeartbeatResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+impl HeartbeatRequest {
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<HeartbeatResponse, Error> {
+        let error_code = if ctx.authorize(operation::READ, resource::GROUP, &self.group_id) {
+            ctx.broker
+                .groups
+                .heartbeat(&self.group_id, self.generation_id, &self.member_id)
+        } else {
+            GROUP_AUTHORIZATION_FAILED
+        };
+
+        Ok(HeartbeatResponse {
+            throttle_time_ms: 0,
+            error_code,
+            tagged_fields: 0,
+        })
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::GROUP_AUTHORIZATION_FAILED,
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        bytes::CompactBytes,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{JoinParams, JoinResult};
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct JoinGroupRequestProtocol {
+    pub name: CompactString,
+    pub metadata: CompactBytes,
+    pub tagged_fields: u8,
+}
+
+/// JoinGroup v9.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct JoinGroupRequest {
+    pub group_id: CompactString,
+    pub session_timeout_ms: i32,
+    pub rebalance_timeout_ms: i32,
+    pub member_id: CompactString,
+    pub group_instance_id: CompactNullableString,
+    pub protocol_type: CompactString,
+    pub protocols: CompactArray<JoinGroupRequestProtocol>,
+    pub reason: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct JoinGroupResponseMember {
+    pub member_id: CompactString,
+    pub group_instance_id: CompactNullableString,
+    pub metadata: CompactBytes,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct JoinGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub generation_id: i32,
+    pub protocol_type: CompactNullableString,
+    pub protocol_name: CompactNullableString,
+    pub leader: CompactString,
+    pub skip_assignment: u8,
+    pub member_id: CompactString,
+    pub members: CompactArray<JoinGroupResponseMember>,
+    pub tagged_fields: u8,
+}
+
+impl From<JoinResult> for JoinGroupResponse {
+    fn from(result: JoinResult) -> Self {
+        let members = result
+            .members
+            .into_iter()
+            .map(|m| JoinGroupResponseMember {
+                member_id: m.member_id.into(),
+                group_instance_id: m.group_instance_id.into(),
+                metadata: m.metadata.into(),
+                tagged_fields: 0,
+            })
+            .collect();
+
+        Self {
+            throttle_time_ms: 0,
+            error_code: result.error_code,
+            generation_id: result.generation_id,
+            protocol_type: result.protocol_type.into(),
+            protocol_name: result.protocol_name.into(),
+            leader: result.leader.into(),
+            skip_assignment: 0,
+            member_id: result.member_id.into(),
+            members: CompactArray(members),
+            tagged_fields: 0,
+        }
+    }
+}
+
+/// Converts a timeout in milliseconds, treating negative values as zero.
+pub(crate) fn millis(value: i32) -> Duration {
+    Duration::from_millis(value.max(0) as u64)
+}
+
+impl JoinGroupRequest {
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<JoinGroupResponse, Error> {
+        if !ctx.authorize(operation::READ, resource::GROUP, &self.group_id) {
+            return Ok(JoinResult::error(GROUP_AUTHORIZATION_FAILED).into());
+        }
+
+        let params = JoinParams {
+            group_id: self.group_id.0.clone(),
+            member_id: self.member_id.0.clone(),
+            group_instance_id: self.group_instance_id.0.clone(),
+            client_id: ctx.client_id.clone().unwrap_or_default(),
+            client_host: format!("/{}", ctx.peer_addr.ip()),
+            session_timeout: millis(self.session_timeout_ms),
+            rebalance_timeout: millis(self.rebalance_timeout_ms),
+            protocol_type: self.protocol_type.0.clone(),
+            protocols: self
+                .protocols
+                .iter()
+                .map(|p| (p.name.0.clone(), p.metadata.0.clone()))
+                .collect(),
+        };
+
+        Ok(ctx.broker.groups.join(params).await.into())
+    }
+}
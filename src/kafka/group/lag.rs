@@ -0,0 +1,84 @@
+use std::io;
+
+use crate::kafka::log::partition::LogManager;
+
+use super::offsets::GroupOffsets;
+
+/// How far a group's committed offset trails a partition's high watermark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionLag {
+    pub topic: String,
+    pub partition: i32,
+    pub committed_offset: i64,
+    /// `None` if the partition has no log on this broker.
+    pub log_end_offset: Option<i64>,
+    pub lag: Option<i64>,
+}
+
+/// Computes the lag of every partition a group committed an offset for,
+/// ordered by topic and partition.
+pub fn group_lag(offsets: &GroupOffsets, logs: &LogManager) -> io::Result<Vec<PartitionLag>> {
+    offsets
+        .iter()
+        .map(|((topic, partition), committed)| {
+            let log_end_offset = logs.log_end_offset(topic, *partition)?;
+
+            Ok(PartitionLag {
+                topic: topic.clone(),
+                partition: *partition,
+                committed_offset: committed.offset,
+                log_end_offset,
+                lag: log_end_offset.map(|end| (end - committed.offset).max(0)),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::kafka::{
+        group::offsets::CommittedOffset,
+        log::{TopicRecordBatch, TopicRecordDisk},
+    };
+
+    #[test]
+    fn test_lag_against_log_end_offset() {
+        let dir = std::env::temp_dir().join(format!("lag-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let logs = LogManager::new(&dir);
+
+        let records = (0..5)
+            .map(|i| TopicRecordDisk::new(i, 0, None, Some("v".into())))
+            .collect();
+        let log = logs.partition("topic", 0).unwrap();
+        log.lock()
+            .unwrap()
+            .append(&mut TopicRecordBatch::new(0, records))
+            .unwrap();
+
+        let offsets = GroupOffsets::from([
+            (
+                ("topic".to_string(), 0),
+                CommittedOffset::new(3, -1, String::new()),
+            ),
+            (
+                ("missing".to_string(), 0),
+                CommittedOffset::new(3, -1, String::new()),
+            ),
+        ]);
+
+        let lag = group_lag(&offsets, &logs).unwrap();
+        assert_eq!((lag[0].topic.as_str(), lag[0].lag), ("missing", None));
+        assert_eq!(lag[1].log_end_offset, Some(5));
+        assert_eq!(lag[1].lag, Some(2));
+
+        // A fresh manager reads the end offset without opening the log.
+        let lag = group_lag(&offsets, &LogManager::new(&dir)).unwrap();
+        assert_eq!(lag[1].lag, Some(2));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
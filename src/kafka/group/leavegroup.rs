@@ -0,0 +1,171 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{GROUP_AUTHORIZATION_FAILED, NONE},
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+        encode_unsigned_varint, unsigned_varint_bytes_wide,
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+#[derive(Debug)]
+pub struct MemberIdentity {
+    pub member_id: CompactString,
+    pub group_instance_id: CompactNullableString,
+    /// Only sent from v5 on.
+    pub reason: CompactNullableString,
+}
+
+/// LeaveGroup v4 and v5, which differ only in the per-member reason.
+#[derive(Debug)]
+pub struct LeaveGroupRequest {
+    pub group_id: CompactString,
+    pub members: Vec<MemberIdentity>,
+}
+
+impl LeaveGroupRequest {
+    pub fn decode_version(bytes: &Bytes, offset: &mut usize, version: i16) -> Self {
+        let group_id = CompactString::decode(bytes, offset);
+        let count = crate::types::decode_unsigned_varint(bytes, offset)
+            .0
+            .saturating_sub(1);
+        let count = crate::types::checked_count(bytes, offset, count as usize);
+
+        let members = (0..count)
+            .map(|_| {
+                let member = MemberIdentity {
+                    member_id: CompactString::decode(bytes, offset),
+                    group_instance_id: CompactNullableString::decode(bytes, offset),
+                    reason: if version >= 5 {
+                        CompactNullableString::decode(bytes, offset)
+                    } else {
+                        CompactNullableString(None)
+                    },
+                };
+                u8::decode(bytes, offset);
+                member
+            })
+            .collect();
+        u8::decode(bytes, offset);
+
+        Self { group_id, members }
+    }
+}
+
+/// Encodes the v5 layout, as the client only sends v5.
+impl Encode for LeaveGroupRequest {
+    fn encode(&self, buf: &mut BytesMut) {
+        self.group_id.encode(buf);
+        encode_unsigned_varint(self.members.len() as u64 + 1, buf);
+        for member in &self.members {
+            member.member_id.encode(buf);
+            member.group_instance_id.encode(buf);
+            member.reason.encode(buf);
+            buf.put_u8(0);
+        }
+        buf.put_u8(0);
+    }
+}
+
+impl Size for LeaveGroupRequest {
+    fn size_in_bytes(&self) -> usize {
+        let members: usize = self
+            .members
+            .iter()
+            .map(|member| {
+                member.member_id.size_in_bytes()
+                    + member.group_instance_id.size_in_bytes()
+                    + member.reason.size_in_bytes()
+                    + 1
+            })
+            .sum();
+        self.group_id.size_in_bytes()
+            + unsigned_varint_bytes_wide(self.members.len() + 1)
+            + members
+            + 1
+    }
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct MemberResponse {
+    pub member_id: CompactString,
+    pub group_instance_id: CompactNullableString,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct LeaveGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub members: CompactArray<MemberResponse>,
+    pub tagged_fields: u8,
+}
+
+impl LeaveGroupRequest {
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<LeaveGroupResponse, Error> {
+        if !ctx.authorize(operation::READ, resource::GROUP, &self.group_id) {
+            return Ok(LeaveGroupResponse {
+                throttle_time_ms: 0,
+                error_code: GROUP_AUTHORIZATION_FAILED,
+                members: CompactArray(vec![]),
+                tagged_fields: 0,
+            });
+        }
+
+        let members = self
+            .members
+            .iter()
+            .map(|member| MemberResponse {
+                member_id: member.member_id.clone(),
+                group_instance_id: member.group_instance_id.clone(),
+                error_code: ctx.broker.groups.leave(
+                    &self.group_id,
+                    &member.member_id,
+                    member.group_instance_id.0.as_deref(),
+                ),
+                tagged_fields: 0,
+            })
+            .collect();
+
+        Ok(LeaveGroupResponse {
+            throttle_time_ms: 0,
+            error_code: NONE,
+            members: CompactArray(members),
+            tagged_fields: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_reason_from_v5() {
+        let v4 = Bytes::from_static(&[2, b'g', 2, 2, b'm', 0, 0, 0]);
+        let mut offset = 0;
+        let request = LeaveGroupRequest::decode_version(&v4, &mut offset, 4);
+        assert_eq!(offset, v4.len());
+        assert_eq!(request.members[0].member_id.0, "m");
+
+        let v5 = Bytes::from_static(&[2, b'g', 2, 2, b'm', 0, 3, b'b', b'y', 0, 0]);
+        let mut offset = 0;
+        let request = LeaveGroupRequest::decode_version(&v5, &mut offset, 5);
+        assert_eq!(offset, v5.len());
+        assert_eq!(request.members[0].reason.0.as_deref(), Some("by"));
+
+        let mut encoded = BytesMut::new();
+        request.encode(&mut encoded);
+        assert_eq!(encoded, v5);
+        assert_eq!(request.size_in_bytes(), v5.len());
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    kafka::{
+        acl::{operation, resource, CLUSTER_NAME},
+        errors::NONE,
+        RequestContext,
+    },
+    types::{array::CompactArray, cstring::CompactString},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{GroupListing, CLASSIC_GROUP_TYPE};
+
+/// ListGroups v5. Empty filters match every group.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ListGroupsRequest {
+    pub states_filter: CompactArray<CompactString>,
+    pub types_filter: CompactArray<CompactString>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ListGroupsResponseGroup {
+    pub group_id: CompactString,
+    pub protocol_type: CompactString,
+    pub group_state: CompactString,
+    pub group_type: CompactString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ListGroupsResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub groups: CompactArray<ListGroupsResponseGroup>,
+    pub tagged_fields: u8,
+}
+
+fn matches(filter: &CompactArray<CompactString>, value: &str) -> bool {
+    filter.is_empty() || filter.iter().any(|f| f.eq_ignore_ascii_case(value))
+}
+
+impl ListGroupsRequest {
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<ListGroupsResponse, Error> {
+        let mut listings: BTreeMap<String, GroupListing> = ctx
+            .broker
+            .groups
+            .list()
+            .into_iter()
+            .map(|listing| (listing.group_id.clone(), listing))
+            .collect();
+
+        // Groups only known from their committed offsets are empty.
+        for group_id in ctx.broker.offsets.groups() {
+            listings
+                .entry(group_id.clone())
+                .or_insert_with(|| GroupListing {
+                    group_id,
+                    protocol_type: String::new(),
+                    state: "Empty".to_string(),
+                    group_type: CLASSIC_GROUP_TYPE,
+                });
+        }
+
+        // Clients that may not describe the cluster only see the groups
+        // they may describe.
+        if !ctx.authorize(operation::DESCRIBE, resource::CLUSTER, CLUSTER_NAME) {
+            ctx.broker.metadata.read(|image| {
+                listings.retain(|group_id, _| {
+                    ctx.authorized(image, operation::DESCRIBE, resource::GROUP, group_id)
+                })
+            });
+        }
+
+        let groups = listings
+            .into_values()
+            .filter(|listing| {
+                matches(&self.states_filter, &listing.state)
+                    && matches(&self.types_filter, listing.group_type)
+            })
+            .map(|listing| ListGroupsResponseGroup {
+                group_id: listing.group_id.into(),
+                protocol_type: listing.protocol_type.into(),
+                group_state: listing.state.into(),
+                group_type: listing.group_type.into(),
+                tagged_fields: 0,
+            })
+            .collect();
+
+        Ok(ListGroupsResponse {
+            throttle_time_ms: 0,
+            error_code: NONE,
+            groups: CompactArray(groups),
+            tagged_fields: 0,
+        })
+    }
+}
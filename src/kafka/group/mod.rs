@@ -0,0 +1,1203 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use super::errors::{
+    COORDINATOR_NOT_AVAILABLE, GROUP_ID_NOT_FOUND, ILLEGAL_GENERATION, INCONSISTENT_GROUP_PROTOCOL,
+    INVALID_SESSION_TIMEOUT, MEMBER_ID_REQUIRED, NONE, NON_EMPTY_GROUP, REBALANCE_IN_PROGRESS,
+    STALE_MEMBER_EPOCH, UNKNOWN_MEMBER_ID,
+};
+use consumer::ConsumerGroup;
+
+pub mod consumer;
+pub mod consumergroupdescribe;
+pub mod consumergroupheartbeat;
+pub mod deletegroups;
+pub mod describegroups;
+pub mod findcoordinator;
+pub mod heartbeat;
+pub mod joingroup;
+pub mod lag;
+pub mod leavegroup;
+pub mod listgroups;
+pub mod offsetcommit;
+pub mod offsetdelete;
+pub mod offsetfetch;
+pub mod offsets;
+pub mod syncgroup;
+
+/// The classic group membership state machine.
+///
+/// A group starts `Empty`. A join moves it to `PreparingRebalance`, where it
+/// waits for every known member to rejoin. It then moves to
+/// `CompletingRebalance` until the leader sends the assignment with its
+/// SyncGroup, and finally to `Stable`. `Dead` groups are being removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupState {
+    Empty,
+    PreparingRebalance,
+    CompletingRebalance,
+    Stable,
+    Dead,
+}
+
+impl GroupState {
+    /// The name reported by ListGroups and DescribeGroups.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Empty => "Empty",
+            Self::PreparingRebalance => "PreparingRebalance",
+            Self::CompletingRebalance => "CompletingRebalance",
+            Self::Stable => "Stable",
+            Self::Dead => "Dead",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupConfig {
+    pub min_session_timeout: Duration,
+    pub max_session_timeout: Duration,
+    /// How long a new group waits for more members before its first rebalance.
+    pub initial_rebalance_delay: Duration,
+    /// Session timeout of consumer protocol members.
+    pub consumer_session_timeout: Duration,
+    /// How often consumer protocol members are asked to heartbeat.
+    pub consumer_heartbeat_interval: Duration,
+}
+
+impl Default for GroupConfig {
+    fn default() -> Self {
+        Self {
+            min_session_timeout: Duration::from_secs(6),
+            max_session_timeout: Duration::from_secs(30 * 60),
+            initial_rebalance_delay: Duration::from_secs(3),
+            consumer_session_timeout: Duration::from_secs(45),
+            consumer_heartbeat_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Member {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub client_id: String,
+    pub client_host: String,
+    pub session_timeout: Duration,
+    pub rebalance_timeout: Duration,
+    /// The protocols this member supports with their metadata, in order of
+    /// preference.
+    pub protocols: Vec<(String, Bytes)>,
+    pub assignment: Bytes,
+    last_heartbeat: Instant,
+    awaiting_join: Option<oneshot::Sender<JoinResult>>,
+    awaiting_sync: Option<oneshot::Sender<SyncResult>>,
+}
+
+impl Member {
+    pub fn metadata(&self, protocol: &str) -> Bytes {
+        self.protocols
+            .iter()
+            .find(|(name, _)| name == protocol)
+            .map(|(_, metadata)| metadata.clone())
+            .unwrap_or_default()
+    }
+
+    fn supports(&self, protocol: &str) -> bool {
+        self.protocols.iter().any(|(name, _)| name == protocol)
+    }
+}
+
+#[derive(Debug)]
+pub struct Group {
+    pub group_id: String,
+    pub state: GroupState,
+    pub generation_id: i32,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub leader: Option<String>,
+    /// Members in the order they joined; the first becomes leader when the
+    /// current one leaves.
+    pub members: Vec<Member>,
+    /// Member ids handed out with MEMBER_ID_REQUIRED that have not rejoined.
+    pending_members: HashMap<String, Instant>,
+    /// Bumped on every rebalance so that stale timers can tell they are stale.
+    rebalance_epoch: u64,
+    /// Set while a new group waits out the initial rebalance delay.
+    initial_delay: bool,
+}
+
+impl Group {
+    fn new(group_id: &str) -> Self {
+        Self {
+            group_id: group_id.to_string(),
+            state: GroupState::Empty,
+            generation_id: 0,
+            protocol_type: None,
+            protocol_name: None,
+            leader: None,
+            members: vec![],
+            pending_members: HashMap::new(),
+            rebalance_epoch: 0,
+            initial_delay: false,
+        }
+    }
+
+    pub fn member(&self, member_id: &str) -> Option<&Member> {
+        self.members.iter().find(|m| m.member_id == member_id)
+    }
+
+    fn member_mut(&mut self, member_id: &str) -> Option<&mut Member> {
+        self.members.iter_mut().find(|m| m.member_id == member_id)
+    }
+
+    /// Hands a static member's place to the id it rejoined with. Requests
+    /// still waiting under the old id are dropped, which fails them.
+    fn replace_member(&mut self, old: &str, new: &str) {
+        if let Some(member) = self.member_mut(old) {
+            member.member_id = new.to_string();
+            member.awaiting_join = None;
+            member.awaiting_sync = None;
+        }
+        if self.leader.as_deref() == Some(old) {
+            self.leader = Some(new.to_string());
+        }
+    }
+
+    /// Whether a member joining with these protocols could agree with the
+    /// others on one of them.
+    fn supports(
+        &self,
+        member_id: &str,
+        protocol_type: &str,
+        protocols: &[(String, Bytes)],
+    ) -> bool {
+        if protocols.is_empty() || protocol_type.is_empty() {
+            return false;
+        }
+
+        let others: Vec<&Member> = self
+            .members
+            .iter()
+            .filter(|m| m.member_id != member_id)
+            .collect();
+
+        if others.is_empty() {
+            return true;
+        }
+
+        self.protocol_type.as_deref() == Some(protocol_type)
+            && protocols
+                .iter()
+                .any(|(name, _)| others.iter().all(|m| m.supports(name)))
+    }
+
+    /// Picks the protocol every member supports that most members prefer,
+    /// breaking ties by the first member's preference.
+    fn select_protocol(&self) -> Option<String> {
+        let first = self.members.first()?;
+        let candidates: Vec<&str> = first
+            .protocols
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| self.members.iter().all(|m| m.supports(name)))
+            .collect();
+
+        let votes = |candidate: &str| {
+            self.members
+                .iter()
+                .filter(|m| {
+                    m.protocols
+                        .iter()
+                        .find(|(name, _)| candidates.contains(&name.as_str()))
+                        .is_some_and(|(name, _)| name == candidate)
+                })
+                .count()
+        };
+
+        candidates
+            .iter()
+            .rev()
+            .max_by_key(|candidate| votes(candidate))
+            .map(|candidate| candidate.to_string())
+    }
+
+    fn all_joined(&self) -> bool {
+        self.members.iter().all(|m| m.awaiting_join.is_some())
+    }
+
+    fn try_complete_join(&mut self) {
+        if self.state == GroupState::PreparingRebalance && !self.initial_delay && self.all_joined()
+        {
+            self.complete_join();
+        }
+    }
+
+    /// Ends the join phase: members that did not rejoin are dropped, the
+    /// generation advances and every joined member gets its response.
+    fn complete_join(&mut self) {
+        self.members.retain(|m| m.awaiting_join.is_some());
+        self.initial_delay = false;
+        self.generation_id += 1;
+
+        if self.members.is_empty() {
+            self.state = GroupState::Empty;
+            self.protocol_name = None;
+            self.leader = None;
+            return;
+        }
+
+        self.protocol_name = self.select_protocol();
+        if !self
+            .leader
+            .as_deref()
+            .is_some_and(|leader| self.member(leader).is_some())
+        {
+            self.leader = Some(self.members[0].member_id.clone());
+        }
+        self.state = GroupState::CompletingRebalance;
+
+        let protocol = self.protocol_name.clone().unwrap_or_default();
+        let leader = self.leader.clone().unwrap_or_default();
+        let joined: Vec<JoinedMember> = self
+            .members
+            .iter()
+            .map(|m| JoinedMember {
+                member_id: m.member_id.clone(),
+                group_instance_id: m.group_instance_id.clone(),
+                metadata: m.metadata(&protocol),
+            })
+            .collect();
+
+        let now = Instant::now();
+        for member in &mut self.members {
+            member.last_heartbeat = now;
+
+            if let Some(sender) = member.awaiting_join.take() {
+                let members = if member.member_id == leader {
+                    joined.clone()
+                } else {
+                    vec![]
+                };
+
+                let _ = sender.send(JoinResult {
+                    error_code: NONE,
+                    generation_id: self.generation_id,
+                    protocol_type: self.protocol_type.clone(),
+                    protocol_name: self.protocol_name.clone(),
+                    leader: leader.clone(),
+                    member_id: member.member_id.clone(),
+                    members,
+                });
+            }
+        }
+    }
+
+    /// Stores the leader's assignment and releases every member waiting in
+    /// SyncGroup.
+    fn complete_sync(&mut self, assignments: Vec<(String, Bytes)>) {
+        let mut assignments: HashMap<String, Bytes> = assignments.into_iter().collect();
+        self.state = GroupState::Stable;
+
+        for member in &mut self.members {
+            member.assignment = assignments.remove(&member.member_id).unwrap_or_default();
+
+            if let Some(sender) = member.awaiting_sync.take() {
+                let _ = sender.send(SyncResult {
+                    error_code: NONE,
+                    protocol_type: self.protocol_type.clone(),
+                    protocol_name: self.protocol_name.clone(),
+                    assignment: member.assignment.clone(),
+                });
+            }
+        }
+    }
+
+    fn fail_sync_waiters(&mut self, error_code: i16) {
+        for member in &mut self.members {
+            if let Some(sender) = member.awaiting_sync.take() {
+                let _ = sender.send(SyncResult::error(error_code));
+            }
+        }
+    }
+
+    fn remove_member(&mut self, member_id: &str) -> Option<Member> {
+        let index = self.members.iter().position(|m| m.member_id == member_id)?;
+        let mut member = self.members.remove(index);
+
+        if let Some(sender) = member.awaiting_join.take() {
+            let _ = sender.send(JoinResult::error(UNKNOWN_MEMBER_ID));
+        }
+        if let Some(sender) = member.awaiting_sync.take() {
+            let _ = sender.send(SyncResult::error(UNKNOWN_MEMBER_ID));
+        }
+        if self.leader.as_deref() == Some(member_id) {
+            self.leader = None;
+        }
+
+        Some(member)
+    }
+}
+
+/// A JoinGroup as seen by the coordinator.
+#[derive(Debug, Clone, Default)]
+pub struct JoinParams {
+    pub group_id: String,
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub client_id: String,
+    pub client_host: String,
+    pub session_timeout: Duration,
+    pub rebalance_timeout: Duration,
+    pub protocol_type: String,
+    pub protocols: Vec<(String, Bytes)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JoinedMember {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub metadata: Bytes,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JoinResult {
+    pub error_code: i16,
+    pub generation_id: i32,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub leader: String,
+    pub member_id: String,
+    /// Every member with its metadata, sent to the leader only.
+    pub members: Vec<JoinedMember>,
+}
+
+impl JoinResult {
+    pub(crate) fn error(error_code: i16) -> Self {
+        Self {
+            error_code,
+            generation_id: -1,
+            ..Default::default()
+        }
+    }
+}
+
+/// A SyncGroup as seen by the coordinator.
+#[derive(Debug, Clone, Default)]
+pub struct SyncParams {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub assignments: Vec<(String, Bytes)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncResult {
+    pub error_code: i16,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub assignment: Bytes,
+}
+
+impl SyncResult {
+    pub(crate) fn error(error_code: i16) -> Self {
+        Self {
+            error_code,
+            ..Default::default()
+        }
+    }
+}
+
+pub const CONSUMER_PROTOCOL_TYPE: &str = "consumer";
+pub const CLASSIC_GROUP_TYPE: &str = "classic";
+pub const CONSUMER_GROUP_TYPE: &str = "consumer";
+
+/// A group as reported by ListGroups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupListing {
+    pub group_id: String,
+    pub protocol_type: String,
+    pub state: String,
+    pub group_type: &'static str,
+}
+
+/// Reads the topics from a consumer protocol subscription: an INT16 version
+/// followed by an array of topic names. Malformed metadata has none.
+fn subscription_topics(metadata: &[u8]) -> Vec<String> {
+    let read_i16 = |at: usize| {
+        metadata
+            .get(at..at + 2)
+            .map(|b| i16::from_be_bytes([b[0], b[1]]))
+    };
+
+    let Some(count) = metadata
+        .get(2..6)
+        .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    else {
+        return vec![];
+    };
+
+    let mut topics = vec![];
+    let mut at = 6;
+    for _ in 0..count.max(0) {
+        let Some(len) = read_i16(at) else {
+            return vec![];
+        };
+        let start = at + 2;
+        let Some(name) = metadata.get(start..start + len.max(0) as usize) else {
+            return vec![];
+        };
+        topics.push(String::from_utf8_lossy(name).into_owned());
+        at = start + len.max(0) as usize;
+    }
+
+    topics
+}
+
+type Groups = Arc<Mutex<HashMap<String, Group>>>;
+type ConsumerGroups = Arc<Mutex<HashMap<String, ConsumerGroup>>>;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Tracks every consumer group served by this broker.
+#[derive(Debug, Clone, Default)]
+pub struct GroupCoordinator {
+    config: GroupConfig,
+    groups: Groups,
+    /// Groups using the consumer protocol. Locked after `groups` when both
+    /// are needed.
+    consumer_groups: ConsumerGroups,
+}
+
+impl GroupCoordinator {
+    pub fn new(config: GroupConfig) -> Self {
+        Self {
+            config,
+            groups: Groups::default(),
+            consumer_groups: ConsumerGroups::default(),
+        }
+    }
+
+    /// Joins a member to a group and waits for the rebalance to complete.
+    pub async fn join(&self, mut params: JoinParams) -> JoinResult {
+        let receiver = {
+            let mut groups = lock(&self.groups);
+
+            if params.session_timeout < self.config.min_session_timeout
+                || params.session_timeout > self.config.max_session_timeout
+            {
+                return JoinResult::error(INVALID_SESSION_TIMEOUT);
+            }
+
+            if lock(&self.consumer_groups)
+                .get(&params.group_id)
+                .is_some_and(|group| !group.members.is_empty())
+            {
+                return JoinResult::error(INCONSISTENT_GROUP_PROTOCOL);
+            }
+
+            let group = groups
+                .entry(params.group_id.clone())
+                .or_insert_with(|| Group::new(&params.group_id));
+
+            if group.state == GroupState::Dead {
+                return JoinResult::error(COORDINATOR_NOT_AVAILABLE);
+            }
+
+            // A static member rejoining without its id, as after a restart,
+            // takes the place of the id it had.
+            let previous = match &params.group_instance_id {
+                Some(instance) if params.member_id.is_empty() => group
+                    .members
+                    .iter()
+                    .find(|m| m.group_instance_id.as_ref() == Some(instance))
+                    .map(|m| m.member_id.clone()),
+                _ => None,
+            };
+            let known_id = previous.as_deref().unwrap_or(&params.member_id);
+            if !group.supports(known_id, &params.protocol_type, &params.protocols) {
+                return JoinResult::error(INCONSISTENT_GROUP_PROTOCOL);
+            }
+
+            // New members are given an id and asked to rejoin with it, so
+            // that a retried join cannot add the same client twice. Static
+            // members (KIP-345) are known by their instance id instead, so
+            // they join with theirs straight away.
+            if params.member_id.is_empty() {
+                let member_id = format!("{}-{}", params.client_id, Uuid::new_v4());
+                match (&params.group_instance_id, previous) {
+                    (None, _) => {
+                        group
+                            .pending_members
+                            .insert(member_id.clone(), Instant::now());
+
+                        return JoinResult {
+                            member_id,
+                            ..JoinResult::error(MEMBER_ID_REQUIRED)
+                        };
+                    }
+                    (Some(_), Some(previous)) => group.replace_member(&previous, &member_id),
+                    (Some(_), None) => {
+                        group
+                            .pending_members
+                            .insert(member_id.clone(), Instant::now());
+                    }
+                }
+                params.member_id = member_id;
+            }
+
+            let pending = group.pending_members.remove(&params.member_id).is_some();
+            let (sender, receiver) = oneshot::channel();
+
+            match group.member_mut(&params.member_id) {
+                Some(member) => {
+                    member.protocols = params.protocols;
+                    member.session_timeout = params.session_timeout;
+                    member.rebalance_timeout = params.rebalance_timeout;
+                    member.awaiting_join = Some(sender);
+                }
+                None if pending => group.members.push(Member {
+                    member_id: params.member_id,
+                    group_instance_id: params.group_instance_id,
+                    client_id: params.client_id,
+                    client_host: params.client_host,
+                    session_timeout: params.session_timeout,
+                    rebalance_timeout: params.rebalance_timeout,
+                    protocols: params.protocols,
+                    assignment: Bytes::new(),
+                    last_heartbeat: Instant::now(),
+                    awaiting_join: Some(sender),
+                    awaiting_sync: None,
+                }),
+                None => return JoinResult::error(UNKNOWN_MEMBER_ID),
+            }
+
+            match group.state {
+                GroupState::Empty => {
+                    group.protocol_type = Some(params.protocol_type);
+                    group.initial_delay = true;
+                    let delay = self
+                        .config
+                        .initial_rebalance_delay
+                        .min(params.rebalance_timeout);
+                    self.prepare_rebalance(group, delay);
+                }
+                GroupState::Stable | GroupState::CompletingRebalance => {
+                    self.prepare_rebalance(group, Self::rebalance_timeout(group));
+                }
+                GroupState::PreparingRebalance | GroupState::Dead => {}
+            }
+
+            group.try_complete_join();
+            receiver
+        };
+
+        receiver
+            .await
+            .unwrap_or_else(|_| JoinResult::error(UNKNOWN_MEMBER_ID))
+    }
+
+    /// Waits for the leader's assignment and returns this member's share.
+    pub async fn sync(&self, params: SyncParams) -> SyncResult {
+        let receiver = {
+            let mut groups = lock(&self.groups);
+
+            let Some(group) = groups.get_mut(&params.group_id) else {
+                return SyncResult::error(UNKNOWN_MEMBER_ID);
+            };
+
+            if group.member(&params.member_id).is_none() {
+                return SyncResult::error(UNKNOWN_MEMBER_ID);
+            }
+
+            if params.generation_id != group.generation_id {
+                return SyncResult::error(ILLEGAL_GENERATION);
+            }
+
+            let mismatched = |requested: &Option<String>, actual: &Option<String>| {
+                requested.is_some() && requested != actual
+            };
+            if mismatched(&params.protocol_type, &group.protocol_type)
+                || mismatched(&params.protocol_name, &group.protocol_name)
+            {
+                return SyncResult::error(INCONSISTENT_GROUP_PROTOCOL);
+            }
+
+            match group.state {
+                GroupState::Empty | GroupState::Dead => {
+                    return SyncResult::error(UNKNOWN_MEMBER_ID)
+                }
+                GroupState::PreparingRebalance => return SyncResult::error(REBALANCE_IN_PROGRESS),
+                GroupState::Stable => {
+                    let member = group
+                        .member(&params.member_id)
+                        .expect("member checked above");
+
+                    return SyncResult {
+                        error_code: NONE,
+                        protocol_type: group.protocol_type.clone(),
+                        protocol_name: group.protocol_name.clone(),
+                        assignment: member.assignment.clone(),
+                    };
+                }
+                GroupState::CompletingRebalance => {}
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let member = group
+                .member_mut(&params.member_id)
+                .expect("member checked above");
+            member.awaiting_sync = Some(sender);
+            member.last_heartbeat = Instant::now();
+
+            if group.leader.as_deref() == Some(params.member_id.as_str()) {
+                group.complete_sync(params.assignments);
+            }
+
+            receiver
+        };
+
+        receiver
+            .await
+            .unwrap_or_else(|_| SyncResult::error(REBALANCE_IN_PROGRESS))
+    }
+
+    /// Keeps a member's session alive, telling it when it must rejoin.
+    pub fn heartbeat(&self, group_id: &str, generation_id: i32, member_id: &str) -> i16 {
+        let mut groups = lock(&self.groups);
+
+        let Some(group) = groups.get_mut(group_id) else {
+            return UNKNOWN_MEMBER_ID;
+        };
+
+        if matches!(group.state, GroupState::Empty | GroupState::Dead) {
+            return UNKNOWN_MEMBER_ID;
+        }
+
+        let state = group.state;
+        let current_generation = group.generation_id;
+
+        let Some(member) = group.member_mut(member_id) else {
+            return UNKNOWN_MEMBER_ID;
+        };
+
+        if generation_id != current_generation {
+            return ILLEGAL_GENERATION;
+        }
+
+        member.last_heartbeat = Instant::now();
+
+        if state == GroupState::PreparingRebalance {
+            REBALANCE_IN_PROGRESS
+        } else {
+            NONE
+        }
+    }
+
+    /// Checks that a member may commit offsets for its group. Groups without
+    /// members also take commits from clients outside the group, which send
+    /// a negative generation. Members of consumer protocol groups send their
+    /// member epoch instead.
+    pub fn validate_commit(&self, group_id: &str, generation_id: i32, member_id: &str) -> i16 {
+        let mut groups = lock(&self.groups);
+
+        if let Some(group) = lock(&self.consumer_groups).get(group_id) {
+            if !group.members.is_empty() || generation_id >= 0 {
+                return match group.members.get(member_id) {
+                    None => UNKNOWN_MEMBER_ID,
+                    Some(member) if member.member_epoch != generation_id => STALE_MEMBER_EPOCH,
+                    Some(_) => NONE,
+                };
+            }
+        }
+
+        let Some(group) = groups.get_mut(group_id) else {
+            return if generation_id < 0 {
+                NONE
+            } else {
+                ILLEGAL_GENERATION
+            };
+        };
+
+        match group.state {
+            GroupState::Dead => return COORDINATOR_NOT_AVAILABLE,
+            GroupState::Empty if generation_id < 0 => return NONE,
+            _ => {}
+        }
+
+        let state = group.state;
+        let current_generation = group.generation_id;
+
+        let Some(member) = group.member_mut(member_id) else {
+            return UNKNOWN_MEMBER_ID;
+        };
+
+        if generation_id != current_generation {
+            return ILLEGAL_GENERATION;
+        }
+
+        if state == GroupState::CompletingRebalance {
+            return REBALANCE_IN_PROGRESS;
+        }
+
+        member.last_heartbeat = Instant::now();
+        NONE
+    }
+
+    /// Removes a member, identified by its member id or, for static members,
+    /// by its group instance id.
+    pub fn leave(&self, group_id: &str, member_id: &str, group_instance_id: Option<&str>) -> i16 {
+        let mut groups = lock(&self.groups);
+
+        let Some(group) = groups.get_mut(group_id) else {
+            return UNKNOWN_MEMBER_ID;
+        };
+
+        let member_id = match group_instance_id {
+            Some(instance) if member_id.is_empty() => group
+                .members
+                .iter()
+                .find(|m| m.group_instance_id.as_deref() == Some(instance))
+                .map(|m| m.member_id.clone()),
+            _ => Some(member_id.to_string()),
+        };
+
+        match member_id.and_then(|id| group.remove_member(&id)) {
+            Some(_) => {
+                self.members_removed(group);
+                NONE
+            }
+            None => UNKNOWN_MEMBER_ID,
+        }
+    }
+
+    /// Removes members whose session timed out, and member ids that were
+    /// never used to rejoin.
+    pub fn expire_members(&self) {
+        let mut groups = lock(&self.groups);
+        let now = Instant::now();
+
+        for group in groups.values_mut() {
+            let max_session = self.config.max_session_timeout;
+            group
+                .pending_members
+                .retain(|_, issued| now.duration_since(*issued) < max_session);
+
+            let expired: Vec<String> = group
+                .members
+                .iter()
+                .filter(|m| {
+                    m.awaiting_join.is_none()
+                        && now.duration_since(m.last_heartbeat) > m.session_timeout
+                })
+                .map(|m| m.member_id.clone())
+                .collect();
+
+            if expired.is_empty() {
+                continue;
+            }
+
+            for member_id in expired {
+                group.remove_member(&member_id);
+            }
+            self.members_removed(group);
+        }
+    }
+
+    /// Checks for expired sessions in the background.
+    pub fn spawn_expiration(&self) {
+        let coordinator = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(500));
+            loop {
+                interval.tick().await;
+                coordinator.expire_members();
+                coordinator.expire_consumer_members();
+            }
+        });
+    }
+
+    /// Every group the coordinator knows of, classic and consumer protocol,
+    /// ordered by group id.
+    pub fn list(&self) -> Vec<GroupListing> {
+        let groups = lock(&self.groups);
+        let consumer_groups = lock(&self.consumer_groups);
+
+        let classic = groups.values().map(|group| GroupListing {
+            group_id: group.group_id.clone(),
+            protocol_type: group.protocol_type.clone().unwrap_or_default(),
+            state: group.state.name().to_string(),
+            group_type: CLASSIC_GROUP_TYPE,
+        });
+        let consumer = consumer_groups.values().map(|group| GroupListing {
+            group_id: group.group_id.clone(),
+            protocol_type: CONSUMER_PROTOCOL_TYPE.to_string(),
+            state: group.state().to_string(),
+            group_type: CONSUMER_GROUP_TYPE,
+        });
+
+        let mut listings: Vec<GroupListing> = classic.chain(consumer).collect();
+        listings.sort_by(|a, b| a.group_id.cmp(&b.group_id));
+        listings
+    }
+
+    /// Removes a group that has no members. Its committed offsets are left
+    /// to the caller.
+    pub fn delete(&self, group_id: &str) -> i16 {
+        let mut groups = lock(&self.groups);
+        let mut consumer_groups = lock(&self.consumer_groups);
+
+        if let Some(group) = groups.get(group_id) {
+            if !group.members.is_empty() {
+                return NON_EMPTY_GROUP;
+            }
+            groups.remove(group_id);
+            return NONE;
+        }
+
+        match consumer_groups.get(group_id) {
+            Some(group) if !group.members.is_empty() => NON_EMPTY_GROUP,
+            Some(_) => {
+                consumer_groups.remove(group_id);
+                NONE
+            }
+            None => GROUP_ID_NOT_FOUND,
+        }
+    }
+
+    /// The topics a group's members are subscribed to, whose offsets may not
+    /// be deleted. `None` if the group is unknown, and NON_EMPTY_GROUP for
+    /// active groups of other protocol types.
+    pub fn subscribed_topics(&self, group_id: &str) -> Result<Option<HashSet<String>>, i16> {
+        let groups = lock(&self.groups);
+
+        if let Some(group) = groups.get(group_id) {
+            return match group.state {
+                GroupState::Dead => Ok(None),
+                GroupState::Empty => Ok(Some(HashSet::new())),
+                _ if group.protocol_type.as_deref() != Some(CONSUMER_PROTOCOL_TYPE) => {
+                    Err(NON_EMPTY_GROUP)
+                }
+                _ => {
+                    let protocol = group.protocol_name.clone().unwrap_or_default();
+                    Ok(Some(
+                        group
+                            .members
+                            .iter()
+                            .flat_map(|m| subscription_topics(&m.metadata(&protocol)))
+                            .collect(),
+                    ))
+                }
+            };
+        }
+
+        Ok(lock(&self.consumer_groups).get(group_id).map(|group| {
+            group
+                .members
+                .values()
+                .flat_map(|m| m.subscribed_topics.iter().cloned())
+                .collect()
+        }))
+    }
+
+    /// Runs `f` against a group, if it exists.
+    pub fn with_group<T>(&self, group_id: &str, f: impl FnOnce(&Group) -> T) -> Option<T> {
+        lock(&self.groups).get(group_id).map(f)
+    }
+
+    fn members_removed(&self, group: &mut Group) {
+        match group.state {
+            GroupState::Stable | GroupState::CompletingRebalance => {
+                self.prepare_rebalance(group, Self::rebalance_timeout(group));
+                group.try_complete_join();
+            }
+            GroupState::PreparingRebalance => group.try_complete_join(),
+            GroupState::Empty | GroupState::Dead => {}
+        }
+    }
+
+    fn rebalance_timeout(group: &Group) -> Duration {
+        group
+            .members
+            .iter()
+            .map(|m| m.rebalance_timeout)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Starts a rebalance that completes once every member has rejoined, or
+    /// after `delay` without the members that have not.
+    fn prepare_rebalance(&self, group: &mut Group, delay: Duration) {
+        if group.state == GroupState::CompletingRebalance {
+            group.fail_sync_waiters(REBALANCE_IN_PROGRESS);
+        }
+
+        group.state = GroupState::PreparingRebalance;
+        group.rebalance_epoch += 1;
+
+        let epoch = group.rebalance_epoch;
+        let group_id = group.group_id.clone();
+        let groups = self.groups.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+
+            let mut groups = lock(&groups);
+            if let Some(group) = groups.get_mut(&group_id) {
+                if group.rebalance_epoch == epoch && group.state == GroupState::PreparingRebalance {
+                    group.complete_join();
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coordinator() -> GroupCoordinator {
+        GroupCoordinator::new(GroupConfig {
+            initial_rebalance_delay: Duration::ZERO,
+            ..GroupConfig::default()
+        })
+    }
+
+    fn params(member_id: &str, protocols: &[&str]) -> JoinParams {
+        JoinParams {
+            group_id: "group".to_string(),
+            member_id: member_id.to_string(),
+            client_id: "client".to_string(),
+            session_timeout: Duration::from_secs(10),
+            rebalance_timeout: Duration::from_secs(5),
+            protocol_type: "consumer".to_string(),
+            protocols: protocols
+                .iter()
+                .map(|name| (name.to_string(), Bytes::from(name.to_string())))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    async fn join_new(coordinator: &GroupCoordinator, protocols: &[&str]) -> String {
+        let first = coordinator.join(params("", protocols)).await;
+        assert_eq!(first.error_code, MEMBER_ID_REQUIRED);
+        first.member_id
+    }
+
+    #[tokio::test]
+    async fn test_static_members_join_in_one_round_trip() {
+        let coordinator = coordinator();
+        let join_static = || JoinParams {
+            group_instance_id: Some("instance".to_string()),
+            ..params("", &["range"])
+        };
+
+        let joined = coordinator.join(join_static()).await;
+        assert_eq!(joined.error_code, NONE);
+        assert!(!joined.member_id.is_empty());
+        assert_eq!(joined.leader, joined.member_id);
+        let first = joined.member_id;
+
+        // Restarted, it rejoins without its id and takes over its place.
+        let rejoined = coordinator.join(join_static()).await;
+        assert_eq!(rejoined.error_code, NONE);
+        assert_ne!(rejoined.member_id, first);
+        assert_eq!(rejoined.leader, rejoined.member_id);
+        assert_eq!(rejoined.members.len(), 1);
+        assert_eq!(
+            rejoined.members[0].group_instance_id.as_deref(),
+            Some("instance")
+        );
+        assert_eq!(
+            coordinator.heartbeat("group", rejoined.generation_id, &first),
+            UNKNOWN_MEMBER_ID
+        );
+    }
+
+    #[tokio::test]
+    async fn test_single_member_rebalance() {
+        let coordinator = coordinator();
+        let member_id = join_new(&coordinator, &["range"]).await;
+
+        let joined = coordinator.join(params(&member_id, &["range"])).await;
+        assert_eq!(joined.error_code, NONE);
+        assert_eq!(joined.generation_id, 1);
+        assert_eq!(joined.leader, member_id);
+        assert_eq!(joined.protocol_name.as_deref(), Some("range"));
+        assert_eq!(joined.members.len(), 1);
+
+        let synced = coordinator
+            .sync(SyncParams {
+                group_id: "group".to_string(),
+                generation_id: 1,
+                member_id: member_id.clone(),
+                assignments: vec![(member_id.clone(), Bytes::from_static(b"p0"))],
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(synced.error_code, NONE);
+        assert_eq!(&synced.assignment[..], b"p0");
+
+        assert_eq!(coordinator.heartbeat("group", 1, &member_id), NONE);
+        assert_eq!(
+            coordinator.heartbeat("group", 0, &member_id),
+            ILLEGAL_GENERATION
+        );
+        assert_eq!(
+            coordinator.heartbeat("group", 1, "nobody"),
+            UNKNOWN_MEMBER_ID
+        );
+
+        assert_eq!(coordinator.leave("group", &member_id, None), NONE);
+        let state = coordinator.with_group("group", |g| (g.state, g.generation_id));
+        assert_eq!(state, Some((GroupState::Empty, 2)));
+        assert_eq!(coordinator.validate_commit("group", -1, ""), NONE);
+    }
+
+    #[tokio::test]
+    async fn test_second_member_triggers_rebalance() {
+        let coordinator = coordinator();
+        let leader = join_new(&coordinator, &["range", "roundrobin"]).await;
+        coordinator
+            .join(params(&leader, &["range", "roundrobin"]))
+            .await;
+
+        let follower = join_new(&coordinator, &["roundrobin"]).await;
+        let follower_join = {
+            let coordinator = coordinator.clone();
+            let follower = follower.clone();
+            tokio::spawn(async move { coordinator.join(params(&follower, &["roundrobin"])).await })
+        };
+
+        // The leader learns about the rebalance from its heartbeat.
+        while coordinator.heartbeat("group", 1, &leader) != REBALANCE_IN_PROGRESS {
+            tokio::task::yield_now().await;
+        }
+
+        let rejoined = coordinator
+            .join(params(&leader, &["range", "roundrobin"]))
+            .await;
+        let follower_join = follower_join.await.unwrap();
+
+        assert_eq!(rejoined.generation_id, 2);
+        assert_eq!(rejoined.protocol_name.as_deref(), Some("roundrobin"));
+        assert_eq!(rejoined.members.len(), 2);
+        assert_eq!(follower_join.leader, leader);
+        assert!(follower_join.members.is_empty());
+
+        let follower_sync = {
+            let coordinator = coordinator.clone();
+            let follower = follower.clone();
+            tokio::spawn(async move {
+                coordinator
+                    .sync(SyncParams {
+                        group_id: "group".to_string(),
+                        generation_id: 2,
+                        member_id: follower,
+                        ..Default::default()
+                    })
+                    .await
+            })
+        };
+
+        coordinator
+            .sync(SyncParams {
+                group_id: "group".to_string(),
+                generation_id: 2,
+                member_id: leader.clone(),
+                assignments: vec![(follower.clone(), Bytes::from_static(b"p1"))],
+                ..Default::default()
+            })
+            .await;
+
+        let follower_sync = follower_sync.await.unwrap();
+        assert_eq!(&follower_sync.assignment[..], b"p1");
+        let state = coordinator.with_group("group", |g| g.state);
+        assert_eq!(state, Some(GroupState::Stable));
+    }
+
+    #[tokio::test]
+    async fn test_join_validation() {
+        let coordinator = coordinator();
+
+        let mut short_session = params("", &["range"]);
+        short_session.session_timeout = Duration::from_secs(1);
+        assert_eq!(
+            coordinator.join(short_session).await.error_code,
+            INVALID_SESSION_TIMEOUT
+        );
+
+        assert_eq!(
+            coordinator
+                .join(params("unknown", &["range"]))
+                .await
+                .error_code,
+            UNKNOWN_MEMBER_ID
+        );
+
+        let member_id = join_new(&coordinator, &["range"]).await;
+        coordinator.join(params(&member_id, &["range"])).await;
+        assert_eq!(
+            coordinator.join(params("", &["sticky"])).await.error_code,
+            INCONSISTENT_GROUP_PROTOCOL
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_member_is_removed() {
+        let coordinator = GroupCoordinator::new(GroupConfig {
+            min_session_timeout: Duration::ZERO,
+            initial_rebalance_delay: Duration::ZERO,
+            ..GroupConfig::default()
+        });
+
+        let mut join = params("", &["range"]);
+        join.session_timeout = Duration::ZERO;
+        join.member_id = coordinator.join(join.clone()).await.member_id;
+        coordinator.join(join).await;
+
+        std::thread::sleep(Duration::from_millis(5));
+        coordinator.expire_members();
+
+        let state = coordinator.with_group("group", |g| (g.state, g.members.len()));
+        assert_eq!(state, Some((GroupState::Empty, 0)));
+    }
+
+    #[tokio::test]
+    async fn test_only_empty_groups_are_deleted() {
+        let coordinator = coordinator();
+        let member_id = join_new(&coordinator, &["range"]).await;
+        coordinator.join(params(&member_id, &["range"])).await;
+
+        let listed = coordinator.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].state, "CompletingRebalance");
+        assert_eq!(listed[0].group_type, CLASSIC_GROUP_TYPE);
+
+        assert_eq!(coordinator.delete("group"), NON_EMPTY_GROUP);
+        assert_eq!(coordinator.leave("group", &member_id, None), NONE);
+        assert_eq!(coordinator.delete("group"), NONE);
+        assert_eq!(coordinator.delete("group"), GROUP_ID_NOT_FOUND);
+        assert!(coordinator.list().is_empty());
+    }
+
+    #[test]
+    fn test_subscription_topics() {
+        let metadata = [0, 1, 0, 0, 0, 2, 0, 1, b'a', 0, 2, b'b', b'c', 0xff, 0xff];
+        assert_eq!(subscription_topics(&metadata), vec!["a", "bc"]);
+        assert!(subscription_topics(&metadata[..9]).is_empty());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{
+            GROUP_AUTHORIZATION_FAILED, NONE, OFFSET_METADATA_TOO_LARGE,
+            TOPIC_AUTHORIZATION_FAILED, UNKNOWN_SERVER_ERROR,
+        },
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::offsets::CommittedOffset;
+
+/// Longest metadata string stored with a commit.
+pub(crate) const MAX_METADATA_SIZE: usize = 4096;
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetCommitRequestPartition {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    pub committed_leader_epoch: i32,
+    pub committed_metadata: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetCommitRequestTopic {
+    pub name: CompactString,
+    pub partitions: CompactArray<OffsetCommitRequestPartition>,
+    pub tagged_fields: u8,
+}
+
+/// OffsetCommit v8 and v9, which share a layout.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetCommitRequest {
+    pub group_id: CompactString,
+    pub generation_id_or_member_epoch: i32,
+    pub member_id: CompactString,
+    pub group_instance_id: CompactNullableString,
+    pub topics: CompactArray<OffsetCommitRequestTopic>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetCommitResponsePartition {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetCommitResponseTopic {
+    pub name: CompactString,
+    pub partitions: CompactArray<OffsetCommitResponsePartition>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetCommitResponse {
+    pub throttle_time_ms: i32,
+    pub topics: CompactArray<OffsetCommitResponseTopic>,
+    pub tagged_fields: u8,
+}
+
+impl OffsetCommitRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<OffsetCommitResponse, Error> {
+        let broker = &ctx.broker;
+        let group_authorized = ctx.authorize(operation::READ, resource::GROUP, &self.group_id);
+        let group_error = if group_authorized {
+            broker.groups.validate_commit(
+                &self.group_id,
+                self.generation_id_or_member_epoch,
+                &self.member_id,
+            )
+        } else {
+            GROUP_AUTHORIZATION_FAILED
+        };
+        let denied = ctx.denied_topics(
+            operation::READ,
+            self.topics.iter().map(|topic| topic.name.as_str()),
+        );
+
+        let error_for = |topic: &OffsetCommitRequestTopic,
+                         partition: &OffsetCommitRequestPartition| {
+            let metadata_size = partition
+                .committed_metadata
+                .0
+                .as_ref()
+                .map_or(0, String::len);
+
+            match group_error {
+                GROUP_AUTHORIZATION_FAILED => GROUP_AUTHORIZATION_FAILED,
+                _ if denied.contains(topic.name.as_str()) => TOPIC_AUTHORIZATION_FAILED,
+                NONE if metadata_size > MAX_METADATA_SIZE => OFFSET_METADATA_TOO_LARGE,
+                error => error,
+            }
+        };
+
+        let commits: Vec<(String, i32, CommittedOffset)> = self
+            .topics
+            .iter()
+            .flat_map(|topic| topic.partitions.iter().map(move |p| (topic, p)))
+            .filter(|(topic, partition)| error_for(topic, partition) == NONE)
+            .map(|(topic, partition)| {
+                (
+                    topic.name.0.clone(),
+                    partition.partition_index,
+                    CommittedOffset::new(
+                        partition.committed_offset,
+                        partition.committed_leader_epoch,
+                        partition.committed_metadata.0.clone().unwrap_or_default(),
+                    ),
+                )
+            })
+            .collect();
+
+        let stored = {
+            let broker = broker.clone();
+            let group_id = self.group_id.0.clone();
+            tokio::task::spawn_blocking(move || broker.offsets.commit(&group_id, commits)).await?
+        };
+
+        if let Err(e) = &stored {
+            eprintln!(
+                "failed to store offsets for group {}: {e:?}",
+                self.group_id.0
+            );
+        }
+
+        let topics = self
+            .topics
+            .iter()
+            .map(|topic| OffsetCommitResponseTopic {
+                name: topic.name.clone(),
+                partitions: CompactArray(
+                    topic
+                        .partitions
+                        .iter()
+                        .map(|partition| OffsetCommitResponsePartition {
+                            partition_index: partition.partition_index,
+                            error_code: match error_for(topic, partition) {
+                                NONE if stored.is_err() => UNKNOWN_SERVER_ERROR,
+                                error => error,
+                            },
+                            tagged_fields: 0,
+                        })
+                        .collect(),
+                ),
+                tagged_fields: 0,
+            })
+            .collect();
+
+        Ok(OffsetCommitResponse {
+            throttle_time_ms: 0,
+            topics: CompactArray(topics),
+            tagged_fields: 0,
+        })
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{
+            GROUP_AUTHORIZATION_FAILED, GROUP_ID_NOT_FOUND, GROUP_SUBSCRIBED_TO_TOPIC, NONE,
+            TOPIC_AUTHORIZATION_FAILED, UNKNOWN_SERVER_ERROR,
+        },
+        RequestContext,
+    },
+    types::{array::Array32, kafkastring::String16},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetDeleteRequestPartition {
+    pub partition_index: i32,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetDeleteRequestTopic {
+    pub name: String16,
+    pub partitions: Array32<OffsetDeleteRequestPartition>,
+}
+
+/// OffsetDelete v0, which has no flexible version.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetDeleteRequest {
+    pub group_id: String16,
+    pub topics: Array32<OffsetDeleteRequestTopic>,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetDeleteResponsePartition {
+    pub partition_index: i32,
+    pub error_code: i16,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetDeleteResponseTopic {
+    pub name: String16,
+    pub partitions: Array32<OffsetDeleteResponsePartition>,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetDeleteResponse {
+    pub error_code: i16,
+    pub throttle_time_ms: i32,
+    pub topics: Array32<OffsetDeleteResponseTopic>,
+}
+
+impl OffsetDeleteRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<OffsetDeleteResponse, Error> {
+        let broker = &ctx.broker;
+        let group_id = &self.group_id.0;
+        if !ctx.authorize(operation::DELETE, resource::GROUP, group_id) {
+            return Ok(self.error(GROUP_AUTHORIZATION_FAILED));
+        }
+        let denied = ctx.denied_topics(
+            operation::READ,
+            self.topics.iter().map(|topic| topic.name.as_str()),
+        );
+
+        let subscribed = match broker.groups.subscribed_topics(group_id) {
+            Ok(Some(topics)) => topics,
+            Ok(None) if broker.offsets.groups().contains(group_id) => Default::default(),
+            Ok(None) => return Ok(self.error(GROUP_ID_NOT_FOUND)),
+            Err(error_code) => return Ok(self.error(error_code)),
+        };
+
+        let deleted: Vec<(String, i32)> = self
+            .topics
+            .iter()
+            .filter(|topic| !subscribed.contains(&topic.name.0) && !denied.contains(&topic.name.0))
+            .flat_map(|topic| {
+                topic
+                    .partitions
+                    .iter()
+                    .map(|p| (topic.name.0.clone(), p.partition_index))
+            })
+            .collect();
+
+        let stored = {
+            let broker = broker.clone();
+            let group_id = group_id.clone();
+            tokio::task::spawn_blocking(move || broker.offsets.delete(&group_id, deleted)).await?
+        };
+
+        if let Err(e) = &stored {
+            eprintln!("failed to delete offsets of group {group_id}: {e:?}");
+        }
+
+        let topics = self
+            .topics
+            .iter()
+            .map(|topic| {
+                let error_code = if denied.contains(&topic.name.0) {
+                    TOPIC_AUTHORIZATION_FAILED
+                } else if subscribed.contains(&topic.name.0) {
+                    GROUP_SUBSCRIBED_TO_TOPIC
+                } else if stored.is_err() {
+                    UNKNOWN_SERVER_ERROR
+                } else {
+                    NONE
+                };
+
+                OffsetDeleteResponseTopic {
+                    name: topic.name.clone(),
+                    partitions: Array32(
+                        topic
+                            .partitions
+                            .iter()
+                            .map(|p| OffsetDeleteResponsePartition {
+                                partition_index: p.partition_index,
+                                error_code,
+                            })
+                            .collect(),
+                    ),
+                }
+            })
+            .collect();
+
+        Ok(OffsetDeleteResponse {
+            error_code: NONE,
+            throttle_time_ms: 0,
+            topics: Array32(topics),
+        })
+    }
+
+    fn error(&self, error_code: i16) -> OffsetDeleteResponse {
+        OffsetDeleteResponse {
+            error_code,
+            throttle_time_ms: 0,
+            topics: Array32::default(),
+        }
+    }
+}
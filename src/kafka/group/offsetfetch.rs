@@ -0,0 +1,277 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{GROUP_AUTHORIZATION_FAILED, NONE, TOPIC_AUTHORIZATION_FAILED},
+        RequestContext,
+    },
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        cstring::{CompactNullableString, CompactString},
+        encode_unsigned_varint, unsigned_varint_bytes_wide,
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetFetchRequestTopic {
+    pub name: CompactString,
+    pub partition_indexes: CompactArray<i32>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug)]
+pub struct OffsetFetchRequestGroup {
+    pub group_id: CompactString,
+    /// Only sent from v9 on, by members of consumer protocol groups.
+    pub member_id: CompactNullableString,
+    pub member_epoch: i32,
+    /// Null asks for every partition with a committed offset.
+    pub topics: CompactNullableArray<OffsetFetchRequestTopic>,
+}
+
+/// OffsetFetch v8 and v9, which look up several groups at once.
+#[derive(Debug)]
+pub struct OffsetFetchRequest {
+    pub groups: Vec<OffsetFetchRequestGroup>,
+    pub require_stable: u8,
+}
+
+impl OffsetFetchRequest {
+    pub fn decode_version(bytes: &Bytes, offset: &mut usize, version: i16) -> Self {
+        let count = crate::types::decode_unsigned_varint(bytes, offset)
+            .0
+            .saturating_sub(1);
+        let count = crate::types::checked_count(bytes, offset, count as usize);
+
+        let groups = (0..count)
+            .map(|_| {
+                let group_id = CompactString::decode(bytes, offset);
+                let (member_id, member_epoch) = if version >= 9 {
+                    (
+                        CompactNullableString::decode(bytes, offset),
+                        i32::decode(bytes, offset),
+                    )
+                } else {
+                    (CompactNullableString(None), -1)
+                };
+                let topics = CompactNullableArray::decode(bytes, offset);
+                u8::decode(bytes, offset);
+
+                OffsetFetchRequestGroup {
+                    group_id,
+                    member_id,
+                    member_epoch,
+                    topics,
+                }
+            })
+            .collect();
+
+        let require_stable = u8::decode(bytes, offset);
+        u8::decode(bytes, offset);
+
+        Self {
+            groups,
+            require_stable,
+        }
+    }
+}
+
+/// Encodes the v9 layout, as the client only sends v9.
+impl Encode for OffsetFetchRequest {
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_unsigned_varint(self.groups.len() as u64 + 1, buf);
+        for group in &self.groups {
+            group.group_id.encode(buf);
+            group.member_id.encode(buf);
+            group.member_epoch.encode(buf);
+            group.topics.encode(buf);
+            buf.put_u8(0);
+        }
+        self.require_stable.encode(buf);
+        buf.put_u8(0);
+    }
+}
+
+impl Size for OffsetFetchRequest {
+    fn size_in_bytes(&self) -> usize {
+        let groups: usize = self
+            .groups
+            .iter()
+            .map(|group| {
+                group.group_id.size_in_bytes()
+                    + group.member_id.size_in_bytes()
+                    + 4
+                    + group.topics.size_in_bytes()
+                    + 1
+            })
+            .sum();
+        unsigned_varint_bytes_wide(self.groups.len() + 1) + groups + 2
+    }
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetFetchResponsePartition {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    pub committed_leader_epoch: i32,
+    pub metadata: CompactNullableString,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetFetchResponseTopic {
+    pub name: CompactString,
+    pub partitions: CompactArray<OffsetFetchResponsePartition>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetFetchResponseGroup {
+    pub group_id: CompactString,
+    pub topics: CompactArray<OffsetFetchResponseTopic>,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct OffsetFetchResponse {
+    pub throttle_time_ms: i32,
+    pub groups: CompactArray<OffsetFetchResponseGroup>,
+    pub tagged_fields: u8,
+}
+
+impl OffsetFetchRequest {
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<OffsetFetchResponse, Error> {
+        let offsets = &ctx.broker.offsets;
+
+        let groups = self
+            .groups
+            .iter()
+            .map(|group| {
+                if !ctx.authorize(operation::DESCRIBE, resource::GROUP, &group.group_id) {
+                    return OffsetFetchResponseGroup {
+                        group_id: group.group_id.clone(),
+                        topics: CompactArray(vec![]),
+                        error_code: GROUP_AUTHORIZATION_FAILED,
+                        tagged_fields: 0,
+                    };
+                }
+
+                let requested: Vec<(String, Vec<i32>)> = match &group.topics.0 {
+                    Some(topics) => topics
+                        .iter()
+                        .map(|t| (t.name.0.clone(), t.partition_indexes.0.clone()))
+                        .collect(),
+                    None => {
+                        let mut all: Vec<(String, Vec<i32>)> = vec![];
+                        for (topic, partition) in offsets.group_offsets(&group.group_id).keys() {
+                            match all.last_mut() {
+                                Some((name, partitions)) if name == topic => {
+                                    partitions.push(*partition)
+                                }
+                                _ => all.push((topic.clone(), vec![*partition])),
+                            }
+                        }
+                        all
+                    }
+                };
+                let denied = ctx.denied_topics(
+                    operation::DESCRIBE,
+                    requested.iter().map(|(name, _)| name.as_str()),
+                );
+                // Topics the client may not describe are failed if it asked
+                // for them, left out if it asked for all.
+                let requested: Vec<(String, Vec<i32>)> = requested
+                    .into_iter()
+                    .filter(|(name, _)| group.topics.0.is_some() || !denied.contains(name))
+                    .collect();
+
+                let topics = requested
+                    .into_iter()
+                    .map(|(name, partitions)| {
+                        let partitions = partitions
+                            .into_iter()
+                            .map(|partition| {
+                                if denied.contains(&name) {
+                                    return OffsetFetchResponsePartition {
+                                        partition_index: partition,
+                                        committed_offset: -1,
+                                        committed_leader_epoch: -1,
+                                        metadata: CompactNullableString(Some(String::new())),
+                                        error_code: TOPIC_AUTHORIZATION_FAILED,
+                                        tagged_fields: 0,
+                                    };
+                                }
+                                let committed = offsets.fetch(&group.group_id, &name, partition);
+
+                                OffsetFetchResponsePartition {
+                                    partition_index: partition,
+                                    committed_offset: committed.as_ref().map_or(-1, |c| c.offset),
+                                    committed_leader_epoch: committed
+                                        .as_ref()
+                                        .map_or(-1, |c| c.leader_epoch),
+                                    metadata: CompactNullableString(Some(
+                                        committed.map(|c| c.metadata).unwrap_or_default(),
+                                    )),
+                                    error_code: NONE,
+                                    tagged_fields: 0,
+                                }
+                            })
+                            .collect();
+
+                        OffsetFetchResponseTopic {
+                            name: name.into(),
+                            partitions: CompactArray(partitions),
+                            tagged_fields: 0,
+                        }
+                    })
+                    .collect();
+
+                OffsetFetchResponseGroup {
+                    group_id: group.group_id.clone(),
+                    topics: CompactArray(topics),
+                    error_code: NONE,
+                    tagged_fields: 0,
+                }
+            })
+            .collect();
+
+        Ok(OffsetFetchResponse {
+            throttle_time_ms: 0,
+            groups: CompactArray(groups),
+            tagged_fields: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_member_fields_from_v9() {
+        let v8 = Bytes::from_static(&[2, 2, b'g', 0, 0, 1, 0]);
+        let mut offset = 0;
+        let request = OffsetFetchRequest::decode_version(&v8, &mut offset, 8);
+        assert_eq!(offset, v8.len());
+        assert_eq!(request.groups[0].member_epoch, -1);
+        assert!(request.groups[0].topics.0.is_none());
+
+        let v9 = Bytes::from_static(&[2, 2, b'g', 2, b'm', 0, 0, 0, 3, 0, 0, 0, 0]);
+        let mut offset = 0;
+        let request = OffsetFetchRequest::decode_version(&v9, &mut offset, 9);
+        assert_eq!(offset, v9.len());
+        assert_eq!(request.groups[0].member_id.0.as_deref(), Some("m"));
+        assert_eq!(request.groups[0].member_epoch, 3);
+
+        let mut encoded = BytesMut::new();
+        request.encode(&mut encoded);
+        assert_eq!(encoded, v9);
+        assert_eq!(request.size_in_bytes(), v9.len());
+    }
+}
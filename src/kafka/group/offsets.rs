@@ -0,0 +1,457 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+use crate::{
+    kafka::log::{
+        partition::{read_batches, LogManager, SharedLog},
+        TopicRecordBatch, TopicRecordDisk, COMMIT_MARKER, TRANSACTIONAL_FLAG,
+    },
+    types::kafkastring::String16,
+    Decode, Encode, Size,
+};
+use encode_derive::{Decode, Size};
+
+/// The internal topic committed offsets are stored in. Every group maps to
+/// its single partition.
+pub const OFFSETS_TOPIC: &str = "__consumer_offsets";
+
+/// Key of an offset commit record, version 1.
+#[derive(Debug, Encode, Decode, Size)]
+struct OffsetCommitKey {
+    version: i16,
+    group: String16,
+    topic: String16,
+    partition: i32,
+}
+
+/// Value of an offset commit record, version 3. A null value deletes the
+/// offset.
+#[derive(Debug, Encode, Decode, Size)]
+struct OffsetCommitValue {
+    version: i16,
+    committed_offset: i64,
+    leader_epoch: i32,
+    metadata: String16,
+    commit_timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommittedOffset {
+    pub offset: i64,
+    pub leader_epoch: i32,
+    pub metadata: String,
+    pub commit_timestamp: i64,
+}
+
+impl CommittedOffset {
+    pub fn new(offset: i64, leader_epoch: i32, metadata: String) -> Self {
+        let commit_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as i64);
+
+        Self {
+            offset,
+            leader_epoch,
+            metadata,
+            commit_timestamp,
+        }
+    }
+}
+
+/// Offsets of one group, by topic and partition.
+pub type GroupOffsets = BTreeMap<(String, i32), CommittedOffset>;
+
+/// Offsets committed in the open transactions, by producer id then group.
+type PendingOffsets = HashMap<i64, HashMap<String, GroupOffsets>>;
+
+/// Committed offsets, cached in memory and persisted to [`OFFSETS_TOPIC`].
+/// Offsets committed in a transaction stay pending until it commits.
+#[derive(Debug)]
+pub struct OffsetStore {
+    log: SharedLog,
+    offsets: Mutex<HashMap<String, GroupOffsets>>,
+    pending: Mutex<PendingOffsets>,
+}
+
+impl OffsetStore {
+    /// Opens the offsets topic and replays it to rebuild the cache.
+    pub fn open(logs: &LogManager) -> io::Result<Self> {
+        let log = logs.partition(OFFSETS_TOPIC, 0)?;
+        let (offsets, pending) = replay(lock(&log).read_batches()?);
+
+        Ok(Self {
+            log,
+            offsets: Mutex::new(offsets),
+            pending: Mutex::new(pending),
+        })
+    }
+
+    /// Replays the offsets topic under `logs` without opening it for
+    /// writing, for tools running alongside the broker.
+    pub fn read(logs: &LogManager) -> io::Result<HashMap<String, GroupOffsets>> {
+        let dir = logs.partition_dir(OFFSETS_TOPIC, 0);
+        if !dir.is_dir() {
+            return Ok(HashMap::new());
+        }
+
+        Ok(replay(read_batches(&dir)?).0)
+    }
+
+    /// Persists a group's commits in one batch, then makes them visible.
+    pub fn commit(
+        &self,
+        group: &str,
+        commits: Vec<(String, i32, CommittedOffset)>,
+    ) -> io::Result<()> {
+        if commits.is_empty() {
+            return Ok(());
+        }
+
+        self.append(commit_records(group, &commits))?;
+
+        let mut offsets = lock(&self.offsets);
+        let entry = offsets.entry(group.to_string()).or_default();
+        for (topic, partition, committed) in commits {
+            entry.insert((topic, partition), committed);
+        }
+
+        Ok(())
+    }
+
+    /// Persists a group's commits in the transaction of a producer. They
+    /// take effect once [`complete_transaction`](Self::complete_transaction)
+    /// commits it.
+    pub fn commit_transactional(
+        &self,
+        producer_id: i64,
+        producer_epoch: i16,
+        group: &str,
+        commits: Vec<(String, i32, CommittedOffset)>,
+    ) -> io::Result<()> {
+        if commits.is_empty() {
+            return Ok(());
+        }
+
+        let mut batch = TopicRecordBatch::new(now(), commit_records(group, &commits));
+        batch.attributes |= TRANSACTIONAL_FLAG;
+        batch.producer_id = producer_id;
+        batch.producer_epoch = producer_epoch;
+        batch.update_crc();
+        lock(&self.log).append(&mut batch)?;
+
+        let mut pending = lock(&self.pending);
+        let entry = pending
+            .entry(producer_id)
+            .or_default()
+            .entry(group.to_string())
+            .or_default();
+        for (topic, partition, committed) in commits {
+            entry.insert((topic, partition), committed);
+        }
+
+        Ok(())
+    }
+
+    /// Writes the marker ending the transaction of a producer, then applies
+    /// the offsets it committed if it commits.
+    pub fn complete_transaction(
+        &self,
+        producer_id: i64,
+        producer_epoch: i16,
+        commit: bool,
+        coordinator_epoch: i32,
+    ) -> io::Result<()> {
+        let mut marker = TopicRecordBatch::control(
+            producer_id,
+            producer_epoch,
+            commit,
+            coordinator_epoch,
+            now(),
+        );
+        lock(&self.log).append(&mut marker)?;
+
+        let Some(groups) = lock(&self.pending).remove(&producer_id) else {
+            return Ok(());
+        };
+        if commit {
+            let mut offsets = lock(&self.offsets);
+            for (group, committed) in groups {
+                offsets.entry(group).or_default().extend(committed);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes every offset committed by `group`.
+    pub fn delete_group(&self, group: &str) -> io::Result<()> {
+        let partitions: Vec<(String, i32)> = self.group_offsets(group).into_keys().collect();
+        self.delete(group, partitions)
+    }
+
+    /// Deletes some of the offsets committed by `group` by appending
+    /// tombstones.
+    pub fn delete(&self, group: &str, partitions: Vec<(String, i32)>) -> io::Result<()> {
+        if partitions.is_empty() {
+            return Ok(());
+        }
+
+        let records = partitions
+            .iter()
+            .enumerate()
+            .map(|(i, (topic, partition))| {
+                TopicRecordDisk::new(
+                    i as i32,
+                    0,
+                    Some(commit_key(group, topic, *partition)),
+                    None,
+                )
+            })
+            .collect();
+
+        self.append(records)?;
+
+        let mut offsets = lock(&self.offsets);
+        if let Some(committed) = offsets.get_mut(group) {
+            for partition in &partitions {
+                committed.remove(partition);
+            }
+            if committed.is_empty() {
+                offsets.remove(group);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn append(&self, records: Vec<TopicRecordDisk>) -> io::Result<()> {
+        lock(&self.log).append(&mut TopicRecordBatch::new(now(), records))?;
+        Ok(())
+    }
+
+    pub fn fetch(&self, group: &str, topic: &str, partition: i32) -> Option<CommittedOffset> {
+        lock(&self.offsets)
+            .get(group)?
+            .get(&(topic.to_string(), partition))
+            .cloned()
+    }
+
+    /// Groups with at least one committed offset.
+    pub fn groups(&self) -> Vec<String> {
+        lock(&self.offsets).keys().cloned().collect()
+    }
+
+    /// Every offset committed by `group`, ordered by topic and partition.
+    pub fn group_offsets(&self, group: &str) -> GroupOffsets {
+        lock(&self.offsets).get(group).cloned().unwrap_or_default()
+    }
+}
+
+/// Rebuilds the committed offsets from the batches of the offsets topic,
+/// with the offsets of the transactions still open.
+fn replay(batches: Vec<TopicRecordBatch>) -> (HashMap<String, GroupOffsets>, PendingOffsets) {
+    let mut offsets: HashMap<String, GroupOffsets> = HashMap::new();
+    let mut pending = PendingOffsets::new();
+
+    for batch in batches {
+        if let Some(marker) = batch.control_type() {
+            if let Some(groups) = pending.remove(&batch.producer_id) {
+                if marker == COMMIT_MARKER {
+                    for (group, committed) in groups {
+                        offsets.entry(group).or_default().extend(committed);
+                    }
+                }
+            }
+            continue;
+        }
+
+        let target = if batch.is_transactional() {
+            pending.entry(batch.producer_id).or_default()
+        } else {
+            &mut offsets
+        };
+        for record in batch.decode_records() {
+            apply(target, record);
+        }
+    }
+
+    offsets.retain(|_, group| !group.is_empty());
+    (offsets, pending)
+}
+
+/// Applies an offset commit record or tombstone. Other records are skipped.
+fn apply(offsets: &mut HashMap<String, GroupOffsets>, record: TopicRecordDisk) {
+    let Some(key) = record.key.0 else {
+        return;
+    };
+
+    // Versions 0 and 1 are offset commits; 2 is group metadata.
+    if i16::decode(&key, &mut 0) > 1 {
+        return;
+    }
+
+    let key = OffsetCommitKey::decode(&key, &mut 0);
+    let group = offsets.entry(key.group.0).or_default();
+    let partition = (key.topic.0, key.partition);
+
+    match record.value.0 {
+        Some(value) if i16::decode(&value, &mut 0) == 3 => {
+            let value = OffsetCommitValue::decode(&value, &mut 0);
+            group.insert(
+                partition,
+                CommittedOffset {
+                    offset: value.committed_offset,
+                    leader_epoch: value.leader_epoch,
+                    metadata: value.metadata.0,
+                    commit_timestamp: value.commit_timestamp,
+                },
+            );
+        }
+        Some(_) => {}
+        None => {
+            group.remove(&partition);
+        }
+    }
+}
+
+fn commit_records(group: &str, commits: &[(String, i32, CommittedOffset)]) -> Vec<TopicRecordDisk> {
+    commits
+        .iter()
+        .enumerate()
+        .map(|(i, (topic, partition, committed))| {
+            let value = OffsetCommitValue {
+                version: 3,
+                committed_offset: committed.offset,
+                leader_epoch: committed.leader_epoch,
+                metadata: committed.metadata.as_str().into(),
+                commit_timestamp: committed.commit_timestamp,
+            };
+            TopicRecordDisk::new(
+                i as i32,
+                0,
+                Some(commit_key(group, topic, *partition)),
+                Some(value.to_bytes()),
+            )
+        })
+        .collect()
+}
+
+fn commit_key(group: &str, topic: &str, partition: i32) -> Bytes {
+    OffsetCommitKey {
+        version: 1,
+        group: group.into(),
+        topic: topic.into(),
+        partition,
+    }
+    .to_bytes()
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("offsets-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_commits_are_replayed_on_open() {
+        let dir = temp_dir("replay");
+
+        let store = OffsetStore::open(&LogManager::new(&dir)).unwrap();
+        let first = CommittedOffset::new(5, 1, "meta".to_string());
+        store
+            .commit("group", vec![("topic".to_string(), 0, first.clone())])
+            .unwrap();
+        store
+            .commit(
+                "group",
+                vec![
+                    (
+                        "topic".to_string(),
+                        1,
+                        CommittedOffset::new(7, -1, String::new()),
+                    ),
+                    (
+                        "topic".to_string(),
+                        0,
+                        CommittedOffset::new(9, 1, String::new()),
+                    ),
+                ],
+            )
+            .unwrap();
+        assert_eq!(store.fetch("group", "topic", 0).unwrap().offset, 9);
+        drop(store);
+
+        let reopened = OffsetStore::open(&LogManager::new(&dir)).unwrap();
+        let offsets = reopened.group_offsets("group");
+        let values: Vec<i64> = offsets.values().map(|c| c.offset).collect();
+        assert_eq!(values, vec![9, 7]);
+        assert_eq!(reopened.fetch("other", "topic", 0), None);
+
+        reopened.delete_group("group").unwrap();
+        drop(reopened);
+        let reopened = OffsetStore::open(&LogManager::new(&dir)).unwrap();
+        assert!(reopened.groups().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_transactional_commits_apply_once_committed() {
+        let dir = temp_dir("transactional");
+        let committed = |offset| {
+            vec![(
+                "topic".to_string(),
+                0,
+                CommittedOffset::new(offset, -1, String::new()),
+            )]
+        };
+
+        let store = OffsetStore::open(&LogManager::new(&dir)).unwrap();
+        store
+            .commit_transactional(7, 0, "group", committed(5))
+            .unwrap();
+        store
+            .commit_transactional(8, 0, "group", committed(9))
+            .unwrap();
+        assert_eq!(store.fetch("group", "topic", 0), None);
+
+        store.complete_transaction(7, 0, true, 0).unwrap();
+        assert_eq!(store.fetch("group", "topic", 0).unwrap().offset, 5);
+        drop(store);
+
+        // The transaction of producer 8 is still open after a restart.
+        let reopened = OffsetStore::open(&LogManager::new(&dir)).unwrap();
+        assert_eq!(reopened.fetch("group", "topic", 0).unwrap().offset, 5);
+        reopened.complete_transaction(8, 0, false, 0).unwrap();
+        assert_eq!(reopened.fetch("group", "topic", 0).unwrap().offset, 5);
+        drop(reopened);
+
+        let reopened = OffsetStore::open(&LogManager::new(&dir)).unwrap();
+        assert_eq!(reopened.fetch("group", "topic", 0).unwrap().offset, 5);
+        assert!(lock(&reopened.pending).is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
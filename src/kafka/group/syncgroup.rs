@@ -0,0 +1,79 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::GROUP_AUTHORIZATION_FAILED,
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        bytes::CompactBytes,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{SyncParams, SyncResult};
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct SyncGroupRequestAssignment {
+    pub member_id: CompactString,
+    pub assignment: CompactBytes,
+    pub tagged_fields: u8,
+}
+
+/// SyncGroup v5. Only the leader sends assignments.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct SyncGroupRequest {
+    pub group_id: CompactString,
+    pub generation_id: i32,
+    pub member_id: CompactString,
+    pub group_instance_id: CompactNullableString,
+    pub protocol_type: CompactNullableString,
+    pub protocol_name: CompactNullableString,
+    pub assignments: CompactArray<SyncGroupRequestAssignment>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct SyncGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub protocol_type: CompactNullableString,
+    pub protocol_name: CompactNullableString,
+    pub assignment: CompactBytes,
+    pub tagged_fields: u8,
+}
+
+impl SyncGroupRequest {
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<SyncGroupResponse, Error> {
+        let params = SyncParams {
+            group_id: self.group_id.0.clone(),
+            generation_id: self.generation_id,
+            member_id: self.member_id.0.clone(),
+            protocol_type: self.protocol_type.0.clone(),
+            protocol_name: self.protocol_name.0.clone(),
+            assignments: self
+                .assignments
+                .iter()
+                .map(|a| (a.member_id.0.clone(), a.assignment.0.clone()))
+                .collect(),
+        };
+
+        let result = if ctx.authorize(operation::READ, resource::GROUP, &self.group_id) {
+            ctx.broker.groups.sync(params).await
+        } else {
+            SyncResult::error(GROUP_AUTHORIZATION_FAILED)
+        };
+
+        Ok(SyncGroupResponse {
+            throttle_time_ms: 0,
+            error_code: result.error_code,
+            protocol_type: result.protocol_type.into(),
+            protocol_name: result.protocol_name.into(),
+            assignment: result.assignment.into(),
+            tagged_fields: 0,
+        })
+    }
+}
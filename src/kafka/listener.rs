@@ -0,0 +1,286 @@
+//! The listeners a broker accepts connections on, named by their security
+//! protocol as in Kafka's `listeners` setting, and the TLS setup of the SSL
+//! ones.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use tokio_rustls::{
+    rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer},
+        server::WebPkiClientVerifier,
+        RootCertStore, ServerConfig,
+    },
+    TlsAcceptor,
+};
+use x509_parser::objects::{oid2abbrev, oid_registry};
+
+/// How connections to a listener are secured and authenticated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityProtocol {
+    Plaintext,
+    Ssl,
+    SaslPlaintext,
+    SaslSsl,
+}
+
+impl SecurityProtocol {
+    pub fn uses_tls(self) -> bool {
+        matches!(self, Self::Ssl | Self::SaslSsl)
+    }
+
+    pub fn uses_sasl(self) -> bool {
+        matches!(self, Self::SaslPlaintext | Self::SaslSsl)
+    }
+}
+
+impl FromStr for SecurityProtocol {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "PLAINTEXT" => Ok(Self::Plaintext),
+            "SSL" => Ok(Self::Ssl),
+            "SASL_PLAINTEXT" => Ok(Self::SaslPlaintext),
+            "SASL_SSL" => Ok(Self::SaslSsl),
+            _ => Err(format!("unknown security protocol {name}")),
+        }
+    }
+}
+
+/// A listener such as `SSL://:9093`. An empty host listens on every
+/// interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listener {
+    pub name: String,
+    pub protocol: SecurityProtocol,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Listener {
+    /// Parses a listener, whose name is its security protocol unless
+    /// `protocols` maps it to one, as Kafka's
+    /// `listener.security.protocol.map`.
+    pub fn parse(
+        listener: &str,
+        protocols: &HashMap<String, SecurityProtocol>,
+    ) -> Result<Self, String> {
+        let invalid = || format!("invalid listener {listener}");
+        let (name, address) = listener.split_once("://").ok_or_else(invalid)?;
+        let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            protocol: match protocols.get(name) {
+                Some(&protocol) => protocol,
+                None => name.parse()?,
+            },
+            host: host.to_string(),
+            port: port.parse().map_err(|_| invalid())?,
+        })
+    }
+
+    /// The address to bind to.
+    pub fn address(&self) -> String {
+        let host = if self.host.is_empty() {
+            "0.0.0.0"
+        } else {
+            &self.host
+        };
+        format!("{host}:{}", self.port)
+    }
+}
+
+/// Whether SSL listeners ask clients for a certificate, as Kafka's
+/// `ssl.client.auth`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClientAuth {
+    #[default]
+    None,
+    Requested,
+    Required,
+}
+
+impl FromStr for ClientAuth {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "none" => Ok(Self::None),
+            "requested" => Ok(Self::Requested),
+            "required" => Ok(Self::Required),
+            _ => Err(format!("unknown client auth {name}")),
+        }
+    }
+}
+
+/// The PEM files SSL listeners are set up from.
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// The broker's certificate chain.
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+    /// The certificates client certificates must be signed by, required
+    /// unless client auth is `none`.
+    pub ca: Option<PathBuf>,
+    pub client_auth: ClientAuth,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_certificates(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certificates = rustls_pemfile::certs(&mut reader).collect::<io::Result<Vec<_>>>()?;
+    if certificates.is_empty() {
+        return Err(invalid_data(format!(
+            "no certificate in {}",
+            path.display()
+        )));
+    }
+    Ok(certificates)
+}
+
+fn read_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| invalid_data(format!("no private key in {}", path.display())))
+}
+
+impl TlsConfig {
+    /// Loads the certificates and key into an acceptor for SSL listeners.
+    pub fn acceptor(&self) -> io::Result<TlsAcceptor> {
+        let builder = ServerConfig::builder();
+        let builder = match (self.client_auth, &self.ca) {
+            (ClientAuth::None, _) => builder.with_no_client_auth(),
+            (_, None) => {
+                return Err(invalid_data(
+                    "client authentication needs a CA certificate".to_string(),
+                ))
+            }
+            (client_auth, Some(ca)) => {
+                let mut roots = RootCertStore::empty();
+                for certificate in read_certificates(ca)? {
+                    roots.add(certificate).map_err(io::Error::other)?;
+                }
+                let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+                let verifier = match client_auth {
+                    ClientAuth::Requested => verifier.allow_unauthenticated(),
+                    _ => verifier,
+                };
+                builder.with_client_cert_verifier(verifier.build().map_err(io::Error::other)?)
+            }
+        };
+
+        let config = builder
+            .with_single_cert(read_certificates(&self.certificate)?, read_key(&self.key)?)
+            .map_err(io::Error::other)?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// The user a client certificate authenticates: its subject in RFC 2253
+/// form, as Kafka's default principal builder names it.
+pub fn certificate_user(certificate: &CertificateDer) -> Option<String> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate).ok()?;
+
+    let rdns: Vec<String> = certificate
+        .subject()
+        .iter()
+        .map(|rdn| {
+            rdn.iter()
+                .map(|attribute| {
+                    let oid = attribute.attr_type();
+                    let name = oid2abbrev(oid, oid_registry())
+                        .map_or_else(|_| oid.to_id_string(), str::to_string);
+                    let value = attribute.as_str().unwrap_or_default();
+                    format!("{name}={}", escape(value))
+                })
+                .collect::<Vec<_>>()
+                .join("+")
+        })
+        .collect();
+
+    // RFC 2253 lists the most specific name first.
+    Some(rdns.into_iter().rev().collect::<Vec<_>>().join(","))
+}
+
+/// Escapes the characters RFC 2253 reserves in attribute values.
+fn escape(value: &str) -> String {
+    let last = value.chars().count().saturating_sub(1);
+    let mut escaped = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        let edge = (i == 0 && matches!(c, ' ' | '#')) || (i == last && c == ' ');
+        if edge || matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Issued to C=US, O="Acme, Inc", CN=alice.
+    const CLIENT_CERTIFICATE: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBiDCCAS+gAwIBAgIUGtcRpajB1RcaXZetFij/Ql8p2ogwCgYIKoZIzj0EAwIw
+EjEQMA4GA1UEAwwHdGVzdC1jYTAgFw0yNjEwMTkwNTA1MzFaGA8yMTI2MDkyNTA1
+MDUzMVowMTELMAkGA1UEBhMCVVMxEjAQBgNVBAoMCUFjbWUsIEluYzEOMAwGA1UE
+AwwFYWxpY2UwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAASQLMIG1AM7psyuO8CF
+n9HjwmQSQTI2eay3r2oNxu05/jvYe9Wnd6IzXLxtIj/1OHwwUP8FZy3Gw4EEiZq0
+oFHFo0IwQDAdBgNVHQ4EFgQUDBsXR4Adwf5kIyjVOmHCoS9j0R8wHwYDVR0jBBgw
+FoAUfwqMrGiLIDIXUNpagT+XUqFXDC8wCgYIKoZIzj0EAwIDRwAwRAIgcVlk6TgF
+UGEhjyMdWz1dpIrvdhlHeIr3etvt5NtbplACIHlCL242tCl3n3wL6QqPM3zafy73
+gcn16kkVBttkIHsQ
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn test_certificate_subject_is_the_user() {
+        let certificate = rustls_pemfile::certs(&mut CLIENT_CERTIFICATE.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            certificate_user(&certificate).as_deref(),
+            Some("CN=alice,O=Acme\\, Inc,C=US")
+        );
+        assert_eq!(certificate_user(&CertificateDer::from(vec![1, 2, 3])), None);
+    }
+
+    #[test]
+    fn test_parse_listeners() {
+        let mut protocols = HashMap::new();
+        assert_eq!(
+            Listener::parse("SSL://:9093", &protocols),
+            Ok(Listener {
+                name: "SSL".to_string(),
+                protocol: SecurityProtocol::Ssl,
+                host: String::new(),
+                port: 9093,
+            })
+        );
+        let listener = Listener::parse("SASL_PLAINTEXT://localhost:9094", &protocols).unwrap();
+        assert_eq!(listener.address(), "localhost:9094");
+        assert!(listener.protocol.uses_sasl() && !listener.protocol.uses_tls());
+
+        assert!(Listener::parse("INTERNAL://:9095", &protocols).is_err());
+        protocols.insert("INTERNAL".to_string(), SecurityProtocol::SaslSsl);
+        let listener = Listener::parse("INTERNAL://:9095", &protocols).unwrap();
+        assert_eq!(listener.protocol, SecurityProtocol::SaslSsl);
+
+        assert!(Listener::parse("PLAINTEXT://localhost", &protocols).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use std::path::PathBuf;
+
+use crate::{
+    types::{array::CompactArray, cstring::CompactString},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{
+    acl::{operation, resource},
+    errors::{NONE, TOPIC_AUTHORIZATION_FAILED, UNKNOWN_TOPIC_OR_PARTITION},
+    fetch::READ_COMMITTED,
+    log::{partition::read_batches, TopicRecordBatch},
+    RequestContext,
+};
+
+/// The offset of the next record to be written.
+pub const LATEST_TIMESTAMP: i64 = -1;
+/// The first offset of the log.
+pub const EARLIEST_TIMESTAMP: i64 = -2;
+/// The offset of the record with the largest timestamp, from v7 on.
+pub const MAX_TIMESTAMP: i64 = -3;
+/// The first offset kept locally, the same as the earliest here, from v8 on.
+pub const EARLIEST_LOCAL_TIMESTAMP: i64 = -4;
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ListOffsetsPartition {
+    pub partition_index: i32,
+    pub current_leader_epoch: i32,
+    /// A timestamp to look up, or one of the special timestamps above.
+    pub timestamp: i64,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ListOffsetsTopic {
+    pub name: CompactString,
+    pub partitions: CompactArray<ListOffsetsPartition>,
+    pub tagged_fields: u8,
+}
+
+/// ListOffsets v6 to v8, which differ only in the special timestamps.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ListOffsetsRequest {
+    pub replica_id: i32,
+    pub isolation_level: i8,
+    pub topics: CompactArray<ListOffsetsTopic>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ListOffsetsPartitionResponse {
+    pub partition_index: i32,
+    pub error_code: i16,
+    /// The timestamp of the record found, -1 for the earliest and latest
+    /// offsets.
+    pub timestamp: i64,
+    /// The offset found, -1 if no record has a timestamp that late.
+    pub found_offset: i64,
+    pub leader_epoch: i32,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ListOffsetsTopicResponse {
+    pub name: CompactString,
+    pub partitions: CompactArray<ListOffsetsPartitionResponse>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ListOffsetsResponse {
+    pub throttle_time_ms: i32,
+    pub topics: CompactArray<ListOffsetsTopicResponse>,
+    pub tagged_fields: u8,
+}
+
+impl ListOffsetsPartitionResponse {
+    fn error(partition_index: i32, error_code: i16) -> Self {
+        Self {
+            partition_index,
+            error_code,
+            timestamp: -1,
+            found_offset: -1,
+            leader_epoch: -1,
+            tagged_fields: 0,
+        }
+    }
+}
+
+impl ListOffsetsRequest {
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<ListOffsetsResponse, Error> {
+        let logs = &ctx.broker.logs;
+        let mut topics = Vec::with_capacity(self.topics.len());
+
+        for topic in self.topics.iter() {
+            let name = topic.name.as_str();
+            let (authorized, epochs) = ctx.broker.metadata.read(|image| {
+                (
+                    ctx.authorized(image, operation::DESCRIBE, resource::TOPIC, name),
+                    image.topic(name).map(|known| {
+                        known
+                            .partitions
+                            .values()
+                            .map(|partition| (partition.id, partition.leader_epoch))
+                            .collect::<Vec<_>>()
+                    }),
+                )
+            });
+
+            let mut partitions = Vec::with_capacity(topic.partitions.len());
+            for requested in topic.partitions.iter() {
+                let index = requested.partition_index;
+                let leader_epoch = epochs
+                    .as_ref()
+                    .and_then(|epochs| epochs.iter().find(|(id, _)| *id == index))
+                    .map(|(_, epoch)| *epoch);
+
+                let response = match (authorized, leader_epoch) {
+                    (false, _) => {
+                        ListOffsetsPartitionResponse::error(index, TOPIC_AUTHORIZATION_FAILED)
+                    }
+                    (true, None) => {
+                        ListOffsetsPartitionResponse::error(index, UNKNOWN_TOPIC_OR_PARTITION)
+                    }
+                    (true, Some(leader_epoch)) => match logs.offsets(name, index)? {
+                        None => {
+                            ListOffsetsPartitionResponse::error(index, UNKNOWN_TOPIC_OR_PARTITION)
+                        }
+                        Some(offsets) => {
+                            let max_offset = if self.isolation_level == READ_COMMITTED {
+                                offsets.last_stable_offset
+                            } else {
+                                offsets.log_end_offset
+                            };
+                            let (timestamp, found_offset) = match requested.timestamp {
+                                LATEST_TIMESTAMP => (-1, max_offset),
+                                EARLIEST_TIMESTAMP | EARLIEST_LOCAL_TIMESTAMP => {
+                                    (-1, offsets.log_start_offset)
+                                }
+                                timestamp => {
+                                    let dir = logs.partition_dir(name, index);
+                                    let range = offsets.log_start_offset..max_offset;
+                                    tokio::task::spawn_blocking(move || {
+                                        lookup(dir, timestamp, range)
+                                    })
+                                    .await??
+                                }
+                            };
+                            ListOffsetsPartitionResponse {
+                                partition_index: index,
+                                error_code: NONE,
+                                timestamp,
+                                found_offset,
+                                leader_epoch,
+                                tagged_fields: 0,
+                            }
+                        }
+                    },
+                };
+                partitions.push(response);
+            }
+
+            topics.push(ListOffsetsTopicResponse {
+                name: topic.name.clone(),
+                partitions: CompactArray(partitions),
+                tagged_fields: 0,
+            });
+        }
+
+        Ok(ListOffsetsResponse {
+            throttle_time_ms: 0,
+            topics: CompactArray(topics),
+            tagged_fields: 0,
+        })
+    }
+}
+
+/// Looks up a timestamp in the log in `dir`, reading the records in `range`.
+fn lookup(
+    dir: PathBuf,
+    timestamp: i64,
+    range: std::ops::Range<i64>,
+) -> std::io::Result<(i64, i64)> {
+    let batches = read_batches(&dir)?;
+    Ok(find_timestamp(&batches, timestamp, range))
+}
+
+/// The timestamp and offset of the first record in `range` whose timestamp
+/// is at least `timestamp`, or of the first record with the largest
+/// timestamp for [`MAX_TIMESTAMP`]. `(-1, -1)` if there is none.
+fn find_timestamp(
+    batches: &[TopicRecordBatch],
+    timestamp: i64,
+    range: std::ops::Range<i64>,
+) -> (i64, i64) {
+    let mut found = (-1, -1);
+    for batch in batches {
+        if batch.is_control() || batch.last_offset() < range.start {
+            continue;
+        }
+        if batch.base_offset >= range.end {
+            break;
+        }
+        if timestamp != MAX_TIMESTAMP && batch.max_timestamp < timestamp {
+            continue;
+        }
+
+        for record in batch.decode_records() {
+            let offset = batch.base_offset + record.delta_offset.0;
+            if !range.contains(&offset) {
+                continue;
+            }
+            let record_timestamp = batch.base_timestamp + record.timestamp.0;
+            if timestamp == MAX_TIMESTAMP {
+                if found.1 == -1 || record_timestamp > found.0 {
+                    found = (record_timestamp, offset);
+                }
+            } else if record_timestamp >= timestamp {
+                return (record_timestamp, offset);
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::{
+        broker::tests::TempBroker,
+        log::{TopicRecordDisk, TRANSACTIONAL_FLAG},
+    };
+
+    fn batch(base_offset: i64, base_timestamp: i64, deltas: &[i64]) -> TopicRecordBatch {
+        let records = deltas
+            .iter()
+            .enumerate()
+            .map(|(i, &delta)| TopicRecordDisk::new(i as i32, delta, None, None))
+            .collect();
+        let mut batch = TopicRecordBatch::new(base_timestamp, records);
+        batch.base_offset = base_offset;
+        batch
+    }
+
+    #[test]
+    fn test_find_timestamp() {
+        let batches = [batch(0, 100, &[0, 10, 5]), batch(3, 200, &[0, 50])];
+
+        assert_eq!(find_timestamp(&batches, 0, 0..5), (100, 0));
+        assert_eq!(find_timestamp(&batches, 105, 0..5), (110, 1));
+        assert_eq!(find_timestamp(&batches, 111, 0..5), (200, 3));
+        assert_eq!(find_timestamp(&batches, 251, 0..5), (-1, -1));
+        assert_eq!(find_timestamp(&batches, MAX_TIMESTAMP, 0..5), (250, 4));
+
+        // Records outside the range, deleted or not yet stable, are skipped.
+        assert_eq!(find_timestamp(&batches, 0, 2..5), (105, 2));
+        assert_eq!(find_timestamp(&batches, MAX_TIMESTAMP, 0..4), (200, 3));
+    }
+
+    fn request(topic: &str, isolation_level: i8, partitions: &[(i32, i64)]) -> ListOffsetsRequest {
+        ListOffsetsRequest {
+            replica_id: -1,
+            isolation_level,
+            topics: CompactArray(vec![ListOffsetsTopic {
+                name: topic.into(),
+                partitions: CompactArray(
+                    partitions
+                        .iter()
+                        .map(|&(partition_index, timestamp)| ListOffsetsPartition {
+                            partition_index,
+                            current_leader_epoch: -1,
+                            timestamp,
+                            tagged_fields: 0,
+                        })
+                        .collect(),
+                ),
+                tagged_fields: 0,
+            }]),
+            tagged_fields: 0,
+        }
+    }
+
+    fn found(response: &ListOffsetsResponse) -> Vec<(i16, i64, i64)> {
+        response.topics[0]
+            .partitions
+            .iter()
+            .map(|p| (p.error_code, p.timestamp, p.found_offset))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_offsets() {
+        let test = TempBroker::open("listoffsets-test");
+        test.create_topic("topic", 1);
+        let log = test.broker.logs.partition("topic", 0).unwrap();
+        {
+            let mut log = log.lock().unwrap();
+            log.append(&mut batch(0, 100, &[0, 10, 5])).unwrap();
+            log.append(&mut batch(0, 200, &[0, 50])).unwrap();
+            // An open transaction holds back read_committed clients.
+            let mut open = batch(0, 300, &[0]);
+            open.attributes = TRANSACTIONAL_FLAG;
+            open.producer_id = 1;
+            open.producer_epoch = 0;
+            open.base_sequence = 0;
+            open.update_crc();
+            log.append(&mut open).unwrap();
+        }
+        let ctx = test.context(2, 8);
+
+        let asked = request(
+            "topic",
+            0,
+            &[
+                (0, LATEST_TIMESTAMP),
+                (0, EARLIEST_TIMESTAMP),
+                (0, 105),
+                (0, MAX_TIMESTAMP),
+                (0, 1000),
+                (1, LATEST_TIMESTAMP),
+            ],
+        );
+        let response = asked.handle_request(&ctx).await.unwrap();
+        assert_eq!(
+            found(&response),
+            [
+                (NONE, -1, 6),
+                (NONE, -1, 0),
+                (NONE, 110, 1),
+                (NONE, 300, 5),
+                (NONE, -1, -1),
+                (UNKNOWN_TOPIC_OR_PARTITION, -1, -1)
+            ]
+        );
+
+        let committed = request("topic", READ_COMMITTED, &[(0, LATEST_TIMESTAMP), (0, 300)]);
+        let response = committed.handle_request(&ctx).await.unwrap();
+        assert_eq!(found(&response), [(NONE, -1, 5), (NONE, -1, -1)]);
+
+        let missing = request("missing", 0, &[(0, LATEST_TIMESTAMP)]);
+        let response = missing.handle_request(&ctx).await.unwrap();
+        assert_eq!(found(&response), [(UNKNOWN_TOPIC_OR_PARTITION, -1, -1)]);
+    }
+}
@@ -1,22 +1,30 @@
 use crate::{
-    types::{array::CVec, cstring::CString, uuid::UUID},
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+        uuid::UUID,
+    },
     Decode, Encode, Size,
 };
 use anyhow::Error;
 use encode_derive::{Decode, Size};
 
-use super::{log::get_topics, BaseRequestV2, BaseResponse, BaseResponseV1};
+use super::{
+    acl::{operation, operation_bits, resource},
+    errors::{TOPIC_AUTHORIZATION_FAILED, UNKNOWN_TOPIC_OR_PARTITION},
+    metadata::TopicImage,
+    RequestContext,
+};
 
 #[derive(Debug, Encode, Decode, Size)]
 pub struct TopicsRequest {
-    pub name: CString,
+    pub name: CompactString,
     pub tag_buffer: u8,
 }
 
 #[derive(Debug, Encode, Decode, Size)]
 pub struct DescribePartitionsRequest {
-    pub basev2: BaseRequestV2,
-    pub topics_array: CVec<TopicsRequest>,
+    pub topics_array: CompactArray<TopicsRequest>,
     pub response_partition_limit: i32,
     pub cursor: u8,
     pub tag_buffer: u8,
@@ -28,34 +36,64 @@ pub struct PartitionResponse {
     pub partition_idx: i32,
     pub leader_id: i32,
     pub leader_epoch: i32,
-    pub replica_nodes: CVec<i32>,
-    pub in_sync_replicas: CVec<i32>,
-    pub eligible_leader_replicas: CVec<i32>,
-    pub last_known_elr: CVec<i32>,
-    pub offline_replica: CVec<i32>,
+    pub replica_nodes: CompactArray<i32>,
+    pub in_sync_replicas: CompactArray<i32>,
+    pub eligible_leader_replicas: CompactArray<i32>,
+    pub last_known_elr: CompactArray<i32>,
+    pub offline_replica: CompactArray<i32>,
     pub tag_buffer: u8,
 }
 
 #[derive(Debug, Encode, Decode, Size)]
 pub struct TopicResponse {
     pub error_code: i16,
-    pub name: CString,
+    pub name: CompactNullableString,
     pub id: UUID,
     pub is_internal: u8,
-    pub partitions_array: CVec<PartitionResponse>,
+    pub partitions_array: CompactArray<PartitionResponse>,
     pub authorized_ops: i32,
     pub tag_buffer: u8,
 }
 
 impl TopicResponse {
-    pub fn unknown_topic(name: &CString) -> Self {
+    /// A known topic, with the operations the client may perform on it.
+    pub fn new(topic: &TopicImage, authorized_ops: i32) -> Self {
+        let partitions = topic
+            .partitions
+            .values()
+            .map(|partition| PartitionResponse {
+                error_code: 0,
+                partition_idx: partition.id,
+                leader_id: partition.leader,
+                leader_epoch: partition.leader_epoch,
+                replica_nodes: partition.replicas.clone(),
+                in_sync_replicas: partition.sync_replicas.clone(),
+                eligible_leader_replicas: CompactArray(vec![]),
+                last_known_elr: CompactArray(vec![]),
+                offline_replica: CompactArray(vec![]),
+                tag_buffer: 0,
+            })
+            .collect();
+
         TopicResponse {
-            error_code: 3,
-            name: CString(name.0.clone(), name.1),
+            error_code: 0,
+            name: CompactNullableString(Some(topic.name.clone())),
+            id: topic.id.clone(),
+            is_internal: 0,
+            partitions_array: CompactArray(partitions),
+            authorized_ops,
+            tag_buffer: 0,
+        }
+    }
+
+    pub fn error(name: &CompactString, error_code: i16, authorized_ops: i32) -> Self {
+        TopicResponse {
+            error_code,
+            name: CompactNullableString(Some(name.0.clone())),
             id: UUID([0x00; 16]),
             is_internal: 0,
-            partitions_array: CVec { data: vec![] },
-            authorized_ops: 0x00000df8,
+            partitions_array: CompactArray(vec![]),
+            authorized_ops,
             tag_buffer: 0,
         }
     }
@@ -63,49 +101,44 @@ impl TopicResponse {
 
 #[derive(Debug, Encode, Decode, Size)]
 pub struct DescribePartitionsResponse {
-    pub basev1: BaseResponseV1,
     pub throttle: i32,
-    pub topics_array: CVec<TopicResponse>,
+    pub topics_array: CompactArray<TopicResponse>,
     pub next_cursor: u8,
     pub tag_buffer: u8,
 }
 
 impl DescribePartitionsRequest {
-    pub async fn handle_request(&self) -> Result<DescribePartitionsResponse, Error> {
-        let base = BaseResponse {
-            size: 0,
-            correlation_id: self.basev2.correlation_id,
-        };
-        let mut topics_array = CVec { data: vec![] };
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<DescribePartitionsResponse, Error> {
+        let mut topics_array = CompactArray(vec![]);
         let throttle = 0;
         let next_cursor = 0xff;
         let tag_buffer = 0;
 
-        let basev1 = BaseResponseV1 { base, tag_buffer };
-
-        let mut topics = get_topics().await?;
-
-        for topic in &self.topics_array.data {
-            if let Some(topic_value) = topics.remove(&topic.name.0) {
-                topics_array.data.push(topic_value);
-            } else {
-                topics_array
-                    .data
-                    .push(TopicResponse::unknown_topic(&topic.name));
+        ctx.broker.metadata.read(|image| {
+            for topic in self.topics_array.iter() {
+                // Whether a topic exists is only told to clients allowed to
+                // describe it.
+                let ops = ctx.authorized_operations(image, resource::TOPIC, &topic.name);
+                let response = if ops & operation_bits([operation::DESCRIBE]) == 0 {
+                    TopicResponse::error(&topic.name, TOPIC_AUTHORIZATION_FAILED, ops)
+                } else {
+                    match image.topic(&topic.name) {
+                        Some(known) => TopicResponse::new(known, ops),
+                        None => TopicResponse::error(&topic.name, UNKNOWN_TOPIC_OR_PARTITION, ops),
+                    }
+                };
+                topics_array.push(response);
             }
-        }
+        });
 
-        let mut response = DescribePartitionsResponse {
-            basev1,
+        Ok(DescribePartitionsResponse {
             throttle,
             topics_array,
             next_cursor,
             tag_buffer,
-        };
-
-        let res_size = response.size_in_bytes() - 4;
-        response.basev1.base.size = res_size as i32;
-
-        Ok(response)
+        })
     }
 }
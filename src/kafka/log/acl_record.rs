@@ -0,0 +1,27 @@
+use crate::{
+    types::{cstring::CompactString, uuid::UUID},
+    Decode, Encode, Size,
+};
+use encode_derive::{Decode, Size};
+
+/// An ACL binding: whether a principal connecting from a host may perform
+/// an operation on the resources a pattern matches.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Size)]
+pub struct AccessControlEntryRecord {
+    pub id: UUID,
+    pub resource_type: i8,
+    pub resource_name: CompactString,
+    pub pattern_type: i8,
+    pub principal: CompactString,
+    pub host: CompactString,
+    pub operation: i8,
+    pub permission_type: i8,
+    pub tagged_fields: u8,
+}
+
+/// Deletes the ACL binding with an id.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct RemoveAccessControlEntryRecord {
+    pub id: UUID,
+    pub tagged_fields: u8,
+}
@@ -0,0 +1,114 @@
+//! Checkpoint files: per-partition offsets kept at the root of the log
+//! directory, in the text format the Java broker uses.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+/// Log start offsets advanced past the first segment by DeleteRecords.
+pub const LOG_START_OFFSET_CHECKPOINT: &str = "log-start-offset-checkpoint";
+
+const VERSION: i32 = 0;
+
+pub type Checkpoint = HashMap<(String, i32), i64>;
+
+fn invalid(path: &Path, line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("malformed line {line:?} in {}", path.display()),
+    )
+}
+
+/// Reads a checkpoint file: a version line, an entry count line, then one
+/// `<topic> <partition> <offset>` line per entry. A missing file is empty.
+pub fn read(path: &Path) -> io::Result<Checkpoint> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Checkpoint::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut lines = data.lines();
+    let mut header = |what: &str| -> io::Result<i64> {
+        let line = lines.next().unwrap_or_default();
+        line.trim()
+            .parse()
+            .map_err(|_| invalid(path, &format!("{what}: {line}")))
+    };
+
+    let version = header("version")?;
+    if version != VERSION as i64 {
+        return Err(invalid(path, &format!("version: {version}")));
+    }
+    let count = header("count")?;
+
+    let mut entries = Checkpoint::new();
+    for line in lines.by_ref().take(count.max(0) as usize) {
+        let mut fields = line.split(' ');
+        let (Some(topic), Some(partition), Some(offset), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid(path, line));
+        };
+        let partition = partition.parse().map_err(|_| invalid(path, line))?;
+        let offset = offset.parse().map_err(|_| invalid(path, line))?;
+        entries.insert((topic.to_string(), partition), offset);
+    }
+
+    if entries.len() as i64 != count {
+        return Err(invalid(path, &format!("count: {count}")));
+    }
+
+    Ok(entries)
+}
+
+/// Replaces the checkpoint file at `path` with `entries`, writing a
+/// temporary file first so that a crash leaves the old file whole.
+pub fn write(path: &Path, entries: &Checkpoint) -> io::Result<()> {
+    let mut sorted: Vec<_> = entries.iter().collect();
+    sorted.sort();
+
+    let mut data = format!("{VERSION}\n{}\n", sorted.len());
+    for ((topic, partition), offset) in sorted {
+        data.push_str(&format!("{topic} {partition} {offset}\n"));
+    }
+
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(data.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dir = std::env::temp_dir().join(format!("checkpoint-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LOG_START_OFFSET_CHECKPOINT);
+
+        assert!(read(&path).unwrap().is_empty());
+
+        let entries = Checkpoint::from([
+            (("orders".to_string(), 1), 42),
+            (("orders".to_string(), 0), 7),
+        ]);
+        write(&path, &entries).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "0\n2\norders 0 7\norders 1 42\n"
+        );
+        assert_eq!(read(&path).unwrap(), entries);
+
+        fs::write(&path, "0\n2\norders 0 7\n").unwrap();
+        assert!(read(&path).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,15 @@
+use crate::{
+    types::cstring::{CompactNullableString, CompactString},
+    Decode, Encode, Size,
+};
+use encode_derive::{Decode, Size};
+
+/// Sets a config of a topic or broker, or removes it when the value is null.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ConfigRecord {
+    pub resource_type: i8,
+    pub resource_name: CompactString,
+    pub name: CompactString,
+    pub value: CompactNullableString,
+    pub tagged_fields: u8,
+}
@@ -1,209 +1,109 @@
+use acl_record::{AccessControlEntryRecord, RemoveAccessControlEntryRecord};
 use anyhow::Error;
 use anyhow::Result;
-use crc32c::crc32c;
+use bytes::{BufMut, Bytes, BytesMut};
+use config_record::ConfigRecord;
 use encode_derive::{Decode, Size};
 use partition_record::PartitionRecord;
-#[allow(non_camel_case_types, non_upper_case_globals, unreachable_patterns)]
-use std::collections::{HashMap, HashSet};
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
-use topic_log::TopicRecord;
+use producer_ids_record::ProducerIdsRecord;
+use quota_record::ClientQuotaRecord;
+use scram_record::{RemoveUserScramCredentialRecord, UserScramCredentialRecord};
+use std::path::PathBuf;
+use topic_log::{RemoveTopicRecord, TopicRecord};
 
 use crate::{
     types::{
-        array::{CSignedVec, CVec},
-        bytes::ByteBuf,
-        cstring::{CSignedString, CString},
+        array::{Array32, VarintArray},
+        bytes::{ByteBuf, NullableVarintBytes},
+        cstring::{CompactString, VarintString},
+        fail, length_prefix,
         record::GenericRecord,
+        records::CompactRecords,
         uvarint::UVarint,
         varint::Varint,
     },
     Decode, Encode, Size,
 };
 
-use super::listpartitions::{PartitionResponse, TopicResponse};
-
+pub mod acl_record;
+pub mod checkpoint;
+pub mod config_record;
+pub mod partition;
 pub mod partition_record;
+pub mod producer_ids_record;
+pub mod producer_state;
+pub mod quota_record;
+pub mod scram_record;
+pub mod segment;
 pub mod topic_log;
+pub mod txn_index;
 
-static CLUSTER_METADATA: &str =
-    "/tmp/kraft-combined-logs/__cluster_metadata-0/00000000000000000000.log";
-
-pub async fn get_topics() -> Result<HashMap<String, TopicResponse>, Error> {
-    let records = get_records_from_disk().await?;
-
-    let mut topics_w_partitions: HashMap<String, Vec<PartitionResponse>> = HashMap::new();
-
-    let filtered_topics: HashSet<String> = records
-        .iter()
-        .flat_map(|batch| &batch.records)
-        .filter_map(|record| {
-            if let RecordValue::Topic(topic) = &record.value.r_record {
-                Some(topic.id.to_string())
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    records
-        .iter()
-        .flat_map(|batch| &batch.records)
-        .filter_map(|record| {
-            if let RecordValue::Partition(partition) = &record.value.r_record {
-                Some(partition)
-            } else {
-                None
-            }
-        })
-        .filter(|partition| filtered_topics.contains(&partition.topic_id.to_string()))
-        .for_each(|partition| {
-            let entry = topics_w_partitions
-                .entry(partition.topic_id.to_string())
-                .or_insert_with(Vec::new);
-
-            let idx = entry.len();
-
-            entry.push(PartitionResponse {
-                error_code: 0,
-                partition_idx: idx as i32,
-                leader_id: partition.leader,
-                leader_epoch: partition.leader_epoch,
-                replica_nodes: partition.replicas.clone(),
-                in_sync_replicas: partition.sync_replicas.clone(),
-                eligible_leader_replicas: CVec { data: vec![] },
-                last_known_elr: CVec { data: vec![] },
-                offline_replica: CVec { data: vec![] },
-                tag_buffer: 0,
-            });
-        });
-
-    let topic_lookup: HashMap<String, &TopicRecord> = records
-        .iter()
-        .flat_map(|batch| &batch.records)
-        .filter_map(|record| {
-            if let RecordValue::Topic(topic) = &record.value.r_record {
-                Some((topic.id.to_string(), topic))
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    let mut topic_map: HashMap<String, TopicResponse> = HashMap::new();
-
-    for (topic_id, partitions) in topics_w_partitions {
-        if let Some(topic) = topic_lookup.get(&topic_id) {
-            let name = topic.name.0.to_string();
-            topic_map.insert(
-                name.clone(),
-                TopicResponse {
-                    error_code: 0,
-                    name: topic.name.clone(),
-                    id: topic.id.clone(),
-                    is_internal: 0,
-                    partitions_array: CVec { data: partitions },
-                    authorized_ops: 0x00000df8,
-                    tag_buffer: topic.tagged_fields,
-                },
-            );
-        }
-    }
+/// Bytes of a batch before the CRC, which covers everything after it.
+const CRC_END: usize = 21;
 
-    Ok(topic_map)
-}
+pub static LOG_DIR: &str = "/tmp/kraft-combined-logs";
 
-fn calculate_crc(batch: &TopicRecordBatch) -> u32 {
-    let mut data: Vec<u8> = Vec::new();
+/// Batch attribute bits marking batches written in a transaction, and the
+/// control batches ending one.
+pub const TRANSACTIONAL_FLAG: i16 = 0x10;
+pub const CONTROL_FLAG: i16 = 0x20;
 
-    data.extend(batch.attributes.encode());
-    data.extend(batch.last_offset_delta.encode());
-    data.extend(batch.base_timestamp.encode());
-    data.extend(batch.max_timestamp.encode());
-    data.extend(batch.producer_id.encode());
-    data.extend(batch.producer_epoch.encode());
-    data.extend(batch.base_sequence.encode());
-    data.extend(batch.records.encode());
-
-    let crc = crc32c(&data);
-    crc
-}
+/// Control record types, the second field of the key of a control record.
+pub const ABORT_MARKER: i16 = 0;
+pub const COMMIT_MARKER: i16 = 1;
 
+/// Locates the batches to return for a fetch at `fetch_offset` from the
+/// partition log in `dir`, up to `max_offset`, without reading them; they
+/// are spliced from the segment file into the response.
 pub async fn get_topic_records_from_disk(
-    name: &str,
-    partition: i32,
-    _idx: i64,
-) -> Result<ByteBuf, Error> {
-    let mut file = File::open(format!(
-        "/tmp/kraft-combined-logs/{}-{}/00000000000000000000.log",
-        name, partition
-    ))
-    .await?;
-
-    let metadata = file.metadata().await?;
-
-    let mut buf = Vec::with_capacity(metadata.len().try_into()?);
-
-    file.read_to_end(&mut buf).await?;
-
-    if buf.is_empty() {
-        return Ok(ByteBuf(vec![], UVarint::new(0, 1)));
-    }
-
-    println!("Contents: {buf:?}");
-
-    let mut another_offset = 0;
-
-    let data_as_bytes = ByteBuf::decode(&buf[..], &mut another_offset);
-
-    Ok(data_as_bytes)
-}
-
-pub async fn get_records_from_disk() -> Result<Vec<RecordBatch>, Error> {
-    let mut file = File::open(CLUSTER_METADATA).await?;
-
-    let metadata = file.metadata().await?;
-
-    let mut buf = Vec::with_capacity(metadata.len().try_into()?);
-
-    file.read_to_end(&mut buf).await?;
-
-    let mut batches: Vec<RecordBatch> = Vec::new();
-
-    let mut offset = 0;
-
-    while offset < buf.len() {
-        let batch = RecordBatch::decode(&buf[..], &mut offset);
-        batches.push(batch);
-    }
-
-    Ok(batches)
+    dir: PathBuf,
+    fetch_offset: i64,
+    max_offset: i64,
+    max_bytes: i32,
+) -> Result<CompactRecords, Error> {
+    let max_bytes = max_bytes.max(0) as u64;
+
+    let region = tokio::task::spawn_blocking(move || {
+        segment::read_region(&dir, fetch_offset, max_offset, max_bytes)
+    })
+    .await??;
+
+    Ok(region.map_or_else(CompactRecords::empty, CompactRecords::File))
 }
 
 #[derive(Debug, Encode, Decode, Size)]
 pub struct FeatureLevelRecord {
-    pub name: CString,
+    pub name: CompactString,
     pub feature_level: i16,
     pub tagged_field: u8,
 }
 
-#[derive(Debug, Encode, Decode, Size)]
-pub struct UnknownRecord {}
+/// A metadata record of a type this broker does not interpret.
+#[derive(Debug)]
+pub struct UnknownRecord {
+    pub record_type: u8,
+    pub version: u8,
+}
 
-#[derive(Debug, Encode, Decode, Size)]
+/// The payload of a metadata record. The framing (record type id and version)
+/// is written by [`GenericRecord`](crate::types::record::GenericRecord).
+#[derive(Debug)]
 pub enum RecordValue {
     Topic(TopicRecord),
     FeatureLevel(FeatureLevelRecord),
     Partition(PartitionRecord),
+    Config(ConfigRecord),
+    RemoveTopic(RemoveTopicRecord),
+    ProducerIds(ProducerIdsRecord),
+    UserScramCredential(UserScramCredentialRecord),
+    RemoveUserScramCredential(RemoveUserScramCredentialRecord),
+    AccessControlEntry(AccessControlEntryRecord),
+    RemoveAccessControlEntry(RemoveAccessControlEntryRecord),
+    ClientQuota(ClientQuotaRecord),
     Unknown(UnknownRecord),
 }
 
-#[derive(Debug, Encode, Decode, Size)]
-pub struct LogFile {
-    pub data: Vec<RecordBatch>,
-}
-
-#[derive(Debug, Encode, Decode, Size)]
+#[derive(Debug, Encode, Size)]
 pub struct TopicRecordBatch {
     pub base_offset: i64,
     pub batch_length: i32,
@@ -220,10 +120,146 @@ pub struct TopicRecordBatch {
     pub records: ByteBuf,
 }
 
+impl Decode for TopicRecordBatch {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let base_offset = i64::decode(bytes, offset);
+        let batch_length = i32::decode(bytes, offset);
+
+        // The records payload runs until the end of this batch, not the buffer.
+        // A truncated last batch is cut short at the end of the buffer.
+        let end = (*offset + batch_length.max(0) as usize).min(bytes.len());
+        let batch = &bytes.slice(..end);
+
+        let decoded = Self {
+            base_offset,
+            batch_length,
+            partition_leader_epoch: i32::decode(batch, offset),
+            magic_byte: u8::decode(batch, offset),
+            crc: u32::decode(batch, offset),
+            attributes: i16::decode(batch, offset),
+            last_offset_delta: i32::decode(batch, offset),
+            base_timestamp: i64::decode(batch, offset),
+            max_timestamp: i64::decode(batch, offset),
+            producer_id: i64::decode(batch, offset),
+            producer_epoch: i16::decode(batch, offset),
+            base_sequence: i32::decode(batch, offset),
+            records: ByteBuf::decode(batch, offset),
+        };
+        // A header running past the batch is malformed, not just cut short.
+        if *offset > end {
+            fail(bytes, offset);
+        }
+        decoded
+    }
+}
+
+impl TopicRecordBatch {
+    /// Builds a batch holding `records`, with lengths and CRC filled in. The
+    /// base offset is assigned when the batch is appended to a log.
+    pub fn new(base_timestamp: i64, records: Vec<TopicRecordDisk>) -> Self {
+        let mut buf = BytesMut::new();
+        buf.put_i32(length_prefix(records.len()));
+        for record in &records {
+            record.encode(&mut buf);
+        }
+
+        let max_delta = records.iter().map(|r| r.timestamp.0).max().unwrap_or(0);
+
+        let mut batch = Self {
+            base_offset: 0,
+            batch_length: 0,
+            partition_leader_epoch: 0,
+            magic_byte: 2,
+            crc: 0,
+            attributes: 0,
+            last_offset_delta: length_prefix::<i32>(records.len()).saturating_sub(1).max(0),
+            base_timestamp,
+            max_timestamp: base_timestamp + max_delta,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records: ByteBuf(buf.freeze()),
+        };
+
+        batch.batch_length = length_prefix(batch.size_in_bytes() - 12);
+        batch.update_crc();
+        batch
+    }
+
+    /// Builds the control batch ending the transaction of a producer, holding
+    /// a single commit or abort marker.
+    pub fn control(
+        producer_id: i64,
+        producer_epoch: i16,
+        commit: bool,
+        coordinator_epoch: i32,
+        timestamp: i64,
+    ) -> Self {
+        let mut key = BytesMut::with_capacity(4);
+        key.put_i16(0);
+        key.put_i16(if commit { COMMIT_MARKER } else { ABORT_MARKER });
+        let mut value = BytesMut::with_capacity(6);
+        value.put_i16(0);
+        value.put_i32(coordinator_epoch);
+
+        let record = TopicRecordDisk::new(0, 0, Some(key.freeze()), Some(value.freeze()));
+        let mut batch = Self::new(timestamp, vec![record]);
+        batch.attributes = TRANSACTIONAL_FLAG | CONTROL_FLAG;
+        batch.producer_id = producer_id;
+        batch.producer_epoch = producer_epoch;
+        batch.update_crc();
+        batch
+    }
+
+    pub fn is_transactional(&self) -> bool {
+        self.attributes & TRANSACTIONAL_FLAG != 0
+    }
+
+    pub fn is_control(&self) -> bool {
+        self.attributes & CONTROL_FLAG != 0
+    }
+
+    /// The marker type of a control batch, `None` for other batches.
+    pub fn control_type(&self) -> Option<i16> {
+        if !self.is_control() {
+            return None;
+        }
+        let key = self.decode_records().into_iter().next()?.key.0?;
+        (key.len() >= 4).then(|| i16::decode(&key, &mut 2))
+    }
+
+    /// Recomputes the CRC-32C, which covers the batch from its attributes on.
+    /// Needed after changing any field other than the base offset, length or
+    /// leader epoch.
+    pub fn update_crc(&mut self) {
+        let bytes = self.to_bytes();
+        self.crc = crc32c::crc32c(&bytes[CRC_END..]);
+    }
+
+    /// Whether the CRC matches the rest of the batch, as received.
+    pub fn crc_matches(&self) -> bool {
+        crc32c::crc32c(&self.to_bytes()[CRC_END..]) == self.crc
+    }
+
+    pub fn last_offset(&self) -> i64 {
+        self.base_offset + self.last_offset_delta as i64
+    }
+
+    pub fn decode_records(&self) -> Vec<TopicRecordDisk> {
+        Array32::decode(&self.records.0, &mut 0).0
+    }
+
+    /// Decodes the records of a batch another broker sent, failing if they
+    /// are truncated or malformed.
+    pub fn try_decode_records(&self) -> Result<Vec<TopicRecordDisk>, Error> {
+        Ok(Array32::try_decode(&self.records.0, &mut 0)?.0)
+    }
+}
+
 #[derive(Debug, Encode, Decode, Size)]
 pub struct TopicHeaders {
-    pub header_key: CString,
-    pub value: Vec<u8>,
+    pub header_key: VarintString,
+    pub value: NullableVarintBytes,
 }
 
 #[derive(Debug, Encode, Decode, Size)]
@@ -232,9 +268,41 @@ pub struct TopicRecordDisk {
     pub attributes: u8,
     pub timestamp: Varint,
     pub delta_offset: Varint,
-    pub key: CSignedVec<i32>,
-    pub value: CSignedString,
-    pub headers_array: CSignedVec<TopicHeaders>,
+    pub key: NullableVarintBytes,
+    pub value: NullableVarintBytes,
+    pub headers_array: VarintArray<TopicHeaders>,
+}
+
+impl TopicRecordDisk {
+    /// A record without headers, `delta_offset` and `timestamp_delta` after
+    /// the start of its batch.
+    pub fn new(
+        delta_offset: i32,
+        timestamp_delta: i64,
+        key: Option<Bytes>,
+        value: Option<Bytes>,
+    ) -> Self {
+        let mut record = Self {
+            length: Varint(0),
+            attributes: 0,
+            timestamp: Varint(timestamp_delta),
+            delta_offset: Varint(delta_offset as i64),
+            key: key.into(),
+            value: value.into(),
+            headers_array: VarintArray(vec![]),
+        };
+
+        record.length = Varint((record.size_in_bytes() - 1) as i64);
+        record
+    }
+
+    /// Adds headers to the record, updating its length.
+    pub fn with_headers(mut self, headers: Vec<TopicHeaders>) -> Self {
+        self.headers_array = VarintArray(headers);
+        self.length = Varint(0);
+        self.length = Varint((self.size_in_bytes() - 1) as i64);
+        self
+    }
 }
 
 #[derive(Debug, Encode, Decode, Size)]
@@ -268,7 +336,7 @@ pub struct RecordBatch {
     pub producer_id: i64,
     pub producer_epoch: i16,
     pub base_sequence: i32,
-    pub records: Vec<Record>,
+    pub records: Array32<Record>,
 }
 
 #[derive(Debug, Encode, Decode, Size)]
@@ -277,7 +345,7 @@ pub struct Record {
     pub attributes: u8,
     pub timestamp: Varint,
     pub delta_offset: Varint,
-    pub key: CSignedVec<i32>,
+    pub key: NullableVarintBytes,
     pub value_length: Varint,
     pub frame_version: u8,
     pub value: GenericRecord,
@@ -287,10 +355,11 @@ pub struct Record {
 #[cfg(test)]
 mod tests {
     use crate::{
-        kafka::log::{RecordBatch, RecordValue, TopicRecordBatch},
-        types::cstring::CString,
-        Decode,
+        kafka::log::{RecordBatch, RecordValue, TopicRecordBatch, TopicRecordDisk},
+        types::{array::Array32, cstring::CompactString},
+        Decode, Encode,
     };
+    use bytes::Bytes;
 
     #[test]
     fn test_record_parsing() {
@@ -323,7 +392,7 @@ mod tests {
 
         let mut offset = 0;
 
-        let decoded = RecordBatch::decode(&test_case_1[..], &mut offset);
+        let decoded = RecordBatch::decode(&Bytes::from(test_case_1), &mut offset);
 
         assert_eq!(decoded.base_offset, 0);
         assert_eq!(decoded.batch_length, 79);
@@ -345,7 +414,7 @@ mod tests {
         match &decoded.records[0].value.r_record {
             RecordValue::FeatureLevel(value) => {
                 assert_eq!(value.feature_level, 20);
-                assert_eq!(value.name, CString("metadata.version".to_string(), 1))
+                assert_eq!(value.name, CompactString("metadata.version".to_string()))
             }
             _ => panic!("Expected Feature level to be decoded"),
         }
@@ -366,15 +435,38 @@ mod tests {
 
         let mut offset = 0;
 
-        let decoded = TopicRecordBatch::decode(&test_case[..], &mut offset);
+        let test_case = Bytes::from(test_case);
+        let decoded = TopicRecordBatch::decode(&test_case, &mut offset);
 
-        println!("{decoded:?}");
+        let records: Array32<TopicRecordDisk> = Array32::decode(&decoded.records.0, &mut 0);
 
         assert_eq!(decoded.base_offset, 0);
+        assert_eq!(offset, test_case.len() - 80);
+
+        let truncated = TopicRecordBatch::decode(&test_case.slice(..70), &mut 0);
+        assert_eq!(truncated.records.0.len(), 70 - 57);
+        assert_eq!(records[0].key.0, None);
         assert_eq!(
-            decoded.records[0].value.0,
-            "Hello Reverse Engineering!".to_string()
+            records[0].value.as_deref(),
+            Some("Hello Reverse Engineering!".as_bytes())
+        );
+
+        let second = TopicRecordBatch::decode(&test_case, &mut 94);
+        let mut rebuilt = TopicRecordBatch::new(
+            second.base_timestamp,
+            vec![TopicRecordDisk::new(
+                0,
+                0,
+                None,
+                Some(Bytes::from_static(b"Hello Earth!")),
+            )],
         );
+        rebuilt.base_offset = 1;
+        rebuilt.producer_id = 0;
+        rebuilt.producer_epoch = 0;
+        rebuilt.base_sequence = 0;
+        rebuilt.update_crc();
+        assert_eq!(rebuilt.to_bytes(), test_case.slice(94..));
     }
 
     #[test]
@@ -394,8 +486,9 @@ mod tests {
 
         let mut data: Vec<TopicRecordBatch> = Vec::new();
 
+        let test_case = Bytes::from(test_case);
         while offset < test_case.len() {
-            let decoded = TopicRecordBatch::decode(&test_case[..], &mut offset);
+            let decoded = TopicRecordBatch::decode(&test_case, &mut offset);
             data.push(decoded);
         }
 
@@ -427,7 +520,7 @@ mod tests {
 
         let mut offset = 0;
 
-        let decoded = RecordBatch::decode(&test_case_2[..], &mut offset);
+        let decoded = RecordBatch::decode(&Bytes::from(test_case_2.clone()), &mut offset);
 
         assert_eq!(decoded.base_offset, 1);
         assert_eq!(decoded.batch_length, 228);
@@ -446,7 +539,7 @@ mod tests {
         assert_eq!(decoded.records[0].attributes, 0);
         assert_eq!(decoded.records[0].timestamp.0, 0);
         assert_eq!(decoded.records[0].delta_offset.0, 0);
-        assert_eq!(decoded.records[0].key.data.len(), 0);
+        assert_eq!(decoded.records[0].key.0, None);
         match &decoded.records[0].value.r_record {
             RecordValue::Topic(record) => {
                 assert_eq!(record.name.0, "saz");
@@ -462,6 +555,7 @@ mod tests {
             _ => panic!("Expected record to be Topic"),
         }
         assert_eq!(decoded.records[0].headers_array.0, 0);
+        assert_eq!(decoded.to_bytes(), test_case_2);
 
         assert_eq!(decoded.records[1].length.0, 72);
         match &decoded.records[1].value.r_record {
@@ -474,18 +568,9 @@ mod tests {
         match &decoded.records[2].value.r_record {
             RecordValue::Partition(record) => {
                 assert_eq!(record.id, 1);
-                assert_eq!(record.replicas.data[0], 1);
+                assert_eq!(record.replicas[0], 1);
             }
             _ => panic!("Expected record to be Topic"),
         }
     }
-
-    //#[tokio::test]
-    //async fn test_decode_from_file() {
-    //    if let Ok(batch_vec) = get_records_from_disk().await {
-    //        assert_eq!(batch_vec.len(), 2);
-    //    } else {
-    //        panic!("Could not parse records from disk")
-    //    }
-    //}
 }
@@ -0,0 +1,934 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use bytes::Bytes;
+use uuid::Uuid;
+
+use crate::{
+    kafka::configs::{LogConfig, LogConfigs},
+    types::uuid::UUID,
+    Decode, Encode,
+};
+
+use super::{
+    checkpoint::{self, Checkpoint, LOG_START_OFFSET_CHECKPOINT},
+    producer_state::{ProducerState, SequenceError},
+    segment::{list_segments, read_batch_position, Segment, BATCH_OVERHEAD},
+    txn_index::{self, AbortedTxn},
+    TopicRecordBatch, ABORT_MARKER,
+};
+
+/// Suffix of the directories of deleted partitions awaiting removal.
+const DELETE_SUFFIX: &str = "-delete";
+
+/// The writable end of a partition: batches are appended to the active
+/// segment, which Fetch reads through [`segment::read_region`](super::segment::read_region).
+#[derive(Debug)]
+pub struct PartitionLog {
+    dir: PathBuf,
+    config: LogConfig,
+    active: Segment,
+    log: File,
+    index: File,
+    active_size: u64,
+    /// When the first batch was appended to the active segment since the
+    /// log was opened; `segment.ms` counts from there.
+    active_since: Option<Instant>,
+    bytes_since_index: u64,
+    log_start_offset: i64,
+    log_end_offset: i64,
+    producers: ProducerState,
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Scans the active segment of the log in `dir`. Returns it with the end of
+/// its last complete batch, the segment's length and the log end offset.
+fn scan_active(dir: &Path) -> io::Result<(Segment, u64, u64, i64)> {
+    let active = list_segments(dir)?
+        .pop()
+        .unwrap_or_else(|| Segment::new(dir, 0));
+
+    let (mut position, mut len) = (0, 0);
+    let mut log_end_offset = active.base_offset;
+
+    if let Ok(reader) = File::open(&active.log_path) {
+        len = reader.metadata()?.len();
+        while let Some(batch) = read_batch_position(&reader, position, len)? {
+            position += batch.size;
+            log_end_offset = batch.last_offset + 1;
+        }
+    }
+
+    Ok((active, position, len, log_end_offset))
+}
+
+/// Reads the log end offset of the log in `dir` without opening it for
+/// writing.
+pub fn read_log_end_offset(dir: &Path) -> io::Result<i64> {
+    Ok(scan_active(dir)?.3)
+}
+
+/// Reads every complete batch in the log in `dir`, oldest first.
+pub fn read_batches(dir: &Path) -> io::Result<Vec<TopicRecordBatch>> {
+    read_batches_from(dir, 0)
+}
+
+/// Reads the complete batches in the log in `dir` starting at or after
+/// `from`, skipping the segments wholly before it.
+fn read_batches_from(dir: &Path, from: i64) -> io::Result<Vec<TopicRecordBatch>> {
+    let segments = list_segments(dir)?;
+    let first = segments
+        .iter()
+        .rposition(|segment| segment.base_offset <= from)
+        .unwrap_or(0);
+    let mut batches = Vec::new();
+
+    for segment in &segments[first..] {
+        let data = Bytes::from(fs::read(&segment.log_path)?);
+        let mut offset = 0;
+
+        while offset + BATCH_OVERHEAD as usize <= data.len() {
+            let batch_length = i32::decode(&data, &mut (offset + 8));
+            let end = offset + BATCH_OVERHEAD as usize + batch_length.max(0) as usize;
+
+            if batch_length <= 0 || end > data.len() {
+                break;
+            }
+
+            let batch = TopicRecordBatch::decode(&data, &mut offset);
+            if batch.base_offset >= from {
+                batches.push(batch);
+            }
+            offset = end;
+        }
+    }
+
+    Ok(batches)
+}
+
+/// Records `batch` in the producer state. Returns the transaction it
+/// aborts if it is an ABORT marker, for the transaction index.
+fn update_producers(producers: &mut ProducerState, batch: &TopicRecordBatch) -> Option<AbortedTxn> {
+    let first_offset = if batch.control_type() == Some(ABORT_MARKER) {
+        producers.txn_first_offset(batch.producer_id)
+    } else {
+        None
+    };
+    producers.update(batch);
+
+    first_offset.map(|first_offset| AbortedTxn {
+        producer_id: batch.producer_id,
+        first_offset,
+        last_offset: batch.base_offset,
+        last_stable_offset: producers
+            .first_unstable_offset()
+            .unwrap_or(batch.base_offset + 1),
+    })
+}
+
+impl PartitionLog {
+    /// Opens the log in `dir`, creating it if needed. A batch left partially
+    /// written at the end of the active segment is cut off. The log starts
+    /// at `log_start_offset` if it was advanced past the first segment. The
+    /// producer state is restored from the latest snapshot and the batches
+    /// appended after it, which also restores the transaction index entries
+    /// of ABORT markers written just before a crash.
+    pub fn open(dir: &Path, config: LogConfig, log_start_offset: i64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let first = list_segments(dir)?.first().map_or(0, |s| s.base_offset);
+        let (active, position, len, log_end_offset) = scan_active(dir)?;
+        let log = open_append(&active.log_path)?;
+
+        if position < len {
+            log.set_len(position)?;
+        }
+
+        let (mut producers, snapshot_offset) = ProducerState::load(dir, log_end_offset)?;
+        let segments = list_segments(dir)?;
+        let replayed = segments
+            .iter()
+            .rposition(|segment| segment.base_offset <= snapshot_offset)
+            .unwrap_or(0);
+        let mut indexed = HashSet::new();
+        for segment in &segments[replayed..] {
+            indexed.extend(txn_index::read(segment)?.iter().map(|txn| txn.last_offset));
+        }
+        for batch in read_batches_from(dir, snapshot_offset)? {
+            let Some(aborted) = update_producers(&mut producers, &batch) else {
+                continue;
+            };
+            if !indexed.contains(&aborted.last_offset) {
+                if let Some(segment) = segments
+                    .iter()
+                    .rev()
+                    .find(|segment| segment.base_offset <= aborted.last_offset)
+                {
+                    txn_index::append(segment, aborted)?;
+                }
+            }
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            config,
+            index: open_append(&active.index_path)?,
+            active,
+            log,
+            active_size: position,
+            active_since: None,
+            bytes_since_index: position,
+            log_start_offset: log_start_offset.max(first).min(log_end_offset),
+            log_end_offset,
+            producers,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Applies changed topic configs from the next append on.
+    pub fn set_config(&mut self, config: LogConfig) {
+        self.config = config;
+    }
+
+    /// The first offset that may be fetched.
+    pub fn log_start_offset(&self) -> i64 {
+        self.log_start_offset
+    }
+
+    /// The offset the next appended record will get.
+    pub fn log_end_offset(&self) -> i64 {
+        self.log_end_offset
+    }
+
+    /// The first offset of the earliest open transaction, or the log end
+    /// offset if none is open. read_committed fetches stop there.
+    pub fn last_stable_offset(&self) -> i64 {
+        self.producers
+            .first_unstable_offset()
+            .unwrap_or(self.log_end_offset)
+    }
+
+    /// Checks the sequence number of a batch of an idempotent producer
+    /// before it is appended, which must happen under the same lock.
+    pub fn check_sequence(&self, batch: &TopicRecordBatch) -> Result<(), SequenceError> {
+        self.producers.check(batch)
+    }
+
+    /// Appends `batch` at the end of the log, assigning its base offset.
+    /// Returns that base offset. A batch larger than `max.message.bytes` is
+    /// rejected with [`io::ErrorKind::InvalidInput`].
+    pub fn append(&mut self, batch: &mut TopicRecordBatch) -> io::Result<i64> {
+        // The base offset is outside the CRC, so it can be set as is.
+        batch.base_offset = self.log_end_offset;
+        let bytes = batch.to_bytes();
+        let size = bytes.len() as u64;
+
+        if size > self.config.max_message_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "The record batch is {size} bytes, larger than max.message.bytes {}",
+                    self.config.max_message_bytes
+                ),
+            ));
+        }
+
+        let full = self.active_size + size > self.config.segment_bytes;
+        let expired = self.active_since.is_some_and(|since| {
+            since.elapsed() > Duration::from_millis(self.config.segment_ms.max(0) as u64)
+        });
+        if self.active_size > 0 && (full || expired) {
+            self.roll()?;
+        }
+        self.active_since.get_or_insert_with(Instant::now);
+
+        if self.bytes_since_index >= self.config.index_interval_bytes {
+            let relative = (batch.base_offset - self.active.base_offset) as u32;
+            let mut entry = [0u8; 8];
+            entry[..4].copy_from_slice(&relative.to_be_bytes());
+            entry[4..].copy_from_slice(&(self.active_size as u32).to_be_bytes());
+            self.index.write_all(&entry)?;
+            self.bytes_since_index = 0;
+        }
+
+        self.log.write_all(&bytes)?;
+
+        self.active_size += size;
+        self.bytes_since_index += size;
+        self.log_end_offset = batch.last_offset() + 1;
+        if let Some(aborted) = update_producers(&mut self.producers, batch) {
+            txn_index::append(&self.active, aborted)?;
+        }
+
+        Ok(batch.base_offset)
+    }
+
+    /// Starts a new active segment, snapshotting the producer state as of
+    /// its base offset.
+    fn roll(&mut self) -> io::Result<()> {
+        self.producers
+            .write_snapshot(&self.dir, self.log_end_offset)?;
+        self.active = Segment::new(&self.dir, self.log_end_offset);
+        self.log = open_append(&self.active.log_path)?;
+        self.index = open_append(&self.active.index_path)?;
+        self.active_size = 0;
+        self.active_since = None;
+        self.bytes_since_index = 0;
+        Ok(())
+    }
+
+    /// Advances the log start offset to `offset`, at most the log end
+    /// offset, and deletes the segments wholly before it. The active segment
+    /// is never deleted.
+    pub fn delete_records_before(&mut self, offset: i64) -> io::Result<()> {
+        if offset <= self.log_start_offset {
+            return Ok(());
+        }
+        self.log_start_offset = offset.min(self.log_end_offset);
+
+        let segments = list_segments(&self.dir)?;
+        for pair in segments.windows(2) {
+            if pair[1].base_offset > self.log_start_offset {
+                break;
+            }
+            pair[0].remove()?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the oldest segments once they are older than `retention.ms`
+    /// or the log is larger than `retention.bytes` without them, unless the
+    /// cleanup policy keeps them. The active segment is never deleted.
+    /// Returns how many segments were deleted.
+    pub fn apply_retention(&mut self, now: SystemTime) -> io::Result<usize> {
+        if !self.config.delete {
+            return Ok(0);
+        }
+
+        let mut segments = Vec::new();
+        for segment in list_segments(&self.dir)? {
+            let metadata = fs::metadata(&segment.log_path)?;
+            segments.push((segment, metadata.len(), metadata.modified()?));
+        }
+
+        let total: u64 = segments.iter().map(|(_, len, _)| len).sum();
+        let mut excess = match self.config.retention_bytes {
+            -1 => 0,
+            limit => total as i64 - limit,
+        };
+        let retention = Duration::from_millis(self.config.retention_ms.max(0) as u64);
+
+        let mut deleted = 0;
+        for (segment, len, modified) in &segments[..segments.len().saturating_sub(1)] {
+            let expired = self.config.retention_ms != -1
+                && now.duration_since(*modified).unwrap_or_default() > retention;
+            let oversized = excess >= *len as i64 && excess > 0;
+            if !expired && !oversized {
+                break;
+            }
+
+            segment.remove()?;
+            excess -= *len as i64;
+            deleted += 1;
+        }
+
+        if let Some((first, _, _)) = segments.get(deleted) {
+            self.log_start_offset = self.log_start_offset.max(first.base_offset);
+        }
+
+        Ok(deleted)
+    }
+
+    /// Reads every complete batch in the log, oldest first.
+    pub fn read_batches(&self) -> io::Result<Vec<TopicRecordBatch>> {
+        read_batches(&self.dir)
+    }
+}
+
+/// Removes the directories of deleted partitions, logging failures.
+pub fn remove_dirs(dirs: Vec<PathBuf>) {
+    for dir in dirs {
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            eprintln!("failed to remove {}: {e:?}", dir.display());
+        }
+    }
+}
+
+/// The offsets bounding what a fetch may read from a partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogOffsets {
+    pub log_start_offset: i64,
+    pub log_end_offset: i64,
+    pub last_stable_offset: i64,
+}
+
+/// A partition log shared between the requests that write to it.
+pub type SharedLog = Arc<Mutex<PartitionLog>>;
+
+/// Opens partition logs on first use and shares them between requests.
+#[derive(Debug)]
+pub struct LogManager {
+    dir: PathBuf,
+    configs: Mutex<LogConfigs>,
+    logs: Mutex<HashMap<(String, i32), SharedLog>>,
+    /// Log start offsets advanced by DeleteRecords, as checkpointed.
+    log_start_offsets: Mutex<Checkpoint>,
+}
+
+impl LogManager {
+    /// Manages the logs in `dir`. An unreadable checkpoint is reported and
+    /// ignored: the log start offsets fall back to the first segments.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let path = dir.join(LOG_START_OFFSET_CHECKPOINT);
+        let log_start_offsets = checkpoint::read(&path).unwrap_or_else(|e| {
+            eprintln!("failed to read {}: {e:?}", path.display());
+            Checkpoint::new()
+        });
+
+        Self {
+            dir,
+            configs: Mutex::new(LogConfigs::default()),
+            logs: Mutex::new(HashMap::new()),
+            log_start_offsets: Mutex::new(log_start_offsets),
+        }
+    }
+
+    /// Records the log start offset of a partition in the checkpoint, or
+    /// drops it when `None`.
+    fn checkpoint(
+        &self,
+        updates: impl IntoIterator<Item = ((String, i32), Option<i64>)>,
+    ) -> io::Result<()> {
+        let mut offsets = self
+            .log_start_offsets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        let mut changed = false;
+        for (key, offset) in updates {
+            changed |= match offset {
+                Some(offset) => offsets.insert(key, offset) != Some(offset),
+                None => offsets.remove(&key).is_some(),
+            };
+        }
+
+        if changed {
+            checkpoint::write(&self.dir.join(LOG_START_OFFSET_CHECKPOINT), &offsets)?;
+        }
+        Ok(())
+    }
+
+    /// Advances the log start offset of a partition to `offset`, or to the
+    /// high watermark for -1, and checkpoints it. Returns the new log start
+    /// offset, or `None` if `offset` is past the high watermark.
+    pub fn delete_records(
+        &self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+    ) -> io::Result<Option<i64>> {
+        let log = self.partition(topic, partition)?;
+        let mut log = log.lock().unwrap_or_else(|e| e.into_inner());
+
+        let offset = match offset {
+            -1 => log.log_end_offset(),
+            offset if offset < 0 || offset > log.log_end_offset() => return Ok(None),
+            offset => offset,
+        };
+
+        log.delete_records_before(offset)?;
+        let log_start_offset = log.log_start_offset();
+        self.checkpoint([((topic.to_string(), partition), Some(log_start_offset))])?;
+
+        Ok(Some(log_start_offset))
+    }
+
+    /// The log start, end and last stable offsets of a partition, `None` if
+    /// it has no log.
+    pub fn offsets(&self, topic: &str, partition: i32) -> io::Result<Option<LogOffsets>> {
+        let key = (topic.to_string(), partition);
+        let open = self
+            .logs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(&key);
+        if !open && !self.partition_dir(topic, partition).is_dir() {
+            return Ok(None);
+        }
+
+        let log = self.partition(topic, partition)?;
+        let log = log.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(Some(LogOffsets {
+            log_start_offset: log.log_start_offset(),
+            log_end_offset: log.log_end_offset(),
+            last_stable_offset: log.last_stable_offset(),
+        }))
+    }
+
+    /// Replaces the topic configs, applying them to the open logs.
+    pub fn set_configs(&self, configs: LogConfigs) {
+        let logs = self.logs.lock().unwrap_or_else(|e| e.into_inner());
+        let mut current = self.configs.lock().unwrap_or_else(|e| e.into_inner());
+
+        for ((topic, _), log) in logs.iter() {
+            let config = configs.get(topic);
+            if config != current.get(topic) {
+                let mut log = log.lock().unwrap_or_else(|e| e.into_inner());
+                log.set_config(config.clone());
+            }
+        }
+
+        *current = configs;
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn partition_dir(&self, topic: &str, partition: i32) -> PathBuf {
+        self.dir.join(format!("{topic}-{partition}"))
+    }
+
+    /// The log end offset of a partition, which is also its high watermark
+    /// on a single broker. `None` if the partition has no log.
+    pub fn log_end_offset(&self, topic: &str, partition: i32) -> io::Result<Option<i64>> {
+        let key = (topic.to_string(), partition);
+        if let Some(log) = self
+            .logs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+        {
+            return Ok(Some(
+                log.lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .log_end_offset(),
+            ));
+        }
+
+        let dir = self.partition_dir(topic, partition);
+        if !dir.is_dir() {
+            return Ok(None);
+        }
+
+        read_log_end_offset(&dir).map(Some)
+    }
+
+    /// Closes the logs of the deleted topic `topic` and renames their
+    /// directories to `<topic>-<partition>.<topic id>-delete`, freeing the
+    /// name at once. Returns the renamed directories, for [`remove_dirs`].
+    pub fn rename_deleted(
+        &self,
+        topic: &str,
+        topic_id: &UUID,
+        partitions: impl IntoIterator<Item = i32>,
+    ) -> io::Result<Vec<PathBuf>> {
+        let mut logs = self.logs.lock().unwrap_or_else(|e| e.into_inner());
+        let mut renamed = Vec::new();
+        let mut dropped = Vec::new();
+
+        for partition in partitions {
+            logs.remove(&(topic.to_string(), partition));
+            dropped.push(((topic.to_string(), partition), None));
+
+            let dir = self.partition_dir(topic, partition);
+            if !dir.is_dir() {
+                continue;
+            }
+
+            let id = Uuid::from_bytes(topic_id.0).simple();
+            let target = self
+                .dir
+                .join(format!("{topic}-{partition}.{id}{DELETE_SUFFIX}"));
+            fs::rename(&dir, &target)?;
+            renamed.push(target);
+        }
+
+        // A topic created with the same name starts from offset 0.
+        self.checkpoint(dropped)?;
+
+        Ok(renamed)
+    }
+
+    /// Directories renamed by [`rename_deleted`](Self::rename_deleted) that
+    /// were not removed before the broker stopped.
+    pub fn deleted_dirs(&self) -> io::Result<Vec<PathBuf>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        let mut dirs = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let deleted = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(DELETE_SUFFIX));
+            if deleted && path.is_dir() {
+                dirs.push(path);
+            }
+        }
+
+        Ok(dirs)
+    }
+
+    pub fn partition(&self, topic: &str, partition: i32) -> io::Result<SharedLog> {
+        let mut logs = self.logs.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (topic.to_string(), partition);
+
+        if let Some(log) = logs.get(&key) {
+            return Ok(log.clone());
+        }
+
+        let config = self
+            .configs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(topic)
+            .clone();
+        let log_start_offset = self
+            .log_start_offsets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+            .copied()
+            .unwrap_or(0);
+        let log = Arc::new(Mutex::new(PartitionLog::open(
+            &self.partition_dir(topic, partition),
+            config,
+            log_start_offset,
+        )?));
+        logs.insert(key, log.clone());
+
+        Ok(log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kafka::log::{
+            segment::{index_lookup, read_region},
+            TopicRecordDisk, TRANSACTIONAL_FLAG,
+        },
+        Size,
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("partition-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn batch(values: &[&str]) -> TopicRecordBatch {
+        let records = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                TopicRecordDisk::new(
+                    i as i32,
+                    0,
+                    None,
+                    Some(Bytes::copy_from_slice(v.as_bytes())),
+                )
+            })
+            .collect();
+        TopicRecordBatch::new(0, records)
+    }
+
+    #[test]
+    fn test_append_assigns_offsets_and_reopens() {
+        let dir = temp_dir("append");
+
+        let mut log = PartitionLog::open(&dir, LogConfig::default(), 0).unwrap();
+        assert_eq!(log.append(&mut batch(&["a", "b"])).unwrap(), 0);
+        assert_eq!(log.append(&mut batch(&["c"])).unwrap(), 2);
+        drop(log);
+
+        // A torn write at the end is dropped on reopen.
+        let segment = dir.join("00000000000000000000.log");
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(&[0, 0, 0, 0, 0, 0, 0, 3, 0, 0]).unwrap();
+
+        let mut log = PartitionLog::open(&dir, LogConfig::default(), 0).unwrap();
+        assert_eq!(log.log_end_offset(), 3);
+        assert_eq!(log.append(&mut batch(&["d"])).unwrap(), 3);
+
+        let batches = log.read_batches().unwrap();
+        let offsets: Vec<i64> = batches.iter().map(|b| b.base_offset).collect();
+        assert_eq!(offsets, vec![0, 2, 3]);
+        assert_eq!(
+            batches[0].decode_records()[1].value.as_deref(),
+            Some(&b"b"[..])
+        );
+
+        // Appended batches are what Fetch serves.
+        let region = read_region(&dir, 2, i64::MAX, u64::MAX).unwrap().unwrap();
+        assert_eq!(region.position, batches[0].size_in_bytes() as u64);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_entries_are_written_every_interval() {
+        let dir = temp_dir("index");
+        let mut log = PartitionLog::open(&dir, LogConfig::default(), 0).unwrap();
+
+        let value = "x".repeat(1000);
+        for _ in 0..10 {
+            log.append(&mut batch(&[&value])).unwrap();
+        }
+
+        let index = fs::read(dir.join("00000000000000000000.index")).unwrap();
+        assert_eq!(index.len(), 8 * 2);
+
+        let segment = Segment::new(&dir, 0);
+        let position = index_lookup(&segment, 9);
+        let region = read_region(&dir, 9, i64::MAX, u64::MAX).unwrap().unwrap();
+        assert!(position > 0 && position <= region.position);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_configs_roll_reject_and_retain() {
+        let dir = temp_dir("config");
+        let size = batch(&["aaaa"]).to_bytes().len() as u64;
+        let config = LogConfig {
+            segment_bytes: size * 2,
+            max_message_bytes: size,
+            ..LogConfig::default()
+        };
+        let mut log = PartitionLog::open(&dir, config.clone(), 0).unwrap();
+
+        for _ in 0..5 {
+            log.append(&mut batch(&["aaaa"])).unwrap();
+        }
+        let error = log.append(&mut batch(&["aaaaa"])).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let bases = |dir: &Path| -> Vec<i64> {
+            list_segments(dir)
+                .unwrap()
+                .iter()
+                .map(|s| s.base_offset)
+                .collect()
+        };
+        assert_eq!(bases(&dir), vec![0, 2, 4]);
+
+        // Nothing is past the default retention.
+        assert_eq!(log.apply_retention(SystemTime::now()).unwrap(), 0);
+
+        log.set_config(LogConfig {
+            retention_bytes: size as i64 * 3,
+            ..config.clone()
+        });
+        assert_eq!(log.apply_retention(SystemTime::now()).unwrap(), 1);
+        assert_eq!(bases(&dir), vec![2, 4]);
+
+        // The active segment is kept whatever its age.
+        log.set_config(LogConfig {
+            retention_ms: 0,
+            ..config
+        });
+        let later = SystemTime::now() + Duration::from_secs(1);
+        assert_eq!(log.apply_retention(later).unwrap(), 1);
+        assert_eq!(bases(&dir), vec![4]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_producer_state_survives_reopen() {
+        let dir = temp_dir("producers");
+        let idempotent = |sequence| {
+            let mut batch = batch(&["aaaa"]);
+            batch.producer_id = 3;
+            batch.producer_epoch = 0;
+            batch.base_sequence = sequence;
+            batch.update_crc();
+            batch
+        };
+        let config = LogConfig {
+            segment_bytes: idempotent(0).to_bytes().len() as u64 * 2,
+            ..LogConfig::default()
+        };
+
+        let mut log = PartitionLog::open(&dir, config.clone(), 0).unwrap();
+        for sequence in 0..3 {
+            let mut batch = idempotent(sequence);
+            log.check_sequence(&batch).unwrap();
+            log.append(&mut batch).unwrap();
+        }
+        drop(log);
+
+        // Rolling to the segment at offset 2 snapshotted the first two
+        // batches; the third is replayed from the log.
+        assert!(dir.join("00000000000000000002.snapshot").is_file());
+        let log = PartitionLog::open(&dir, config, 0).unwrap();
+        assert_eq!(
+            log.check_sequence(&idempotent(2)),
+            Err(SequenceError::Duplicate(2))
+        );
+        assert!(log.check_sequence(&idempotent(3)).is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_aborted_transactions_are_indexed() {
+        let dir = temp_dir("txn");
+        let transactional = |producer_id| {
+            let mut batch = batch(&["a", "b"]);
+            batch.attributes |= TRANSACTIONAL_FLAG;
+            batch.producer_id = producer_id;
+            batch.producer_epoch = 0;
+            batch.update_crc();
+            batch
+        };
+        let marker = |producer_id, commit| TopicRecordBatch::control(producer_id, 0, commit, 0, 0);
+
+        let mut log = PartitionLog::open(&dir, LogConfig::default(), 0).unwrap();
+        log.append(&mut batch(&["plain"])).unwrap();
+        log.append(&mut transactional(1)).unwrap();
+        log.append(&mut transactional(2)).unwrap();
+        assert_eq!((log.last_stable_offset(), log.log_end_offset()), (1, 5));
+
+        log.append(&mut marker(1, false)).unwrap();
+        assert_eq!(log.last_stable_offset(), 3);
+        log.append(&mut marker(2, true)).unwrap();
+        assert_eq!(log.last_stable_offset(), 7);
+
+        let aborted = AbortedTxn {
+            producer_id: 1,
+            first_offset: 1,
+            last_offset: 5,
+            last_stable_offset: 3,
+        };
+        assert_eq!(
+            txn_index::collect_aborted(&dir, 0, 7).unwrap(),
+            vec![aborted]
+        );
+        drop(log);
+
+        // An entry lost in a crash is restored from the marker.
+        fs::remove_file(Segment::new(&dir, 0).txn_index_path).unwrap();
+        let log = PartitionLog::open(&dir, LogConfig::default(), 0).unwrap();
+        assert_eq!(log.last_stable_offset(), 7);
+        drop(log);
+        PartitionLog::open(&dir, LogConfig::default(), 0).unwrap();
+        assert_eq!(
+            txn_index::collect_aborted(&dir, 0, 7).unwrap(),
+            vec![aborted]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_delete_records_advances_log_start_offset() {
+        let dir = temp_dir("records");
+        let logs = LogManager::new(&dir);
+        let size = batch(&["a"]).to_bytes().len() as u64;
+        logs.set_configs(LogConfigs {
+            default: LogConfig {
+                segment_bytes: size * 2,
+                ..LogConfig::default()
+            },
+            topics: HashMap::new(),
+        });
+
+        let log = logs.partition("topic", 0).unwrap();
+        for _ in 0..5 {
+            log.lock().unwrap().append(&mut batch(&["a"])).unwrap();
+        }
+        drop(log);
+
+        assert_eq!(logs.delete_records("topic", 0, 6).unwrap(), None);
+        assert_eq!(logs.delete_records("topic", 0, 3).unwrap(), Some(3));
+        // Only the segment wholly before offset 3 is deleted.
+        let bases: Vec<i64> = list_segments(&logs.partition_dir("topic", 0))
+            .unwrap()
+            .iter()
+            .map(|s| s.base_offset)
+            .collect();
+        assert_eq!(bases, vec![2, 4]);
+        assert_eq!(logs.delete_records("topic", 0, 1).unwrap(), Some(3));
+
+        // The log start offset survives a restart.
+        let reopened = LogManager::new(&dir);
+        assert_eq!(
+            reopened.offsets("topic", 0).unwrap(),
+            Some(LogOffsets {
+                log_start_offset: 3,
+                log_end_offset: 5,
+                last_stable_offset: 5,
+            })
+        );
+        assert_eq!(reopened.delete_records("topic", 0, -1).unwrap(), Some(5));
+        assert_eq!(reopened.offsets("missing", 0).unwrap(), None);
+
+        // Deleting the topic forgets it.
+        reopened
+            .rename_deleted("topic", &UUID([1; 16]), [0])
+            .unwrap();
+        assert!(LogManager::new(&dir)
+            .log_start_offsets
+            .lock()
+            .unwrap()
+            .is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_deleted_partitions_are_renamed() {
+        let dir = temp_dir("delete");
+        let logs = LogManager::new(&dir);
+
+        let log = logs.partition("topic", 0).unwrap();
+        log.lock().unwrap().append(&mut batch(&["a"])).unwrap();
+        drop(log);
+
+        let renamed = logs.rename_deleted("topic", &UUID([1; 16]), 0..2).unwrap();
+        assert_eq!(
+            renamed,
+            vec![dir.join("topic-0.01010101010101010101010101010101-delete")]
+        );
+        assert_eq!(logs.deleted_dirs().unwrap(), renamed);
+
+        // The name is free again, with an empty log.
+        assert_eq!(logs.log_end_offset("topic", 0).unwrap(), None);
+        assert_eq!(
+            logs.partition("topic", 0)
+                .unwrap()
+                .lock()
+                .unwrap()
+                .log_end_offset(),
+            0
+        );
+
+        remove_dirs(renamed);
+        assert!(logs.deleted_dirs().unwrap().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,12 @@
+use crate::{Decode, Encode, Size};
+use encode_derive::{Decode, Size};
+
+/// Allocates the block of producer ids up to `next_producer_id` to a broker.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ProducerIdsRecord {
+    pub broker_id: i32,
+    pub broker_epoch: i64,
+    /// The first producer id after the allocated block.
+    pub next_producer_id: i64,
+    pub tagged_fields: u8,
+}
@@ -0,0 +1,439 @@
+//! The state of the idempotent producers writing to a partition: the last
+//! batches each one appended, against which the sequence numbers of new
+//! batches are checked, and where their open transaction starts. It is
+//! snapshotted to `<offset>.snapshot` files, in
+//! the format the Java broker uses, when the active segment rolls.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use encode_derive::{Decode, Size};
+
+use crate::{types::array::Array32, Decode, Encode, Size};
+
+use super::{segment::segment_file_name, TopicRecordBatch};
+
+/// Batches remembered per producer to detect duplicates, as many as a
+/// producer may have in flight.
+const CACHED_BATCHES: usize = 5;
+
+const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+const SNAPSHOT_VERSION: i16 = 1;
+
+/// Bytes of a snapshot before the entries covered by its CRC.
+const SNAPSHOT_HEADER: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BatchMetadata {
+    first_sequence: i32,
+    last_sequence: i32,
+    first_offset: i64,
+    last_offset: i64,
+    timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProducerEntry {
+    epoch: i16,
+    /// Oldest first. Control batches and batches written by a coordinator
+    /// carry no sequence numbers and are not remembered.
+    batches: VecDeque<BatchMetadata>,
+    /// The offset of the first batch of the open transaction.
+    current_txn_first_offset: Option<i64>,
+}
+
+/// Why a batch of an idempotent producer cannot be appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceError {
+    /// The batch was already appended, at this base offset.
+    Duplicate(i64),
+    OutOfOrder {
+        expected: i32,
+        received: i32,
+    },
+    /// A producer with a newer epoch took over the producer id.
+    Fenced {
+        epoch: i16,
+        received: i16,
+    },
+}
+
+/// Adds `delta` to a sequence number, which wraps around to 0.
+fn increment(sequence: i32, delta: i32) -> i32 {
+    if sequence > i32::MAX - delta {
+        delta - (i32::MAX - sequence) - 1
+    } else {
+        sequence + delta
+    }
+}
+
+fn decrement(sequence: i32, delta: i32) -> i32 {
+    if sequence < delta {
+        i32::MAX - (delta - sequence) + 1
+    } else {
+        sequence - delta
+    }
+}
+
+/// The producers of a partition, by producer id.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProducerState {
+    producers: HashMap<i64, ProducerEntry>,
+}
+
+impl ProducerState {
+    /// Checks that `batch` follows the last batch of its producer. Batches
+    /// without a producer id are not checked.
+    pub fn check(&self, batch: &TopicRecordBatch) -> Result<(), SequenceError> {
+        if batch.producer_id < 0 {
+            return Ok(());
+        }
+
+        let received = batch.base_sequence;
+        let entry = match self.producers.get(&batch.producer_id) {
+            Some(entry) if batch.producer_epoch < entry.epoch => {
+                return Err(SequenceError::Fenced {
+                    epoch: entry.epoch,
+                    received: batch.producer_epoch,
+                })
+            }
+            // Markers only need a current epoch.
+            _ if batch.is_control() => return Ok(()),
+            Some(entry) if batch.producer_epoch == entry.epoch => entry,
+            // A new producer, or a new epoch, starts over from 0.
+            _ if received == 0 => return Ok(()),
+            _ => {
+                return Err(SequenceError::OutOfOrder {
+                    expected: 0,
+                    received,
+                })
+            }
+        };
+
+        let last_sequence = increment(received, batch.last_offset_delta);
+        if let Some(duplicate) = entry
+            .batches
+            .iter()
+            .find(|b| b.first_sequence == received && b.last_sequence == last_sequence)
+        {
+            return Err(SequenceError::Duplicate(duplicate.first_offset));
+        }
+
+        let expected = entry
+            .batches
+            .back()
+            .map_or(0, |last| increment(last.last_sequence, 1));
+        if received != expected {
+            return Err(SequenceError::OutOfOrder { expected, received });
+        }
+
+        Ok(())
+    }
+
+    /// The first offset of the open transaction of a producer.
+    pub fn txn_first_offset(&self, producer_id: i64) -> Option<i64> {
+        self.producers
+            .get(&producer_id)
+            .and_then(|entry| entry.current_txn_first_offset)
+    }
+
+    /// The first offset of the earliest open transaction: read_committed
+    /// consumers read up to it.
+    pub fn first_unstable_offset(&self) -> Option<i64> {
+        self.producers
+            .values()
+            .filter_map(|entry| entry.current_txn_first_offset)
+            .min()
+    }
+
+    /// Records `batch`, once appended with its offsets assigned.
+    pub fn update(&mut self, batch: &TopicRecordBatch) {
+        if batch.producer_id < 0 {
+            return;
+        }
+
+        let entry = self
+            .producers
+            .entry(batch.producer_id)
+            .or_insert_with(|| ProducerEntry {
+                epoch: batch.producer_epoch,
+                batches: VecDeque::new(),
+                current_txn_first_offset: None,
+            });
+        if batch.producer_epoch != entry.epoch {
+            entry.epoch = batch.producer_epoch;
+            entry.batches.clear();
+        }
+
+        if batch.is_control() {
+            entry.current_txn_first_offset = None;
+            return;
+        }
+        if batch.is_transactional() {
+            entry
+                .current_txn_first_offset
+                .get_or_insert(batch.base_offset);
+        }
+        if batch.base_sequence < 0 {
+            return;
+        }
+
+        entry.batches.push_back(BatchMetadata {
+            first_sequence: batch.base_sequence,
+            last_sequence: increment(batch.base_sequence, batch.last_offset_delta),
+            first_offset: batch.base_offset,
+            last_offset: batch.last_offset(),
+            timestamp: batch.max_timestamp,
+        });
+        if entry.batches.len() > CACHED_BATCHES {
+            entry.batches.pop_front();
+        }
+    }
+
+    /// Writes the state as of `offset`, the log end offset, to the snapshot
+    /// for that offset, then deletes the older snapshots.
+    pub fn write_snapshot(&self, dir: &Path, offset: i64) -> io::Result<()> {
+        let mut entries: Vec<SnapshotEntry> = self
+            .producers
+            .iter()
+            .map(|(&producer_id, entry)| {
+                let last = entry.batches.back();
+                SnapshotEntry {
+                    producer_id,
+                    producer_epoch: entry.epoch,
+                    last_sequence: last.map_or(-1, |b| b.last_sequence),
+                    last_offset: last.map_or(-1, |b| b.last_offset),
+                    offset_delta: last.map_or(0, |b| (b.last_offset - b.first_offset) as i32),
+                    timestamp: last.map_or(-1, |b| b.timestamp),
+                    coordinator_epoch: -1,
+                    current_txn_first_offset: entry.current_txn_first_offset.unwrap_or(-1),
+                }
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.producer_id);
+
+        let entries = Array32(entries).to_bytes();
+        let mut data = BytesMut::with_capacity(SNAPSHOT_HEADER + entries.len());
+        data.put_i16(SNAPSHOT_VERSION);
+        data.put_u32(crc32c::crc32c(&entries));
+        data.put_slice(&entries);
+
+        let path = dir.join(segment_file_name(offset, SNAPSHOT_EXTENSION));
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        fs::rename(tmp, path)?;
+
+        for (older, path) in list_snapshots(dir)? {
+            if older < offset {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the latest snapshot in `dir` at or before `log_end_offset`,
+    /// returning it with the offset the log must be replayed from to bring
+    /// it up to date. Snapshots past the log end, left by a log cut short,
+    /// are deleted; an unreadable snapshot is reported and skipped.
+    pub fn load(dir: &Path, log_end_offset: i64) -> io::Result<(Self, i64)> {
+        let mut snapshots = list_snapshots(dir)?;
+
+        for (offset, path) in &snapshots {
+            if *offset > log_end_offset {
+                fs::remove_file(path)?;
+            }
+        }
+        snapshots.retain(|(offset, _)| *offset <= log_end_offset);
+
+        while let Some((offset, path)) = snapshots.pop() {
+            match read_snapshot(&path) {
+                Ok(state) => return Ok((state, offset)),
+                Err(e) => eprintln!("failed to read {}: {e:?}", path.display()),
+            }
+        }
+
+        Ok((Self::default(), 0))
+    }
+}
+
+/// The last batch of a producer, as saved in a snapshot. Fields are -1 if
+/// there is none.
+#[derive(Debug, Encode, Decode, Size)]
+struct SnapshotEntry {
+    producer_id: i64,
+    producer_epoch: i16,
+    last_sequence: i32,
+    last_offset: i64,
+    offset_delta: i32,
+    timestamp: i64,
+    coordinator_epoch: i32,
+    current_txn_first_offset: i64,
+}
+
+/// The snapshots in `dir`, ordered by offset.
+fn list_snapshots(dir: &Path) -> io::Result<Vec<(i64, PathBuf)>> {
+    let mut snapshots = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(SNAPSHOT_EXTENSION) {
+            continue;
+        }
+        if let Some(offset) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse().ok())
+        {
+            snapshots.push((offset, path));
+        }
+    }
+
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+fn read_snapshot(path: &Path) -> io::Result<ProducerState> {
+    let data = Bytes::from(fs::read(path)?);
+    let invalid = |what: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{what} in {}", path.display()),
+        )
+    };
+
+    if data.len() < SNAPSHOT_HEADER {
+        return Err(invalid("truncated snapshot"));
+    }
+    let version = i16::decode(&data, &mut 0);
+    if version != SNAPSHOT_VERSION {
+        return Err(invalid(&format!("unknown version {version}")));
+    }
+    if u32::decode(&data, &mut 2) != crc32c::crc32c(&data[SNAPSHOT_HEADER..]) {
+        return Err(invalid("CRC mismatch"));
+    }
+
+    let entries: Array32<SnapshotEntry> = Array32::decode(&data, &mut { SNAPSHOT_HEADER });
+    let producers = entries
+        .0
+        .into_iter()
+        .map(|entry| {
+            let batch = BatchMetadata {
+                first_sequence: decrement(entry.last_sequence, entry.offset_delta),
+                last_sequence: entry.last_sequence,
+                first_offset: entry.last_offset - entry.offset_delta as i64,
+                last_offset: entry.last_offset,
+                timestamp: entry.timestamp,
+            };
+            let producer = ProducerEntry {
+                epoch: entry.producer_epoch,
+                batches: (entry.last_sequence >= 0)
+                    .then_some(batch)
+                    .into_iter()
+                    .collect(),
+                current_txn_first_offset: (entry.current_txn_first_offset >= 0)
+                    .then_some(entry.current_txn_first_offset),
+            };
+            (entry.producer_id, producer)
+        })
+        .collect();
+
+    Ok(ProducerState { producers })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::log::TopicRecordDisk;
+
+    fn batch(producer_id: i64, epoch: i16, sequence: i32, count: i32) -> TopicRecordBatch {
+        let records = (0..count)
+            .map(|i| TopicRecordDisk::new(i, 0, None, None))
+            .collect();
+        let mut batch = TopicRecordBatch::new(0, records);
+        batch.producer_id = producer_id;
+        batch.producer_epoch = epoch;
+        batch.base_sequence = sequence;
+        batch
+    }
+
+    fn append(state: &mut ProducerState, mut batch: TopicRecordBatch, offset: i64) {
+        batch.base_offset = offset;
+        state.check(&batch).unwrap();
+        state.update(&batch);
+    }
+
+    #[test]
+    fn test_sequences_are_checked_and_snapshotted() {
+        let mut state = ProducerState::default();
+        assert_eq!(
+            state.check(&batch(7, 0, 1, 1)),
+            Err(SequenceError::OutOfOrder {
+                expected: 0,
+                received: 1
+            })
+        );
+        append(&mut state, batch(7, 0, 0, 2), 10);
+        append(&mut state, batch(7, 0, 2, 1), 12);
+
+        assert_eq!(
+            state.check(&batch(7, 0, 0, 2)),
+            Err(SequenceError::Duplicate(10))
+        );
+        assert_eq!(
+            state.check(&batch(7, 0, 4, 1)),
+            Err(SequenceError::OutOfOrder {
+                expected: 3,
+                received: 4
+            })
+        );
+        assert!(state.check(&batch(-1, -1, -1, 1)).is_ok());
+
+        // A new epoch starts over and fences the old one.
+        append(&mut state, batch(7, 1, 0, 1), 13);
+        assert_eq!(
+            state.check(&batch(7, 0, 3, 1)),
+            Err(SequenceError::Fenced {
+                epoch: 1,
+                received: 0
+            })
+        );
+
+        // Sequence numbers wrap around.
+        let mut wrapping = batch(8, 0, i32::MAX, 2);
+        wrapping.base_offset = 14;
+        state.update(&wrapping);
+        assert!(state.check(&batch(8, 0, 1, 1)).is_ok());
+
+        let dir = std::env::temp_dir().join(format!("producer-state-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        state.write_snapshot(&dir, 10).unwrap();
+        state.write_snapshot(&dir, 16).unwrap();
+        assert_eq!(list_snapshots(&dir).unwrap().len(), 1);
+
+        let (loaded, offset) = ProducerState::load(&dir, 20).unwrap();
+        assert_eq!(offset, 16);
+        assert!(loaded.check(&batch(7, 1, 1, 1)).is_ok());
+        assert!(loaded.check(&batch(8, 0, 1, 1)).is_ok());
+        assert_eq!(
+            loaded.check(&batch(8, 0, i32::MAX, 2)),
+            Err(SequenceError::Duplicate(14))
+        );
+
+        // A snapshot past a log cut short is dropped.
+        let (loaded, offset) = ProducerState::load(&dir, 15).unwrap();
+        assert_eq!((loaded, offset), (ProducerState::default(), 0));
+        assert!(list_snapshots(&dir).unwrap().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,27 @@
+use crate::{
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use encode_derive::{Decode, Size};
+
+/// One component of a quota entity: a user or a client id, or the default
+/// for its type if the name is null.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Size)]
+pub struct EntityData {
+    pub entity_type: CompactString,
+    pub entity_name: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+/// Sets or removes one quota value of an entity.
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct ClientQuotaRecord {
+    pub entity: CompactArray<EntityData>,
+    pub key: CompactString,
+    pub value: f64,
+    pub remove: u8,
+    pub tagged_fields: u8,
+}
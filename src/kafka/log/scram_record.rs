@@ -0,0 +1,26 @@
+use crate::{
+    types::{bytes::CompactBytes, cstring::CompactString},
+    Decode, Encode, Size,
+};
+use encode_derive::{Decode, Size};
+
+/// The SCRAM credential of a user for one mechanism.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Size)]
+pub struct UserScramCredentialRecord {
+    pub name: CompactString,
+    /// 1 for SCRAM-SHA-256, 2 for SCRAM-SHA-512.
+    pub mechanism: i8,
+    pub salt: CompactBytes,
+    pub stored_key: CompactBytes,
+    pub server_key: CompactBytes,
+    pub iterations: i32,
+    pub tagged_fields: u8,
+}
+
+/// Deletes the SCRAM credential of a user for one mechanism.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct RemoveUserScramCredentialRecord {
+    pub name: CompactString,
+    pub mechanism: i8,
+    pub tagged_fields: u8,
+}
@@ -0,0 +1,360 @@
+use std::{
+    fs::{self, File},
+    io,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::frame::FileRegion;
+
+/// Bytes before the records in a batch: base offset and batch length.
+pub const BATCH_OVERHEAD: u64 = 12;
+
+/// Bytes of a batch header needed to know the offsets it spans.
+const BATCH_HEADER_PREFIX: usize = 27;
+
+/// Size of one `.index` entry: relative offset and file position, both
+/// big-endian `INT32`s.
+pub const INDEX_ENTRY_SIZE: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub base_offset: i64,
+    pub log_path: PathBuf,
+    pub index_path: PathBuf,
+    pub txn_index_path: PathBuf,
+}
+
+/// The offsets spanned by a batch and where it sits in its segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchPosition {
+    pub base_offset: i64,
+    pub last_offset: i64,
+    pub position: u64,
+    pub size: u64,
+}
+
+impl Segment {
+    pub fn new(dir: &Path, base_offset: i64) -> Self {
+        Self {
+            base_offset,
+            log_path: dir.join(segment_file_name(base_offset, "log")),
+            index_path: dir.join(segment_file_name(base_offset, "index")),
+            txn_index_path: dir.join(segment_file_name(base_offset, "txnindex")),
+        }
+    }
+
+    /// Deletes the files of the segment.
+    pub fn remove(&self) -> io::Result<()> {
+        fs::remove_file(&self.log_path)?;
+        for path in [&self.index_path, &self.txn_index_path] {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn segment_file_name(base_offset: i64, extension: &str) -> String {
+    format!("{base_offset:020}.{extension}")
+}
+
+/// Lists the segments of a partition directory, ordered by base offset.
+pub fn list_segments(dir: &Path) -> io::Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.extension().and_then(|e| e.to_str()) != Some("log") {
+            continue;
+        }
+
+        let base_offset = match path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<i64>().ok())
+        {
+            Some(base_offset) => base_offset,
+            None => continue,
+        };
+
+        segments.push(Segment {
+            base_offset,
+            index_path: path.with_extension("index"),
+            txn_index_path: path.with_extension("txnindex"),
+            log_path: path,
+        });
+    }
+
+    segments.sort_by_key(|segment| segment.base_offset);
+
+    Ok(segments)
+}
+
+/// Returns the file position the offset index gives for `offset`, falling back
+/// to the start of the segment when there is no usable index.
+///
+/// Entries are sorted by offset, so the index is binary searched with one
+/// positioned read per probe instead of being read whole.
+pub fn index_lookup(segment: &Segment, offset: i64) -> u64 {
+    let Ok(index) = File::open(&segment.index_path) else {
+        return 0;
+    };
+    let Ok(metadata) = index.metadata() else {
+        return 0;
+    };
+
+    let entries = metadata.len() / INDEX_ENTRY_SIZE as u64;
+
+    let read_entry = |i: u64| -> Option<(i64, u64)> {
+        let mut entry = [0u8; INDEX_ENTRY_SIZE];
+        index
+            .read_exact_at(&mut entry, i * INDEX_ENTRY_SIZE as u64)
+            .ok()?;
+
+        let relative = u32::from_be_bytes(entry[0..4].try_into().unwrap()) as i64;
+        let position = u32::from_be_bytes(entry[4..8].try_into().unwrap()) as u64;
+
+        // Index files are preallocated and zero-filled past the last entry.
+        if i > 0 && relative == 0 {
+            return None;
+        }
+
+        Some((segment.base_offset + relative, position))
+    };
+
+    // Find the first entry past `offset`; the one before it is the answer.
+    let (mut low, mut high) = (0, entries);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match read_entry(mid) {
+            Some((entry_offset, _)) if entry_offset <= offset => low = mid + 1,
+            _ => high = mid,
+        }
+    }
+
+    match low {
+        0 => 0,
+        n => read_entry(n - 1).map_or(0, |(_, position)| position),
+    }
+}
+
+/// Reads the header of the batch starting at `position`, if a complete one is
+/// there.
+pub fn read_batch_position(
+    file: &File,
+    position: u64,
+    file_len: u64,
+) -> io::Result<Option<BatchPosition>> {
+    if position + BATCH_HEADER_PREFIX as u64 > file_len {
+        return Ok(None);
+    }
+
+    let mut header = [0u8; BATCH_HEADER_PREFIX];
+    file.read_exact_at(&mut header, position)?;
+
+    let base_offset = i64::from_be_bytes(header[0..8].try_into().unwrap());
+    let batch_length = i32::from_be_bytes(header[8..12].try_into().unwrap());
+    let last_offset_delta = i32::from_be_bytes(header[23..27].try_into().unwrap());
+
+    let size = BATCH_OVERHEAD + batch_length.max(0) as u64;
+
+    if batch_length <= 0 || position + size > file_len {
+        return Ok(None);
+    }
+
+    Ok(Some(BatchPosition {
+        base_offset,
+        last_offset: base_offset + last_offset_delta as i64,
+        position,
+        size,
+    }))
+}
+
+/// Finds the region of `segment` holding the batches from `fetch_offset`
+/// onward, capped at `max_bytes` except that the first batch is always
+/// returned whole. Batches from `max_offset` on are left out.
+pub fn read_segment_region(
+    segment: &Segment,
+    fetch_offset: i64,
+    max_offset: i64,
+    max_bytes: u64,
+) -> io::Result<Option<FileRegion>> {
+    let file = File::open(&segment.log_path)?;
+    let file_len = file.metadata()?.len();
+
+    let mut position = index_lookup(segment, fetch_offset);
+    let mut start = None;
+    let mut end = position;
+
+    while let Some(batch) = read_batch_position(&file, position, file_len)? {
+        if batch.base_offset >= max_offset {
+            break;
+        }
+        position += batch.size;
+
+        match start {
+            None if batch.last_offset < fetch_offset => continue,
+            None => {
+                start = Some(batch.position);
+                end = position;
+            }
+            Some(start) => {
+                if position - start > max_bytes {
+                    break;
+                }
+                end = position;
+            }
+        }
+    }
+
+    Ok(start.map(|start| FileRegion {
+        file: Arc::new(file),
+        position: start,
+        len: end - start,
+    }))
+}
+
+/// Finds the record data to return for a fetch at `fetch_offset` across the
+/// segments in `dir`, up to `max_offset`. Nothing is returned for offsets
+/// before the first segment, whose records were deleted.
+pub fn read_region(
+    dir: &Path,
+    fetch_offset: i64,
+    max_offset: i64,
+    max_bytes: u64,
+) -> io::Result<Option<FileRegion>> {
+    let segments = list_segments(dir)?;
+
+    let Some(first) = segments
+        .iter()
+        .rposition(|segment| segment.base_offset <= fetch_offset)
+    else {
+        return Ok(None);
+    };
+
+    for segment in &segments[first..] {
+        if segment.base_offset >= max_offset {
+            break;
+        }
+        if let Some(region) = read_segment_region(segment, fetch_offset, max_offset, max_bytes)? {
+            return Ok(Some(region));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(base_offset: i64, last_offset_delta: i32, payload: usize) -> Vec<u8> {
+        let batch_length = (BATCH_HEADER_PREFIX - 12 + payload) as i32;
+        let mut v = Vec::new();
+        v.extend_from_slice(&base_offset.to_be_bytes());
+        v.extend_from_slice(&batch_length.to_be_bytes());
+        v.extend_from_slice(&[0; 11]);
+        v.extend_from_slice(&last_offset_delta.to_be_bytes());
+        v.extend(vec![0xab; payload]);
+        v
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("segment-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_read_region_skips_earlier_batches() {
+        let dir = temp_dir("skip");
+        let first = batch(0, 1, 10);
+        let second = batch(2, 0, 20);
+        let third = batch(3, 2, 5);
+
+        let mut log = first.clone();
+        log.extend(&second);
+        log.extend(&third);
+        fs::write(dir.join(segment_file_name(0, "log")), &log).unwrap();
+
+        let region = read_region(&dir, 2, i64::MAX, u64::MAX).unwrap().unwrap();
+        assert_eq!(region.position, first.len() as u64);
+        assert_eq!(region.len, (second.len() + third.len()) as u64);
+
+        let region = read_region(&dir, 1, i64::MAX, 1).unwrap().unwrap();
+        assert_eq!(region.position, 0);
+        assert_eq!(region.len, first.len() as u64);
+
+        assert!(read_region(&dir, 6, i64::MAX, u64::MAX).unwrap().is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_lookup_binary_search() {
+        let dir = temp_dir("search");
+        let segment = Segment {
+            base_offset: 1000,
+            log_path: dir.join(segment_file_name(1000, "log")),
+            index_path: dir.join(segment_file_name(1000, "index")),
+            txn_index_path: dir.join(segment_file_name(1000, "txnindex")),
+        };
+
+        let mut index = Vec::new();
+        for i in 0..100u32 {
+            index.extend_from_slice(&(i * 10).to_be_bytes());
+            index.extend_from_slice(&(i * 4096).to_be_bytes());
+        }
+        index.extend_from_slice(&[0; 8 * 50]);
+        fs::write(&segment.index_path, &index).unwrap();
+
+        assert_eq!(index_lookup(&segment, 999), 0);
+        assert_eq!(index_lookup(&segment, 1000), 0);
+        assert_eq!(index_lookup(&segment, 1009), 0);
+        assert_eq!(index_lookup(&segment, 1010), 4096);
+        assert_eq!(index_lookup(&segment, 1555), 55 * 4096);
+        assert_eq!(index_lookup(&segment, 5000), 99 * 4096);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_region_uses_index_and_later_segments() {
+        let dir = temp_dir("index");
+        let first = batch(0, 0, 10);
+        let second = batch(1, 0, 10);
+
+        let mut log = first.clone();
+        log.extend(&second);
+        fs::write(dir.join(segment_file_name(0, "log")), &log).unwrap();
+
+        let mut index = Vec::new();
+        index.extend_from_slice(&1u32.to_be_bytes());
+        index.extend_from_slice(&(first.len() as u32).to_be_bytes());
+        index.extend_from_slice(&[0; 16]);
+        fs::write(dir.join(segment_file_name(0, "index")), &index).unwrap();
+
+        fs::write(dir.join(segment_file_name(2, "log")), batch(2, 0, 4)).unwrap();
+
+        let segments = list_segments(&dir).unwrap();
+        assert_eq!(index_lookup(&segments[0], 1), first.len() as u64);
+        assert_eq!(index_lookup(&segments[0], 0), 0);
+        assert_eq!(index_lookup(&segments[0], 5), first.len() as u64);
+
+        let region = read_region(&dir, 2, i64::MAX, u64::MAX).unwrap().unwrap();
+        assert_eq!(region.position, 0);
+        assert_eq!(region.read().unwrap(), batch(2, 0, 4));
+
+        // Offsets before the first segment are not served from it.
+        segments[0].remove().unwrap();
+        assert!(read_region(&dir, 1, i64::MAX, u64::MAX).unwrap().is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
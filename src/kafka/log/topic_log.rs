@@ -1,12 +1,19 @@
 use crate::{
-    types::{cstring::CString, uuid::UUID},
+    types::{cstring::CompactString, uuid::UUID},
     Decode, Encode, Size,
 };
 use encode_derive::{Decode, Size};
 
 #[derive(Debug, Encode, Decode, Size)]
 pub struct TopicRecord {
-    pub name: CString,
+    pub name: CompactString,
     pub id: UUID,
     pub tagged_fields: u8,
 }
+
+/// Removes a topic and its partitions from the metadata.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct RemoveTopicRecord {
+    pub topic_id: UUID,
+    pub tagged_fields: u8,
+}
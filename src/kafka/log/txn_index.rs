@@ -0,0 +1,160 @@
+//! Transaction indexes: a `.txnindex` file per segment listing the
+//! transactions aborted by a marker in that segment, in the format the Java
+//! broker uses. read_committed fetches return the ones overlapping the
+//! fetched offsets, so that consumers can skip their records.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use bytes::{BufMut, BytesMut};
+
+use super::segment::{list_segments, Segment};
+
+const VERSION: i16 = 0;
+
+/// Size of one entry: version, producer id, first, last and last stable
+/// offsets.
+pub const TXN_INDEX_ENTRY_SIZE: usize = 34;
+
+/// A transaction aborted by the marker at `last_offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbortedTxn {
+    pub producer_id: i64,
+    pub first_offset: i64,
+    pub last_offset: i64,
+    /// The last stable offset once the transaction was aborted.
+    pub last_stable_offset: i64,
+}
+
+impl AbortedTxn {
+    fn to_bytes(self) -> BytesMut {
+        let mut buf = BytesMut::with_capacity(TXN_INDEX_ENTRY_SIZE);
+        buf.put_i16(VERSION);
+        buf.put_i64(self.producer_id);
+        buf.put_i64(self.first_offset);
+        buf.put_i64(self.last_offset);
+        buf.put_i64(self.last_stable_offset);
+        buf
+    }
+
+    fn from_bytes(entry: &[u8]) -> Self {
+        let field = |at: usize| i64::from_be_bytes(entry[at..at + 8].try_into().unwrap());
+        Self {
+            producer_id: field(2),
+            first_offset: field(10),
+            last_offset: field(18),
+            last_stable_offset: field(26),
+        }
+    }
+}
+
+/// Appends an aborted transaction to the index of `segment`.
+pub fn append(segment: &Segment, aborted: AbortedTxn) -> io::Result<()> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&segment.txn_index_path)?
+        .write_all(&aborted.to_bytes())
+}
+
+/// Reads the aborted transactions in the index of `segment`. A missing index
+/// is empty, and an entry cut off at the end is ignored.
+pub fn read(segment: &Segment) -> io::Result<Vec<AbortedTxn>> {
+    let data = match fs::read(&segment.txn_index_path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(data
+        .chunks_exact(TXN_INDEX_ENTRY_SIZE)
+        .filter(|entry| i16::from_be_bytes([entry[0], entry[1]]) == VERSION)
+        .map(AbortedTxn::from_bytes)
+        .collect())
+}
+
+/// The transactions aborted in the log in `dir` that have records between
+/// `fetch_offset` and `upper_bound`, exclusive. Their markers come after
+/// their records, so segments before the one holding `fetch_offset` are
+/// skipped; later ones are not, as a transaction may span segments.
+pub fn collect_aborted(
+    dir: &Path,
+    fetch_offset: i64,
+    upper_bound: i64,
+) -> io::Result<Vec<AbortedTxn>> {
+    let segments = list_segments(dir)?;
+    let first = segments
+        .iter()
+        .rposition(|segment| segment.base_offset <= fetch_offset)
+        .unwrap_or(0);
+
+    let mut aborted = Vec::new();
+    for segment in &segments[first..] {
+        aborted.extend(
+            read(segment)?
+                .into_iter()
+                .filter(|txn| txn.last_offset >= fetch_offset && txn.first_offset < upper_bound),
+        );
+    }
+
+    Ok(aborted)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("txn-index-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_aborted_transactions_overlapping_a_range() {
+        let dir = temp_dir();
+        let aborted = |producer_id, first_offset, last_offset| AbortedTxn {
+            producer_id,
+            first_offset,
+            last_offset,
+            last_stable_offset: last_offset + 1,
+        };
+
+        let first = Segment::new(&dir, 0);
+        let second = Segment::new(&dir, 10);
+        for segment in [&first, &second] {
+            fs::write(&segment.log_path, []).unwrap();
+        }
+        append(&first, aborted(1, 2, 5)).unwrap();
+        append(&second, aborted(2, 4, 12)).unwrap();
+        append(&second, aborted(3, 13, 15)).unwrap();
+
+        assert_eq!(read(&first).unwrap(), vec![aborted(1, 2, 5)]);
+        assert_eq!(
+            collect_aborted(&dir, 0, 20).unwrap(),
+            vec![aborted(1, 2, 5), aborted(2, 4, 12), aborted(3, 13, 15)]
+        );
+        assert_eq!(
+            collect_aborted(&dir, 6, 13).unwrap(),
+            vec![aborted(2, 4, 12)]
+        );
+        assert!(collect_aborted(&dir, 16, 20).unwrap().is_empty());
+
+        // A torn entry at the end is skipped.
+        let mut data = fs::read(&second.txn_index_path).unwrap();
+        data.truncate(data.len() - 1);
+        fs::write(&second.txn_index_path, data).unwrap();
+        assert_eq!(read(&second).unwrap(), vec![aborted(2, 4, 12)]);
+
+        second.remove().unwrap();
+        assert!(!second.txn_index_path.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,412 @@
+//! The cluster metadata log and the in-memory image replayed from it.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    types::{record::GenericRecord, uuid::UUID},
+    Decode, Encode, Size,
+};
+
+use super::configs::TOPIC_RESOURCE;
+use super::log::{
+    acl_record::AccessControlEntryRecord,
+    partition::{LogManager, SharedLog},
+    partition_record::PartitionRecord,
+    quota_record::ClientQuotaRecord,
+    scram_record::UserScramCredentialRecord,
+    RecordValue, TopicRecordBatch, TopicRecordDisk,
+};
+use super::quota::{self, QuotaEntity};
+
+/// The internal topic holding the cluster metadata records.
+pub const METADATA_TOPIC: &str = "__cluster_metadata";
+
+/// Version of the frame around every metadata record value.
+const FRAME_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicImage {
+    pub name: String,
+    pub id: UUID,
+    pub partitions: BTreeMap<i32, PartitionRecord>,
+}
+
+/// The topics the metadata log describes once every record is applied.
+#[derive(Debug, Default, Clone)]
+pub struct MetadataImage {
+    topics: HashMap<UUID, TopicImage>,
+    names: HashMap<String, UUID>,
+    configs: HashMap<(i8, String), BTreeMap<String, String>>,
+    next_producer_id: i64,
+    scram_credentials: HashMap<(String, i8), UserScramCredentialRecord>,
+    acls: BTreeMap<UUID, AccessControlEntryRecord>,
+    client_quotas: BTreeMap<QuotaEntity, BTreeMap<String, f64>>,
+}
+
+impl MetadataImage {
+    pub fn topic(&self, name: &str) -> Option<&TopicImage> {
+        self.topics.get(self.names.get(name)?)
+    }
+
+    pub fn topic_by_id(&self, id: &UUID) -> Option<&TopicImage> {
+        self.topics.get(id)
+    }
+
+    pub fn topics(&self) -> impl Iterator<Item = &TopicImage> {
+        self.topics.values()
+    }
+
+    /// The configs set on a resource, by name.
+    pub fn configs(&self, resource_type: i8, name: &str) -> Option<&BTreeMap<String, String>> {
+        self.configs.get(&(resource_type, name.to_string()))
+    }
+
+    /// The SCRAM credential of `user` for a mechanism.
+    pub fn scram_credential(
+        &self,
+        user: &str,
+        mechanism: i8,
+    ) -> Option<&UserScramCredentialRecord> {
+        self.scram_credentials.get(&(user.to_string(), mechanism))
+    }
+
+    /// Every ACL binding, by id.
+    pub fn acls(&self) -> impl Iterator<Item = &AccessControlEntryRecord> {
+        self.acls.values()
+    }
+
+    /// Every entity with client quotas, and its quota values by key.
+    pub fn client_quotas(&self) -> impl Iterator<Item = (&QuotaEntity, &BTreeMap<String, f64>)> {
+        self.client_quotas.iter()
+    }
+
+    /// The quota values set on an entity, by key.
+    pub fn client_quota(&self, entity: &QuotaEntity) -> Option<&BTreeMap<String, f64>> {
+        self.client_quotas.get(entity)
+    }
+
+    /// The first producer id not yet allocated to a broker.
+    pub fn next_producer_id(&self) -> i64 {
+        self.next_producer_id
+    }
+
+    /// Applies one record, as replaying the log does.
+    pub(crate) fn apply(&mut self, record: &RecordValue) {
+        match record {
+            RecordValue::Topic(topic) => {
+                self.names.insert(topic.name.0.clone(), topic.id.clone());
+                self.topics.insert(
+                    topic.id.clone(),
+                    TopicImage {
+                        name: topic.name.0.clone(),
+                        id: topic.id.clone(),
+                        partitions: BTreeMap::new(),
+                    },
+                );
+            }
+            RecordValue::Partition(partition) => {
+                // Partitions of unknown topics are dropped, as the broker does.
+                if let Some(topic) = self.topics.get_mut(&partition.topic_id) {
+                    topic.partitions.insert(partition.id, partition.clone());
+                }
+            }
+            RecordValue::Config(config) => {
+                let key = (config.resource_type, config.resource_name.0.clone());
+                match &config.value.0 {
+                    Some(value) => {
+                        let configs = self.configs.entry(key).or_default();
+                        configs.insert(config.name.0.clone(), value.clone());
+                    }
+                    None => {
+                        if let Some(configs) = self.configs.get_mut(&key) {
+                            configs.remove(&config.name.0);
+                            if configs.is_empty() {
+                                self.configs.remove(&key);
+                            }
+                        }
+                    }
+                }
+            }
+            RecordValue::RemoveTopic(record) => {
+                if let Some(topic) = self.topics.remove(&record.topic_id) {
+                    self.names.remove(&topic.name);
+                    self.configs.remove(&(TOPIC_RESOURCE, topic.name));
+                }
+            }
+            RecordValue::ProducerIds(record) => {
+                self.next_producer_id = self.next_producer_id.max(record.next_producer_id);
+            }
+            RecordValue::UserScramCredential(record) => {
+                let key = (record.name.0.clone(), record.mechanism);
+                self.scram_credentials.insert(key, record.clone());
+            }
+            RecordValue::RemoveUserScramCredential(record) => {
+                self.scram_credentials
+                    .remove(&(record.name.0.clone(), record.mechanism));
+            }
+            RecordValue::AccessControlEntry(record) => {
+                self.acls.insert(record.id.clone(), record.clone());
+            }
+            RecordValue::RemoveAccessControlEntry(record) => {
+                self.acls.remove(&record.id);
+            }
+            RecordValue::ClientQuota(record) => self.apply_client_quota(record),
+            RecordValue::FeatureLevel(_) | RecordValue::Unknown(_) => {}
+        }
+    }
+
+    fn apply_client_quota(&mut self, record: &ClientQuotaRecord) {
+        let entity = quota::entity(&record.entity);
+        if record.remove == 0 {
+            let values = self.client_quotas.entry(entity).or_default();
+            values.insert(record.key.0.clone(), record.value);
+        } else if let Some(values) = self.client_quotas.get_mut(&entity) {
+            values.remove(&record.key.0);
+            if values.is_empty() {
+                self.client_quotas.remove(&entity);
+            }
+        }
+    }
+}
+
+/// Decodes a metadata record value: the frame version, then the record.
+fn decode_value(value: &Bytes) -> Option<RecordValue> {
+    if value.len() < 3 {
+        return None;
+    }
+
+    Some(GenericRecord::decode(value, &mut 1).r_record)
+}
+
+fn encode_value(record: RecordValue) -> (Bytes, RecordValue) {
+    let record = GenericRecord { r_record: record };
+    let mut buf = BytesMut::with_capacity(1 + record.size_in_bytes());
+    buf.put_u8(FRAME_VERSION);
+    record.encode(&mut buf);
+
+    (buf.freeze(), record.r_record)
+}
+
+/// The metadata log of this broker, which is also its controller.
+#[derive(Debug)]
+pub struct ClusterMetadata {
+    log: SharedLog,
+    image: RwLock<MetadataImage>,
+    /// Held throughout an update, so that updates see each other's records
+    /// while readers only wait for them to be applied.
+    updating: Mutex<()>,
+}
+
+impl ClusterMetadata {
+    /// Opens the metadata log and replays it into an image.
+    pub fn open(logs: &LogManager) -> io::Result<Self> {
+        let log = logs.partition(METADATA_TOPIC, 0)?;
+        let mut image = MetadataImage::default();
+
+        for batch in lock(&log).read_batches()? {
+            for record in batch.decode_records() {
+                if let Some(value) = record.value.0.as_ref().and_then(decode_value) {
+                    image.apply(&value);
+                }
+            }
+        }
+
+        Ok(Self {
+            log,
+            image: RwLock::new(image),
+            updating: Mutex::new(()),
+        })
+    }
+
+    /// Runs `f` against the current image.
+    pub fn read<T>(&self, f: impl FnOnce(&MetadataImage) -> T) -> T {
+        f(&self.image())
+    }
+
+    fn image(&self) -> RwLockReadGuard<'_, MetadataImage> {
+        self.image.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Appends the records `f` derives from the current image in one batch,
+    /// then applies them. Updates run one at a time, so that they are
+    /// validated against each other. They block on the log's disk I/O, so
+    /// async callers run them with `spawn_blocking`; readers are not held
+    /// up by it.
+    pub fn update<T>(
+        &self,
+        f: impl FnOnce(&MetadataImage) -> (Vec<RecordValue>, T),
+    ) -> io::Result<T> {
+        self.update_then(f, |_, _| Ok(()))
+    }
+
+    /// Like [`update`](Self::update), then runs `then` on the updated image
+    /// and the result before releasing the image, so that the side effects
+    /// of updates on the logs happen in the same order as the updates.
+    pub fn update_then<T>(
+        &self,
+        f: impl FnOnce(&MetadataImage) -> (Vec<RecordValue>, T),
+        then: impl FnOnce(&MetadataImage, &T) -> io::Result<()>,
+    ) -> io::Result<T> {
+        let _updating = lock(&self.updating);
+        let (records, result) = f(&self.image());
+
+        if records.is_empty() {
+            return Ok(result);
+        }
+
+        let (values, records): (Vec<Bytes>, Vec<RecordValue>) =
+            records.into_iter().map(encode_value).unzip();
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as i64);
+        let disk_records = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| TopicRecordDisk::new(i as i32, 0, None, Some(value)))
+            .collect();
+
+        lock(&self.log).append(&mut TopicRecordBatch::new(timestamp, disk_records))?;
+
+        {
+            let mut image = self.image.write().unwrap_or_else(|e| e.into_inner());
+            for record in &records {
+                image.apply(record);
+            }
+        }
+
+        then(&self.image(), &result)?;
+        Ok(result)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        kafka::log::topic_log::{RemoveTopicRecord, TopicRecord},
+        types::{array::CompactArray, cstring::CompactString},
+    };
+
+    fn partition(id: i32, topic_id: &UUID) -> RecordValue {
+        RecordValue::Partition(PartitionRecord {
+            id,
+            topic_id: topic_id.clone(),
+            replicas: CompactArray(vec![1]),
+            sync_replicas: CompactArray(vec![1]),
+            removing_replicas: CompactArray(vec![]),
+            adding_replicas: CompactArray(vec![]),
+            leader: 1,
+            leader_epoch: 0,
+            partition_epoch: 0,
+            directories: CompactArray(vec![UUID([0; 16])]),
+            tagged_fields: 0,
+        })
+    }
+
+    #[test]
+    fn test_updates_are_replayed_on_open() {
+        let dir = std::env::temp_dir().join(format!("metadata-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let id = UUID([9; 16]);
+        let metadata = ClusterMetadata::open(&LogManager::new(&dir)).unwrap();
+        metadata
+            .update(|image| {
+                assert!(image.topic("topic").is_none());
+                let topic = RecordValue::Topic(TopicRecord {
+                    name: CompactString("topic".to_string()),
+                    id: id.clone(),
+                    tagged_fields: 0,
+                });
+                (vec![topic, partition(0, &id), partition(1, &id)], ())
+            })
+            .unwrap();
+        assert_eq!(
+            metadata.read(|image| image.topic("topic").unwrap().partitions.len()),
+            2
+        );
+        drop(metadata);
+
+        let reopened = ClusterMetadata::open(&LogManager::new(&dir)).unwrap();
+        let topic = reopened
+            .read(|image| image.topic_by_id(&id).cloned())
+            .unwrap();
+        assert_eq!(topic.name, "topic");
+        assert_eq!(topic.partitions[&1].leader, 1);
+
+        reopened
+            .update(|_| {
+                let remove = RecordValue::RemoveTopic(RemoveTopicRecord {
+                    topic_id: id.clone(),
+                    tagged_fields: 0,
+                });
+                (vec![remove], ())
+            })
+            .unwrap();
+        drop(reopened);
+
+        let reopened = ClusterMetadata::open(&LogManager::new(&dir)).unwrap();
+        assert!(reopened.read(|image| image.topic("topic").is_none()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reads_go_on_while_an_update_writes_the_log() {
+        use std::{sync::mpsc, sync::Arc, thread, time::Duration};
+
+        let dir = std::env::temp_dir().join(format!("metadata-read-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let metadata = Arc::new(ClusterMetadata::open(&LogManager::new(&dir)).unwrap());
+
+        // With the log busy, the update waits to append its records.
+        let log = lock(&metadata.log);
+        let (derived, records_derived) = mpsc::channel();
+        let updater = {
+            let metadata = metadata.clone();
+            thread::spawn(move || {
+                metadata.update(|_| {
+                    derived.send(()).unwrap();
+                    let topic = RecordValue::Topic(TopicRecord {
+                        name: CompactString("topic".to_string()),
+                        id: UUID([7; 16]),
+                        tagged_fields: 0,
+                    });
+                    (vec![topic], ())
+                })
+            })
+        };
+        records_derived.recv().unwrap();
+
+        let (sender, read) = mpsc::channel();
+        {
+            let metadata = metadata.clone();
+            thread::spawn(move || {
+                sender
+                    .send(metadata.read(|image| image.topic("topic").is_none()))
+                    .unwrap()
+            });
+        }
+        assert_eq!(read.recv_timeout(Duration::from_secs(5)), Ok(true));
+
+        drop(log);
+        updater.join().unwrap().unwrap();
+        assert!(metadata.read(|image| image.topic("topic").is_some()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,68 +1,345 @@
-use crate::{Decode, Encode, Size};
-use encode_derive::{Decode, Size};
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
 
-#[derive(Debug, Encode, Decode, Size)]
-pub struct BaseRequest {
-    pub size: i32,
-    pub api_key: i16,
-    pub api_versions: i16,
-    pub correlation_id: i32,
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    types::{checked_count, decode_unsigned_varint, kafkastring::NullableString, take},
+    Decode, Encode,
+};
+
+use acl::Requester;
+use broker::Broker;
+use metadata::MetadataImage;
+use sasl::Session;
+
+pub mod acl;
+pub mod apiversions;
+pub mod broker;
+pub mod configs;
+pub mod errors;
+pub mod fetch;
+pub mod fetch_session;
+pub mod group;
+pub mod listener;
+pub mod listoffsets;
+pub mod listpartitions;
+pub mod log;
+pub mod metadata;
+pub mod produce;
+pub mod producer;
+pub mod quota;
+pub mod sasl;
+pub mod topics;
+pub mod txn;
+
+/// The first version of each API that uses flexible (compact, tagged) encoding,
+/// or `None` for APIs that never do.
+fn first_flexible_version(api_key: i16) -> Option<i16> {
+    match api_key {
+        0 => Some(9),
+        1 => Some(12),
+        2 => Some(6),
+        3 => Some(9),
+        8 => Some(8),
+        9 => Some(6),
+        10 => Some(3),
+        11 => Some(6),
+        12 => Some(4),
+        13 => Some(4),
+        14 => Some(4),
+        15 => Some(5),
+        16 => Some(3),
+        18 => Some(3),
+        19 => Some(5),
+        20 => Some(4),
+        21 => Some(2),
+        22 => Some(2),
+        24 => Some(3),
+        25 => Some(3),
+        26 => Some(3),
+        27 => Some(1),
+        28 => Some(3),
+        29 => Some(2),
+        30 => Some(2),
+        31 => Some(2),
+        32 => Some(4),
+        36 => Some(2),
+        37 => Some(2),
+        42 => Some(2),
+        44 => Some(1),
+        48 => Some(1),
+        49 => Some(1),
+        51 => Some(0),
+        68 => Some(0),
+        69 => Some(0),
+        75 => Some(0),
+        _ => None,
+    }
 }
 
-#[derive(Debug, Encode, Decode, Size)]
-pub struct BaseRequestV2 {
-    pub size: i32,
-    pub api_key: i16,
-    pub api_versions: i16,
-    pub correlation_id: i32,
-    pub client_id: String,
-    pub tag_buffer: u8,
+fn is_flexible(api_key: i16, api_version: i16) -> bool {
+    first_flexible_version(api_key).is_some_and(|first| api_version >= first)
 }
 
-#[derive(Debug, Encode, Decode, Size)]
-pub struct BaseResponse {
-    pub size: i32,
+/// Request header v2 adds tagged fields to v1, which adds the client id to v0.
+pub fn request_header_version(api_key: i16, api_version: i16) -> i16 {
+    match (api_key, api_version) {
+        // ControlledShutdown v0 predates the client id.
+        (7, 0) => 0,
+        _ if is_flexible(api_key, api_version) => 2,
+        _ => 1,
+    }
+}
+
+/// Response header v1 adds tagged fields to v0. ApiVersions always answers
+/// with v0 so that clients can parse it before any version is negotiated.
+pub fn response_header_version(api_key: i16, api_version: i16) -> i16 {
+    if api_key != 18 && is_flexible(api_key, api_version) {
+        1
+    } else {
+        0
+    }
+}
+
+/// The fields of a request header, decoded according to its version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHeader {
+    pub api_key: i16,
+    pub api_version: i16,
     pub correlation_id: i32,
+    pub client_id: Option<String>,
 }
 
-impl BaseResponse {
-    pub fn new_base(corr_id: i32) -> Self {
+impl Decode for RequestHeader {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let api_key = i16::decode(bytes, offset);
+        let api_version = i16::decode(bytes, offset);
+        let correlation_id = i32::decode(bytes, offset);
+
+        let header_version = request_header_version(api_key, api_version);
+
+        let client_id = if header_version >= 1 {
+            NullableString::decode(bytes, offset).0
+        } else {
+            None
+        };
+
+        if header_version >= 2 {
+            skip_tagged_fields(bytes, offset);
+        }
+
         Self {
-            size: 0,
-            correlation_id: corr_id,
+            api_key,
+            api_version,
+            correlation_id,
+            client_id,
         }
     }
 }
 
-#[derive(Debug, Encode, Decode, Size)]
-pub struct BaseResponseV1 {
-    pub base: BaseResponse,
-    pub tag_buffer: u8,
+/// Encodes the header in the version the API and its version call for.
+impl Encode for RequestHeader {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_i16(self.api_key);
+        buf.put_i16(self.api_version);
+        buf.put_i32(self.correlation_id);
+
+        let header_version = request_header_version(self.api_key, self.api_version);
+        if header_version >= 1 {
+            NullableString(self.client_id.clone()).encode(buf);
+        }
+        if header_version >= 2 {
+            buf.put_u8(0);
+        }
+    }
 }
 
-pub mod apiversions;
-pub mod fetch;
-pub mod listpartitions;
-pub mod log;
+/// Skips a tagged field buffer: a count followed by (tag, size, data) entries.
+pub(crate) fn skip_tagged_fields(bytes: &Bytes, offset: &mut usize) {
+    let (count, _) = decode_unsigned_varint(bytes, offset);
+
+    for _ in 0..checked_count(bytes, offset, count as usize) {
+        decode_unsigned_varint(bytes, offset);
+        let (size, _) = decode_unsigned_varint(bytes, offset);
+        take(bytes, offset, size as usize);
+    }
+}
+
+/// What a handler knows about the request it is serving, beyond its body.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub api_key: i16,
+    pub api_version: i16,
+    pub correlation_id: i32,
+    pub client_id: Option<String>,
+    pub peer_addr: SocketAddr,
+    /// The size of the request, without its size prefix.
+    pub request_size: usize,
+    pub broker: Arc<Broker>,
+    /// The authentication state of the connection the request came on.
+    pub session: Arc<Mutex<Session>>,
+}
+
+impl RequestContext {
+    pub fn new(
+        header: RequestHeader,
+        peer_addr: SocketAddr,
+        request_size: usize,
+        broker: Arc<Broker>,
+        session: Arc<Mutex<Session>>,
+    ) -> Self {
+        Self {
+            api_key: header.api_key,
+            api_version: header.api_version,
+            correlation_id: header.correlation_id,
+            client_id: header.client_id,
+            peer_addr,
+            request_size,
+            broker,
+            session,
+        }
+    }
+
+    pub fn response_header_version(&self) -> i16 {
+        response_header_version(self.api_key, self.api_version)
+    }
+
+    /// The principal of the connection and the host it comes from.
+    pub fn requester(&self) -> Requester {
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        Requester {
+            principal: session.principal(),
+            host: self.peer_addr.ip().to_string(),
+        }
+    }
+
+    /// Whether the client may perform `operation` on a resource, by the
+    /// ACLs of `image`.
+    pub fn authorized(
+        &self,
+        image: &MetadataImage,
+        operation: i8,
+        resource_type: i8,
+        name: &str,
+    ) -> bool {
+        self.broker.config.authorizer.authorize(
+            image.acls(),
+            &self.requester(),
+            operation,
+            resource_type,
+            name,
+        )
+    }
+
+    /// Like [`authorized`](Self::authorized), reading the image itself, so
+    /// not to be called while it is held.
+    pub fn authorize(&self, operation: i8, resource_type: i8, name: &str) -> bool {
+        !self.broker.config.authorizer.enabled
+            || self
+                .broker
+                .metadata
+                .read(|image| self.authorized(image, operation, resource_type, name))
+    }
+
+    /// The topics among `names` the client may not perform `operation` on.
+    pub fn denied_topics<'a>(
+        &self,
+        operation: i8,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> HashSet<String> {
+        if !self.broker.config.authorizer.enabled {
+            return HashSet::new();
+        }
+        self.broker.metadata.read(|image| {
+            names
+                .into_iter()
+                .filter(|name| !self.authorized(image, operation, acl::resource::TOPIC, name))
+                .map(str::to_string)
+                .collect()
+        })
+    }
+
+    /// Whether the client may perform `operation` on some resource of a type.
+    pub fn authorize_any(&self, operation: i8, resource_type: i8) -> bool {
+        !self.broker.config.authorizer.enabled
+            || self.broker.metadata.read(|image| {
+                self.broker.config.authorizer.authorize_any(
+                    image.acls(),
+                    &self.requester(),
+                    operation,
+                    resource_type,
+                )
+            })
+    }
+
+    /// The bit field of the operations the client may perform on a
+    /// resource.
+    pub fn authorized_operations(
+        &self,
+        image: &MetadataImage,
+        resource_type: i8,
+        name: &str,
+    ) -> i32 {
+        self.broker.config.authorizer.authorized_operations(
+            image.acls(),
+            &self.requester(),
+            resource_type,
+            name,
+        )
+    }
+}
 
 #[cfg(test)]
 mod tests {
+    use bytes::Bytes;
+
     use crate::Decode;
 
-    use super::BaseRequestV2;
+    use super::*;
 
     #[test]
-    fn test_decode_base_request_v2() {
-        let test_request = vec![
-            0x00, 0x00, 0x00, 0x20, 0x00, 0x4B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x09,
-            0x6B, 0x61, 0x66, 0x6B, 0x61, 0x2D, 0x63, 0x6C, 0x69, 0x00, 0x02, 0x04, 0x66, 0x6F,
-            0x6F, 0x00, 0x00, 0x00, 0x00, 0x64, 0xFF, 0x00,
-        ];
+    fn test_decode_request_header_v2() {
+        let test_request = Bytes::from(vec![
+            0x00, 0x4B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x09, 0x6B, 0x61, 0x66, 0x6B,
+            0x61, 0x2D, 0x63, 0x6C, 0x69, 0x00, 0x02, 0x04, 0x66, 0x6F, 0x6F, 0x00, 0x00, 0x00,
+            0x00, 0x64, 0xFF, 0x00,
+        ]);
 
         let mut offset = 0;
 
-        let base_decoded = BaseRequestV2::decode(&test_request[..], &mut offset);
+        let header = RequestHeader::decode(&test_request, &mut offset);
+
+        assert_eq!(header.api_key, 75);
+        assert_eq!(header.correlation_id, 7);
+        assert_eq!(header.client_id.as_deref(), Some("kafka-cli"));
+        assert_eq!(offset, 20);
+
+        let mut encoded = BytesMut::new();
+        header.encode(&mut encoded);
+        assert_eq!(encoded, test_request[..20]);
+    }
+
+    #[test]
+    fn test_header_versions_follow_api_version() {
+        // ApiVersions v0 has no tagged fields in its request header, v3 does;
+        // the response header stays v0 either way.
+        assert_eq!(request_header_version(18, 0), 1);
+        assert_eq!(request_header_version(18, 3), 2);
+        assert_eq!(response_header_version(18, 3), 0);
+
+        assert_eq!(request_header_version(1, 11), 1);
+        assert_eq!(response_header_version(1, 11), 0);
+        assert_eq!(request_header_version(1, 12), 2);
+        assert_eq!(response_header_version(1, 12), 1);
 
-        assert_eq!(base_decoded.client_id, "kafka-cli")
+        let header = Bytes::from_static(&[0, 18, 0, 0, 0, 0, 0, 9, 0xff, 0xff, 0xaa]);
+        let mut offset = 0;
+        let decoded = RequestHeader::decode(&header, &mut offset);
+        assert_eq!(decoded.client_id, None);
+        assert_eq!(offset, 10);
     }
 }
@@ -0,0 +1,314 @@
+use bytes::Bytes;
+
+use crate::{
+    types::{
+        array::CompactArray,
+        bytes::CompactNullableBytes,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{
+    acl::{operation, resource},
+    errors::{
+        CORRUPT_MESSAGE, DUPLICATE_SEQUENCE_NUMBER, INVALID_PRODUCER_EPOCH, INVALID_RECORD,
+        INVALID_REQUIRED_ACKS, MESSAGE_TOO_LARGE, NONE, OUT_OF_ORDER_SEQUENCE_NUMBER,
+        TOPIC_AUTHORIZATION_FAILED, TRANSACTIONAL_ID_AUTHORIZATION_FAILED, UNKNOWN_SERVER_ERROR,
+        UNKNOWN_TOPIC_OR_PARTITION,
+    },
+    log::{
+        partition::LogManager, producer_state::SequenceError, segment::BATCH_OVERHEAD,
+        TopicRecordBatch,
+    },
+    RequestContext,
+};
+
+/// Bytes of a batch after its length field when it holds no records.
+const MIN_BATCH_LENGTH: i32 = 49;
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct PartitionProduceData {
+    pub index: i32,
+    pub records: CompactNullableBytes,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct TopicProduceData {
+    pub name: CompactString,
+    pub partition_data: CompactArray<PartitionProduceData>,
+    pub tagged_fields: u8,
+}
+
+/// Produce v9-11.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ProduceRequest {
+    pub transactional_id: CompactNullableString,
+    /// 0 for no response, 1 or -1 for a response once the records are
+    /// written, which on a single broker is the same.
+    pub acks: i16,
+    pub timeout_ms: i32,
+    pub topic_data: CompactArray<TopicProduceData>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct BatchIndexAndErrorMessage {
+    pub batch_index: i32,
+    pub batch_index_error_message: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct PartitionProduceResponse {
+    pub index: i32,
+    pub error_code: i16,
+    pub base_offset: i64,
+    /// -1 as records keep the time they were created at.
+    pub log_append_time_ms: i64,
+    pub log_start_offset: i64,
+    pub record_errors: CompactArray<BatchIndexAndErrorMessage>,
+    pub error_message: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct TopicProduceResponse {
+    pub name: CompactString,
+    pub partition_responses: CompactArray<PartitionProduceResponse>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ProduceResponse {
+    pub responses: CompactArray<TopicProduceResponse>,
+    pub throttle_time_ms: i32,
+    pub tagged_fields: u8,
+}
+
+impl PartitionProduceResponse {
+    fn error(index: i32, error_code: i16, message: Option<String>) -> Self {
+        Self {
+            index,
+            error_code,
+            base_offset: -1,
+            log_append_time_ms: -1,
+            log_start_offset: -1,
+            record_errors: CompactArray::default(),
+            error_message: CompactNullableString(message),
+            tagged_fields: 0,
+        }
+    }
+}
+
+/// Splits the records sent for a partition into batches, checking that
+/// they are whole, intact and of the current format.
+fn decode_batches(records: &Bytes) -> Result<Vec<TopicRecordBatch>, (i16, String)> {
+    let corrupt = |message: &str| (CORRUPT_MESSAGE, message.to_string());
+    let mut batches = Vec::new();
+    let mut offset = 0;
+
+    while offset < records.len() {
+        let header_end = offset + BATCH_OVERHEAD as usize;
+        if header_end > records.len() {
+            return Err(corrupt("Truncated record batch"));
+        }
+
+        let batch_length = i32::decode(records, &mut (offset + 8));
+        let end = header_end + batch_length.max(0) as usize;
+        if batch_length < MIN_BATCH_LENGTH || end > records.len() {
+            return Err(corrupt(
+                "Record batch length does not match the records sent",
+            ));
+        }
+
+        let magic = records[header_end + 4];
+        if magic != 2 {
+            return Err((
+                INVALID_RECORD,
+                format!("Record batch magic {magic} is not supported, only 2 is"),
+            ));
+        }
+
+        let batch = TopicRecordBatch::decode(records, &mut offset);
+        if !batch.crc_matches() {
+            return Err(corrupt("Record batch CRC does not match its contents"));
+        }
+        // Only the transaction coordinator writes markers.
+        if batch.is_control() {
+            return Err((
+                INVALID_RECORD,
+                "Control batches cannot be produced".to_string(),
+            ));
+        }
+        batches.push(batch);
+        offset = end;
+    }
+
+    if batches.is_empty() {
+        return Err((INVALID_RECORD, "No record batches were sent".to_string()));
+    }
+
+    Ok(batches)
+}
+
+/// Appends the batches sent for a partition, once the sequence numbers of
+/// idempotent producers are checked against those already appended.
+fn append(logs: &LogManager, topic: &str, data: &PartitionProduceData) -> PartitionProduceResponse {
+    let index = data.index;
+    let error = |code, message| PartitionProduceResponse::error(index, code, message);
+
+    let records = data.records.0.clone().unwrap_or_default();
+    let batches = match decode_batches(&records) {
+        Ok(batches) => batches,
+        Err((code, message)) => return error(code, Some(message)),
+    };
+    // Sequence numbers are checked against the last appended batch only.
+    if batches.len() > 1 && batches.iter().any(|batch| batch.producer_id >= 0) {
+        return error(
+            INVALID_RECORD,
+            Some("Idempotent producers must send one batch per partition".to_string()),
+        );
+    }
+
+    let log = match logs.partition(topic, index) {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("failed to open {topic}-{index}: {e:?}");
+            return error(UNKNOWN_SERVER_ERROR, None);
+        }
+    };
+    let mut log = log.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut base_offset = None;
+    for mut batch in batches {
+        let producer_id = batch.producer_id;
+        match log.check_sequence(&batch) {
+            Ok(()) => {}
+            // Producers take this as success, with the offset it got then.
+            Err(SequenceError::Duplicate(offset)) => {
+                return PartitionProduceResponse {
+                    base_offset: offset,
+                    log_start_offset: log.log_start_offset(),
+                    ..error(DUPLICATE_SEQUENCE_NUMBER, None)
+                };
+            }
+            Err(SequenceError::OutOfOrder { expected, received }) => {
+                return error(
+                    OUT_OF_ORDER_SEQUENCE_NUMBER,
+                    Some(format!(
+                        "Out of order sequence number for producer {producer_id}: \
+                         {received} (expected {expected})"
+                    )),
+                );
+            }
+            Err(SequenceError::Fenced { epoch, received }) => {
+                return error(
+                    INVALID_PRODUCER_EPOCH,
+                    Some(format!(
+                        "Epoch {received} of producer {producer_id} is older than \
+                         the current epoch {epoch}"
+                    )),
+                );
+            }
+        }
+
+        match log.append(&mut batch) {
+            Ok(offset) => {
+                base_offset.get_or_insert(offset);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+                return error(MESSAGE_TOO_LARGE, Some(e.to_string()));
+            }
+            Err(e) => {
+                eprintln!("failed to append to {topic}-{index}: {e:?}");
+                return error(UNKNOWN_SERVER_ERROR, None);
+            }
+        }
+    }
+
+    PartitionProduceResponse {
+        base_offset: base_offset.unwrap_or(-1),
+        log_start_offset: log.log_start_offset(),
+        ..error(NONE, None)
+    }
+}
+
+impl ProduceRequest {
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<ProduceResponse, Error> {
+        let ctx = ctx.clone();
+        let broker = ctx.broker.clone();
+        let topics = self.topic_data.0.clone();
+        let acks = self.acks;
+        let transactional_id = self.transactional_id.0.clone();
+
+        let responses = tokio::task::spawn_blocking(move || {
+            // The image stays locked so that the topic cannot be deleted
+            // meanwhile.
+            broker.metadata.read(|image| {
+                let transaction_denied = transactional_id.as_ref().is_some_and(|id| {
+                    !ctx.authorized(image, operation::WRITE, resource::TRANSACTIONAL_ID, id)
+                });
+                topics
+                    .iter()
+                    .map(|topic| {
+                        let known = image.topic(&topic.name);
+                        let error_code = if transaction_denied {
+                            Some(TRANSACTIONAL_ID_AUTHORIZATION_FAILED)
+                        } else if !ctx.authorized(
+                            image,
+                            operation::WRITE,
+                            resource::TOPIC,
+                            &topic.name,
+                        ) {
+                            Some(TOPIC_AUTHORIZATION_FAILED)
+                        } else {
+                            None
+                        };
+                        let partitions = topic
+                            .partition_data
+                            .iter()
+                            .map(|data| {
+                                let index = data.index;
+                                if let Some(error_code) = error_code {
+                                    PartitionProduceResponse::error(index, error_code, None)
+                                } else if !matches!(acks, -1..=1) {
+                                    PartitionProduceResponse::error(
+                                        index,
+                                        INVALID_REQUIRED_ACKS,
+                                        None,
+                                    )
+                                } else if !known.is_some_and(|t| t.partitions.contains_key(&index))
+                                {
+                                    PartitionProduceResponse::error(
+                                        index,
+                                        UNKNOWN_TOPIC_OR_PARTITION,
+                                        None,
+                                    )
+                                } else {
+                                    append(&broker.logs, &topic.name, data)
+                                }
+                            })
+                            .collect();
+
+                        TopicProduceResponse {
+                            name: topic.name.clone(),
+                            partition_responses: CompactArray(partitions),
+                            tagged_fields: 0,
+                        }
+                    })
+                    .collect()
+            })
+        })
+        .await?;
+
+        Ok(ProduceResponse {
+            responses: CompactArray(responses),
+            throttle_time_ms: 0,
+            tagged_fields: 0,
+        })
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource, CLUSTER_NAME},
+        errors::{
+            CLUSTER_AUTHORIZATION_FAILED, NONE, TRANSACTIONAL_ID_AUTHORIZATION_FAILED,
+            UNKNOWN_SERVER_ERROR,
+        },
+        RequestContext,
+    },
+    types::cstring::CompactNullableString,
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+/// InitProducerId v3+.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct InitProducerIdRequest {
+    /// Null for a producer that is idempotent but not transactional.
+    pub transactional_id: CompactNullableString,
+    pub transaction_timeout_ms: i32,
+    /// The current producer id and epoch, or -1 for a new producer.
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct InitProducerIdResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub tagged_fields: u8,
+}
+
+impl InitProducerIdResponse {
+    fn error(error_code: i16) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code,
+            producer_id: -1,
+            producer_epoch: -1,
+            tagged_fields: 0,
+        }
+    }
+}
+
+impl InitProducerIdRequest {
+    /// Gives an idempotent producer a new producer id at epoch 0, even if it
+    /// had one: its sequence numbers start over. A transactional producer
+    /// keeps its producer id with a bumped epoch, from the transaction
+    /// coordinator.
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<InitProducerIdResponse, Error> {
+        if let Some(transactional_id) = self.transactional_id.0.clone() {
+            if !ctx.authorize(
+                operation::WRITE,
+                resource::TRANSACTIONAL_ID,
+                &transactional_id,
+            ) {
+                return Ok(InitProducerIdResponse::error(
+                    TRANSACTIONAL_ID_AUTHORIZATION_FAILED,
+                ));
+            }
+            let broker = ctx.broker.clone();
+            let timeout_ms = self.transaction_timeout_ms;
+            let expected =
+                (self.producer_id >= 0).then_some((self.producer_id, self.producer_epoch));
+
+            let result = tokio::task::spawn_blocking(move || {
+                broker.transactions.init_producer_id(
+                    &broker,
+                    &transactional_id,
+                    timeout_ms,
+                    expected,
+                )
+            })
+            .await?;
+
+            return Ok(match result {
+                Ok((producer_id, producer_epoch)) => InitProducerIdResponse {
+                    producer_id,
+                    producer_epoch,
+                    ..InitProducerIdResponse::error(NONE)
+                },
+                Err(error_code) => InitProducerIdResponse::error(error_code),
+            });
+        }
+
+        // Idempotent producers need to write to the cluster or some topic.
+        if !ctx.authorize(operation::IDEMPOTENT_WRITE, resource::CLUSTER, CLUSTER_NAME)
+            && !ctx.authorize_any(operation::WRITE, resource::TOPIC)
+        {
+            return Ok(InitProducerIdResponse::error(CLUSTER_AUTHORIZATION_FAILED));
+        }
+
+        let broker = ctx.broker.clone();
+        let producer_id = tokio::task::spawn_blocking(move || {
+            broker
+                .producer_ids
+                .next(&broker.metadata, broker.config.node_id)
+        })
+        .await?;
+
+        Ok(match producer_id {
+            Ok(producer_id) => InitProducerIdResponse {
+                producer_id,
+                producer_epoch: 0,
+                ..InitProducerIdResponse::error(NONE)
+            },
+            Err(e) => {
+                eprintln!("failed to allocate a producer id: {e:?}");
+                InitProducerIdResponse::error(UNKNOWN_SERVER_ERROR)
+            }
+        })
+    }
+}
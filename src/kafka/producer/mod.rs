@@ -0,0 +1,71 @@
+//! Producer ids for idempotent producers. They are handed out from blocks
+//! allocated in the metadata log, so that no id is reused after a restart.
+
+use std::{io, ops::Range, sync::Mutex};
+
+use super::{
+    log::{producer_ids_record::ProducerIdsRecord, RecordValue},
+    metadata::ClusterMetadata,
+};
+
+pub mod initproducerid;
+
+/// How many producer ids are allocated at a time.
+const BLOCK_SIZE: i64 = 1000;
+
+/// The block of producer ids this broker hands out.
+#[derive(Debug, Default)]
+pub struct ProducerIds {
+    block: Mutex<Range<i64>>,
+}
+
+impl ProducerIds {
+    /// Hands out the next producer id, allocating a new block once the
+    /// current one is used up. The rest of a block is lost on restart.
+    pub fn next(&self, metadata: &ClusterMetadata, node_id: i32) -> io::Result<i64> {
+        let mut block = self.block.lock().unwrap_or_else(|e| e.into_inner());
+
+        if block.is_empty() {
+            let start = metadata.update(|image| {
+                let start = image.next_producer_id();
+                let record = RecordValue::ProducerIds(ProducerIdsRecord {
+                    broker_id: node_id,
+                    broker_epoch: 0,
+                    next_producer_id: start + BLOCK_SIZE,
+                    tagged_fields: 0,
+                });
+                (vec![record], start)
+            })?;
+            *block = start..start + BLOCK_SIZE;
+        }
+
+        Ok(block.next().expect("a non-empty block"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::kafka::log::partition::LogManager;
+
+    #[test]
+    fn test_blocks_are_not_reused_after_restart() {
+        let dir = std::env::temp_dir().join(format!("producer-ids-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let metadata = ClusterMetadata::open(&LogManager::new(&dir)).unwrap();
+        let ids = ProducerIds::default();
+        assert_eq!(ids.next(&metadata, 1).unwrap(), 0);
+        assert_eq!(ids.next(&metadata, 1).unwrap(), 1);
+        drop(metadata);
+
+        let metadata = ClusterMetadata::open(&LogManager::new(&dir)).unwrap();
+        assert_eq!(metadata.read(|image| image.next_producer_id()), BLOCK_SIZE);
+        let ids = ProducerIds::default();
+        assert_eq!(ids.next(&metadata, 1).unwrap(), BLOCK_SIZE);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
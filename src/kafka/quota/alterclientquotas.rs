@@ -0,0 +1,149 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource, CLUSTER_NAME},
+        errors::{CLUSTER_AUTHORIZATION_FAILED, INVALID_REQUEST, NONE},
+        log::{
+            quota_record::{ClientQuotaRecord, EntityData},
+            RecordValue,
+        },
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{entity, invalid_entity, invalid_quota};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct OpData {
+    pub key: CompactString,
+    pub value: f64,
+    pub remove: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct EntryData {
+    pub entity: CompactArray<EntityData>,
+    pub ops: CompactArray<OpData>,
+    pub tagged_fields: u8,
+}
+
+/// AlterClientQuotas v1.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AlterClientQuotasRequest {
+    pub entries: CompactArray<EntryData>,
+    pub validate_only: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct EntryResult {
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub entity: CompactArray<EntityData>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AlterClientQuotasResponse {
+    pub throttle_time_ms: i32,
+    pub entries: CompactArray<EntryResult>,
+    pub tagged_fields: u8,
+}
+
+impl EntryResult {
+    fn new(entry: &EntryData, error_code: i16, error_message: Option<String>) -> Self {
+        Self {
+            error_code,
+            error_message: CompactNullableString(error_message),
+            entity: entry.entity.clone(),
+            tagged_fields: 0,
+        }
+    }
+}
+
+impl EntryData {
+    /// Why the entry cannot be applied, if it cannot. Values removed are not
+    /// checked.
+    fn invalid(&self) -> Option<String> {
+        invalid_entity(&self.entity).or_else(|| {
+            self.ops.iter().find_map(|op| {
+                let value = if op.remove == 0 { op.value } else { 0.0 };
+                invalid_quota(op.key.as_str(), value)
+            })
+        })
+    }
+}
+
+impl AlterClientQuotasRequest {
+    /// Sets and removes the quotas of each entry whose entity and ops are
+    /// all valid. Removing a quota that is not set does nothing.
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<AlterClientQuotasResponse, Error> {
+        if !ctx.authorize(operation::ALTER_CONFIGS, resource::CLUSTER, CLUSTER_NAME) {
+            let entries = self
+                .entries
+                .iter()
+                .map(|entry| {
+                    let message = "Cluster authorization failed".to_string();
+                    EntryResult::new(entry, CLUSTER_AUTHORIZATION_FAILED, Some(message))
+                })
+                .collect();
+            return Ok(AlterClientQuotasResponse {
+                throttle_time_ms: 0,
+                entries: CompactArray(entries),
+                tagged_fields: 0,
+            });
+        }
+
+        let entries = self.entries.0.clone();
+        let validate_only = self.validate_only != 0;
+        let broker = ctx.broker.clone();
+        let results = tokio::task::spawn_blocking(move || {
+            broker.metadata.update(|image| {
+                let mut records = Vec::new();
+                let results = entries
+                    .iter()
+                    .map(|entry| {
+                        if let Some(message) = entry.invalid() {
+                            return EntryResult::new(entry, INVALID_REQUEST, Some(message));
+                        }
+                        let current = image.client_quota(&entity(&entry.entity));
+                        for op in entry.ops.iter() {
+                            let removed = op.remove != 0;
+                            let set =
+                                current.is_some_and(|values| values.contains_key(op.key.as_str()));
+                            if validate_only || (removed && !set) {
+                                continue;
+                            }
+                            records.push(RecordValue::ClientQuota(ClientQuotaRecord {
+                                entity: entry.entity.clone(),
+                                key: op.key.clone(),
+                                value: if removed { 0.0 } else { op.value },
+                                remove: op.remove,
+                                tagged_fields: 0,
+                            }));
+                        }
+                        EntryResult::new(entry, NONE, None)
+                    })
+                    .collect();
+                (records, results)
+            })
+        })
+        .await??;
+
+        Ok(AlterClientQuotasResponse {
+            throttle_time_ms: 0,
+            entries: CompactArray(results),
+            tagged_fields: 0,
+        })
+    }
+}
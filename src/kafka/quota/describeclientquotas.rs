@@ -0,0 +1,250 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    kafka::{
+        acl::{operation, resource, CLUSTER_NAME},
+        errors::{CLUSTER_AUTHORIZATION_FAILED, INVALID_REQUEST, NONE},
+        log::quota_record::EntityData,
+        RequestContext,
+    },
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{entity_data, QuotaEntity, CLIENT_ID, USER};
+
+/// The component must have the match string as its name, or be the default
+/// if it is null.
+const MATCH_EXACT: i8 = 0;
+/// The component must be the default of its type.
+const MATCH_DEFAULT: i8 = 1;
+/// The component may have any name but the default.
+const MATCH_SPECIFIED: i8 = 2;
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ComponentData {
+    pub entity_type: CompactString,
+    pub match_type: i8,
+    pub match_string: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+impl ComponentData {
+    fn invalid(&self) -> Option<String> {
+        let entity_type = self.entity_type.as_str();
+        if entity_type != USER && entity_type != CLIENT_ID {
+            return Some(format!(
+                "Unsupported client quota entity type {entity_type}"
+            ));
+        }
+        match (self.match_type, &self.match_string.0) {
+            (MATCH_EXACT, _) => None,
+            (MATCH_DEFAULT | MATCH_SPECIFIED, None) => None,
+            (MATCH_DEFAULT | MATCH_SPECIFIED, Some(_)) => Some(format!(
+                "Match type {} must not have a match string",
+                self.match_type
+            )),
+            (match_type, _) => Some(format!("Unknown match type {match_type}")),
+        }
+    }
+
+    fn matches(&self, entity: &QuotaEntity) -> bool {
+        let Some(name) = entity.get(self.entity_type.as_str()) else {
+            return false;
+        };
+        match self.match_type {
+            MATCH_EXACT => *name == self.match_string.0,
+            MATCH_DEFAULT => name.is_none(),
+            _ => name.is_some(),
+        }
+    }
+}
+
+/// DescribeClientQuotas v1.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeClientQuotasRequest {
+    pub components: CompactArray<ComponentData>,
+    /// Whether entities must have no components beyond those filtered on.
+    pub strict: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct ValueData {
+    pub key: CompactString,
+    pub value: f64,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct EntryData {
+    pub entity: CompactArray<EntityData>,
+    pub values: CompactArray<ValueData>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DescribeClientQuotasResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub entries: CompactNullableArray<EntryData>,
+    pub tagged_fields: u8,
+}
+
+impl DescribeClientQuotasResponse {
+    fn error(error_code: i16, error_message: String) -> Self {
+        Self {
+            throttle_time_ms: 0,
+            error_code,
+            error_message: CompactNullableString(Some(error_message)),
+            entries: CompactNullableArray(None),
+            tagged_fields: 0,
+        }
+    }
+}
+
+impl DescribeClientQuotasRequest {
+    fn invalid(&self) -> Option<String> {
+        let mut types = HashSet::new();
+        self.components.iter().find_map(|component| {
+            component.invalid().or_else(|| {
+                (!types.insert(component.entity_type.as_str())).then(|| {
+                    format!(
+                        "Entity type {} cannot appear more than once in the filter",
+                        component.entity_type.as_str()
+                    )
+                })
+            })
+        })
+    }
+
+    fn matches(&self, entity: &QuotaEntity) -> bool {
+        self.components
+            .iter()
+            .all(|component| component.matches(entity))
+            && (self.strict == 0 || entity.len() == self.components.len())
+    }
+
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<DescribeClientQuotasResponse, Error> {
+        if let Some(message) = self.invalid() {
+            return Ok(DescribeClientQuotasResponse::error(
+                INVALID_REQUEST,
+                message,
+            ));
+        }
+
+        let entries = ctx.broker.metadata.read(|image| {
+            let operation = operation::DESCRIBE_CONFIGS;
+            ctx.authorized(image, operation, resource::CLUSTER, CLUSTER_NAME)
+                .then(|| {
+                    image
+                        .client_quotas()
+                        .filter(|(entity, _)| self.matches(entity))
+                        .map(|(entity, values)| entry(entity, values))
+                        .collect::<Vec<_>>()
+                })
+        });
+        let Some(entries) = entries else {
+            return Ok(DescribeClientQuotasResponse::error(
+                CLUSTER_AUTHORIZATION_FAILED,
+                "Cluster authorization failed".to_string(),
+            ));
+        };
+
+        Ok(DescribeClientQuotasResponse {
+            throttle_time_ms: 0,
+            error_code: NONE,
+            error_message: CompactNullableString(None),
+            entries: CompactNullableArray(Some(entries)),
+            tagged_fields: 0,
+        })
+    }
+}
+
+fn entry(entity: &QuotaEntity, values: &BTreeMap<String, f64>) -> EntryData {
+    EntryData {
+        entity: entity_data(entity),
+        values: CompactArray(
+            values
+                .iter()
+                .map(|(key, &value)| ValueData {
+                    key: CompactString(key.clone()),
+                    value,
+                    tagged_fields: 0,
+                })
+                .collect(),
+        ),
+        tagged_fields: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(entity_type: &str, match_type: i8, name: Option<&str>) -> ComponentData {
+        ComponentData {
+            entity_type: CompactString(entity_type.to_string()),
+            match_type,
+            match_string: CompactNullableString(name.map(str::to_string)),
+            tagged_fields: 0,
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let alice: QuotaEntity = [(USER.to_string(), Some("alice".to_string()))].into();
+        let default_user: QuotaEntity = [(USER.to_string(), None)].into();
+        let alice_app: QuotaEntity = [
+            (USER.to_string(), Some("alice".to_string())),
+            (CLIENT_ID.to_string(), Some("app".to_string())),
+        ]
+        .into();
+
+        let request = |components, strict| DescribeClientQuotasRequest {
+            components: CompactArray(components),
+            strict,
+            tagged_fields: 0,
+        };
+
+        let exact = request(vec![component(USER, MATCH_EXACT, Some("alice"))], 0);
+        assert!(exact.matches(&alice));
+        assert!(exact.matches(&alice_app));
+        assert!(!exact.matches(&default_user));
+
+        let strict = request(vec![component(USER, MATCH_SPECIFIED, None)], 1);
+        assert!(strict.matches(&alice));
+        assert!(!strict.matches(&alice_app));
+        assert!(!strict.matches(&default_user));
+
+        let default = request(vec![component(USER, MATCH_DEFAULT, None)], 0);
+        assert!(default.matches(&default_user));
+        assert!(!default.matches(&alice));
+
+        // No components and not strict matches every entity.
+        assert!(request(vec![], 0).matches(&alice_app));
+
+        let twice = request(
+            vec![
+                component(USER, MATCH_DEFAULT, None),
+                component(USER, MATCH_SPECIFIED, None),
+            ],
+            0,
+        );
+        assert!(twice.invalid().is_some());
+        assert!(
+            request(vec![component(USER, MATCH_DEFAULT, Some("alice"))], 0)
+                .invalid()
+                .is_some()
+        );
+    }
+}
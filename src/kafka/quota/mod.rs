@@ -0,0 +1,506 @@
+//! Client quotas, as in Kafka: byte rates for produce and fetch and a share
+//! of request handling time, set on users, client ids or both by quota
+//! records in the metadata log. Usage is tracked over a sliding window of
+//! samples; a client over its quota is told how long it is throttled for in
+//! the response, and its connection is muted for that long.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Size,
+};
+
+use super::{log::quota_record::EntityData, metadata::MetadataImage, RequestContext};
+
+pub mod alterclientquotas;
+pub mod describeclientquotas;
+
+pub const USER: &str = "user";
+pub const CLIENT_ID: &str = "client-id";
+
+pub const PRODUCER_BYTE_RATE: &str = "producer_byte_rate";
+pub const CONSUMER_BYTE_RATE: &str = "consumer_byte_rate";
+pub const REQUEST_PERCENTAGE: &str = "request_percentage";
+
+const PRODUCE: i16 = 0;
+const FETCH: i16 = 1;
+
+/// The components of an entity quotas are set on, by type. A `None` name
+/// stands for the default of its type.
+pub type QuotaEntity = BTreeMap<String, Option<String>>;
+
+pub fn entity(data: &[EntityData]) -> QuotaEntity {
+    data.iter()
+        .map(|component| {
+            (
+                component.entity_type.0.clone(),
+                component.entity_name.0.clone(),
+            )
+        })
+        .collect()
+}
+
+pub fn entity_data(entity: &QuotaEntity) -> CompactArray<EntityData> {
+    CompactArray(
+        entity
+            .iter()
+            .map(|(entity_type, name)| EntityData {
+                entity_type: CompactString(entity_type.clone()),
+                entity_name: CompactNullableString(name.clone()),
+                tagged_fields: 0,
+            })
+            .collect(),
+    )
+}
+
+/// Why quotas cannot be set on an entity, if they cannot: it must have a
+/// user, a client id or both.
+pub fn invalid_entity(data: &[EntityData]) -> Option<String> {
+    if data.is_empty() {
+        return Some("Invalid empty client quota entity".to_string());
+    }
+    let mut types = HashSet::new();
+    for component in data {
+        let entity_type = component.entity_type.as_str();
+        if entity_type != USER && entity_type != CLIENT_ID {
+            return Some(format!(
+                "Unsupported client quota entity type {entity_type}"
+            ));
+        }
+        if !types.insert(entity_type) {
+            return Some(format!("Duplicate entity type {entity_type}"));
+        }
+    }
+    None
+}
+
+/// Why a quota value cannot be set, if it cannot. Byte rates are whole
+/// numbers.
+pub fn invalid_quota(key: &str, value: f64) -> Option<String> {
+    match key {
+        _ if !value.is_finite() || value < 0.0 => {
+            Some(format!("Invalid value {value} for configuration key {key}"))
+        }
+        PRODUCER_BYTE_RATE | CONSUMER_BYTE_RATE if value.fract() != 0.0 => Some(format!(
+            "Invalid value {value} for configuration key {key}, must be an integer"
+        )),
+        PRODUCER_BYTE_RATE | CONSUMER_BYTE_RATE | REQUEST_PERCENTAGE => None,
+        _ => Some(format!("Invalid configuration key {key}")),
+    }
+}
+
+/// The entities a quota of a client may be set on, most specific first, as
+/// in Kafka.
+fn candidates(user: &str, client_id: &str) -> [QuotaEntity; 8] {
+    let entity = |components: &[(&str, Option<&str>)]| -> QuotaEntity {
+        components
+            .iter()
+            .map(|(entity_type, name)| (entity_type.to_string(), name.map(str::to_string)))
+            .collect()
+    };
+
+    [
+        entity(&[(USER, Some(user)), (CLIENT_ID, Some(client_id))]),
+        entity(&[(USER, Some(user)), (CLIENT_ID, None)]),
+        entity(&[(USER, Some(user))]),
+        entity(&[(USER, None), (CLIENT_ID, Some(client_id))]),
+        entity(&[(USER, None), (CLIENT_ID, None)]),
+        entity(&[(USER, None)]),
+        entity(&[(CLIENT_ID, Some(client_id))]),
+        entity(&[(CLIENT_ID, None)]),
+    ]
+}
+
+/// Whose usage a quota holds: the user and client id of the entity the
+/// quota is set on, with defaults replaced by the client's, so that every
+/// client under a default quota is held to it on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Sensor {
+    key: &'static str,
+    user: Option<String>,
+    client_id: Option<String>,
+}
+
+/// The quota on `key` of a client, and the sensor tracking its usage.
+fn client_quota(
+    image: &MetadataImage,
+    key: &'static str,
+    user: &str,
+    client_id: &str,
+) -> Option<(Sensor, f64)> {
+    candidates(user, client_id).into_iter().find_map(|entity| {
+        let bound = *image.client_quota(&entity)?.get(key)?;
+        let sensor = Sensor {
+            key,
+            user: entity.contains_key(USER).then(|| user.to_string()),
+            client_id: entity
+                .contains_key(CLIENT_ID)
+                .then(|| client_id.to_string()),
+        };
+        Some((sensor, bound))
+    })
+}
+
+/// How usage is sampled: `samples` windows of `size` each, as Kafka's
+/// `quota.window.num` and `quota.window.size.seconds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaWindow {
+    pub samples: usize,
+    pub size: Duration,
+}
+
+impl Default for QuotaWindow {
+    fn default() -> Self {
+        Self {
+            samples: 11,
+            size: Duration::from_secs(1),
+        }
+    }
+}
+
+impl QuotaWindow {
+    /// The longest a client is throttled for: the time its whole usage
+    /// takes to fall out of the window.
+    fn max_throttle(&self) -> Duration {
+        self.size * self.samples as u32
+    }
+}
+
+/// A rate over a sliding window, as Kafka's sampled `Rate`: values add up in
+/// the sample of their window, and samples older than the whole window are
+/// dropped.
+#[derive(Debug, Default)]
+struct Rate {
+    /// The start of each sample's window and the values recorded in it.
+    samples: VecDeque<(Instant, f64)>,
+}
+
+impl Rate {
+    fn purge(&mut self, now: Instant, window: &QuotaWindow) {
+        while let Some(&(start, _)) = self.samples.front() {
+            if now.duration_since(start) < window.max_throttle() {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    fn record(&mut self, value: f64, now: Instant, window: &QuotaWindow) {
+        self.purge(now, window);
+        match self.samples.back_mut() {
+            Some((start, total)) if now.duration_since(*start) < window.size => *total += value,
+            _ => {
+                self.samples.push_back((now, value));
+                if self.samples.len() > window.samples {
+                    self.samples.pop_front();
+                }
+            }
+        }
+    }
+
+    /// The rate per second and the time it is measured over, which is at
+    /// least all windows but one, so that a burst in a fresh window does
+    /// not look like a high rate.
+    fn measure(&mut self, now: Instant, window: &QuotaWindow) -> (f64, Duration) {
+        self.purge(now, window);
+        let total: f64 = self.samples.iter().map(|(_, value)| value).sum();
+        let elapsed = self
+            .samples
+            .front()
+            .map_or(Duration::ZERO, |&(start, _)| now.duration_since(start))
+            .max(window.size * window.samples.saturating_sub(1).max(1) as u32);
+        (total / elapsed.as_secs_f64(), elapsed)
+    }
+}
+
+/// A response that tells its client how long it is throttled for.
+pub trait Throttle: Size {
+    fn set_throttle_time_ms(&mut self, throttle_time_ms: i32);
+
+    /// Drops what a throttled response holds back. Fetches hold back their
+    /// records, as in Kafka, so that a consumer over its quota gets nothing
+    /// more until its throttle time is over.
+    fn hold_back(&mut self) {}
+}
+
+/// Implements [`Throttle`] for responses whose throttle time is a field.
+macro_rules! throttle_time_field {
+    ($($response:ty => $field:ident),* $(,)?) => {
+        $(
+            impl Throttle for $response {
+                fn set_throttle_time_ms(&mut self, throttle_time_ms: i32) {
+                    self.$field = throttle_time_ms;
+                }
+            }
+        )*
+    };
+}
+
+throttle_time_field!(
+    super::acl::createacls::CreateAclsResponse => throttle_time_ms,
+    super::acl::deleteacls::DeleteAclsResponse => throttle_time_ms,
+    super::acl::describeacls::DescribeAclsResponse => throttle_time_ms,
+    super::apiversions::ApiVersionsResponse => throttle_time_ms,
+    super::configs::describeconfigs::DescribeConfigsResponse => throttle_time_ms,
+    super::configs::incrementalalterconfigs::IncrementalAlterConfigsResponse => throttle_time_ms,
+    super::group::consumergroupdescribe::ConsumerGroupDescribeResponse => throttle_time_ms,
+    super::group::consumergroupheartbeat::ConsumerGroupHeartbeatResponse => throttle_time_ms,
+    super::group::deletegroups::DeleteGroupsResponse => throttle_time_ms,
+    super::group::describegroups::DescribeGroupsResponse => throttle_time_ms,
+    super::group::findcoordinator::FindCoordinatorResponse => throttle_time_ms,
+    super::group::heartbeat::HeartbeatResponse => throttle_time_ms,
+    super::group::joingroup::JoinGroupResponse => throttle_time_ms,
+    super::group::leavegroup::LeaveGroupResponse => throttle_time_ms,
+    super::group::listgroups::ListGroupsResponse => throttle_time_ms,
+    super::group::offsetcommit::OffsetCommitResponse => throttle_time_ms,
+    super::group::offsetdelete::OffsetDeleteResponse => throttle_time_ms,
+    super::group::offsetfetch::OffsetFetchResponse => throttle_time_ms,
+    super::group::syncgroup::SyncGroupResponse => throttle_time_ms,
+    super::listoffsets::ListOffsetsResponse => throttle_time_ms,
+    super::listpartitions::DescribePartitionsResponse => throttle,
+    super::produce::ProduceResponse => throttle_time_ms,
+    super::producer::initproducerid::InitProducerIdResponse => throttle_time_ms,
+    super::quota::alterclientquotas::AlterClientQuotasResponse => throttle_time_ms,
+    super::quota::describeclientquotas::DescribeClientQuotasResponse => throttle_time_ms,
+    super::sasl::alteruserscramcredentials::AlterUserScramCredentialsResponse => throttle_time_ms,
+    super::topics::createpartitions::CreatePartitionsResponse => throttle_time_ms,
+    super::topics::createtopics::CreateTopicsResponse => throttle_time_ms,
+    super::topics::deleterecords::DeleteRecordsResponse => throttle_time_ms,
+    super::topics::deletetopics::DeleteTopicsResponse => throttle_time_ms,
+    super::topics::metadata::MetadataResponse => throttle_time_ms,
+    super::txn::addoffsetstotxn::AddOffsetsToTxnResponse => throttle_time_ms,
+    super::txn::addpartitionstotxn::AddPartitionsToTxnResponse => throttle_time_ms,
+    super::txn::endtxn::EndTxnResponse => throttle_time_ms,
+    super::txn::txnoffsetcommit::TxnOffsetCommitResponse => throttle_time_ms,
+);
+
+/// Runs `future`, adding up the time spent polling it: the time a request
+/// keeps the runtime busy, leaving out what it waits on, such as a
+/// rebalance or the blocking pool.
+pub async fn timed<F: Future>(future: F) -> (F::Output, Duration) {
+    let mut future = std::pin::pin!(future);
+    let mut busy = Duration::ZERO;
+    let output = std::future::poll_fn(|cx| {
+        let started = Instant::now();
+        let poll = future.as_mut().poll(cx);
+        busy += started.elapsed();
+        poll
+    })
+    .await;
+
+    (output, busy)
+}
+
+/// The usage of every client with a quota.
+#[derive(Debug, Default)]
+pub struct QuotaManager {
+    window: QuotaWindow,
+    rates: Mutex<HashMap<Sensor, Rate>>,
+}
+
+impl QuotaManager {
+    pub fn new(window: QuotaWindow) -> Self {
+        Self {
+            window,
+            rates: Mutex::default(),
+        }
+    }
+
+    /// Adds `value` to the usage a sensor tracks and returns how long its
+    /// client is throttled for: long enough for its rate to fall back to
+    /// `bound`.
+    fn record(&self, sensor: &Sensor, bound: f64, value: f64, now: Instant) -> Duration {
+        let mut rates = self.rates.lock().unwrap_or_else(|e| e.into_inner());
+        if !rates.contains_key(sensor) {
+            // Sensors of clients that went quiet are dropped.
+            rates.retain(|_, rate| {
+                rate.purge(now, &self.window);
+                !rate.samples.is_empty()
+            });
+        }
+        let rate = rates.entry(sensor.clone()).or_default();
+        rate.record(value, now, &self.window);
+
+        let (observed, elapsed) = rate.measure(now, &self.window);
+        if observed <= bound {
+            return Duration::ZERO;
+        }
+        if bound <= 0.0 {
+            return self.window.max_throttle();
+        }
+        elapsed
+            .mul_f64((observed - bound) / bound)
+            .min(self.window.max_throttle())
+    }
+
+    /// Records a served request against its client's quotas: the time spent
+    /// handling it, and the bytes produced or fetched. Sets the throttle
+    /// time of the response and returns how long the client's connection
+    /// is muted for.
+    pub fn throttle<T: Throttle>(
+        &self,
+        ctx: &RequestContext,
+        busy: Duration,
+        response: &mut T,
+    ) -> Duration {
+        let user = {
+            let session = ctx.session.lock().unwrap_or_else(|e| e.into_inner());
+            session.user_name().to_string()
+        };
+        let client_id = ctx.client_id.as_deref().unwrap_or_default();
+        let (bytes_key, bytes) = match ctx.api_key {
+            PRODUCE => (PRODUCER_BYTE_RATE, ctx.request_size),
+            FETCH => (CONSUMER_BYTE_RATE, response.size_in_bytes()),
+            _ => ("", 0),
+        };
+        let (request_quota, bytes_quota) = ctx.broker.metadata.read(|image| {
+            (
+                client_quota(image, REQUEST_PERCENTAGE, &user, client_id),
+                client_quota(image, bytes_key, &user, client_id),
+            )
+        });
+
+        let now = Instant::now();
+        let mut throttle = Duration::ZERO;
+        if let Some((sensor, bound)) = &request_quota {
+            let percentage = busy.as_secs_f64() * 100.0;
+            throttle = throttle.max(self.record(sensor, *bound, percentage, now));
+        }
+        if let Some((sensor, bound)) = &bytes_quota {
+            let bytes = bytes as f64;
+            throttle = throttle.max(self.record(sensor, *bound, bytes, now));
+            // A fetch that is not sent does not count against the quota.
+            if ctx.api_key == FETCH && !throttle.is_zero() {
+                self.record(sensor, *bound, -bytes, now);
+            }
+        }
+
+        if !throttle.is_zero() {
+            if ctx.api_key == FETCH {
+                response.hold_back();
+            }
+            response.set_throttle_time_ms(throttle.as_millis().min(i32::MAX as u128) as i32);
+        }
+        throttle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(image: &mut MetadataImage, components: &[(&str, Option<&str>)], key: &str, value: f64) {
+        use crate::kafka::log::{quota_record::ClientQuotaRecord, RecordValue};
+
+        let entity = components
+            .iter()
+            .map(|(entity_type, name)| (entity_type.to_string(), name.map(str::to_string)))
+            .collect();
+        image.apply(&RecordValue::ClientQuota(ClientQuotaRecord {
+            entity: entity_data(&entity),
+            key: CompactString(key.to_string()),
+            value,
+            remove: 0,
+            tagged_fields: 0,
+        }));
+    }
+
+    #[test]
+    fn test_client_quota_precedence() {
+        let mut image = MetadataImage::default();
+        assert!(client_quota(&image, PRODUCER_BYTE_RATE, "alice", "app").is_none());
+
+        set(&mut image, &[(CLIENT_ID, None)], PRODUCER_BYTE_RATE, 100.0);
+        let (sensor, bound) = client_quota(&image, PRODUCER_BYTE_RATE, "alice", "app").unwrap();
+        assert_eq!(bound, 100.0);
+        assert_eq!(sensor.user, None);
+        assert_eq!(sensor.client_id.as_deref(), Some("app"));
+
+        // A user's own quota wins over any default, even one with a client id.
+        set(
+            &mut image,
+            &[(USER, None), (CLIENT_ID, None)],
+            PRODUCER_BYTE_RATE,
+            200.0,
+        );
+        set(
+            &mut image,
+            &[(USER, Some("alice"))],
+            PRODUCER_BYTE_RATE,
+            300.0,
+        );
+        let (sensor, bound) = client_quota(&image, PRODUCER_BYTE_RATE, "alice", "app").unwrap();
+        assert_eq!(bound, 300.0);
+        assert_eq!(sensor.user.as_deref(), Some("alice"));
+        assert_eq!(sensor.client_id, None);
+        assert_eq!(
+            client_quota(&image, PRODUCER_BYTE_RATE, "bob", "app")
+                .unwrap()
+                .1,
+            200.0
+        );
+
+        // Quotas of other keys do not apply.
+        assert!(client_quota(&image, CONSUMER_BYTE_RATE, "alice", "app").is_none());
+    }
+
+    #[test]
+    fn test_throttle_time() {
+        let window = QuotaWindow {
+            samples: 11,
+            size: Duration::from_secs(1),
+        };
+        let quotas = QuotaManager::new(window);
+        let sensor = Sensor {
+            key: PRODUCER_BYTE_RATE,
+            user: None,
+            client_id: Some("app".to_string()),
+        };
+        let start = Instant::now();
+
+        // The rate is measured over ten seconds at first, so 1000 bytes is
+        // at the quota of 100 bytes a second and 2000 bytes twice over it.
+        assert_eq!(quotas.record(&sensor, 100.0, 1000.0, start), Duration::ZERO);
+        assert_eq!(
+            quotas.record(&sensor, 100.0, 1000.0, start),
+            Duration::from_secs(10)
+        );
+        // Throttling never lasts longer than the whole window.
+        assert_eq!(
+            quotas.record(&sensor, 100.0, 100_000.0, start),
+            window.max_throttle()
+        );
+
+        // Once its samples fall out of the window, the client is back under.
+        let later = start + window.max_throttle();
+        assert_eq!(quotas.record(&sensor, 100.0, 10.0, later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_invalid_quotas() {
+        assert!(invalid_quota(PRODUCER_BYTE_RATE, 1024.0).is_none());
+        assert!(invalid_quota(REQUEST_PERCENTAGE, 12.5).is_none());
+        assert!(invalid_quota(CONSUMER_BYTE_RATE, 12.5).is_some());
+        assert!(invalid_quota(PRODUCER_BYTE_RATE, -1.0).is_some());
+        assert!(invalid_quota("connection_creation_rate", 10.0).is_some());
+
+        let component = |entity_type: &str| EntityData {
+            entity_type: CompactString(entity_type.to_string()),
+            entity_name: CompactNullableString(None),
+            tagged_fields: 0,
+        };
+        assert!(invalid_entity(&[component(USER), component(CLIENT_ID)]).is_none());
+        assert!(invalid_entity(&[]).is_some());
+        assert!(invalid_entity(&[component(USER), component(USER)]).is_some());
+        assert!(invalid_entity(&[component("ip")]).is_some());
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+
+use crate::{
+    kafka::{
+        acl::{operation, resource, CLUSTER_NAME},
+        errors::{
+            CLUSTER_AUTHORIZATION_FAILED, DUPLICATE_RESOURCE, NONE, RESOURCE_NOT_FOUND,
+            UNACCEPTABLE_CREDENTIAL, UNSUPPORTED_SASL_MECHANISM,
+        },
+        log::{scram_record::RemoveUserScramCredentialRecord, RecordValue},
+        metadata::MetadataImage,
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        bytes::CompactBytes,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::scram::{ScramMechanism, MAX_ITERATIONS, MIN_ITERATIONS};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct ScramCredentialDeletion {
+    pub name: CompactString,
+    pub mechanism: i8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct ScramCredentialUpsertion {
+    pub name: CompactString,
+    pub mechanism: i8,
+    pub iterations: i32,
+    pub salt: CompactBytes,
+    /// The password salted by the client, so that it never reaches the
+    /// broker.
+    pub salted_password: CompactBytes,
+    pub tagged_fields: u8,
+}
+
+/// AlterUserScramCredentials v0.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AlterUserScramCredentialsRequest {
+    pub deletions: CompactArray<ScramCredentialDeletion>,
+    pub upsertions: CompactArray<ScramCredentialUpsertion>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AlterUserScramCredentialsResult {
+    pub user: CompactString,
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AlterUserScramCredentialsResponse {
+    pub throttle_time_ms: i32,
+    pub results: CompactArray<AlterUserScramCredentialsResult>,
+    pub tagged_fields: u8,
+}
+
+/// One alteration of the request, checked against the image.
+enum Alteration {
+    Delete(ScramCredentialDeletion),
+    Upsert(ScramCredentialUpsertion),
+}
+
+impl Alteration {
+    fn user(&self) -> &str {
+        match self {
+            Self::Delete(deletion) => &deletion.name,
+            Self::Upsert(upsertion) => &upsertion.name,
+        }
+    }
+
+    fn mechanism(&self) -> i8 {
+        match self {
+            Self::Delete(deletion) => deletion.mechanism,
+            Self::Upsert(upsertion) => upsertion.mechanism,
+        }
+    }
+
+    fn record(&self, image: &MetadataImage) -> Result<RecordValue, (i16, String)> {
+        let Some(mechanism) = ScramMechanism::from_id(self.mechanism()) else {
+            return Err((
+                UNSUPPORTED_SASL_MECHANISM,
+                "Unknown SCRAM mechanism".to_string(),
+            ));
+        };
+        if self.user().is_empty() {
+            return Err((
+                UNACCEPTABLE_CREDENTIAL,
+                "Username must not be empty".to_string(),
+            ));
+        }
+
+        match self {
+            Self::Delete(deletion) => {
+                if image
+                    .scram_credential(&deletion.name, deletion.mechanism)
+                    .is_none()
+                {
+                    return Err((
+                        RESOURCE_NOT_FOUND,
+                        "Attempt to delete a user credential that does not exist".to_string(),
+                    ));
+                }
+                Ok(RecordValue::RemoveUserScramCredential(
+                    RemoveUserScramCredentialRecord {
+                        name: deletion.name.clone(),
+                        mechanism: deletion.mechanism,
+                        tagged_fields: 0,
+                    },
+                ))
+            }
+            Self::Upsert(upsertion) => {
+                if !(MIN_ITERATIONS..=MAX_ITERATIONS).contains(&upsertion.iterations) {
+                    return Err((
+                        UNACCEPTABLE_CREDENTIAL,
+                        format!("Iterations must be between {MIN_ITERATIONS} and {MAX_ITERATIONS}"),
+                    ));
+                }
+                if upsertion.salt.is_empty() || upsertion.salted_password.is_empty() {
+                    return Err((
+                        UNACCEPTABLE_CREDENTIAL,
+                        "Salt and salted password must not be empty".to_string(),
+                    ));
+                }
+                Ok(RecordValue::UserScramCredential(mechanism.credential(
+                    &upsertion.name,
+                    &upsertion.salt,
+                    &upsertion.salted_password,
+                    upsertion.iterations,
+                )))
+            }
+        }
+    }
+}
+
+impl AlterUserScramCredentialsRequest {
+    /// Alters the credentials of each user at once: if one of a user's
+    /// alterations fails, none of them is made.
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<AlterUserScramCredentialsResponse, Error> {
+        let alterations: Vec<Alteration> = self
+            .deletions
+            .iter()
+            .cloned()
+            .map(Alteration::Delete)
+            .chain(self.upsertions.iter().cloned().map(Alteration::Upsert))
+            .collect();
+
+        let mut users: Vec<String> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut duplicated = HashSet::new();
+        for alteration in &alterations {
+            let user = alteration.user().to_string();
+            if !users.contains(&user) {
+                users.push(user.clone());
+            }
+            if !seen.insert((user.clone(), alteration.mechanism())) {
+                duplicated.insert(user);
+            }
+        }
+
+        let authorized = ctx.authorize(operation::ALTER, resource::CLUSTER, CLUSTER_NAME);
+        let broker = ctx.broker.clone();
+        let altered = users.clone();
+        let outcomes = tokio::task::spawn_blocking(move || {
+            broker.metadata.update(|image| {
+                let mut records = Vec::new();
+                let outcomes: Vec<Result<(), (i16, String)>> = altered
+                    .iter()
+                    .map(|user| {
+                        if !authorized {
+                            return Err((
+                                CLUSTER_AUTHORIZATION_FAILED,
+                                "Cluster authorization failed".to_string(),
+                            ));
+                        }
+                        if duplicated.contains(user) {
+                            return Err((
+                                DUPLICATE_RESOURCE,
+                                "A user credential cannot be altered twice in the same request"
+                                    .to_string(),
+                            ));
+                        }
+                        let changes = alterations
+                            .iter()
+                            .filter(|alteration| alteration.user() == user)
+                            .map(|alteration| alteration.record(image))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        records.extend(changes);
+                        Ok(())
+                    })
+                    .collect();
+
+                (records, outcomes)
+            })
+        })
+        .await??;
+
+        let results = users
+            .into_iter()
+            .zip(outcomes)
+            .map(|(user, outcome)| {
+                let (error_code, error_message) = match outcome {
+                    Ok(()) => (NONE, None),
+                    Err((code, message)) => (code, Some(message)),
+                };
+                AlterUserScramCredentialsResult {
+                    user: CompactString(user),
+                    error_code,
+                    error_message: CompactNullableString(error_message),
+                    tagged_fields: 0,
+                }
+            })
+            .collect();
+
+        Ok(AlterUserScramCredentialsResponse {
+            throttle_time_ms: 0,
+            results: CompactArray(results),
+            tagged_fields: 0,
+        })
+    }
+}
@@ -0,0 +1,237 @@
+//! SASL authentication with the PLAIN and SCRAM mechanisms. Each connection
+//! has a [`Session`] tracking how far its client got; while SASL is enabled,
+//! only the authentication APIs are served until it is authenticated.
+//! Credentials are SCRAM records in the metadata log, which PLAIN checks
+//! passwords against too.
+
+use std::{fs, io, path::Path};
+
+use scram::{random_bytes, ScramMechanism, ScramServer, MIN_ITERATIONS};
+
+use super::{
+    log::{scram_record::UserScramCredentialRecord, RecordValue},
+    metadata::ClusterMetadata,
+};
+
+pub mod alteruserscramcredentials;
+pub mod saslauthenticate;
+pub mod saslhandshake;
+pub mod scram;
+
+pub const PLAIN: &str = "PLAIN";
+
+/// APIs served before authentication: ApiVersions, so that clients can find
+/// the handshake versions, SaslHandshake and SaslAuthenticate.
+const AUTHENTICATION_APIS: [i16; 3] = [17, 18, 36];
+
+/// A mechanism a client can authenticate with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mechanism {
+    Plain,
+    Scram(ScramMechanism),
+}
+
+impl Mechanism {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            PLAIN => Some(Self::Plain),
+            _ => ScramMechanism::from_name(name).map(Self::Scram),
+        }
+    }
+}
+
+/// Where a connection is in the authentication exchange.
+#[derive(Debug, Clone, Default)]
+pub enum SaslState {
+    /// Waiting for SaslHandshake.
+    #[default]
+    Handshake,
+    /// Waiting for the first SaslAuthenticate with the mechanism chosen.
+    Authenticate(Mechanism),
+    /// Waiting for the client's final SCRAM message.
+    ScramFinal(Box<ScramServer>),
+    Authenticated,
+    Failed,
+}
+
+/// The authentication state of one connection.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    pub state: SaslState,
+    /// The user the connection authenticated as, by SASL or by its client
+    /// certificate.
+    pub user: Option<String>,
+    /// Whether the connection came on a SASL listener, on which it must
+    /// authenticate before anything else.
+    pub sasl_required: bool,
+}
+
+impl Session {
+    pub fn new(sasl_required: bool) -> Self {
+        Self {
+            sasl_required,
+            ..Self::default()
+        }
+    }
+
+    /// The principal requests on this connection are made by.
+    pub fn principal(&self) -> String {
+        format!("User:{}", self.user_name())
+    }
+
+    /// The name of that principal, which user quotas are set on.
+    pub fn user_name(&self) -> &str {
+        self.user.as_deref().unwrap_or("ANONYMOUS")
+    }
+
+    /// Whether the connection may send `api_key` requests. Without SASL
+    /// every connection may send anything.
+    pub fn may_send(&self, api_key: i16) -> bool {
+        match self.state {
+            _ if !self.sasl_required => true,
+            SaslState::Authenticated => true,
+            SaslState::Failed => false,
+            _ => AUTHENTICATION_APIS.contains(&api_key),
+        }
+    }
+
+    pub fn authenticated(&mut self, user: String) {
+        self.state = SaslState::Authenticated;
+        self.user = Some(user);
+    }
+}
+
+/// Returned for a request sent before the connection authenticated, upon
+/// which the connection is closed.
+#[derive(Debug, thiserror::Error)]
+#[error("api key {api_key} is not allowed before SASL authentication completes")]
+pub struct AuthenticationRequired {
+    pub api_key: i16,
+}
+
+/// Whether `password` matches one of the SCRAM credentials of `user`.
+pub fn verify_plain(metadata: &ClusterMetadata, user: &str, password: &str) -> bool {
+    let credentials: Vec<(ScramMechanism, UserScramCredentialRecord)> = metadata.read(|image| {
+        ScramMechanism::ALL
+            .into_iter()
+            .filter_map(|mechanism| {
+                let credential = image.scram_credential(user, mechanism as i8)?;
+                Some((mechanism, credential.clone()))
+            })
+            .collect()
+    });
+
+    credentials
+        .iter()
+        .any(|(mechanism, credential)| mechanism.verify_password(credential, password))
+}
+
+/// Adds the credentials listed in a local file to the metadata log, for
+/// the users that have none yet for a mechanism. Each line holds a SCRAM
+/// mechanism, a user and a password; blank lines and `#` comments are
+/// skipped.
+pub fn load_credentials(metadata: &ClusterMetadata, path: &Path) -> io::Result<()> {
+    let invalid = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "invalid SCRAM credential line in {}: {line}",
+                path.display()
+            ),
+        )
+    };
+
+    let mut listed = Vec::new();
+    for line in fs::read_to_string(path)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(mechanism), Some(user), Some(password), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid(line));
+        };
+        let mechanism = ScramMechanism::from_name(mechanism).ok_or_else(|| invalid(line))?;
+        listed.push((mechanism, user.to_string(), password.to_string()));
+    }
+
+    metadata.update(|image| {
+        let records = listed
+            .iter()
+            .filter(|(mechanism, user, _)| image.scram_credential(user, *mechanism as i8).is_none())
+            .map(|(mechanism, user, password)| {
+                let salt = random_bytes(32);
+                let salted = mechanism.salted_password(password.as_bytes(), &salt, MIN_ITERATIONS);
+                RecordValue::UserScramCredential(mechanism.credential(
+                    user,
+                    &salt,
+                    &salted,
+                    MIN_ITERATIONS,
+                ))
+            })
+            .collect();
+        (records, ())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::log::partition::LogManager;
+
+    #[test]
+    fn test_credentials_are_loaded_once_and_replayed() {
+        let dir = std::env::temp_dir().join(format!("sasl-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("credentials");
+        fs::write(
+            &file,
+            "# users\nSCRAM-SHA-256 alice alice-secret\nSCRAM-SHA-512 bob bob-secret\n",
+        )
+        .unwrap();
+
+        let logs = LogManager::new(dir.join("logs"));
+        let metadata = ClusterMetadata::open(&logs).unwrap();
+        load_credentials(&metadata, &file).unwrap();
+        let salt = metadata.read(|image| image.scram_credential("alice", 1).unwrap().salt.clone());
+
+        // A changed password does not replace an existing credential.
+        fs::write(&file, "SCRAM-SHA-256 alice other\n").unwrap();
+        load_credentials(&metadata, &file).unwrap();
+        assert!(verify_plain(&metadata, "alice", "alice-secret"));
+        assert!(!verify_plain(&metadata, "alice", "other"));
+        assert!(!verify_plain(&metadata, "carol", "alice-secret"));
+        drop(metadata);
+
+        let metadata = ClusterMetadata::open(&LogManager::new(dir.join("logs"))).unwrap();
+        metadata.read(|image| {
+            assert_eq!(image.scram_credential("alice", 1).unwrap().salt, salt);
+            assert!(image.scram_credential("alice", 2).is_none());
+        });
+        assert!(verify_plain(&metadata, "bob", "bob-secret"));
+
+        fs::write(&file, "SCRAM-SHA-1 carol secret\n").unwrap();
+        assert!(load_credentials(&metadata, &file).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_session_gates_requests_until_authenticated() {
+        assert!(Session::new(false).may_send(3));
+
+        let mut session = Session::new(true);
+        assert!(session.may_send(17));
+        assert!(!session.may_send(3));
+        assert_eq!(session.principal(), "User:ANONYMOUS");
+
+        session.authenticated("alice".to_string());
+        assert!(session.may_send(3));
+        assert_eq!(session.principal(), "User:alice");
+
+        session.state = SaslState::Failed;
+        assert!(!session.may_send(18));
+    }
+}
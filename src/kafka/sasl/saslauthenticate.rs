@@ -0,0 +1,174 @@
+use crate::{
+    kafka::{
+        errors::{ILLEGAL_SASL_STATE, NONE, SASL_AUTHENTICATION_FAILED},
+        RequestContext,
+    },
+    types::{bytes::CompactBytes, cstring::CompactNullableString},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{
+    scram::{ClientFirst, ScramServer},
+    verify_plain, Mechanism, SaslState,
+};
+
+/// SaslAuthenticate v2.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct SaslAuthenticateRequest {
+    pub auth_bytes: CompactBytes,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct SaslAuthenticateResponse {
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub auth_bytes: CompactBytes,
+    /// 0 as sessions do not expire.
+    pub session_lifetime_ms: i64,
+    pub tagged_fields: u8,
+}
+
+impl SaslAuthenticateResponse {
+    fn new(error_code: i16, error_message: Option<&str>, auth_bytes: Vec<u8>) -> Self {
+        Self {
+            error_code,
+            error_message: CompactNullableString(error_message.map(str::to_string)),
+            auth_bytes: auth_bytes.into(),
+            session_lifetime_ms: 0,
+            tagged_fields: 0,
+        }
+    }
+}
+
+/// The user a PLAIN message ("authzid\0user\0password") authenticates if
+/// the password is right. Kafka has no authorization identity other than
+/// the user.
+fn parse_plain(message: &str) -> Option<(&str, &str)> {
+    let mut parts = message.splitn(3, '\0');
+    let (authzid, user, password) = (parts.next()?, parts.next()?, parts.next()?);
+    (!user.is_empty() && (authzid.is_empty() || authzid == user)).then_some((user, password))
+}
+
+impl SaslAuthenticateRequest {
+    /// Takes the next step of the exchange for the mechanism picked by the
+    /// handshake. A failed exchange leaves the connection unable to send
+    /// anything else.
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<SaslAuthenticateResponse, Error> {
+        let broker = ctx.broker.clone();
+        let session = ctx.session.clone();
+        let auth_bytes = self.auth_bytes.0.clone();
+
+        // Deriving keys from passwords takes a while.
+        let response = tokio::task::spawn_blocking(move || {
+            let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+            let message = String::from_utf8_lossy(&auth_bytes);
+
+            let step = match std::mem::take(&mut session.state) {
+                SaslState::Authenticate(Mechanism::Plain) => parse_plain(&message)
+                    .filter(|(user, password)| verify_plain(&broker.metadata, user, password))
+                    .map(|(user, _)| {
+                        session.authenticated(user.to_string());
+                        Vec::new()
+                    }),
+                SaslState::Authenticate(Mechanism::Scram(mechanism)) => {
+                    ClientFirst::parse(&message).map(|first| {
+                        let credential = broker
+                            .metadata
+                            .read(|image| {
+                                image
+                                    .scram_credential(&first.user, mechanism as i8)
+                                    .cloned()
+                            })
+                            .unwrap_or_else(|| mechanism.unknown_user_credential(&first.user));
+                        let (server, server_first) =
+                            ScramServer::start(mechanism, credential, first);
+                        session.state = SaslState::ScramFinal(Box::new(server));
+                        server_first.into_bytes()
+                    })
+                }
+                SaslState::ScramFinal(server) => server.finish(&message).map(|server_final| {
+                    session.authenticated(server.user().to_string());
+                    server_final.into_bytes()
+                }),
+                state => {
+                    session.state = state;
+                    return SaslAuthenticateResponse::new(
+                        ILLEGAL_SASL_STATE,
+                        Some("SaslAuthenticate is only expected after SaslHandshake"),
+                        Vec::new(),
+                    );
+                }
+            };
+
+            match step {
+                Some(auth_bytes) => SaslAuthenticateResponse::new(NONE, None, auth_bytes),
+                None => {
+                    session.state = SaslState::Failed;
+                    SaslAuthenticateResponse::new(
+                        SASL_AUTHENTICATION_FAILED,
+                        Some("Authentication failed: Invalid username or password"),
+                        Vec::new(),
+                    )
+                }
+            }
+        })
+        .await?;
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plain() {
+        assert_eq!(parse_plain("\0alice\0secret"), Some(("alice", "secret")));
+        assert_eq!(
+            parse_plain("alice\0alice\0se\0cret"),
+            Some(("alice", "se\0cret"))
+        );
+        assert_eq!(parse_plain("bob\0alice\0secret"), None);
+        assert_eq!(parse_plain("\0\0secret"), None);
+        assert_eq!(parse_plain("alice secret"), None);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_scram_users_fail_at_the_final_message() {
+        use crate::kafka::{broker::tests::TempBroker, sasl::scram::ScramMechanism};
+        use bytes::Bytes;
+
+        let test = TempBroker::open("sasl-unknown-user-test");
+        let ctx = test.context(36, 2);
+        ctx.session.lock().unwrap().state =
+            SaslState::Authenticate(Mechanism::Scram(ScramMechanism::Sha256));
+        let authenticate = |message: &'static str| SaslAuthenticateRequest {
+            auth_bytes: CompactBytes(Bytes::from_static(message.as_bytes())),
+            tagged_fields: 0,
+        };
+
+        let first = authenticate("n,,n=nobody,r=abc")
+            .handle_request(&ctx)
+            .await
+            .unwrap();
+        assert_eq!(first.error_code, NONE);
+        assert!(first.auth_bytes.starts_with(b"r=abc"));
+
+        let last = authenticate("c=biws,r=abc,p=cHJvb2Y=")
+            .handle_request(&ctx)
+            .await
+            .unwrap();
+        assert_eq!(last.error_code, SASL_AUTHENTICATION_FAILED);
+        assert!(matches!(
+            ctx.session.lock().unwrap().state,
+            SaslState::Failed
+        ));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{
+    kafka::{
+        errors::{ILLEGAL_SASL_STATE, NONE, UNSUPPORTED_SASL_MECHANISM},
+        RequestContext,
+    },
+    types::{array::Array32, kafkastring::String16},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{Mechanism, SaslState};
+
+/// SaslHandshake v1: the tokens follow in SaslAuthenticate requests.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct SaslHandshakeRequest {
+    pub mechanism: String16,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct SaslHandshakeResponse {
+    pub error_code: i16,
+    /// The mechanisms enabled on the broker.
+    pub mechanisms: Array32<String16>,
+}
+
+impl SaslHandshakeRequest {
+    /// Picks the mechanism the connection authenticates with, once.
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<SaslHandshakeResponse, Error> {
+        let enabled = &ctx.broker.config.sasl_enabled_mechanisms;
+        let mut session = ctx.session.lock().unwrap_or_else(|e| e.into_inner());
+
+        let error_code = match Mechanism::from_name(&self.mechanism) {
+            _ if !matches!(session.state, SaslState::Handshake) => ILLEGAL_SASL_STATE,
+            Some(mechanism) if enabled.contains(&self.mechanism.0) => {
+                session.state = SaslState::Authenticate(mechanism);
+                NONE
+            }
+            _ => UNSUPPORTED_SASL_MECHANISM,
+        };
+
+        Ok(SaslHandshakeResponse {
+            error_code,
+            mechanisms: Array32(enabled.iter().map(|name| name.as_str().into()).collect()),
+        })
+    }
+}
@@ -0,0 +1,351 @@
+//! SCRAM (RFC 5802) as Kafka uses it: the server side of the exchange, and
+//! the keys stored for each user in place of their password.
+
+use std::sync::OnceLock;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{kafka::log::scram_record::UserScramCredentialRecord, types::cstring::CompactString};
+
+/// Bounds Kafka puts on the iterations of a credential.
+pub const MIN_ITERATIONS: i32 = 4096;
+pub const MAX_ITERATIONS: i32 = 16384;
+
+/// The SCRAM mechanisms, numbered as in credential records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScramMechanism {
+    Sha256 = 1,
+    Sha512 = 2,
+}
+
+impl ScramMechanism {
+    pub const ALL: [Self; 2] = [Self::Sha256, Self::Sha512];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "SCRAM-SHA-256",
+            Self::Sha512 => "SCRAM-SHA-512",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mechanism| mechanism.name() == name)
+    }
+
+    pub fn from_id(id: i8) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|&mechanism| mechanism as i8 == id)
+    }
+
+    fn hash(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+            Self::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    fn hmac(self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("any key size");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Self::Sha512 => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("any key size");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    /// Hi() of the RFC: PBKDF2 with this mechanism's HMAC.
+    pub fn salted_password(self, password: &[u8], salt: &[u8], iterations: i32) -> Vec<u8> {
+        let rounds = iterations.max(1) as u32;
+        match self {
+            Self::Sha256 => {
+                let mut out = [0; 32];
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, rounds, &mut out);
+                out.to_vec()
+            }
+            Self::Sha512 => {
+                let mut out = [0; 64];
+                pbkdf2::pbkdf2_hmac::<Sha512>(password, salt, rounds, &mut out);
+                out.to_vec()
+            }
+        }
+    }
+
+    /// The stored and server keys derived from a salted password.
+    pub fn keys(self, salted_password: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let client_key = self.hmac(salted_password, b"Client Key");
+        let stored_key = self.hash(&client_key);
+        let server_key = self.hmac(salted_password, b"Server Key");
+        (stored_key, server_key)
+    }
+
+    /// The credential record of `user` for a salted password.
+    pub fn credential(
+        self,
+        user: &str,
+        salt: &[u8],
+        salted_password: &[u8],
+        iterations: i32,
+    ) -> UserScramCredentialRecord {
+        let (stored_key, server_key) = self.keys(salted_password);
+        UserScramCredentialRecord {
+            name: CompactString(user.to_string()),
+            mechanism: self as i8,
+            salt: Bytes::copy_from_slice(salt).into(),
+            stored_key: stored_key.into(),
+            server_key: server_key.into(),
+            iterations,
+            tagged_fields: 0,
+        }
+    }
+
+    /// A credential for a user without one, so that the exchange goes on as
+    /// for any user and fails only at the client's proof; failing at once
+    /// would tell which users exist. The salt stays the same across
+    /// attempts, as a real one would, and no password matches the keys.
+    pub fn unknown_user_credential(self, user: &str) -> UserScramCredentialRecord {
+        static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+        let secret = SECRET.get_or_init(|| random_bytes(32));
+        let salt = self.hmac(secret, user.as_bytes());
+        self.credential(user, &salt[..32], &random_bytes(32), MIN_ITERATIONS)
+    }
+
+    /// Whether `password` is the one `credential` was derived from.
+    pub fn verify_password(self, credential: &UserScramCredentialRecord, password: &str) -> bool {
+        let salted =
+            self.salted_password(password.as_bytes(), &credential.salt, credential.iterations);
+        self.keys(&salted).0 == credential.stored_key.0
+    }
+}
+
+/// Random bytes for salts and nonces.
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Decodes a saslname, in which `,` and `=` are escaped.
+fn decode_name(name: &str) -> Option<String> {
+    let decoded = name.replace("=2C", ",").replace("=3D", "=");
+    (!decoded.contains('=')).then_some(decoded)
+}
+
+/// The value of attribute `name` in a comma separated message.
+fn attribute(message: &str, name: char) -> Option<&str> {
+    message.split(',').find_map(|part| {
+        let mut chars = part.chars();
+        (chars.next() == Some(name) && chars.next() == Some('=')).then(|| &part[2..])
+    })
+}
+
+/// The client's first message, split into its GS2 header and the rest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientFirst {
+    pub gs2_header: String,
+    pub bare: String,
+    pub user: String,
+    pub nonce: String,
+}
+
+impl ClientFirst {
+    pub fn parse(message: &str) -> Option<Self> {
+        let mut parts = message.splitn(3, ',');
+        let binding = parts.next()?;
+        let authzid = parts.next()?;
+        let bare = parts.next()?;
+
+        // Channel binding is not supported, and Kafka has no authorization
+        // identity other than the user.
+        if !matches!(binding, "n" | "y") {
+            return None;
+        }
+        let user = decode_name(attribute(bare, 'n')?)?;
+        if !authzid.is_empty() && authzid.strip_prefix("a=").and_then(decode_name)? != user {
+            return None;
+        }
+
+        Some(Self {
+            gs2_header: format!("{binding},{authzid},"),
+            bare: bare.to_string(),
+            user,
+            nonce: attribute(bare, 'r')?.to_string(),
+        })
+    }
+}
+
+/// A SCRAM exchange waiting for the client's final message.
+#[derive(Debug, Clone)]
+pub struct ScramServer {
+    mechanism: ScramMechanism,
+    credential: UserScramCredentialRecord,
+    client_first: ClientFirst,
+    server_first: String,
+    nonce: String,
+}
+
+impl ScramServer {
+    /// Answers the client's first message with the server's, adding to the
+    /// client's nonce.
+    pub fn start(
+        mechanism: ScramMechanism,
+        credential: UserScramCredentialRecord,
+        client_first: ClientFirst,
+    ) -> (Self, String) {
+        let nonce = format!(
+            "{}{}",
+            client_first.nonce,
+            STANDARD.encode(random_bytes(18))
+        );
+        let server_first = format!(
+            "r={nonce},s={},i={}",
+            STANDARD.encode(&credential.salt.0),
+            credential.iterations
+        );
+
+        let server = Self {
+            mechanism,
+            credential,
+            client_first,
+            server_first: server_first.clone(),
+            nonce,
+        };
+        (server, server_first)
+    }
+
+    pub fn user(&self) -> &str {
+        &self.client_first.user
+    }
+
+    /// Checks the client's proof, returning the server's final message with
+    /// its own signature if the client knew the password.
+    pub fn finish(&self, client_final: &str) -> Option<String> {
+        let (without_proof, proof) = client_final.rsplit_once(",p=")?;
+        let proof = STANDARD.decode(proof).ok()?;
+
+        let binding = STANDARD.encode(&self.client_first.gs2_header);
+        if attribute(without_proof, 'c')? != binding || attribute(without_proof, 'r')? != self.nonce
+        {
+            return None;
+        }
+
+        let auth_message = format!(
+            "{},{},{without_proof}",
+            self.client_first.bare, self.server_first
+        );
+        let mechanism = self.mechanism;
+        let client_signature = mechanism.hmac(&self.credential.stored_key, auth_message.as_bytes());
+        if proof.len() != client_signature.len() {
+            return None;
+        }
+
+        let client_key: Vec<u8> = proof
+            .iter()
+            .zip(&client_signature)
+            .map(|(a, b)| a ^ b)
+            .collect();
+        if mechanism.hash(&client_key) != self.credential.stored_key.0 {
+            return None;
+        }
+
+        let server_signature = mechanism.hmac(&self.credential.server_key, auth_message.as_bytes());
+        Some(format!("v={}", STANDARD.encode(server_signature)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The client side of the exchange, as in RFC 5802.
+    fn client_final(
+        mechanism: ScramMechanism,
+        password: &str,
+        client_first_bare: &str,
+        server_first: &str,
+    ) -> (String, String) {
+        let nonce = attribute(server_first, 'r').unwrap();
+        let salt = STANDARD
+            .decode(attribute(server_first, 's').unwrap())
+            .unwrap();
+        let iterations = attribute(server_first, 'i').unwrap().parse().unwrap();
+
+        let salted = mechanism.salted_password(password.as_bytes(), &salt, iterations);
+        let client_key = mechanism.hmac(&salted, b"Client Key");
+        let (stored_key, server_key) = mechanism.keys(&salted);
+
+        let without_proof = format!("c=biws,r={nonce}");
+        let auth_message = format!("{client_first_bare},{server_first},{without_proof}");
+        let signature = mechanism.hmac(&stored_key, auth_message.as_bytes());
+        let proof: Vec<u8> = client_key
+            .iter()
+            .zip(&signature)
+            .map(|(a, b)| a ^ b)
+            .collect();
+        let server_signature = mechanism.hmac(&server_key, auth_message.as_bytes());
+
+        (
+            format!("{without_proof},p={}", STANDARD.encode(proof)),
+            format!("v={}", STANDARD.encode(server_signature)),
+        )
+    }
+
+    #[test]
+    fn test_scram_exchange() {
+        for mechanism in ScramMechanism::ALL {
+            let salt = random_bytes(16);
+            let salted = mechanism.salted_password(b"secret", &salt, MIN_ITERATIONS);
+            let credential = mechanism.credential("al,ice", &salt, &salted, MIN_ITERATIONS);
+            assert!(mechanism.verify_password(&credential, "secret"));
+            assert!(!mechanism.verify_password(&credential, "guess"));
+
+            let first = ClientFirst::parse("n,,n=al=2Cice,r=fyko+d2lbbFgONRv9qkxdawL").unwrap();
+            assert_eq!(first.user, "al,ice");
+            let (server, server_first) = ScramServer::start(mechanism, credential, first.clone());
+            assert!(server_first.starts_with("r=fyko+d2lbbFgONRv9qkxdawL"));
+
+            let (message, expected) = client_final(mechanism, "secret", &first.bare, &server_first);
+            assert_eq!(server.finish(&message), Some(expected));
+
+            let (wrong, _) = client_final(mechanism, "guess", &first.bare, &server_first);
+            assert_eq!(server.finish(&wrong), None);
+        }
+
+        assert_eq!(ClientFirst::parse("p=tls-unique,,n=alice,r=abc"), None);
+        assert_eq!(ClientFirst::parse("n,a=bob,n=alice,r=abc"), None);
+        assert!(ClientFirst::parse("n,a=alice,n=alice,r=abc").is_some());
+    }
+
+    #[test]
+    fn test_unknown_users_fail_only_at_the_proof() {
+        for mechanism in ScramMechanism::ALL {
+            let first = ClientFirst::parse("n,,n=nobody,r=abc").unwrap();
+            let credential = mechanism.unknown_user_credential("nobody");
+            let (server, server_first) = ScramServer::start(mechanism, credential, first.clone());
+            assert!(server_first.starts_with("r=abc"));
+            assert_eq!(attribute(&server_first, 'i'), Some("4096"));
+
+            // The salt is the same on every attempt, and differs by user.
+            let salt = attribute(&server_first, 's').unwrap();
+            let again = mechanism.unknown_user_credential("nobody");
+            assert_eq!(STANDARD.encode(&again.salt.0), salt);
+            let other = mechanism.unknown_user_credential("somebody");
+            assert_ne!(STANDARD.encode(&other.salt.0), salt);
+
+            let (message, _) = client_final(mechanism, "secret", &first.bare, &server_first);
+            assert_eq!(server.finish(&message), None);
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{
+            INVALID_PARTITIONS, INVALID_REPLICA_ASSIGNMENT, INVALID_REQUEST, NONE,
+            TOPIC_AUTHORIZATION_FAILED, UNKNOWN_SERVER_ERROR, UNKNOWN_TOPIC_OR_PARTITION,
+        },
+        log::RecordValue,
+        metadata::TopicImage,
+        RequestContext,
+    },
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        cstring::{CompactNullableString, CompactString},
+        uuid::UUID,
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+use super::{check_replicas, partition_record};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct CreatePartitionsAssignment {
+    pub broker_ids: CompactArray<i32>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct CreatePartitionsTopic {
+    pub name: CompactString,
+    /// The new partition count.
+    pub count: i32,
+    /// The replicas of each new partition, or null to let the broker pick.
+    pub assignments: CompactNullableArray<CreatePartitionsAssignment>,
+    pub tagged_fields: u8,
+}
+
+/// CreatePartitions v3.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct CreatePartitionsRequest {
+    pub topics: CompactArray<CreatePartitionsTopic>,
+    pub timeout_ms: i32,
+    pub validate_only: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct CreatePartitionsTopicResult {
+    pub name: CompactString,
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct CreatePartitionsResponse {
+    pub throttle_time_ms: i32,
+    pub results: CompactArray<CreatePartitionsTopicResult>,
+    pub tagged_fields: u8,
+}
+
+/// The partitions added to a topic, numbered from `first`.
+#[derive(Debug)]
+struct NewPartitions {
+    topic_id: UUID,
+    first: i32,
+    replicas: Vec<Vec<i32>>,
+}
+
+type Outcome = Result<NewPartitions, (i16, String)>;
+
+/// Works out the replicas of the partitions `request` adds to `topic`.
+fn plan(request: &CreatePartitionsTopic, topic: Option<&TopicImage>, node_id: i32) -> Outcome {
+    let topic = topic.ok_or_else(|| {
+        (
+            UNKNOWN_TOPIC_OR_PARTITION,
+            "This server does not host this topic-partition.".to_string(),
+        )
+    })?;
+
+    let existing = topic.partitions.len() as i32;
+    if request.count < existing {
+        return Err((
+            INVALID_PARTITIONS,
+            format!(
+                "The topic {} currently has {existing} partition(s); {} would not be an \
+                 increase.",
+                topic.name, request.count
+            ),
+        ));
+    }
+    if request.count == existing {
+        return Err((
+            INVALID_PARTITIONS,
+            format!("Topic already has {existing} partition(s)."),
+        ));
+    }
+
+    let added = (request.count - existing) as usize;
+    let replicas = match &request.assignments.0 {
+        None => vec![vec![node_id]; added],
+        Some(assignments) => {
+            if assignments.len() != added {
+                return Err((
+                    INVALID_REPLICA_ASSIGNMENT,
+                    format!(
+                        "Attempted to add {added} additional partition(s), but only {} \
+                         assignment(s) were specified.",
+                        assignments.len()
+                    ),
+                ));
+            }
+
+            let replication_factor = topic.partitions.values().next().map(|p| p.replicas.len());
+            assignments
+                .iter()
+                .enumerate()
+                .map(|(i, assignment)| {
+                    let brokers = &assignment.broker_ids.0;
+                    check_replicas(existing + i as i32, brokers, node_id)
+                        .map_err(|message| (INVALID_REPLICA_ASSIGNMENT, message))?;
+
+                    if let Some(factor) = replication_factor.filter(|&f| f != brokers.len()) {
+                        return Err((
+                            INVALID_REPLICA_ASSIGNMENT,
+                            format!(
+                                "The manual partition assignment includes a partition with {} \
+                                 replica(s), but this is not consistent with previous \
+                                 partitions, which have {factor} replica(s).",
+                                brokers.len()
+                            ),
+                        ));
+                    }
+
+                    Ok(brokers.clone())
+                })
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    Ok(NewPartitions {
+        topic_id: topic.id.clone(),
+        first: existing,
+        replicas,
+    })
+}
+
+fn partition_records(new: &NewPartitions) -> impl Iterator<Item = RecordValue> + '_ {
+    new.replicas.iter().enumerate().map(|(i, replicas)| {
+        RecordValue::Partition(partition_record(
+            &new.topic_id,
+            new.first + i as i32,
+            replicas.clone(),
+        ))
+    })
+}
+
+impl CreatePartitionsRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<CreatePartitionsResponse, Error> {
+        let ctx = ctx.clone();
+        let broker = ctx.broker.clone();
+        let topics = self.topics.0.clone();
+        let validate_only = self.validate_only != 0;
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for topic in &self.topics {
+            *counts.entry(&topic.name).or_default() += 1;
+        }
+        let duplicated: Vec<bool> = self
+            .topics
+            .iter()
+            .map(|t| counts[t.name.as_str()] > 1)
+            .collect();
+
+        let outcomes = tokio::task::spawn_blocking(move || {
+            broker.metadata.update_then(
+                |image| {
+                    let outcomes: Vec<Outcome> = topics
+                        .iter()
+                        .zip(&duplicated)
+                        .map(|(topic, &duplicated)| {
+                            if duplicated {
+                                return Err((INVALID_REQUEST, "Duplicate topic name.".to_string()));
+                            }
+                            if !ctx.authorized(
+                                image,
+                                operation::ALTER,
+                                resource::TOPIC,
+                                &topic.name,
+                            ) {
+                                return Err((
+                                    TOPIC_AUTHORIZATION_FAILED,
+                                    "Authorization failed.".to_string(),
+                                ));
+                            }
+                            plan(topic, image.topic(&topic.name), broker.config.node_id)
+                        })
+                        .collect();
+
+                    let records = if validate_only {
+                        vec![]
+                    } else {
+                        outcomes
+                            .iter()
+                            .flatten()
+                            .flat_map(partition_records)
+                            .collect()
+                    };
+
+                    (records, outcomes)
+                },
+                |_, outcomes| {
+                    for (topic, outcome) in topics.iter().zip(outcomes) {
+                        let Ok(new) = outcome else {
+                            continue;
+                        };
+                        for i in 0..new.replicas.len() as i32 {
+                            broker.logs.partition(&topic.name, new.first + i)?;
+                        }
+                    }
+                    Ok(())
+                },
+            )
+        })
+        .await?;
+
+        let outcomes = match outcomes {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                eprintln!("failed to create partitions: {e:?}");
+                self.topics
+                    .iter()
+                    .map(|_| Err((UNKNOWN_SERVER_ERROR, e.to_string())))
+                    .collect()
+            }
+        };
+
+        let results = self
+            .topics
+            .iter()
+            .zip(outcomes)
+            .map(|(topic, outcome)| {
+                let (error_code, message) =
+                    outcome.map_or_else(|e| (e.0, Some(e.1)), |_| (NONE, None));
+                CreatePartitionsTopicResult {
+                    name: topic.name.clone(),
+                    error_code,
+                    error_message: CompactNullableString(message),
+                    tagged_fields: 0,
+                }
+            })
+            .collect();
+
+        Ok(CreatePartitionsResponse {
+            throttle_time_ms: 0,
+            results: CompactArray(results),
+            tagged_fields: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn request(count: i32, assignments: Option<Vec<Vec<i32>>>) -> CreatePartitionsTopic {
+        CreatePartitionsTopic {
+            name: "t".into(),
+            count,
+            assignments: CompactNullableArray(assignments.map(|assignments| {
+                assignments
+                    .into_iter()
+                    .map(|brokers| CreatePartitionsAssignment {
+                        broker_ids: CompactArray(brokers),
+                        tagged_fields: 0,
+                    })
+                    .collect()
+            })),
+            tagged_fields: 0,
+        }
+    }
+
+    fn error(outcome: Outcome) -> i16 {
+        outcome.map_or_else(|(code, _)| code, |_| NONE)
+    }
+
+    #[test]
+    fn test_plan_grows_but_never_shrinks() {
+        let id = UUID([1; 16]);
+        let topic = TopicImage {
+            name: "t".to_string(),
+            id: id.clone(),
+            partitions: BTreeMap::from([
+                (0, partition_record(&id, 0, vec![1])),
+                (1, partition_record(&id, 1, vec![1])),
+            ]),
+        };
+
+        let new = plan(&request(4, None), Some(&topic), 1).unwrap();
+        assert_eq!((new.first, new.replicas.len()), (2, 2));
+        let manual = plan(&request(3, Some(vec![vec![1]])), Some(&topic), 1).unwrap();
+        assert_eq!(manual.replicas, vec![vec![1]]);
+
+        assert_eq!(
+            error(plan(&request(1, None), Some(&topic), 1)),
+            INVALID_PARTITIONS
+        );
+        assert_eq!(
+            error(plan(&request(2, None), Some(&topic), 1)),
+            INVALID_PARTITIONS
+        );
+        assert_eq!(
+            error(plan(&request(4, Some(vec![vec![1]])), Some(&topic), 1)),
+            INVALID_REPLICA_ASSIGNMENT
+        );
+        assert_eq!(
+            error(plan(&request(3, Some(vec![vec![2]])), Some(&topic), 1)),
+            INVALID_REPLICA_ASSIGNMENT
+        );
+        assert_eq!(
+            error(plan(&request(3, None), None, 1)),
+            UNKNOWN_TOPIC_OR_PARTITION
+        );
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    kafka::{
+        acl::{operation, resource, CLUSTER_NAME},
+        configs::{self, incrementalalterconfigs::changes, ConfigLayers, TOPIC_RESOURCE},
+        errors::{
+            INVALID_CONFIG, INVALID_PARTITIONS, INVALID_REPLICATION_FACTOR,
+            INVALID_REPLICA_ASSIGNMENT, INVALID_REQUEST, INVALID_TOPIC_EXCEPTION, NONE,
+            TOPIC_ALREADY_EXISTS, TOPIC_AUTHORIZATION_FAILED, UNKNOWN_SERVER_ERROR,
+        },
+        log::{topic_log::TopicRecord, RecordValue},
+        metadata::MetadataImage,
+        RequestContext,
+    },
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        cstring::{CompactNullableString, CompactString},
+        uuid::UUID,
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+use uuid::Uuid;
+
+use super::{check_replicas, collides, partition_record, validate_name};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct CreatableReplicaAssignment {
+    pub partition_index: i32,
+    pub broker_ids: CompactArray<i32>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct CreatableTopicConfig {
+    pub name: CompactString,
+    pub value: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct CreatableTopic {
+    pub name: CompactString,
+    /// -1 for the default, and when assignments are given.
+    pub num_partitions: i32,
+    /// -1 for the default, and when assignments are given.
+    pub replication_factor: i16,
+    pub assignments: CompactArray<CreatableReplicaAssignment>,
+    pub configs: CompactArray<CreatableTopicConfig>,
+    pub tagged_fields: u8,
+}
+
+/// CreateTopics v7.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct CreateTopicsRequest {
+    pub topics: CompactArray<CreatableTopic>,
+    pub timeout_ms: i32,
+    pub validate_only: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct CreatableTopicConfigs {
+    pub name: CompactString,
+    pub value: CompactNullableString,
+    pub read_only: u8,
+    pub config_source: i8,
+    pub is_sensitive: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct CreatableTopicResult {
+    pub name: CompactString,
+    pub topic_id: UUID,
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub num_partitions: i32,
+    pub replication_factor: i16,
+    pub configs: CompactNullableArray<CreatableTopicConfigs>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct CreateTopicsResponse {
+    pub throttle_time_ms: i32,
+    pub topics: CompactArray<CreatableTopicResult>,
+    pub tagged_fields: u8,
+}
+
+/// Partitions and replication factor used when a request leaves them at -1.
+const DEFAULT_PARTITIONS: i32 = 1;
+const DEFAULT_REPLICATION_FACTOR: i16 = 1;
+
+/// A topic that passed validation, with the replicas of each partition and
+/// the configs set on it.
+#[derive(Debug)]
+struct NewTopic {
+    id: UUID,
+    replicas: Vec<Vec<i32>>,
+    configs: BTreeMap<String, String>,
+    /// Every config of the topic as the response reports it.
+    described: Vec<CreatableTopicConfigs>,
+}
+
+type Outcome = Result<NewTopic, (i16, String)>;
+
+/// Works out the replicas of each partition of `topic`, all on this broker.
+fn plan(topic: &CreatableTopic, node_id: i32, image: &MetadataImage) -> Outcome {
+    let name = &topic.name.0;
+    validate_name(name).map_err(|message| (INVALID_TOPIC_EXCEPTION, message))?;
+
+    if image.topic(name).is_some() {
+        return Err((
+            TOPIC_ALREADY_EXISTS,
+            format!("Topic '{name}' already exists."),
+        ));
+    }
+    if let Some(other) = image.topics().find(|t| collides(name, &t.name)) {
+        return Err((
+            INVALID_TOPIC_EXCEPTION,
+            format!(
+                "Topic '{name}' collides with existing topic: {}",
+                other.name
+            ),
+        ));
+    }
+
+    let replicas = if topic.assignments.is_empty() {
+        let partitions = match topic.num_partitions {
+            -1 => DEFAULT_PARTITIONS,
+            n if n < 1 => {
+                return Err((
+                    INVALID_PARTITIONS,
+                    "Number of partitions must be larger than 0.".to_string(),
+                ))
+            }
+            n => n,
+        };
+        let replication_factor = match topic.replication_factor {
+            -1 => DEFAULT_REPLICATION_FACTOR,
+            n if n < 1 => {
+                return Err((
+                    INVALID_REPLICATION_FACTOR,
+                    "Replication factor must be larger than 0.".to_string(),
+                ))
+            }
+            n => n,
+        };
+        if replication_factor > 1 {
+            return Err((
+                INVALID_REPLICATION_FACTOR,
+                format!(
+                    "Unable to replicate the partition {replication_factor} time(s): The target \
+                     replication factor of {replication_factor} cannot be reached because only \
+                     1 broker(s) are registered."
+                ),
+            ));
+        }
+
+        vec![vec![node_id]; partitions as usize]
+    } else {
+        if topic.num_partitions != -1 || topic.replication_factor != -1 {
+            return Err((
+                INVALID_REQUEST,
+                "Both numPartitions or replicationFactor and replicasAssignments were set. Both \
+                 cannot be used at the same time."
+                    .to_string(),
+            ));
+        }
+
+        assignments(&topic.assignments, node_id)
+            .map_err(|message| (INVALID_REPLICA_ASSIGNMENT, message))?
+    };
+
+    let configs = topic_configs(&topic.configs)?;
+    let layers = ConfigLayers::topic(image, Some(&configs), node_id);
+    let described = configs::CONFIGS
+        .iter()
+        .map(|def| {
+            let effective = layers.synonyms(def).swap_remove(0);
+            CreatableTopicConfigs {
+                name: def.name.into(),
+                value: CompactNullableString(Some(effective.value)),
+                read_only: 0,
+                config_source: effective.source,
+                is_sensitive: 0,
+                tagged_fields: 0,
+            }
+        })
+        .collect();
+
+    Ok(NewTopic {
+        id: UUID(*Uuid::new_v4().as_bytes()),
+        replicas,
+        configs,
+        described,
+    })
+}
+
+/// Checks the configs a topic is created with.
+fn topic_configs(
+    configs: &[CreatableTopicConfig],
+) -> Result<BTreeMap<String, String>, (i16, String)> {
+    let mut values = BTreeMap::new();
+
+    for config in configs {
+        let name = &config.name.0;
+        let def = configs::find(TOPIC_RESOURCE, name)
+            .ok_or_else(|| (INVALID_CONFIG, format!("Unknown topic config name: {name}")))?;
+        let value = config.value.0.as_ref().ok_or_else(|| {
+            (
+                INVALID_REQUEST,
+                format!("Null value not supported for topic configs: {name}"),
+            )
+        })?;
+        def.validate(TOPIC_RESOURCE, value)
+            .map_err(|message| (INVALID_CONFIG, message))?;
+
+        if values.insert(name.clone(), value.clone()).is_some() {
+            return Err((
+                INVALID_REQUEST,
+                format!("Duplicate topic config name: {name}"),
+            ));
+        }
+    }
+
+    Ok(values)
+}
+
+/// Checks manual assignments, which must cover partitions 0 to n - 1 once
+/// each, with replicas on known brokers.
+fn assignments(
+    assignments: &[CreatableReplicaAssignment],
+    node_id: i32,
+) -> Result<Vec<Vec<i32>>, String> {
+    let mut sorted: Vec<&CreatableReplicaAssignment> = assignments.iter().collect();
+    sorted.sort_by_key(|a| a.partition_index);
+
+    sorted
+        .into_iter()
+        .enumerate()
+        .map(|(i, assignment)| {
+            if assignment.partition_index != i as i32 {
+                return Err("Partitions should be consecutive and start from 0".to_string());
+            }
+
+            check_replicas(i as i32, &assignment.broker_ids, node_id)?;
+            Ok(assignment.broker_ids.0.clone())
+        })
+        .collect()
+}
+
+fn topic_records(name: &str, topic: &NewTopic) -> Vec<RecordValue> {
+    let mut records = vec![RecordValue::Topic(TopicRecord {
+        name: name.into(),
+        id: topic.id.clone(),
+        tagged_fields: 0,
+    })];
+
+    records.extend(changes(
+        TOPIC_RESOURCE,
+        name,
+        &BTreeMap::new(),
+        &topic.configs,
+    ));
+    records.extend(topic.replicas.iter().enumerate().map(|(i, replicas)| {
+        RecordValue::Partition(partition_record(&topic.id, i as i32, replicas.clone()))
+    }));
+
+    records
+}
+
+impl CreateTopicsRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<CreateTopicsResponse, Error> {
+        let ctx = ctx.clone();
+        let broker = ctx.broker.clone();
+        let topics = self.topics.0.clone();
+        let validate_only = self.validate_only != 0;
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for topic in &self.topics {
+            *counts.entry(&topic.name).or_default() += 1;
+        }
+        let duplicated: Vec<bool> = self
+            .topics
+            .iter()
+            .map(|t| counts[t.name.as_str()] > 1)
+            .collect();
+
+        let outcomes = tokio::task::spawn_blocking(move || {
+            broker.metadata.update_then(
+                |image| {
+                    // Creating topics on the cluster allows creating any.
+                    let cluster_create =
+                        ctx.authorized(image, operation::CREATE, resource::CLUSTER, CLUSTER_NAME);
+                    let outcomes: Vec<Outcome> = topics
+                        .iter()
+                        .zip(&duplicated)
+                        .map(|(topic, &duplicated)| {
+                            if duplicated {
+                                return Err((
+                                    INVALID_REQUEST,
+                                    format!(
+                                        "Create topics request from client contains duplicate \
+                                         topic {}",
+                                        topic.name.0
+                                    ),
+                                ));
+                            }
+                            if !cluster_create
+                                && !ctx.authorized(
+                                    image,
+                                    operation::CREATE,
+                                    resource::TOPIC,
+                                    &topic.name,
+                                )
+                            {
+                                return Err((
+                                    TOPIC_AUTHORIZATION_FAILED,
+                                    "Authorization failed.".to_string(),
+                                ));
+                            }
+                            plan(topic, broker.config.node_id, image)
+                        })
+                        .collect();
+
+                    let records = if validate_only {
+                        vec![]
+                    } else {
+                        topics
+                            .iter()
+                            .zip(&outcomes)
+                            .filter_map(|(topic, outcome)| {
+                                Some(topic_records(&topic.name, outcome.as_ref().ok()?))
+                            })
+                            .flatten()
+                            .collect()
+                    };
+
+                    (records, outcomes)
+                },
+                |image, outcomes| {
+                    broker.sync_log_configs(image);
+                    for (topic, outcome) in topics.iter().zip(outcomes) {
+                        let Ok(new) = outcome else {
+                            continue;
+                        };
+                        for partition in 0..new.replicas.len() as i32 {
+                            broker.logs.partition(&topic.name, partition)?;
+                        }
+                    }
+                    Ok(())
+                },
+            )
+        })
+        .await?;
+
+        let outcomes = match outcomes {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                eprintln!("failed to create topics: {e:?}");
+                self.topics
+                    .iter()
+                    .map(|_| Err((UNKNOWN_SERVER_ERROR, e.to_string())))
+                    .collect()
+            }
+        };
+
+        let topics = self
+            .topics
+            .iter()
+            .zip(outcomes)
+            .map(|(topic, outcome)| match outcome {
+                Ok(new) => CreatableTopicResult {
+                    name: topic.name.clone(),
+                    // Nothing was allocated when only validating.
+                    topic_id: if validate_only { UUID([0; 16]) } else { new.id },
+                    error_code: NONE,
+                    error_message: CompactNullableString(None),
+                    num_partitions: new.replicas.len() as i32,
+                    replication_factor: new.replicas[0].len() as i16,
+                    configs: CompactNullableArray(Some(new.described)),
+                    tagged_fields: 0,
+                },
+                Err((error_code, message)) => CreatableTopicResult {
+                    name: topic.name.clone(),
+                    topic_id: UUID([0; 16]),
+                    error_code,
+                    error_message: CompactNullableString(Some(message)),
+                    num_partitions: -1,
+                    replication_factor: -1,
+                    configs: CompactNullableArray::null(),
+                    tagged_fields: 0,
+                },
+            })
+            .collect();
+
+        Ok(CreateTopicsResponse {
+            throttle_time_ms: 0,
+            topics: CompactArray(topics),
+            tagged_fields: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(name: &str, partitions: i32, replication_factor: i16) -> CreatableTopic {
+        CreatableTopic {
+            name: name.into(),
+            num_partitions: partitions,
+            replication_factor,
+            assignments: CompactArray::default(),
+            configs: CompactArray::default(),
+            tagged_fields: 0,
+        }
+    }
+
+    fn error(outcome: Outcome) -> i16 {
+        outcome.map_or_else(|(code, _)| code, |_| NONE)
+    }
+
+    #[test]
+    fn test_plan_validation() {
+        let image = MetadataImage::default();
+
+        let planned = plan(&topic("t", -1, -1), 1, &image).unwrap();
+        assert_eq!(planned.replicas, vec![vec![1]]);
+        assert_eq!(
+            plan(&topic("t", 3, 1), 1, &image).unwrap().replicas.len(),
+            3
+        );
+
+        assert_eq!(
+            error(plan(&topic("t", 0, 1), 1, &image)),
+            INVALID_PARTITIONS
+        );
+        assert_eq!(
+            error(plan(&topic("t", 1, 3), 1, &image)),
+            INVALID_REPLICATION_FACTOR
+        );
+        assert_eq!(
+            error(plan(&topic("a b", 1, 1), 1, &image)),
+            INVALID_TOPIC_EXCEPTION
+        );
+
+        let mut manual = topic("t", -1, -1);
+        manual.assignments = CompactArray(vec![
+            CreatableReplicaAssignment {
+                partition_index: 1,
+                broker_ids: CompactArray(vec![1]),
+                tagged_fields: 0,
+            },
+            CreatableReplicaAssignment {
+                partition_index: 0,
+                broker_ids: CompactArray(vec![1]),
+                tagged_fields: 0,
+            },
+        ]);
+        assert_eq!(plan(&manual, 1, &image).unwrap().replicas.len(), 2);
+        assert_eq!(error(plan(&manual, 2, &image)), INVALID_REPLICA_ASSIGNMENT);
+
+        manual.num_partitions = 2;
+        assert_eq!(error(plan(&manual, 1, &image)), INVALID_REQUEST);
+
+        let mut configured = topic("t", 1, 1);
+        configured.configs = CompactArray(vec![CreatableTopicConfig {
+            name: "retention.ms".into(),
+            value: CompactNullableString(Some("1000".to_string())),
+            tagged_fields: 0,
+        }]);
+        let planned = plan(&configured, 1, &image).unwrap();
+        let retention = planned
+            .described
+            .iter()
+            .find(|c| c.name.0 == "retention.ms")
+            .unwrap();
+        assert_eq!(retention.value.0.as_deref(), Some("1000"));
+        assert_eq!(retention.config_source, configs::TOPIC_CONFIG);
+
+        configured.configs[0].value = CompactNullableString(Some("-5".to_string()));
+        assert_eq!(error(plan(&configured, 1, &image)), INVALID_CONFIG);
+        configured.configs[0].name = "retention.hours".into();
+        assert_eq!(error(plan(&configured, 1, &image)), INVALID_CONFIG);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{
+            NONE, OFFSET_OUT_OF_RANGE, TOPIC_AUTHORIZATION_FAILED, UNKNOWN_SERVER_ERROR,
+            UNKNOWN_TOPIC_OR_PARTITION,
+        },
+        RequestContext,
+    },
+    types::{array::CompactArray, cstring::CompactString},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct DeleteRecordsPartition {
+    pub partition_index: i32,
+    /// Records before this offset are deleted; -1 for the high watermark.
+    pub delete_before: i64,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct DeleteRecordsTopic {
+    pub name: CompactString,
+    pub partitions: CompactArray<DeleteRecordsPartition>,
+    pub tagged_fields: u8,
+}
+
+/// DeleteRecords v2.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteRecordsRequest {
+    pub topics: CompactArray<DeleteRecordsTopic>,
+    pub timeout_ms: i32,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteRecordsPartitionResult {
+    pub partition_index: i32,
+    /// The log start offset once the records are deleted.
+    pub low_watermark: i64,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteRecordsTopicResult {
+    pub name: CompactString,
+    pub partitions: CompactArray<DeleteRecordsPartitionResult>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteRecordsResponse {
+    pub throttle_time_ms: i32,
+    pub topics: CompactArray<DeleteRecordsTopicResult>,
+    pub tagged_fields: u8,
+}
+
+impl DeleteRecordsRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<DeleteRecordsResponse, Error> {
+        let ctx = ctx.clone();
+        let broker = ctx.broker.clone();
+        let topics = self.topics.0.clone();
+
+        let topics = tokio::task::spawn_blocking(move || {
+            // The image stays locked so that the topic cannot be deleted
+            // meanwhile.
+            broker.metadata.read(|image| {
+                topics
+                    .iter()
+                    .map(|topic| {
+                        let known = image.topic(&topic.name);
+                        let authorized =
+                            ctx.authorized(image, operation::DELETE, resource::TOPIC, &topic.name);
+                        let partitions = topic
+                            .partitions
+                            .iter()
+                            .map(|partition| {
+                                let index = partition.partition_index;
+                                let result =
+                                    |low_watermark, error_code| DeleteRecordsPartitionResult {
+                                        partition_index: index,
+                                        low_watermark,
+                                        error_code,
+                                        tagged_fields: 0,
+                                    };
+
+                                if !authorized {
+                                    return result(-1, TOPIC_AUTHORIZATION_FAILED);
+                                }
+                                if !known.is_some_and(|t| t.partitions.contains_key(&index)) {
+                                    return result(-1, UNKNOWN_TOPIC_OR_PARTITION);
+                                }
+
+                                match broker.logs.delete_records(
+                                    &topic.name,
+                                    index,
+                                    partition.delete_before,
+                                ) {
+                                    Ok(Some(low_watermark)) => result(low_watermark, NONE),
+                                    Ok(None) => result(-1, OFFSET_OUT_OF_RANGE),
+                                    Err(e) => {
+                                        eprintln!(
+                                            "failed to delete records of {}-{index}: {e:?}",
+                                            topic.name.0
+                                        );
+                                        result(-1, UNKNOWN_SERVER_ERROR)
+                                    }
+                                }
+                            })
+                            .collect();
+
+                        DeleteRecordsTopicResult {
+                            name: topic.name.clone(),
+                            partitions: CompactArray(partitions),
+                            tagged_fields: 0,
+                        }
+                    })
+                    .collect()
+            })
+        })
+        .await?;
+
+        Ok(DeleteRecordsResponse {
+            throttle_time_ms: 0,
+            topics: CompactArray(topics),
+            tagged_fields: 0,
+        })
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{
+            INVALID_REQUEST, NONE, TOPIC_AUTHORIZATION_FAILED, UNKNOWN_SERVER_ERROR,
+            UNKNOWN_TOPIC_ID, UNKNOWN_TOPIC_OR_PARTITION,
+        },
+        log::{partition::remove_dirs, topic_log::RemoveTopicRecord, RecordValue},
+        metadata::{MetadataImage, TopicImage},
+        RequestContext,
+    },
+    types::{array::CompactArray, cstring::CompactNullableString, uuid::UUID},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct DeleteTopicState {
+    /// Null when the topic is given by id.
+    pub name: CompactNullableString,
+    /// All zeros when the topic is given by name.
+    pub topic_id: UUID,
+    pub tagged_fields: u8,
+}
+
+/// DeleteTopics v6.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteTopicsRequest {
+    pub topics: CompactArray<DeleteTopicState>,
+    pub timeout_ms: i32,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeletableTopicResult {
+    pub name: CompactNullableString,
+    pub topic_id: UUID,
+    pub error_code: i16,
+    pub error_message: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct DeleteTopicsResponse {
+    pub throttle_time_ms: i32,
+    pub responses: CompactArray<DeletableTopicResult>,
+    pub tagged_fields: u8,
+}
+
+type Outcome = Result<TopicImage, (i16, String)>;
+
+/// Finds the topic `state` names, either by name or by id.
+fn resolve(state: &DeleteTopicState, image: &MetadataImage) -> Outcome {
+    let by_id = state.topic_id != UUID([0; 16]);
+
+    match &state.name.0 {
+        Some(_) if by_id => Err((
+            INVALID_REQUEST,
+            "You may not specify both topic name and topic id.".to_string(),
+        )),
+        Some(name) => image.topic(name).cloned().ok_or_else(|| {
+            (
+                UNKNOWN_TOPIC_OR_PARTITION,
+                "This server does not host this topic-partition.".to_string(),
+            )
+        }),
+        None if by_id => image.topic_by_id(&state.topic_id).cloned().ok_or_else(|| {
+            (
+                UNKNOWN_TOPIC_ID,
+                "This server does not host this topic ID.".to_string(),
+            )
+        }),
+        None => Err((
+            INVALID_REQUEST,
+            "Neither topic name nor id were specified.".to_string(),
+        )),
+    }
+}
+
+/// Resolves every topic of a request. A topic named more than once, by
+/// name or by id, is not deleted.
+fn resolve_all(topics: &[DeleteTopicState], image: &MetadataImage) -> Vec<Outcome> {
+    let mut outcomes: Vec<Outcome> = topics.iter().map(|t| resolve(t, image)).collect();
+
+    let mut counts: HashMap<UUID, usize> = HashMap::new();
+    for topic in outcomes.iter().flatten() {
+        *counts.entry(topic.id.clone()).or_default() += 1;
+    }
+
+    for outcome in &mut outcomes {
+        if let Ok(topic) = outcome {
+            if counts[&topic.id] > 1 {
+                *outcome = Err((INVALID_REQUEST, "Duplicate topic name or id.".to_string()));
+            }
+        }
+    }
+
+    outcomes
+}
+
+impl DeleteTopicsRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<DeleteTopicsResponse, Error> {
+        let ctx = ctx.clone();
+        let broker = ctx.broker.clone();
+        let topics = self.topics.0.clone();
+
+        let outcomes = tokio::task::spawn_blocking(move || {
+            let mut renamed = Vec::new();
+
+            let outcomes = broker.metadata.update_then(
+                |image| {
+                    let outcomes: Vec<Outcome> = resolve_all(&topics, image)
+                        .into_iter()
+                        .map(|outcome| match outcome {
+                            Ok(topic)
+                                if !ctx.authorized(
+                                    image,
+                                    operation::DELETE,
+                                    resource::TOPIC,
+                                    &topic.name,
+                                ) =>
+                            {
+                                Err((
+                                    TOPIC_AUTHORIZATION_FAILED,
+                                    "Authorization failed.".to_string(),
+                                ))
+                            }
+                            outcome => outcome,
+                        })
+                        .collect();
+                    let records = outcomes
+                        .iter()
+                        .flatten()
+                        .map(|topic| {
+                            RecordValue::RemoveTopic(RemoveTopicRecord {
+                                topic_id: topic.id.clone(),
+                                tagged_fields: 0,
+                            })
+                        })
+                        .collect();
+
+                    (records, outcomes)
+                },
+                |_, outcomes| {
+                    for topic in outcomes.iter().flatten() {
+                        renamed.extend(broker.logs.rename_deleted(
+                            &topic.name,
+                            &topic.id,
+                            topic.partitions.keys().copied(),
+                        )?);
+                    }
+                    Ok(())
+                },
+            );
+
+            // The logs are removed once the response is on its way.
+            if !renamed.is_empty() {
+                tokio::task::spawn_blocking(move || remove_dirs(renamed));
+            }
+
+            outcomes
+        })
+        .await?;
+
+        let outcomes = match outcomes {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                eprintln!("failed to delete topics: {e:?}");
+                self.topics
+                    .iter()
+                    .map(|_| Err((UNKNOWN_SERVER_ERROR, e.to_string())))
+                    .collect()
+            }
+        };
+
+        let responses = self
+            .topics
+            .iter()
+            .zip(outcomes)
+            .map(|(state, outcome)| match outcome {
+                Ok(topic) => DeletableTopicResult {
+                    name: CompactNullableString(Some(topic.name)),
+                    topic_id: topic.id,
+                    error_code: NONE,
+                    error_message: CompactNullableString(None),
+                    tagged_fields: 0,
+                },
+                Err((error_code, message)) => DeletableTopicResult {
+                    name: state.name.clone(),
+                    topic_id: state.topic_id.clone(),
+                    error_code,
+                    error_message: CompactNullableString(Some(message)),
+                    tagged_fields: 0,
+                },
+            })
+            .collect();
+
+        Ok(DeleteTopicsResponse {
+            throttle_time_ms: 0,
+            responses: CompactArray(responses),
+            tagged_fields: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(name: Option<&str>, id: u8) -> DeleteTopicState {
+        DeleteTopicState {
+            name: CompactNullableString(name.map(str::to_string)),
+            topic_id: UUID([id; 16]),
+            tagged_fields: 0,
+        }
+    }
+
+    fn error(outcome: &Outcome) -> i16 {
+        outcome.as_ref().map_or_else(|(code, _)| *code, |_| NONE)
+    }
+
+    #[test]
+    fn test_resolve_by_name_or_id() {
+        let image = MetadataImage::default();
+
+        assert_eq!(
+            error(&resolve(&state(Some("t"), 0), &image)),
+            UNKNOWN_TOPIC_OR_PARTITION
+        );
+        assert_eq!(error(&resolve(&state(None, 1), &image)), UNKNOWN_TOPIC_ID);
+        assert_eq!(
+            error(&resolve(&state(Some("t"), 1), &image)),
+            INVALID_REQUEST
+        );
+        assert_eq!(error(&resolve(&state(None, 0), &image)), INVALID_REQUEST);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{
+    kafka::{
+        acl::{operation, operation_bits, resource, OPERATIONS_NOT_REQUESTED},
+        errors::{NONE, TOPIC_AUTHORIZATION_FAILED, UNKNOWN_TOPIC_ID, UNKNOWN_TOPIC_OR_PARTITION},
+        metadata::TopicImage,
+        RequestContext,
+    },
+    types::{
+        array::{CompactArray, CompactNullableArray},
+        cstring::{CompactNullableString, CompactString},
+        uuid::UUID,
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+/// A topic asked for by name or, if the name is null, by id.
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct MetadataRequestTopic {
+    pub topic_id: UUID,
+    pub name: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+/// Metadata v12.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct MetadataRequest {
+    /// Null for every topic.
+    pub topics: CompactNullableArray<MetadataRequestTopic>,
+    /// Ignored: topics are only created by CreateTopics.
+    pub allow_auto_topic_creation: u8,
+    pub include_topic_authorized_operations: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct MetadataResponseBroker {
+    pub node_id: i32,
+    pub host: CompactString,
+    pub port: i32,
+    pub rack: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct MetadataResponsePartition {
+    pub error_code: i16,
+    pub partition_index: i32,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub replica_nodes: CompactArray<i32>,
+    pub isr_nodes: CompactArray<i32>,
+    pub offline_replicas: CompactArray<i32>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct MetadataResponseTopic {
+    pub error_code: i16,
+    pub name: CompactNullableString,
+    pub topic_id: UUID,
+    pub is_internal: u8,
+    pub partitions: CompactArray<MetadataResponsePartition>,
+    pub topic_authorized_operations: i32,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct MetadataResponse {
+    pub throttle_time_ms: i32,
+    pub brokers: CompactArray<MetadataResponseBroker>,
+    pub cluster_id: CompactNullableString,
+    pub controller_id: i32,
+    pub topics: CompactArray<MetadataResponseTopic>,
+    pub tagged_fields: u8,
+}
+
+impl MetadataResponseTopic {
+    fn new(topic: &TopicImage, topic_authorized_operations: i32) -> Self {
+        let partitions = topic
+            .partitions
+            .values()
+            .map(|partition| MetadataResponsePartition {
+                error_code: NONE,
+                partition_index: partition.id,
+                leader_id: partition.leader,
+                leader_epoch: partition.leader_epoch,
+                replica_nodes: partition.replicas.clone(),
+                isr_nodes: partition.sync_replicas.clone(),
+                offline_replicas: CompactArray(vec![]),
+                tagged_fields: 0,
+            })
+            .collect();
+
+        Self {
+            error_code: NONE,
+            name: CompactNullableString(Some(topic.name.clone())),
+            topic_id: topic.id.clone(),
+            is_internal: 0,
+            partitions: CompactArray(partitions),
+            topic_authorized_operations,
+            tagged_fields: 0,
+        }
+    }
+
+    fn error(requested: &MetadataRequestTopic, error_code: i16) -> Self {
+        Self {
+            error_code,
+            name: requested.name.clone(),
+            topic_id: requested.topic_id.clone(),
+            is_internal: 0,
+            partitions: CompactArray(vec![]),
+            topic_authorized_operations: OPERATIONS_NOT_REQUESTED,
+            tagged_fields: 0,
+        }
+    }
+}
+
+impl MetadataRequest {
+    /// Describes the topics asked for, or every topic the client may
+    /// describe, and this broker as the only one of the cluster.
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<MetadataResponse, Error> {
+        let config = &ctx.broker.config;
+        let topics = ctx.broker.metadata.read(|image| match &self.topics.0 {
+            None => image
+                .topics()
+                .filter_map(|topic| {
+                    let ops = ctx.authorized_operations(image, resource::TOPIC, &topic.name);
+                    describable(ops).then(|| MetadataResponseTopic::new(topic, self.reported(ops)))
+                })
+                .collect(),
+            Some(requested) => requested
+                .iter()
+                .map(|requested| {
+                    let known = match &requested.name.0 {
+                        Some(name) => image.topic(name).ok_or(UNKNOWN_TOPIC_OR_PARTITION),
+                        None => image
+                            .topic_by_id(&requested.topic_id)
+                            .ok_or(UNKNOWN_TOPIC_ID),
+                    };
+                    // Whether a topic exists is only told to clients allowed
+                    // to describe it.
+                    let name = known
+                        .map(|topic| topic.name.as_str())
+                        .ok()
+                        .or(requested.name.0.as_deref());
+                    let ops = name.map_or(0, |name| {
+                        ctx.authorized_operations(image, resource::TOPIC, name)
+                    });
+                    match known {
+                        // An unknown id names no topic to be kept secret.
+                        Err(error_code) if name.is_none() => {
+                            MetadataResponseTopic::error(requested, error_code)
+                        }
+                        _ if !describable(ops) => {
+                            MetadataResponseTopic::error(requested, TOPIC_AUTHORIZATION_FAILED)
+                        }
+                        Ok(topic) => MetadataResponseTopic::new(topic, self.reported(ops)),
+                        Err(error_code) => MetadataResponseTopic::error(requested, error_code),
+                    }
+                })
+                .collect(),
+        });
+
+        Ok(MetadataResponse {
+            throttle_time_ms: 0,
+            brokers: CompactArray(vec![MetadataResponseBroker {
+                node_id: config.node_id,
+                host: config.host.as_str().into(),
+                port: config.port,
+                rack: CompactNullableString(None),
+                tagged_fields: 0,
+            }]),
+            cluster_id: CompactNullableString(None),
+            controller_id: config.node_id,
+            topics: CompactArray(topics),
+            tagged_fields: 0,
+        })
+    }
+
+    /// The operations told to the client, only if it asked for them.
+    fn reported(&self, ops: i32) -> i32 {
+        if self.include_topic_authorized_operations != 0 {
+            ops
+        } else {
+            OPERATIONS_NOT_REQUESTED
+        }
+    }
+}
+
+fn describable(ops: i32) -> bool {
+    ops & operation_bits([operation::DESCRIBE]) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kafka::broker::tests::TempBroker;
+
+    fn by_name(name: &str) -> MetadataRequestTopic {
+        MetadataRequestTopic {
+            topic_id: UUID([0; 16]),
+            name: CompactNullableString(Some(name.to_string())),
+            tagged_fields: 0,
+        }
+    }
+
+    fn request(topics: Option<Vec<MetadataRequestTopic>>) -> MetadataRequest {
+        MetadataRequest {
+            topics: CompactNullableArray(topics),
+            allow_auto_topic_creation: 0,
+            include_topic_authorized_operations: 0,
+            tagged_fields: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metadata_lists_every_topic_or_those_asked_for() {
+        let test = TempBroker::open("metadata-test");
+        let first = test.create_topic("first", 2);
+        test.create_topic("second", 1);
+        let ctx = test.context(3, 12);
+
+        let response = request(None).handle_request(&ctx).await.unwrap();
+        assert_eq!(response.brokers.len(), 1);
+        assert_eq!(response.brokers[0].node_id, response.controller_id);
+        let mut topics: Vec<(String, usize)> = response
+            .topics
+            .iter()
+            .map(|t| (t.name.0.clone().unwrap(), t.partitions.len()))
+            .collect();
+        topics.sort();
+        assert_eq!(
+            topics,
+            [("first".to_string(), 2), ("second".to_string(), 1)]
+        );
+
+        let unknown_id = MetadataRequestTopic {
+            topic_id: UUID([9; 16]),
+            name: CompactNullableString(None),
+            tagged_fields: 0,
+        };
+        let known_id = MetadataRequestTopic {
+            topic_id: first.clone(),
+            ..unknown_id.clone()
+        };
+        let mut asked = request(Some(vec![
+            by_name("first"),
+            by_name("missing"),
+            known_id,
+            unknown_id,
+        ]));
+        let response = asked.handle_request(&ctx).await.unwrap();
+        let topics = &response.topics;
+        assert_eq!(topics.len(), 4);
+        assert_eq!((topics[0].error_code, &topics[0].topic_id), (NONE, &first));
+        assert_eq!(topics[0].partitions[1].partition_index, 1);
+        assert_eq!(
+            topics[0].topic_authorized_operations,
+            OPERATIONS_NOT_REQUESTED
+        );
+        assert_eq!(topics[1].error_code, UNKNOWN_TOPIC_OR_PARTITION);
+        assert_eq!(topics[2].name.0.as_deref(), Some("first"));
+        assert_eq!(topics[3].error_code, UNKNOWN_TOPIC_ID);
+
+        // The operations are only reported when asked for.
+        asked.include_topic_authorized_operations = 1;
+        let response = asked.handle_request(&ctx).await.unwrap();
+        assert!(describable(response.topics[0].topic_authorized_operations));
+    }
+}
@@ -0,0 +1,101 @@
+//! Topic administration: creating and deleting topics and partitions in the
+//! metadata log, and deleting records from partition logs.
+
+use crate::{
+    kafka::log::partition_record::PartitionRecord,
+    types::{array::CompactArray, uuid::UUID},
+};
+
+pub mod createpartitions;
+pub mod createtopics;
+pub mod deleterecords;
+pub mod deletetopics;
+pub mod metadata;
+
+/// Longest topic name, leaving room for the partition suffix of its
+/// directories.
+const MAX_NAME_LENGTH: usize = 249;
+
+/// Checks a topic name, returning why it is invalid.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Topic name is illegal, it can't be empty".to_string());
+    }
+    if name == "." || name == ".." {
+        return Err("Topic name cannot be \".\" or \"..\"".to_string());
+    }
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "Topic name is illegal, it can't be longer than {MAX_NAME_LENGTH} characters"
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return Err(format!(
+            "Topic name \"{name}\" is illegal, it contains a character other than ASCII \
+             alphanumerics, '.', '_' and '-'"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Topic names that would share metric names, since '.' and '_' are
+/// interchangeable there.
+pub fn collides(a: &str, b: &str) -> bool {
+    a != b && a.replace('.', "_") == b.replace('.', "_")
+}
+
+/// Checks the replicas manually assigned to `partition`: this broker is the
+/// only one registered.
+pub fn check_replicas(partition: i32, brokers: &[i32], node_id: i32) -> Result<(), String> {
+    if brokers.is_empty() {
+        return Err(format!("Partition {partition} has no replicas"));
+    }
+    if brokers.len() > 1 {
+        return Err(format!(
+            "Partition {partition} has duplicate or unknown replicas"
+        ));
+    }
+    if let Some(unknown) = brokers.iter().find(|&&b| b != node_id) {
+        return Err(format!("Broker {unknown} is not registered"));
+    }
+
+    Ok(())
+}
+
+/// A new partition led by the first of its replicas.
+pub fn partition_record(topic_id: &UUID, partition: i32, replicas: Vec<i32>) -> PartitionRecord {
+    PartitionRecord {
+        id: partition,
+        topic_id: topic_id.clone(),
+        // Every replica's log directory is unassigned.
+        directories: CompactArray(vec![UUID([0; 16]); replicas.len()]),
+        leader: replicas[0],
+        sync_replicas: CompactArray(replicas.clone()),
+        replicas: CompactArray(replicas),
+        removing_replicas: CompactArray(vec![]),
+        adding_replicas: CompactArray(vec![]),
+        leader_epoch: 0,
+        partition_epoch: 0,
+        tagged_fields: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("orders.v1_-").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("..").is_err());
+        assert!(validate_name("a/b").is_err());
+        assert!(validate_name(&"a".repeat(250)).is_err());
+        assert!(collides("a.b", "a_b"));
+        assert!(!collides("a.b", "a.b"));
+    }
+}
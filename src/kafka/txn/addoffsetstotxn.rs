@@ -0,0 +1,79 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{GROUP_AUTHORIZATION_FAILED, NONE, TRANSACTIONAL_ID_AUTHORIZATION_FAILED},
+        group::offsets::OFFSETS_TOPIC,
+        RequestContext,
+    },
+    types::cstring::CompactString,
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+/// AddOffsetsToTxn v3.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AddOffsetsToTxnRequest {
+    pub transactional_id: CompactString,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub group_id: CompactString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AddOffsetsToTxnResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+impl AddOffsetsToTxnRequest {
+    /// Adds the offsets topic to the transaction, so that the offsets the
+    /// producer then commits with TxnOffsetCommit are part of it. Every
+    /// group commits to the same single partition.
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<AddOffsetsToTxnResponse, Error> {
+        let denied = if !ctx.authorize(
+            operation::WRITE,
+            resource::TRANSACTIONAL_ID,
+            &self.transactional_id,
+        ) {
+            Some(TRANSACTIONAL_ID_AUTHORIZATION_FAILED)
+        } else if !ctx.authorize(operation::READ, resource::GROUP, &self.group_id) {
+            Some(GROUP_AUTHORIZATION_FAILED)
+        } else {
+            None
+        };
+        if let Some(error_code) = denied {
+            return Ok(AddOffsetsToTxnResponse {
+                throttle_time_ms: 0,
+                error_code,
+                tagged_fields: 0,
+            });
+        }
+
+        let broker = ctx.broker.clone();
+        let transactional_id = self.transactional_id.0.clone();
+        let producer_id = self.producer_id;
+        let producer_epoch = self.producer_epoch;
+
+        let result = tokio::task::spawn_blocking(move || {
+            broker.transactions.add_partitions(
+                &transactional_id,
+                producer_id,
+                producer_epoch,
+                [(OFFSETS_TOPIC.to_string(), 0)],
+            )
+        })
+        .await?;
+
+        Ok(AddOffsetsToTxnResponse {
+            throttle_time_ms: 0,
+            error_code: result.err().unwrap_or(NONE),
+            tagged_fields: 0,
+        })
+    }
+}
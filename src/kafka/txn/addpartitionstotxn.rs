@@ -0,0 +1,147 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{
+            NONE, OPERATION_NOT_ATTEMPTED, TOPIC_AUTHORIZATION_FAILED,
+            TRANSACTIONAL_ID_AUTHORIZATION_FAILED, UNKNOWN_TOPIC_OR_PARTITION,
+        },
+        RequestContext,
+    },
+    types::{array::CompactArray, cstring::CompactString},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct AddPartitionsToTxnTopic {
+    pub name: CompactString,
+    pub partitions: CompactArray<i32>,
+    pub tagged_fields: u8,
+}
+
+/// AddPartitionsToTxn v3, sent by producers.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AddPartitionsToTxnRequest {
+    pub transactional_id: CompactString,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub topics: CompactArray<AddPartitionsToTxnTopic>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AddPartitionsToTxnPartitionResult {
+    pub partition_index: i32,
+    pub partition_error_code: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AddPartitionsToTxnTopicResult {
+    pub name: CompactString,
+    pub results_by_partition: CompactArray<AddPartitionsToTxnPartitionResult>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct AddPartitionsToTxnResponse {
+    pub throttle_time_ms: i32,
+    pub results: CompactArray<AddPartitionsToTxnTopicResult>,
+    pub tagged_fields: u8,
+}
+
+impl AddPartitionsToTxnRequest {
+    /// Adds every partition or none: if one is unknown, the others are not
+    /// attempted.
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<AddPartitionsToTxnResponse, Error> {
+        let transaction_authorized = ctx.authorize(
+            operation::WRITE,
+            resource::TRANSACTIONAL_ID,
+            &self.transactional_id,
+        );
+        let denied = ctx.denied_topics(
+            operation::WRITE,
+            self.topics.iter().map(|topic| topic.name.as_str()),
+        );
+        let broker = ctx.broker.clone();
+        let transactional_id = self.transactional_id.0.clone();
+        let producer_id = self.producer_id;
+        let producer_epoch = self.producer_epoch;
+        let topics = self.topics.0.clone();
+
+        let results = tokio::task::spawn_blocking(move || {
+            let unknown: Vec<(String, i32)> = broker.metadata.read(|image| {
+                topics
+                    .iter()
+                    .flat_map(|topic| {
+                        let known = image.topic(&topic.name);
+                        topic
+                            .partitions
+                            .iter()
+                            .filter(move |index| {
+                                !known.is_some_and(|t| t.partitions.contains_key(index))
+                            })
+                            .map(|&index| (topic.name.0.clone(), index))
+                    })
+                    .collect()
+            });
+
+            let error = if !transaction_authorized {
+                TRANSACTIONAL_ID_AUTHORIZATION_FAILED
+            } else if unknown.is_empty() && denied.is_empty() {
+                let partitions = topics.iter().flat_map(|topic| {
+                    topic
+                        .partitions
+                        .iter()
+                        .map(|&index| (topic.name.0.clone(), index))
+                });
+                broker
+                    .transactions
+                    .add_partitions(&transactional_id, producer_id, producer_epoch, partitions)
+                    .err()
+                    .unwrap_or(NONE)
+            } else {
+                OPERATION_NOT_ATTEMPTED
+            };
+
+            topics
+                .iter()
+                .map(|topic| AddPartitionsToTxnTopicResult {
+                    name: topic.name.clone(),
+                    results_by_partition: CompactArray(
+                        topic
+                            .partitions
+                            .iter()
+                            .map(|&partition_index| AddPartitionsToTxnPartitionResult {
+                                partition_index,
+                                partition_error_code: if !transaction_authorized {
+                                    error
+                                } else if denied.contains(&topic.name.0) {
+                                    TOPIC_AUTHORIZATION_FAILED
+                                } else if unknown.contains(&(topic.name.0.clone(), partition_index))
+                                {
+                                    UNKNOWN_TOPIC_OR_PARTITION
+                                } else {
+                                    error
+                                },
+                                tagged_fields: 0,
+                            })
+                            .collect(),
+                    ),
+                    tagged_fields: 0,
+                })
+                .collect()
+        })
+        .await?;
+
+        Ok(AddPartitionsToTxnResponse {
+            throttle_time_ms: 0,
+            results: CompactArray(results),
+            tagged_fields: 0,
+        })
+    }
+}
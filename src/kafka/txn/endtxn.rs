@@ -0,0 +1,70 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{NONE, TRANSACTIONAL_ID_AUTHORIZATION_FAILED},
+        RequestContext,
+    },
+    types::cstring::CompactString,
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+/// EndTxn v3.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct EndTxnRequest {
+    pub transactional_id: CompactString,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    /// 1 to commit the transaction, 0 to abort it.
+    pub committed: u8,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct EndTxnResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+impl EndTxnRequest {
+    /// Answers once the markers are written to every partition of the
+    /// transaction.
+    pub async fn handle_request(&self, ctx: &RequestContext) -> Result<EndTxnResponse, Error> {
+        if !ctx.authorize(
+            operation::WRITE,
+            resource::TRANSACTIONAL_ID,
+            &self.transactional_id,
+        ) {
+            return Ok(EndTxnResponse {
+                throttle_time_ms: 0,
+                error_code: TRANSACTIONAL_ID_AUTHORIZATION_FAILED,
+                tagged_fields: 0,
+            });
+        }
+
+        let broker = ctx.broker.clone();
+        let transactional_id = self.transactional_id.0.clone();
+        let producer_id = self.producer_id;
+        let producer_epoch = self.producer_epoch;
+        let commit = self.committed != 0;
+
+        let result = tokio::task::spawn_blocking(move || {
+            broker.transactions.end_txn(
+                &broker,
+                &transactional_id,
+                producer_id,
+                producer_epoch,
+                commit,
+            )
+        })
+        .await?;
+
+        Ok(EndTxnResponse {
+            throttle_time_ms: 0,
+            error_code: result.err().unwrap_or(NONE),
+            tagged_fields: 0,
+        })
+    }
+}
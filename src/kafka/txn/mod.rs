@@ -0,0 +1,477 @@
+//! The transaction coordinator: it tracks the transaction of each
+//! transactional id in the [`TRANSACTION_STATE_TOPIC`] log and ends it by
+//! writing COMMIT or ABORT markers into every partition it wrote to.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    broker::Broker,
+    errors::{
+        CONCURRENT_TRANSACTIONS, INVALID_PRODUCER_EPOCH, INVALID_PRODUCER_ID_MAPPING,
+        INVALID_REQUEST, INVALID_TRANSACTION_TIMEOUT, INVALID_TXN_STATE, NONE,
+        UNKNOWN_SERVER_ERROR, UNKNOWN_TOPIC_OR_PARTITION,
+    },
+    log::partition::LogManager,
+};
+use state::{TransactionLog, TransactionMetadata, TxnState};
+use writetxnmarkers::write_markers;
+
+pub mod addoffsetstotxn;
+pub mod addpartitionstotxn;
+pub mod endtxn;
+pub mod state;
+pub mod txnoffsetcommit;
+pub mod writetxnmarkers;
+
+/// The epoch of this coordinator, written into every marker. There is a
+/// single coordinator, so it never changes.
+pub const COORDINATOR_EPOCH: i32 = 0;
+
+/// The longest transaction timeout a producer may ask for.
+const MAX_TRANSACTION_TIMEOUT_MS: i32 = 900_000;
+
+/// How often open transactions are checked against their timeout.
+const EXPIRATION_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub struct TransactionCoordinator {
+    log: TransactionLog,
+    transactions: Mutex<HashMap<String, TransactionMetadata>>,
+}
+
+impl TransactionCoordinator {
+    /// Opens the transaction log, replaying the state of every
+    /// transactional id.
+    pub fn open(logs: &LogManager) -> io::Result<Self> {
+        let (log, transactions) = TransactionLog::open(logs)?;
+
+        Ok(Self {
+            log,
+            transactions: Mutex::new(transactions),
+        })
+    }
+
+    /// Gives a transactional producer its producer id and a new epoch,
+    /// fencing any older instance. A transaction left open by that instance
+    /// is aborted first.
+    ///
+    /// `expected` is the producer id and epoch the producer had, if any; it
+    /// must still be current.
+    pub fn init_producer_id(
+        &self,
+        broker: &Broker,
+        transactional_id: &str,
+        timeout_ms: i32,
+        expected: Option<(i64, i16)>,
+    ) -> Result<(i64, i16), i16> {
+        if transactional_id.is_empty() {
+            return Err(INVALID_REQUEST);
+        }
+        if !(1..=MAX_TRANSACTION_TIMEOUT_MS).contains(&timeout_ms) {
+            return Err(INVALID_TRANSACTION_TIMEOUT);
+        }
+
+        let mut transactions = lock(&self.transactions);
+
+        let mut metadata = match transactions.get(transactional_id) {
+            None => {
+                let producer_id = allocate_producer_id(broker)?;
+                TransactionMetadata {
+                    producer_id,
+                    producer_epoch: 0,
+                    timeout_ms,
+                    state: TxnState::Empty,
+                    partitions: BTreeSet::new(),
+                    last_update_ms: now(),
+                    start_ms: -1,
+                }
+            }
+            Some(current) => {
+                if expected.is_some_and(|expected| {
+                    expected != (current.producer_id, current.producer_epoch)
+                }) {
+                    return Err(INVALID_PRODUCER_EPOCH);
+                }
+
+                let mut metadata = current.clone();
+                match metadata.state {
+                    // The markers of the last transaction are still due.
+                    TxnState::PrepareCommit | TxnState::PrepareAbort => {
+                        return Err(CONCURRENT_TRANSACTIONS)
+                    }
+                    TxnState::Ongoing => {
+                        metadata.producer_epoch = metadata.producer_epoch.saturating_add(1);
+                        metadata = self.complete(
+                            &mut transactions,
+                            broker,
+                            transactional_id,
+                            &metadata,
+                            false,
+                        )?;
+                    }
+                    _ => {}
+                }
+
+                if metadata.producer_epoch >= i16::MAX - 1 {
+                    metadata.producer_id = allocate_producer_id(broker)?;
+                    metadata.producer_epoch = 0;
+                } else {
+                    metadata.producer_epoch += 1;
+                }
+                metadata.timeout_ms = timeout_ms;
+                metadata
+            }
+        };
+
+        metadata = metadata.transition(TxnState::Empty);
+        self.persist(&mut transactions, transactional_id, metadata.clone())?;
+        Ok((metadata.producer_id, metadata.producer_epoch))
+    }
+
+    /// Adds partitions to the transaction of a producer, starting one if
+    /// none is open.
+    pub fn add_partitions(
+        &self,
+        transactional_id: &str,
+        producer_id: i64,
+        producer_epoch: i16,
+        partitions: impl IntoIterator<Item = (String, i32)>,
+    ) -> Result<(), i16> {
+        let mut transactions = lock(&self.transactions);
+        let current = current(&transactions, transactional_id, producer_id, producer_epoch)?;
+
+        let mut metadata = match current.state {
+            TxnState::PrepareCommit | TxnState::PrepareAbort => {
+                return Err(CONCURRENT_TRANSACTIONS)
+            }
+            TxnState::Ongoing => current.clone(),
+            _ => {
+                let mut metadata = current.transition(TxnState::Ongoing);
+                metadata.start_ms = metadata.last_update_ms;
+                metadata
+            }
+        };
+
+        let count = metadata.partitions.len();
+        metadata.partitions.extend(partitions);
+        if current.state == TxnState::Ongoing && metadata.partitions.len() == count {
+            return Ok(());
+        }
+
+        metadata.last_update_ms = now();
+        self.persist(&mut transactions, transactional_id, metadata)
+    }
+
+    /// Commits or aborts the open transaction of a producer.
+    pub fn end_txn(
+        &self,
+        broker: &Broker,
+        transactional_id: &str,
+        producer_id: i64,
+        producer_epoch: i16,
+        commit: bool,
+    ) -> Result<(), i16> {
+        let mut transactions = lock(&self.transactions);
+        let current = current(&transactions, transactional_id, producer_id, producer_epoch)?;
+
+        let completed = match (current.state, commit) {
+            (TxnState::Ongoing, _)
+            | (TxnState::PrepareCommit, true)
+            | (TxnState::PrepareAbort, false) => current.clone(),
+            // A retry of an EndTxn that already succeeded.
+            (TxnState::CompleteCommit, true) | (TxnState::CompleteAbort, false) => return Ok(()),
+            _ => return Err(INVALID_TXN_STATE),
+        };
+
+        self.complete(
+            &mut transactions,
+            broker,
+            transactional_id,
+            &completed,
+            commit,
+        )?;
+        Ok(())
+    }
+
+    /// Checks that `partition` is in the open transaction of a producer.
+    pub fn verify(
+        &self,
+        transactional_id: &str,
+        producer_id: i64,
+        producer_epoch: i16,
+        partition: &(String, i32),
+    ) -> Result<(), i16> {
+        let transactions = lock(&self.transactions);
+        let current = current(&transactions, transactional_id, producer_id, producer_epoch)?;
+
+        if current.state == TxnState::Ongoing && current.partitions.contains(partition) {
+            Ok(())
+        } else {
+            Err(INVALID_TXN_STATE)
+        }
+    }
+
+    /// Aborts the transactions open for longer than their timeout, bumping
+    /// the epoch so that their producer is fenced.
+    pub fn abort_expired(&self, broker: &Broker) {
+        let mut transactions = lock(&self.transactions);
+        let now = now();
+
+        let expired: Vec<(String, TransactionMetadata)> = transactions
+            .iter()
+            .filter(|(_, metadata)| {
+                metadata.state == TxnState::Ongoing
+                    && now - metadata.start_ms > i64::from(metadata.timeout_ms)
+            })
+            .map(|(id, metadata)| (id.clone(), metadata.clone()))
+            .collect();
+
+        for (transactional_id, mut metadata) in expired {
+            metadata.producer_epoch = metadata.producer_epoch.saturating_add(1);
+            if self
+                .complete(
+                    &mut transactions,
+                    broker,
+                    &transactional_id,
+                    &metadata,
+                    false,
+                )
+                .is_err()
+            {
+                eprintln!("failed to abort the transaction of {transactional_id}");
+            }
+        }
+    }
+
+    /// Ends a transaction: records the decision, writes the markers, then
+    /// records that it is complete. Until the markers are written it stays
+    /// in its prepare state, from which EndTxn may be retried.
+    fn complete(
+        &self,
+        transactions: &mut HashMap<String, TransactionMetadata>,
+        broker: &Broker,
+        transactional_id: &str,
+        metadata: &TransactionMetadata,
+        commit: bool,
+    ) -> Result<TransactionMetadata, i16> {
+        let (prepare, complete) = if commit {
+            (TxnState::PrepareCommit, TxnState::CompleteCommit)
+        } else {
+            (TxnState::PrepareAbort, TxnState::CompleteAbort)
+        };
+
+        let prepared = metadata.transition(prepare);
+        self.persist(transactions, transactional_id, prepared.clone())?;
+
+        let errors = write_markers(
+            broker,
+            prepared.producer_id,
+            prepared.producer_epoch,
+            commit,
+            COORDINATOR_EPOCH,
+            prepared.partitions.iter().cloned(),
+        );
+        // A partition deleted meanwhile needs no marker.
+        if errors
+            .iter()
+            .any(|&error| error != NONE && error != UNKNOWN_TOPIC_OR_PARTITION)
+        {
+            eprintln!("failed to write the markers of {transactional_id}: {errors:?}");
+            return Err(UNKNOWN_SERVER_ERROR);
+        }
+
+        let completed = prepared.transition(complete);
+        self.persist(transactions, transactional_id, completed.clone())?;
+        Ok(completed)
+    }
+
+    fn persist(
+        &self,
+        transactions: &mut HashMap<String, TransactionMetadata>,
+        transactional_id: &str,
+        metadata: TransactionMetadata,
+    ) -> Result<(), i16> {
+        self.write(transactional_id, &metadata)?;
+        transactions.insert(transactional_id.to_string(), metadata);
+        Ok(())
+    }
+
+    fn write(&self, transactional_id: &str, metadata: &TransactionMetadata) -> Result<(), i16> {
+        self.log.write(transactional_id, metadata).map_err(|e| {
+            eprintln!("failed to write the transaction state of {transactional_id}: {e:?}");
+            UNKNOWN_SERVER_ERROR
+        })
+    }
+}
+
+impl Broker {
+    /// Aborts timed out transactions in the background.
+    pub fn spawn_transaction_expiration(self: &Arc<Self>) {
+        let broker = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EXPIRATION_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let broker = broker.clone();
+                let _ =
+                    tokio::task::spawn_blocking(move || broker.transactions.abort_expired(&broker))
+                        .await;
+            }
+        });
+    }
+}
+
+/// The state of `transactional_id`, if `producer_id` and `producer_epoch`
+/// are its current ones.
+fn current<'a>(
+    transactions: &'a HashMap<String, TransactionMetadata>,
+    transactional_id: &str,
+    producer_id: i64,
+    producer_epoch: i16,
+) -> Result<&'a TransactionMetadata, i16> {
+    match transactions.get(transactional_id) {
+        Some(metadata) if metadata.producer_id == producer_id => {
+            if metadata.producer_epoch == producer_epoch {
+                Ok(metadata)
+            } else {
+                Err(INVALID_PRODUCER_EPOCH)
+            }
+        }
+        _ => Err(INVALID_PRODUCER_ID_MAPPING),
+    }
+}
+
+fn allocate_producer_id(broker: &Broker) -> Result<i64, i16> {
+    broker
+        .producer_ids
+        .next(&broker.metadata, broker.config.node_id)
+        .map_err(|e| {
+            eprintln!("failed to allocate a producer id: {e:?}");
+            UNKNOWN_SERVER_ERROR
+        })
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::kafka::{
+        broker::BrokerConfig,
+        group::offsets::{CommittedOffset, OFFSETS_TOPIC},
+    };
+
+    fn open(dir: &std::path::Path) -> Broker {
+        Broker::open(BrokerConfig {
+            log_dir: dir.to_path_buf(),
+            ..BrokerConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_transactions_are_ended_and_replayed() {
+        let dir = std::env::temp_dir().join(format!("txn-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let offsets = (OFFSETS_TOPIC.to_string(), 0);
+
+        let broker = open(&dir);
+        let txns = &broker.transactions;
+        assert_eq!(
+            txns.init_producer_id(&broker, "t", 0, None),
+            Err(INVALID_TRANSACTION_TIMEOUT)
+        );
+        let (producer_id, epoch) = txns.init_producer_id(&broker, "t", 1000, None).unwrap();
+        assert_eq!(epoch, 0);
+
+        assert_eq!(
+            txns.end_txn(&broker, "t", producer_id, epoch, true),
+            Err(INVALID_TXN_STATE)
+        );
+        assert_eq!(
+            txns.add_partitions("t", producer_id + 1, epoch, [offsets.clone()]),
+            Err(INVALID_PRODUCER_ID_MAPPING)
+        );
+        txns.add_partitions("t", producer_id, epoch, [offsets.clone()])
+            .unwrap();
+        txns.verify("t", producer_id, epoch, &offsets).unwrap();
+
+        let commit = vec![(
+            "topic".to_string(),
+            0,
+            CommittedOffset::new(3, -1, String::new()),
+        )];
+        broker
+            .offsets
+            .commit_transactional(producer_id, epoch, "group", commit)
+            .unwrap();
+        txns.end_txn(&broker, "t", producer_id, epoch, true)
+            .unwrap();
+        // A retry succeeds, the opposite decision does not.
+        txns.end_txn(&broker, "t", producer_id, epoch, true)
+            .unwrap();
+        assert_eq!(
+            txns.end_txn(&broker, "t", producer_id, epoch, false),
+            Err(INVALID_TXN_STATE)
+        );
+        assert_eq!(broker.offsets.fetch("group", "topic", 0).unwrap().offset, 3);
+
+        // A transaction left open is aborted when the producer restarts.
+        txns.add_partitions("t", producer_id, epoch, [offsets.clone()])
+            .unwrap();
+        let commit = vec![(
+            "topic".to_string(),
+            0,
+            CommittedOffset::new(8, -1, String::new()),
+        )];
+        broker
+            .offsets
+            .commit_transactional(producer_id, epoch, "group", commit)
+            .unwrap();
+        drop(broker);
+
+        let broker = open(&dir);
+        let txns = &broker.transactions;
+        assert_eq!(lock(&txns.transactions)["t"].state, TxnState::Ongoing);
+        assert_eq!(
+            txns.init_producer_id(&broker, "t", 1000, Some((producer_id, epoch + 1))),
+            Err(INVALID_PRODUCER_EPOCH)
+        );
+        assert_eq!(
+            txns.init_producer_id(&broker, "t", 1000, None),
+            Ok((producer_id, 2))
+        );
+        assert_eq!(broker.offsets.fetch("group", "topic", 0).unwrap().offset, 3);
+        assert_eq!(
+            txns.add_partitions("t", producer_id, epoch, [offsets]),
+            Err(INVALID_PRODUCER_EPOCH)
+        );
+        drop(broker);
+
+        let broker = open(&dir);
+        let metadata = &lock(&broker.transactions.transactions)["t"];
+        assert_eq!(
+            (metadata.producer_epoch, metadata.state),
+            (2, TxnState::Empty)
+        );
+        assert!(metadata.partitions.is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+};
+
+use crate::{
+    kafka::log::{
+        partition::{LogManager, SharedLog},
+        TopicRecordBatch, TopicRecordDisk,
+    },
+    types::{array::Array32, kafkastring::String16},
+    Decode, Encode, Size,
+};
+use encode_derive::{Decode, Size};
+
+use super::{lock, now};
+
+/// The internal topic the state of every transaction is stored in, on its
+/// single partition.
+pub const TRANSACTION_STATE_TOPIC: &str = "__transaction_state";
+
+/// The states of a transaction, numbered as in the transaction log.
+///
+/// A transactional id starts `Empty`. Adding partitions makes it `Ongoing`.
+/// EndTxn moves it to `PrepareCommit` or `PrepareAbort` while the markers
+/// are written, then to `CompleteCommit` or `CompleteAbort`, from which the
+/// next transaction starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxnState {
+    Empty = 0,
+    Ongoing = 1,
+    PrepareCommit = 2,
+    PrepareAbort = 3,
+    CompleteCommit = 4,
+    CompleteAbort = 5,
+}
+
+impl TxnState {
+    fn from_status(status: i8) -> Option<Self> {
+        Some(match status {
+            0 => Self::Empty,
+            1 => Self::Ongoing,
+            2 => Self::PrepareCommit,
+            3 => Self::PrepareAbort,
+            4 => Self::CompleteCommit,
+            5 => Self::CompleteAbort,
+            _ => return None,
+        })
+    }
+}
+
+/// What the coordinator knows of a transactional id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionMetadata {
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub timeout_ms: i32,
+    pub state: TxnState,
+    /// The partitions written in the current transaction.
+    pub partitions: BTreeSet<(String, i32)>,
+    pub last_update_ms: i64,
+    pub start_ms: i64,
+}
+
+impl TransactionMetadata {
+    /// Moves to `state`, keeping the partitions of the transaction only
+    /// while it is open.
+    pub fn transition(&self, state: TxnState) -> Self {
+        let mut next = self.clone();
+        next.state = state;
+        next.last_update_ms = now();
+        if matches!(
+            state,
+            TxnState::Empty | TxnState::CompleteCommit | TxnState::CompleteAbort
+        ) {
+            next.partitions.clear();
+        }
+        next
+    }
+}
+
+/// Key of a transaction log record, version 0.
+#[derive(Debug, Encode, Decode, Size)]
+struct TransactionLogKey {
+    version: i16,
+    transactional_id: String16,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+struct TransactionLogPartitions {
+    topic: String16,
+    partition_ids: Array32<i32>,
+}
+
+/// Value of a transaction log record, version 0.
+#[derive(Debug, Encode, Decode, Size)]
+struct TransactionLogValue {
+    version: i16,
+    producer_id: i64,
+    producer_epoch: i16,
+    transaction_timeout_ms: i32,
+    transaction_status: i8,
+    transaction_partitions: Array32<TransactionLogPartitions>,
+    transaction_last_update_timestamp_ms: i64,
+    transaction_start_timestamp_ms: i64,
+}
+
+/// The state of every transactional id, persisted to
+/// [`TRANSACTION_STATE_TOPIC`].
+#[derive(Debug)]
+pub struct TransactionLog {
+    log: SharedLog,
+}
+
+impl TransactionLog {
+    /// Opens the transaction log, returning it with the state it replays.
+    pub fn open(logs: &LogManager) -> io::Result<(Self, HashMap<String, TransactionMetadata>)> {
+        let log = logs.partition(TRANSACTION_STATE_TOPIC, 0)?;
+        let mut transactions = HashMap::new();
+
+        for batch in lock(&log).read_batches()? {
+            for record in batch.decode_records() {
+                let (Some(key), Some(value)) = (record.key.0, record.value.0) else {
+                    continue;
+                };
+                if i16::decode(&key, &mut 0) != 0 || i16::decode(&value, &mut 0) != 0 {
+                    continue;
+                }
+
+                let key = TransactionLogKey::decode(&key, &mut 0);
+                let value = TransactionLogValue::decode(&value, &mut 0);
+                let Some(state) = TxnState::from_status(value.transaction_status) else {
+                    continue;
+                };
+
+                let partitions = value
+                    .transaction_partitions
+                    .0
+                    .into_iter()
+                    .flat_map(|topic| {
+                        let name = topic.topic.0;
+                        topic
+                            .partition_ids
+                            .0
+                            .into_iter()
+                            .map(move |partition| (name.clone(), partition))
+                    })
+                    .collect();
+
+                transactions.insert(
+                    key.transactional_id.0,
+                    TransactionMetadata {
+                        producer_id: value.producer_id,
+                        producer_epoch: value.producer_epoch,
+                        timeout_ms: value.transaction_timeout_ms,
+                        state,
+                        partitions,
+                        last_update_ms: value.transaction_last_update_timestamp_ms,
+                        start_ms: value.transaction_start_timestamp_ms,
+                    },
+                );
+            }
+        }
+
+        Ok((Self { log }, transactions))
+    }
+
+    /// Appends the new state of a transactional id.
+    pub fn write(&self, transactional_id: &str, metadata: &TransactionMetadata) -> io::Result<()> {
+        let mut topics: Vec<TransactionLogPartitions> = Vec::new();
+        for (topic, partition) in &metadata.partitions {
+            match topics.last_mut() {
+                Some(last) if last.topic.0 == *topic => last.partition_ids.0.push(*partition),
+                _ => topics.push(TransactionLogPartitions {
+                    topic: topic.as_str().into(),
+                    partition_ids: Array32(vec![*partition]),
+                }),
+            }
+        }
+
+        let key = TransactionLogKey {
+            version: 0,
+            transactional_id: transactional_id.into(),
+        };
+        let value = TransactionLogValue {
+            version: 0,
+            producer_id: metadata.producer_id,
+            producer_epoch: metadata.producer_epoch,
+            transaction_timeout_ms: metadata.timeout_ms,
+            transaction_status: metadata.state as i8,
+            transaction_partitions: Array32(topics),
+            transaction_last_update_timestamp_ms: metadata.last_update_ms,
+            transaction_start_timestamp_ms: metadata.start_ms,
+        };
+
+        let record = TopicRecordDisk::new(0, 0, Some(key.to_bytes()), Some(value.to_bytes()));
+        lock(&self.log).append(&mut TopicRecordBatch::new(now(), vec![record]))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{
+    kafka::{
+        acl::{operation, resource},
+        errors::{
+            GROUP_AUTHORIZATION_FAILED, NONE, OFFSET_METADATA_TOO_LARGE,
+            TOPIC_AUTHORIZATION_FAILED, TRANSACTIONAL_ID_AUTHORIZATION_FAILED,
+            UNKNOWN_SERVER_ERROR,
+        },
+        group::{
+            offsetcommit::MAX_METADATA_SIZE,
+            offsets::{CommittedOffset, OFFSETS_TOPIC},
+        },
+        RequestContext,
+    },
+    types::{
+        array::CompactArray,
+        cstring::{CompactNullableString, CompactString},
+    },
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct TxnOffsetCommitRequestPartition {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    pub committed_leader_epoch: i32,
+    pub committed_metadata: CompactNullableString,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct TxnOffsetCommitRequestTopic {
+    pub name: CompactString,
+    pub partitions: CompactArray<TxnOffsetCommitRequestPartition>,
+    pub tagged_fields: u8,
+}
+
+/// TxnOffsetCommit v3.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct TxnOffsetCommitRequest {
+    pub transactional_id: CompactString,
+    pub group_id: CompactString,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub generation_id: i32,
+    pub member_id: CompactString,
+    pub group_instance_id: CompactNullableString,
+    pub topics: CompactArray<TxnOffsetCommitRequestTopic>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct TxnOffsetCommitResponsePartition {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct TxnOffsetCommitResponseTopic {
+    pub name: CompactString,
+    pub partitions: CompactArray<TxnOffsetCommitResponsePartition>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct TxnOffsetCommitResponse {
+    pub throttle_time_ms: i32,
+    pub topics: CompactArray<TxnOffsetCommitResponseTopic>,
+    pub tagged_fields: u8,
+}
+
+impl TxnOffsetCommitRequest {
+    /// Stores the offsets in the producer's transaction, which AddOffsetsToTxn
+    /// must have added the offsets topic to. They become visible once it
+    /// commits.
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<TxnOffsetCommitResponse, Error> {
+        let broker = &ctx.broker;
+        let denied = ctx.denied_topics(
+            operation::READ,
+            self.topics.iter().map(|topic| topic.name.as_str()),
+        );
+        let group_error = if !ctx.authorize(
+            operation::WRITE,
+            resource::TRANSACTIONAL_ID,
+            &self.transactional_id,
+        ) {
+            TRANSACTIONAL_ID_AUTHORIZATION_FAILED
+        } else if !ctx.authorize(operation::READ, resource::GROUP, &self.group_id) {
+            GROUP_AUTHORIZATION_FAILED
+        } else {
+            match broker
+                .groups
+                .validate_commit(&self.group_id, self.generation_id, &self.member_id)
+            {
+                NONE => broker
+                    .transactions
+                    .verify(
+                        &self.transactional_id,
+                        self.producer_id,
+                        self.producer_epoch,
+                        &(OFFSETS_TOPIC.to_string(), 0),
+                    )
+                    .err()
+                    .unwrap_or(NONE),
+                error => error,
+            }
+        };
+
+        let error_for = |topic: &TxnOffsetCommitRequestTopic,
+                         partition: &TxnOffsetCommitRequestPartition| {
+            let metadata_size = partition
+                .committed_metadata
+                .0
+                .as_ref()
+                .map_or(0, String::len);
+
+            match group_error {
+                NONE if denied.contains(topic.name.as_str()) => TOPIC_AUTHORIZATION_FAILED,
+                NONE if metadata_size > MAX_METADATA_SIZE => OFFSET_METADATA_TOO_LARGE,
+                error => error,
+            }
+        };
+
+        let commits: Vec<(String, i32, CommittedOffset)> = self
+            .topics
+            .iter()
+            .flat_map(|topic| topic.partitions.iter().map(move |p| (topic, p)))
+            .filter(|(topic, partition)| error_for(topic, partition) == NONE)
+            .map(|(topic, partition)| {
+                (
+                    topic.name.0.clone(),
+                    partition.partition_index,
+                    CommittedOffset::new(
+                        partition.committed_offset,
+                        partition.committed_leader_epoch,
+                        partition.committed_metadata.0.clone().unwrap_or_default(),
+                    ),
+                )
+            })
+            .collect();
+
+        let stored = {
+            let broker = broker.clone();
+            let group_id = self.group_id.0.clone();
+            let producer_id = self.producer_id;
+            let producer_epoch = self.producer_epoch;
+            tokio::task::spawn_blocking(move || {
+                broker
+                    .offsets
+                    .commit_transactional(producer_id, producer_epoch, &group_id, commits)
+            })
+            .await?
+        };
+
+        if let Err(e) = &stored {
+            eprintln!(
+                "failed to store offsets for group {} in a transaction: {e:?}",
+                self.group_id.0
+            );
+        }
+
+        let topics = self
+            .topics
+            .iter()
+            .map(|topic| TxnOffsetCommitResponseTopic {
+                name: topic.name.clone(),
+                partitions: CompactArray(
+                    topic
+                        .partitions
+                        .iter()
+                        .map(|partition| TxnOffsetCommitResponsePartition {
+                            partition_index: partition.partition_index,
+                            error_code: match error_for(topic, partition) {
+                                NONE if stored.is_err() => UNKNOWN_SERVER_ERROR,
+                                error => error,
+                            },
+                            tagged_fields: 0,
+                        })
+                        .collect(),
+                ),
+                tagged_fields: 0,
+            })
+            .collect();
+
+        Ok(TxnOffsetCommitResponse {
+            throttle_time_ms: 0,
+            topics: CompactArray(topics),
+            tagged_fields: 0,
+        })
+    }
+}
@@ -0,0 +1,216 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    kafka::{
+        acl::{operation, resource, CLUSTER_NAME},
+        broker::Broker,
+        errors::{
+            CLUSTER_AUTHORIZATION_FAILED, INVALID_PRODUCER_EPOCH, NONE, UNKNOWN_SERVER_ERROR,
+            UNKNOWN_TOPIC_OR_PARTITION,
+        },
+        group::offsets::OFFSETS_TOPIC,
+        log::{producer_state::SequenceError, TopicRecordBatch},
+        RequestContext,
+    },
+    types::{array::CompactArray, cstring::CompactString},
+    Decode, Encode, Size,
+};
+use anyhow::Error;
+use encode_derive::{Decode, Size};
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct WritableTxnMarkerTopic {
+    pub name: CompactString,
+    pub partition_indexes: CompactArray<i32>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Size)]
+pub struct WritableTxnMarker {
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    /// 1 to commit the transaction, 0 to abort it.
+    pub transaction_result: u8,
+    pub topics: CompactArray<WritableTxnMarkerTopic>,
+    pub coordinator_epoch: i32,
+    pub tagged_fields: u8,
+}
+
+/// WriteTxnMarkers v1.
+#[derive(Debug, Encode, Decode, Size)]
+pub struct WriteTxnMarkersRequest {
+    pub markers: CompactArray<WritableTxnMarker>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct WritableTxnMarkerPartitionResult {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct WritableTxnMarkerTopicResult {
+    pub name: CompactString,
+    pub partitions: CompactArray<WritableTxnMarkerPartitionResult>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct WritableTxnMarkerResult {
+    pub producer_id: i64,
+    pub topics: CompactArray<WritableTxnMarkerTopicResult>,
+    pub tagged_fields: u8,
+}
+
+#[derive(Debug, Encode, Decode, Size)]
+pub struct WriteTxnMarkersResponse {
+    pub markers: CompactArray<WritableTxnMarkerResult>,
+    pub tagged_fields: u8,
+}
+
+/// Appends a COMMIT or ABORT marker for a producer to each partition,
+/// returning the error of each in order. Markers for the offsets topic
+/// complete the offsets the producer committed in the transaction.
+pub(crate) fn write_markers(
+    broker: &Broker,
+    producer_id: i64,
+    producer_epoch: i16,
+    commit: bool,
+    coordinator_epoch: i32,
+    partitions: impl IntoIterator<Item = (String, i32)>,
+) -> Vec<i16> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64);
+
+    // The image stays locked so that no topic is deleted meanwhile.
+    broker.metadata.read(|image| {
+        partitions
+            .into_iter()
+            .map(|(topic, index)| {
+                if topic == OFFSETS_TOPIC {
+                    return match broker.offsets.complete_transaction(
+                        producer_id,
+                        producer_epoch,
+                        commit,
+                        coordinator_epoch,
+                    ) {
+                        Ok(()) => NONE,
+                        Err(e) => {
+                            eprintln!("failed to complete the offsets of {producer_id}: {e:?}");
+                            UNKNOWN_SERVER_ERROR
+                        }
+                    };
+                }
+
+                if !image
+                    .topic(&topic)
+                    .is_some_and(|t| t.partitions.contains_key(&index))
+                {
+                    return UNKNOWN_TOPIC_OR_PARTITION;
+                }
+
+                let log = match broker.logs.partition(&topic, index) {
+                    Ok(log) => log,
+                    Err(e) => {
+                        eprintln!("failed to open {topic}-{index}: {e:?}");
+                        return UNKNOWN_SERVER_ERROR;
+                    }
+                };
+                let mut log = log.lock().unwrap_or_else(|e| e.into_inner());
+
+                let mut marker = TopicRecordBatch::control(
+                    producer_id,
+                    producer_epoch,
+                    commit,
+                    coordinator_epoch,
+                    timestamp,
+                );
+                if let Err(SequenceError::Fenced { .. }) = log.check_sequence(&marker) {
+                    return INVALID_PRODUCER_EPOCH;
+                }
+
+                match log.append(&mut marker) {
+                    Ok(_) => NONE,
+                    Err(e) => {
+                        eprintln!("failed to append a marker to {topic}-{index}: {e:?}");
+                        UNKNOWN_SERVER_ERROR
+                    }
+                }
+            })
+            .collect()
+    })
+}
+
+impl WriteTxnMarkersRequest {
+    pub async fn handle_request(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<WriteTxnMarkersResponse, Error> {
+        // Markers are written by transaction coordinators only.
+        let authorized = ctx.authorize(operation::CLUSTER_ACTION, resource::CLUSTER, CLUSTER_NAME);
+        let broker = ctx.broker.clone();
+        let markers = self.markers.0.clone();
+
+        let markers = tokio::task::spawn_blocking(move || {
+            markers
+                .iter()
+                .map(|marker| {
+                    let partitions = marker.topics.iter().flat_map(|topic| {
+                        topic
+                            .partition_indexes
+                            .iter()
+                            .map(|&index| (topic.name.0.clone(), index))
+                    });
+                    let mut errors = if authorized {
+                        write_markers(
+                            &broker,
+                            marker.producer_id,
+                            marker.producer_epoch,
+                            marker.transaction_result != 0,
+                            marker.coordinator_epoch,
+                            partitions,
+                        )
+                    } else {
+                        partitions.map(|_| CLUSTER_AUTHORIZATION_FAILED).collect()
+                    }
+                    .into_iter();
+
+                    let topics = marker
+                        .topics
+                        .iter()
+                        .map(|topic| WritableTxnMarkerTopicResult {
+                            name: topic.name.clone(),
+                            partitions: CompactArray(
+                                topic
+                                    .partition_indexes
+                                    .iter()
+                                    .map(|&partition_index| WritableTxnMarkerPartitionResult {
+                                        partition_index,
+                                        error_code: errors.next().unwrap_or(UNKNOWN_SERVER_ERROR),
+                                        tagged_fields: 0,
+                                    })
+                                    .collect(),
+                            ),
+                            tagged_fields: 0,
+                        })
+                        .collect();
+
+                    WritableTxnMarkerResult {
+                        producer_id: marker.producer_id,
+                        topics: CompactArray(topics),
+                        tagged_fields: 0,
+                    }
+                })
+                .collect()
+        })
+        .await?;
+
+        Ok(WriteTxnMarkersResponse {
+            markers: CompactArray(markers),
+            tagged_fields: 0,
+        })
+    }
+}
@@ -1,51 +1,168 @@
 use anyhow::{anyhow, Error};
+use bytes::{Bytes, BytesMut};
 use encode_derive::Encode;
-use kafka::apiversions::ApiVersionsRequest;
-use kafka::fetch::FetchRequest;
+use frame::{Frame, Transport, WriteFailed};
+use kafka::acl::createacls::CreateAclsRequest;
+use kafka::acl::deleteacls::DeleteAclsRequest;
+use kafka::acl::describeacls::DescribeAclsRequest;
+use kafka::apiversions::{is_version_supported, ApiVersionsRequest};
+use kafka::broker::Broker;
+use kafka::configs::describeconfigs::DescribeConfigsRequest;
+use kafka::configs::incrementalalterconfigs::IncrementalAlterConfigsRequest;
+use kafka::fetch::{FetchRequest, LegacyFetchRequest};
+use kafka::group::consumergroupdescribe::ConsumerGroupDescribeRequest;
+use kafka::group::consumergroupheartbeat::ConsumerGroupHeartbeatRequest;
+use kafka::group::deletegroups::DeleteGroupsRequest;
+use kafka::group::describegroups::DescribeGroupsRequest;
+use kafka::group::listgroups::ListGroupsRequest;
+use kafka::group::offsetdelete::OffsetDeleteRequest;
+use kafka::group::{
+    findcoordinator::FindCoordinatorRequest, heartbeat::HeartbeatRequest,
+    joingroup::JoinGroupRequest, leavegroup::LeaveGroupRequest, offsetcommit::OffsetCommitRequest,
+    offsetfetch::OffsetFetchRequest, syncgroup::SyncGroupRequest,
+};
+use kafka::listoffsets::ListOffsetsRequest;
 use kafka::listpartitions::DescribePartitionsRequest;
-use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
+use kafka::produce::ProduceRequest;
+use kafka::producer::initproducerid::InitProducerIdRequest;
+use kafka::quota::alterclientquotas::AlterClientQuotasRequest;
+use kafka::quota::describeclientquotas::DescribeClientQuotasRequest;
+use kafka::quota::{timed, Throttle};
+use kafka::sasl::alteruserscramcredentials::AlterUserScramCredentialsRequest;
+use kafka::sasl::saslauthenticate::SaslAuthenticateRequest;
+use kafka::sasl::saslhandshake::SaslHandshakeRequest;
+use kafka::sasl::{AuthenticationRequired, Session};
+use kafka::topics::createpartitions::CreatePartitionsRequest;
+use kafka::topics::createtopics::CreateTopicsRequest;
+use kafka::topics::deleterecords::DeleteRecordsRequest;
+use kafka::topics::deletetopics::DeleteTopicsRequest;
+use kafka::topics::metadata::MetadataRequest;
+use kafka::txn::addoffsetstotxn::AddOffsetsToTxnRequest;
+use kafka::txn::addpartitionstotxn::AddPartitionsToTxnRequest;
+use kafka::txn::endtxn::EndTxnRequest;
+use kafka::txn::txnoffsetcommit::TxnOffsetCommitRequest;
+use kafka::txn::writetxnmarkers::WriteTxnMarkersRequest;
+use kafka::{RequestContext, RequestHeader};
+use std::{
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-#[derive(Debug, Encode)]
+#[derive(Debug, Encode, encode_derive::Size)]
 pub struct ErrorResponse {
     pub code: i16,
 }
 
-pub async fn respond(socket: &mut TcpStream, buf: &[u8]) {
-    if let Err(e) = socket.write_all(buf).await {
-        eprintln!("failed to write to socket; err = {e:?}");
-        return;
+/// Starts a response frame with the size prefix and the response header.
+/// Header v0 is just the correlation id; v1 adds an empty tagged field buffer.
+pub fn response_frame(correlation_id: i32, header_version: i16, body_size: usize) -> Frame {
+    let header_size = if header_version >= 1 { 5 } else { 4 };
+    let mut frame = Frame::with_capacity(4 + header_size + body_size);
+
+    frame.put(&((header_size + body_size) as i32));
+    frame.put(&correlation_id);
+    if header_version >= 1 {
+        frame.put(&0u8);
     }
-    let _ = socket.flush().await;
+
+    frame
 }
 
-pub async fn handle_client(buf: &[u8], socket: &mut TcpStream) -> Result<(), Error> {
-    let key: i16 = i16::decode(&buf[4..6], &mut 0);
+pub fn encode_response<T: EncodeFrame + Size>(
+    correlation_id: i32,
+    header_version: i16,
+    body: &T,
+) -> Frame {
+    let mut frame = response_frame(correlation_id, header_version, body.size_in_bytes());
+    body.encode_frame(&mut frame);
+    frame
+}
 
-    let handler = get_handler(key, buf);
+/// Serves one request. `buf` holds the request without its size prefix.
+/// Returns how long the connection is muted for, as its client went over a
+/// quota. Fails with [`AuthenticationRequired`] for requests the session may
+/// not send yet, and with [`WriteFailed`] for a response only partly written,
+/// upon both of which the connection should be closed.
+pub async fn handle_client<S: Transport>(
+    buf: &Bytes,
+    peer_addr: SocketAddr,
+    broker: &Arc<Broker>,
+    session: &Arc<Mutex<Session>>,
+    socket: &mut S,
+) -> Result<Duration, Error> {
+    let mut offset = 0;
+    let header = RequestHeader::decode(buf, &mut offset);
+    let ctx = RequestContext::new(
+        header,
+        peer_addr,
+        buf.len(),
+        broker.clone(),
+        session.clone(),
+    );
 
-    match handler {
-        Some(h) => {
-            handle_request(h, socket).await;
-            Ok(())
+    let allowed = session
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .may_send(ctx.api_key);
+    if !allowed {
+        return Err(AuthenticationRequired {
+            api_key: ctx.api_key,
         }
-        None => Err(anyhow!("Error while getting handler")),
+        .into());
+    }
+
+    let handler = get_handler(&ctx, buf, &mut offset);
+
+    match handler {
+        Some(h) => Ok(handle_request(h, &ctx, socket).await?),
+        None => Err(anyhow!(
+            "Error while getting handler for api key {} v{}",
+            ctx.api_key,
+            ctx.api_version
+        )),
     }
 }
 
+pub mod cli;
+pub mod client;
+pub mod frame;
 pub mod kafka;
 pub mod types;
 
 pub trait Encode {
-    fn encode(&self) -> Vec<u8>;
+    fn encode(&self, buf: &mut BytesMut);
+
+    /// Encodes into a buffer allocated up front with exactly
+    /// `size_in_bytes()` capacity.
+    fn to_bytes(&self) -> Bytes
+    where
+        Self: Size,
+    {
+        let mut buf = BytesMut::with_capacity(self.size_in_bytes());
+        self.encode(&mut buf);
+        buf.freeze()
+    }
 }
 
-pub trait Decode: Sized {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self;
+/// Encodes a value into a [`Frame`], which unlike a plain buffer can carry
+/// file regions. Derived `Encode` types get an implementation that puts the
+/// whole value; `#[derive(EncodeFrame)]` encodes field by field instead.
+pub trait EncodeFrame {
+    fn encode_frame(&self, frame: &mut Frame);
 }
 
-pub trait Offset {
-    fn size(&self) -> usize;
+pub trait Decode: Sized {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self;
+
+    /// Decodes untrusted input, failing if it is truncated or malformed
+    /// rather than returning what could be read of it.
+    fn try_decode(bytes: &Bytes, offset: &mut usize) -> Result<Self, Error> {
+        let value = Self::decode(bytes, offset);
+        types::check_decoded(bytes, *offset)?;
+        Ok(value)
+    }
 }
 
 pub trait Size {
@@ -53,52 +170,323 @@ pub trait Size {
 }
 
 pub enum Handler {
+    AddOffsetsToTxn(AddOffsetsToTxnRequest),
+    AddPartitionsToTxn(AddPartitionsToTxnRequest),
+    AlterClientQuotas(AlterClientQuotasRequest),
+    AlterUserScramCredentials(AlterUserScramCredentialsRequest),
     ApiVersions(ApiVersionsRequest),
+    ConsumerGroupDescribe(ConsumerGroupDescribeRequest),
+    ConsumerGroupHeartbeat(ConsumerGroupHeartbeatRequest),
+    CreateAcls(CreateAclsRequest),
+    CreatePartitions(CreatePartitionsRequest),
+    CreateTopics(CreateTopicsRequest),
+    DeleteAcls(DeleteAclsRequest),
+    DeleteGroups(DeleteGroupsRequest),
+    DeleteRecords(DeleteRecordsRequest),
+    DeleteTopics(DeleteTopicsRequest),
+    DescribeAcls(DescribeAclsRequest),
+    DescribeClientQuotas(DescribeClientQuotasRequest),
+    DescribeConfigs(DescribeConfigsRequest),
+    DescribeGroups(DescribeGroupsRequest),
     DescribeTopicPartitions(DescribePartitionsRequest),
+    EndTxn(EndTxnRequest),
     Fetch(FetchRequest),
+    LegacyFetch(LegacyFetchRequest),
+    FindCoordinator(FindCoordinatorRequest),
+    Heartbeat(HeartbeatRequest),
+    IncrementalAlterConfigs(IncrementalAlterConfigsRequest),
+    InitProducerId(InitProducerIdRequest),
+    JoinGroup(JoinGroupRequest),
+    LeaveGroup(LeaveGroupRequest),
+    ListGroups(ListGroupsRequest),
+    ListOffsets(ListOffsetsRequest),
+    Metadata(MetadataRequest),
+    OffsetCommit(OffsetCommitRequest),
+    OffsetDelete(OffsetDeleteRequest),
+    OffsetFetch(OffsetFetchRequest),
+    Produce(ProduceRequest),
+    SaslAuthenticate(SaslAuthenticateRequest),
+    SaslHandshake(SaslHandshakeRequest),
+    SyncGroup(SyncGroupRequest),
+    TxnOffsetCommit(TxnOffsetCommitRequest),
+    WriteTxnMarkers(WriteTxnMarkersRequest),
 }
 
-pub fn get_handler(key: i16, request: &[u8]) -> Option<Handler> {
-    let mut offset = 0;
+/// Decodes the request body following the header. ApiVersions is decoded for
+/// any version so that it can report the versions we do support.
+pub fn get_handler(ctx: &RequestContext, request: &Bytes, offset: &mut usize) -> Option<Handler> {
+    let version = ctx.api_version;
 
-    match key {
-        1 => Some(Handler::Fetch(FetchRequest::decode(request, &mut offset))),
-        18 => Some(Handler::ApiVersions(ApiVersionsRequest::decode(
-            request,
-            &mut offset,
+    if ctx.api_key != 18
+        && !is_version_supported("supported_versions.json", ctx.api_key, version).unwrap_or(false)
+    {
+        return None;
+    }
+
+    match ctx.api_key {
+        0 => Some(Handler::Produce(ProduceRequest::decode(request, offset))),
+        1 if version < 13 => Some(Handler::LegacyFetch(LegacyFetchRequest::decode_version(
+            request, offset, version,
+        ))),
+        1 => Some(Handler::Fetch(FetchRequest::decode_version(
+            request, offset, version,
+        ))),
+        2 => Some(Handler::ListOffsets(ListOffsetsRequest::decode(
+            request, offset,
+        ))),
+        3 => Some(Handler::Metadata(MetadataRequest::decode(request, offset))),
+        8 => Some(Handler::OffsetCommit(OffsetCommitRequest::decode(
+            request, offset,
+        ))),
+        9 => Some(Handler::OffsetFetch(OffsetFetchRequest::decode_version(
+            request, offset, version,
+        ))),
+        10 => Some(Handler::FindCoordinator(FindCoordinatorRequest::decode(
+            request, offset,
+        ))),
+        11 => Some(Handler::JoinGroup(JoinGroupRequest::decode(
+            request, offset,
+        ))),
+        12 => Some(Handler::Heartbeat(HeartbeatRequest::decode(
+            request, offset,
+        ))),
+        13 => Some(Handler::LeaveGroup(LeaveGroupRequest::decode_version(
+            request, offset, version,
+        ))),
+        14 => Some(Handler::SyncGroup(SyncGroupRequest::decode(
+            request, offset,
+        ))),
+        15 => Some(Handler::DescribeGroups(DescribeGroupsRequest::decode(
+            request, offset,
+        ))),
+        16 => Some(Handler::ListGroups(ListGroupsRequest::decode(
+            request, offset,
+        ))),
+        17 => Some(Handler::SaslHandshake(SaslHandshakeRequest::decode(
+            request, offset,
+        ))),
+        18 => Some(Handler::ApiVersions(ApiVersionsRequest::decode_version(
+            request, offset, version,
+        ))),
+        19 => Some(Handler::CreateTopics(CreateTopicsRequest::decode(
+            request, offset,
+        ))),
+        20 => Some(Handler::DeleteTopics(DeleteTopicsRequest::decode(
+            request, offset,
+        ))),
+        21 => Some(Handler::DeleteRecords(DeleteRecordsRequest::decode(
+            request, offset,
+        ))),
+        22 => Some(Handler::InitProducerId(InitProducerIdRequest::decode(
+            request, offset,
+        ))),
+        24 => Some(Handler::AddPartitionsToTxn(
+            AddPartitionsToTxnRequest::decode(request, offset),
+        )),
+        25 => Some(Handler::AddOffsetsToTxn(AddOffsetsToTxnRequest::decode(
+            request, offset,
+        ))),
+        26 => Some(Handler::EndTxn(EndTxnRequest::decode(request, offset))),
+        27 => Some(Handler::WriteTxnMarkers(WriteTxnMarkersRequest::decode(
+            request, offset,
         ))),
+        28 => Some(Handler::TxnOffsetCommit(TxnOffsetCommitRequest::decode(
+            request, offset,
+        ))),
+        29 => Some(Handler::DescribeAcls(DescribeAclsRequest::decode(
+            request, offset,
+        ))),
+        30 => Some(Handler::CreateAcls(CreateAclsRequest::decode(
+            request, offset,
+        ))),
+        31 => Some(Handler::DeleteAcls(DeleteAclsRequest::decode(
+            request, offset,
+        ))),
+        32 => Some(Handler::DescribeConfigs(DescribeConfigsRequest::decode(
+            request, offset,
+        ))),
+        36 => Some(Handler::SaslAuthenticate(SaslAuthenticateRequest::decode(
+            request, offset,
+        ))),
+        37 => Some(Handler::CreatePartitions(CreatePartitionsRequest::decode(
+            request, offset,
+        ))),
+        42 => Some(Handler::DeleteGroups(DeleteGroupsRequest::decode(
+            request, offset,
+        ))),
+        44 => Some(Handler::IncrementalAlterConfigs(
+            IncrementalAlterConfigsRequest::decode(request, offset),
+        )),
+        47 => Some(Handler::OffsetDelete(OffsetDeleteRequest::decode(
+            request, offset,
+        ))),
+        48 => Some(Handler::DescribeClientQuotas(
+            DescribeClientQuotasRequest::decode(request, offset),
+        )),
+        49 => Some(Handler::AlterClientQuotas(
+            AlterClientQuotasRequest::decode(request, offset),
+        )),
+        51 => Some(Handler::AlterUserScramCredentials(
+            AlterUserScramCredentialsRequest::decode(request, offset),
+        )),
+        68 => Some(Handler::ConsumerGroupHeartbeat(
+            ConsumerGroupHeartbeatRequest::decode(request, offset),
+        )),
+        69 => Some(Handler::ConsumerGroupDescribe(
+            ConsumerGroupDescribeRequest::decode(request, offset),
+        )),
         75 => Some(Handler::DescribeTopicPartitions(
-            DescribePartitionsRequest::decode(request, &mut offset),
+            DescribePartitionsRequest::decode(request, offset),
         )),
         _ => None,
     }
 }
 
-pub async fn handle_request(handler: Handler, socket: &mut TcpStream) {
-    match handler {
-        Handler::ApiVersions(request) => {
-            if let Ok(value) = request.handle_request().await {
-                respond(socket, &value.encode()[..]).await
-            } else {
-                let err = ErrorResponse { code: -1 };
-                respond(socket, &err.encode()[..]).await
-            };
+/// Frames a handler's response, or a bare error code if it failed.
+fn encode_result<T: EncodeFrame + Size>(ctx: &RequestContext, result: Result<T, Error>) -> Frame {
+    let header_version = ctx.response_header_version();
+
+    match result {
+        Ok(value) => encode_response(ctx.correlation_id, header_version, &value),
+        Err(e) => {
+            eprintln!("failed to handle api key {}: {e:?}", ctx.api_key);
+            encode_response(
+                ctx.correlation_id,
+                header_version,
+                &ErrorResponse { code: -1 },
+            )
         }
+    }
+}
+
+/// Serves a request counted against its client's quotas. Returns its framed
+/// response, with the throttle time set, and how long the connection is
+/// muted for.
+async fn respond<T, F>(ctx: &RequestContext, response: F) -> (Frame, Duration)
+where
+    T: EncodeFrame + Throttle,
+    F: Future<Output = Result<T, Error>>,
+{
+    let (mut result, busy) = timed(response).await;
+    let throttle = match &mut result {
+        Ok(response) => ctx.broker.quotas.throttle(ctx, busy, response),
+        Err(_) => Duration::ZERO,
+    };
+
+    (encode_result(ctx, result), throttle)
+}
+
+/// Serves a request and writes its response. Returns how long the connection
+/// is muted for.
+pub async fn handle_request<S: Transport>(
+    handler: Handler,
+    ctx: &RequestContext,
+    socket: &mut S,
+) -> Result<Duration, WriteFailed> {
+    // The SASL exchange comes before the client is known and WriteTxnMarkers
+    // is sent by brokers, so neither is throttled.
+    let (frame, throttle) = match handler {
+        Handler::AddOffsetsToTxn(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::AddPartitionsToTxn(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::AlterClientQuotas(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::AlterUserScramCredentials(request) => {
+            respond(ctx, request.handle_request(ctx)).await
+        }
+        Handler::ApiVersions(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::ConsumerGroupDescribe(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::ConsumerGroupHeartbeat(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::CreateAcls(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::CreatePartitions(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::CreateTopics(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::DeleteAcls(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::DeleteGroups(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::DeleteRecords(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::DeleteTopics(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::DescribeAcls(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::DescribeClientQuotas(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::DescribeConfigs(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::DescribeGroups(request) => respond(ctx, request.handle_request(ctx)).await,
         Handler::DescribeTopicPartitions(request) => {
-            if let Ok(value) = request.handle_request().await {
-                respond(socket, &value.encode()[..]).await
-            } else {
-                let err = ErrorResponse { code: -1 };
-                respond(socket, &err.encode()[..]).await
-            }
+            respond(ctx, request.handle_request(ctx)).await
         }
-        Handler::Fetch(request) => {
-            if let Ok(value) = request.handle_request().await {
-                respond(socket, &value.encode()[..]).await
-            } else {
-                let err = ErrorResponse { code: -1 };
-                respond(socket, &err.encode()[..]).await
+        Handler::EndTxn(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::Fetch(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::LegacyFetch(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::FindCoordinator(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::Heartbeat(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::IncrementalAlterConfigs(request) => {
+            respond(ctx, request.handle_request(ctx)).await
+        }
+        Handler::InitProducerId(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::JoinGroup(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::LeaveGroup(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::ListGroups(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::ListOffsets(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::Metadata(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::OffsetCommit(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::OffsetDelete(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::OffsetFetch(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::Produce(request) => {
+            let (frame, throttle) = respond(ctx, request.handle_request(ctx)).await;
+            // Producers sending acks=0 expect no response, but are muted all
+            // the same.
+            if request.acks == 0 {
+                return Ok(throttle);
             }
+            (frame, throttle)
         }
+        Handler::SaslAuthenticate(request) => (
+            encode_result(ctx, request.handle_request(ctx).await),
+            Duration::ZERO,
+        ),
+        Handler::SaslHandshake(request) => (
+            encode_result(ctx, request.handle_request(ctx).await),
+            Duration::ZERO,
+        ),
+        Handler::SyncGroup(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::TxnOffsetCommit(request) => respond(ctx, request.handle_request(ctx)).await,
+        Handler::WriteTxnMarkers(request) => (
+            encode_result(ctx, request.handle_request(ctx).await),
+            Duration::ZERO,
+        ),
+    };
+
+    frame.write_to(socket).await.map_err(WriteFailed)?;
+    Ok(throttle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Encode, encode_derive::Decode, encode_derive::Size)]
+    enum Tagged {
+        Short(i16),
+        Long(i64),
+    }
+
+    #[test]
+    fn test_enum_derives_round_trip() {
+        for value in [Tagged::Short(-2), Tagged::Long(1 << 40)] {
+            let encoded = value.to_bytes();
+            assert_eq!(encoded.len(), value.size_in_bytes());
+            assert_eq!(Tagged::decode(&encoded, &mut 0), value);
+        }
+
+        assert_eq!(Tagged::Long(1).to_bytes()[0], 1);
+    }
+
+    #[test]
+    fn test_response_header_versions() {
+        let body = ErrorResponse { code: 35 };
+
+        let v0 = encode_response(7, 0, &body).into_bytes().unwrap();
+        assert_eq!(&v0[..], &[0, 0, 0, 6, 0, 0, 0, 7, 0, 35]);
+
+        let v1 = encode_response(7, 1, &body).into_bytes().unwrap();
+        assert_eq!(&v1[..], &[0, 0, 0, 7, 0, 0, 0, 7, 0, 0, 35]);
+
+        let size = i32::decode(&v1, &mut 0);
+        assert_eq!(size as usize, v1.len() - 4);
     }
 }
@@ -1,40 +1,141 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
 use bytes::BytesMut;
-use codecrafters_kafka::handle_client;
+use codecrafters_kafka::frame::{Transport, WriteFailed};
+use codecrafters_kafka::kafka::broker::{Broker, BrokerConfig};
+use codecrafters_kafka::kafka::listener::{certificate_user, Listener};
+use codecrafters_kafka::kafka::sasl::{AuthenticationRequired, Session};
+use codecrafters_kafka::{cli, handle_client};
 use tokio::io::AsyncReadExt;
 use tokio::net::TcpListener;
-
-static SERVER_ADDRESS: &str = "127.0.0.1:9092";
+use tokio_rustls::TlsAcceptor;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let listener = TcpListener::bind(SERVER_ADDRESS).await?;
-    println!("Starting server at {SERVER_ADDRESS}");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some(cli::CONSUMER_GROUPS) {
+        return Ok(cli::consumer_groups(&args[1..])?);
+    }
+
+    // The first argument may be a server.properties file.
+    let config = match args.first() {
+        Some(path) => BrokerConfig::from_properties(Path::new(path))?,
+        None => BrokerConfig::default(),
+    };
+
+    // Every listener is bound before any connection is served, so that a
+    // broker that cannot serve all of them does not start.
+    let acceptor = if config.listeners.iter().any(|l| l.protocol.uses_tls()) {
+        Some(config.tls.acceptor()?)
+    } else {
+        None
+    };
+    let mut sockets = Vec::new();
+    for listener in &config.listeners {
+        let address = listener.address();
+        sockets.push((listener.clone(), TcpListener::bind(&address).await?));
+        println!("Starting server at {address} ({})", listener.name);
+    }
+
+    let broker = Arc::new(Broker::open(config)?);
+    broker.groups.spawn_expiration();
+    broker.spawn_log_cleanup()?;
+    broker.spawn_retention();
+    broker.spawn_transaction_expiration();
+
+    let mut accepting = Vec::new();
+    for (listener, socket) in sockets {
+        let acceptor = acceptor.clone().filter(|_| listener.protocol.uses_tls());
+        accepting.push(tokio::spawn(accept(
+            listener,
+            socket,
+            acceptor,
+            broker.clone(),
+        )));
+    }
+    for accepting in accepting {
+        accepting.await??;
+    }
+
+    Ok(())
+}
 
+/// Accepts the connections of one listener, with a TLS handshake first if
+/// it has an acceptor.
+async fn accept(
+    listener: Listener,
+    socket: TcpListener,
+    acceptor: Option<TlsAcceptor>,
+    broker: Arc<Broker>,
+) -> std::io::Result<()> {
     loop {
-        let (mut socket, _) = listener.accept().await?;
+        let (socket, peer_addr) = socket.accept().await?;
+        let broker = broker.clone();
+        let acceptor = acceptor.clone();
+        let mut session = Session::new(listener.protocol.uses_sasl());
 
         tokio::spawn(async move {
-            let mut buf = BytesMut::with_capacity(1024);
-
-            loop {
-                buf.resize(buf.capacity(), 0);
-                let _n = match socket.read(&mut buf).await {
-                    Ok(0) => {
-                        println!("Connection closed by client.");
-                        return;
-                    }
-                    Ok(n) => n,
-                    Err(e) => {
-                        eprintln!("failed to read from socket; err = {e:?}");
-                        return;
-                    }
-                };
-                let result = handle_client(&buf[..], &mut socket).await;
-
-                if let Err(result) = result {
-                    eprintln!("{:?}", result)
+            let Some(acceptor) = acceptor else {
+                return serve(socket, peer_addr, broker, session).await;
+            };
+
+            let socket = match acceptor.accept(socket).await {
+                Ok(socket) => socket,
+                Err(e) => {
+                    eprintln!("TLS handshake with {peer_addr} failed: {e}");
+                    return;
                 }
-            }
+            };
+            // A verified client certificate authenticates its subject.
+            session.user = socket
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certificates| certificates.first())
+                .and_then(certificate_user);
+            serve(socket, peer_addr, broker, session).await
         });
     }
 }
+
+/// Serves the requests of one connection until it is closed.
+async fn serve<S: Transport>(
+    mut socket: S,
+    peer_addr: SocketAddr,
+    broker: Arc<Broker>,
+    session: Session,
+) {
+    let session = Arc::new(Mutex::new(session));
+
+    loop {
+        // Every request is an INT32 size followed by that many bytes.
+        let size = match socket.read_i32().await {
+            Ok(size) => size.max(0) as usize,
+            Err(_) => {
+                println!("Connection closed by client.");
+                return;
+            }
+        };
+
+        let mut buf = BytesMut::zeroed(size);
+        if let Err(e) = socket.read_exact(&mut buf).await {
+            eprintln!("failed to read from socket; err = {e:?}");
+            return;
+        }
+
+        let result = handle_client(&buf.freeze(), peer_addr, &broker, &session, &mut socket).await;
+
+        match result {
+            Err(e) if e.is::<AuthenticationRequired>() || e.is::<WriteFailed>() => {
+                eprintln!("closing connection from {peer_addr}: {e}");
+                return;
+            }
+            Err(e) => eprintln!("{:?}", e),
+            // A client over its quota is not read from until its throttle
+            // time is over.
+            Ok(throttle) => tokio::time::sleep(throttle).await,
+        }
+    }
+}
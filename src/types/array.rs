@@ -1,214 +1,306 @@
-use std::{fmt::Debug, u8};
+use std::ops::{Deref, DerefMut};
 
-use crate::*;
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{frame::Frame, *};
 
 use super::{
-    signed_varint_bytes_wide, unsigned_varint_bytes_wide, uvarint::UVarint, varint::Varint,
+    checked_count, decode_signed_varint, decode_unsigned_varint, encode_signed_varint,
+    encode_unsigned_varint, length_prefix, signed_varint_bytes_wide, unsigned_varint_bytes_wide,
+    uvarint::UVarint, varint::Varint,
 };
 
-#[derive(Debug)]
-pub struct CSignedVec<T>
-where
-    T: Encode + Decode + Debug,
-{
-    pub data: Vec<T>,
+/// `ARRAY`: an `INT32` element count followed by the elements. Never null.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Array32<T>(pub Vec<T>);
+
+/// `ARRAY` that may be null, encoded with a count of `-1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NullableArray32<T>(pub Option<Vec<T>>);
+
+/// `COMPACT_ARRAY`: an unsigned varint holding `count + 1` followed by the
+/// elements. Never null.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactArray<T>(pub Vec<T>);
+
+/// `COMPACT_ARRAY` that may be null, encoded with a count prefix of `0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactNullableArray<T>(pub Option<Vec<T>>);
+
+/// An array prefixed by a zigzag varint count, as used by record headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarintArray<T>(pub Vec<T>);
+
+fn decode_elements<T: Decode>(bytes: &Bytes, offset: &mut usize, count: usize) -> Vec<T> {
+    let count = checked_count(bytes, offset, count);
+    (0..count).map(|_| T::decode(bytes, offset)).collect()
 }
 
-impl<T> Encode for CSignedVec<T>
-where
-    T: Encode + Debug + Decode,
-{
-    fn encode(&self) -> Vec<u8> {
-        let mut v = Vec::new();
+fn encode_elements<T: Encode>(buf: &mut BytesMut, data: &[T]) {
+    for value in data {
+        value.encode(buf);
+    }
+}
 
-        if self.data.is_empty() {
-            return vec![0];
+fn elements_size<T: Size>(data: &[T]) -> usize {
+    data.iter().map(|e| e.size_in_bytes()).sum()
+}
+
+macro_rules! impl_array_helpers {
+    ($name:ident) => {
+        impl<T> Default for $name<T> {
+            fn default() -> Self {
+                Self(Vec::new())
+            }
         }
 
-        let size = Varint::new(self.data.len() as i64);
+        impl<T> From<Vec<T>> for $name<T> {
+            fn from(vec: Vec<T>) -> Self {
+                Self(vec)
+            }
+        }
 
-        v.extend_from_slice(&size.encode());
+        impl<T> Deref for $name<T> {
+            type Target = Vec<T>;
 
-        for value in &self.data {
-            v.extend_from_slice(&value.encode());
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
         }
-        v
-    }
+
+        impl<T> DerefMut for $name<T> {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.0
+            }
+        }
+
+        impl<'a, T> IntoIterator for &'a $name<T> {
+            type Item = &'a T;
+            type IntoIter = std::slice::Iter<'a, T>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.0.iter()
+            }
+        }
+    };
 }
 
-impl<T> Decode for CSignedVec<T>
-where
-    T: Decode + Encode + Debug,
-{
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let size = Varint::decode(bytes, offset);
+macro_rules! impl_nullable_array_helpers {
+    ($name:ident) => {
+        impl<T> Default for $name<T> {
+            fn default() -> Self {
+                Self(Some(Vec::new()))
+            }
+        }
 
-        if size.0 == 0 || size.0 == -1 {
-            return Self { data: vec![] };
+        impl<T> From<Vec<T>> for $name<T> {
+            fn from(vec: Vec<T>) -> Self {
+                Self(Some(vec))
+            }
         }
 
-        let data = (0..size.0).map(|_| T::decode(bytes, offset)).collect();
+        impl<T> From<Option<Vec<T>>> for $name<T> {
+            fn from(vec: Option<Vec<T>>) -> Self {
+                Self(vec)
+            }
+        }
 
-        Self { data }
-    }
+        impl<T> $name<T> {
+            pub fn null() -> Self {
+                Self(None)
+            }
+
+            pub fn as_slice(&self) -> &[T] {
+                self.0.as_deref().unwrap_or(&[])
+            }
+        }
+    };
 }
 
-impl<T> Offset for CSignedVec<T>
-where
-    T: Decode + Encode + Debug,
-{
-    fn size(&self) -> usize {
-        self.data.len()
+impl_array_helpers!(Array32);
+impl_array_helpers!(CompactArray);
+impl_array_helpers!(VarintArray);
+impl_nullable_array_helpers!(NullableArray32);
+impl_nullable_array_helpers!(CompactNullableArray);
+
+impl<T: Encode> Encode for Array32<T> {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_i32(length_prefix(self.0.len()));
+        encode_elements(buf, &self.0);
     }
 }
 
-impl<T> Size for CSignedVec<T>
-where
-    T: Decode + Encode + Debug + Size,
-{
-    fn size_in_bytes(&self) -> usize {
-        let size_prefix = signed_varint_bytes_wide(self.data.len());
-        let elements_size: usize = self.data.iter().map(|e| e.size_in_bytes()).sum();
+impl<T: Decode> Decode for Array32<T> {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let size = i32::decode(bytes, offset);
 
-        size_prefix + elements_size
+        Self(decode_elements(bytes, offset, size.max(0) as usize))
     }
 }
 
-impl<T> From<Vec<T>> for CVec<T>
-where
-    T: Encode + Decode + Debug,
-{
-    fn from(vec: Vec<T>) -> Self {
-        CVec { data: vec }
+impl<T: Size> Size for Array32<T> {
+    fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<i32>() + elements_size(&self.0)
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct CVec<T>
-where
-    T: Encode + Decode + Debug,
-{
-    pub data: Vec<T>,
+impl<T: Encode> Encode for NullableArray32<T> {
+    fn encode(&self, buf: &mut BytesMut) {
+        match &self.0 {
+            Some(data) => {
+                buf.put_i32(length_prefix(data.len()));
+                encode_elements(buf, data);
+            }
+            None => buf.put_i32(-1),
+        }
+    }
 }
 
-impl<T> Encode for CVec<T>
-where
-    T: Encode + Debug + Decode,
-{
-    fn encode(&self) -> Vec<u8> {
-        let mut v = Vec::new();
+impl<T: Decode> Decode for NullableArray32<T> {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let size = i32::decode(bytes, offset);
 
-        if self.data.is_empty() {
-            return vec![0];
+        if size < 0 {
+            return Self(None);
         }
 
-        let size = UVarint::new(
-            (self.data.len() - 1) as u64,
-            unsigned_varint_bytes_wide(self.data.len()),
-        );
+        Self(Some(decode_elements(bytes, offset, size as usize)))
+    }
+}
 
-        v.extend_from_slice(&size.encode());
+impl<T: Size> Size for NullableArray32<T> {
+    fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<i32>() + self.0.as_deref().map_or(0, elements_size)
+    }
+}
 
-        for value in &self.data {
-            v.extend_from_slice(&value.encode());
-        }
-        v
+impl<T: Encode> Encode for CompactArray<T> {
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_unsigned_varint(self.0.len() as u64 + 1, buf);
+        encode_elements(buf, &self.0);
     }
 }
 
-impl<T> Decode for CVec<T>
-where
-    T: Decode + Encode + Debug,
-{
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let size = UVarint::decode(bytes, offset);
+impl<T: Decode> Decode for CompactArray<T> {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (size, _) = decode_unsigned_varint(bytes, offset);
 
-        if size.0 == 0 {
-            *offset += 1;
-            return Self { data: vec![] };
-        }
+        Self(decode_elements(
+            bytes,
+            offset,
+            size.saturating_sub(1) as usize,
+        ))
+    }
+}
 
-        let data = (0..size.0 - 1).map(|_| T::decode(bytes, offset)).collect();
+impl<T: Size> Size for CompactArray<T> {
+    fn size_in_bytes(&self) -> usize {
+        unsigned_varint_bytes_wide(self.0.len() + 1) + elements_size(&self.0)
+    }
+}
 
-        Self { data }
+impl<T: Encode> Encode for CompactNullableArray<T> {
+    fn encode(&self, buf: &mut BytesMut) {
+        match &self.0 {
+            Some(data) => {
+                encode_unsigned_varint(data.len() as u64 + 1, buf);
+                encode_elements(buf, data);
+            }
+            None => buf.put_u8(0),
+        }
     }
 }
 
-impl<T> Offset for CVec<T>
-where
-    T: Decode + Encode + Debug,
-{
-    fn size(&self) -> usize {
-        self.data.len()
+impl<T: Decode> Decode for CompactNullableArray<T> {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (size, _) = decode_unsigned_varint(bytes, offset);
+
+        if size == 0 {
+            return Self(None);
+        }
+
+        Self(Some(decode_elements(bytes, offset, (size - 1) as usize)))
     }
 }
 
-impl<T> Size for CVec<T>
-where
-    T: Decode + Encode + Debug + Size,
-{
+impl<T: Size> Size for CompactNullableArray<T> {
     fn size_in_bytes(&self) -> usize {
-        let size_prefix = unsigned_varint_bytes_wide(self.data.len() + 1);
-        let elements_size: usize = self.data.iter().map(|e| e.size_in_bytes()).sum();
-
-        size_prefix + elements_size
+        match &self.0 {
+            Some(data) => unsigned_varint_bytes_wide(data.len() + 1) + elements_size(data),
+            None => 1,
+        }
     }
 }
 
-impl<T> Encode for Vec<T>
-where
-    T: Encode + Debug,
-{
-    fn encode(&self) -> Vec<u8> {
-        let mut v = Vec::new();
+impl<T: Encode> Encode for VarintArray<T> {
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_signed_varint(self.0.len() as i64, buf);
+        encode_elements(buf, &self.0);
+    }
+}
 
-        let size: i32 = self.len() as i32;
+impl<T: Decode> Decode for VarintArray<T> {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (size, _) = decode_signed_varint(bytes, offset);
 
-        if size == 0 {
-            return vec![u8::MAX];
-        }
+        Self(decode_elements(bytes, offset, size.max(0) as usize))
+    }
+}
 
-        v.extend_from_slice(&size.encode());
+impl<T: Size> Size for VarintArray<T> {
+    fn size_in_bytes(&self) -> usize {
+        signed_varint_bytes_wide(self.0.len() as i64) + elements_size(&self.0)
+    }
+}
 
-        for value in self {
-            v.extend_from_slice(&value.encode());
-        }
-        v
+fn encode_frame_elements<T: EncodeFrame>(frame: &mut Frame, data: &[T]) {
+    for value in data {
+        value.encode_frame(frame);
     }
 }
 
-impl<T> Decode for Vec<T>
-where
-    T: Decode + Debug,
-{
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let size = i32::decode(bytes, offset);
+impl<T: EncodeFrame> EncodeFrame for Array32<T> {
+    fn encode_frame(&self, frame: &mut Frame) {
+        frame.put(&length_prefix::<i32>(self.0.len()));
+        encode_frame_elements(frame, &self.0);
+    }
+}
 
-        if size == -1 || size == 0 {
-            *offset += 1;
-            return vec![];
+impl<T: EncodeFrame> EncodeFrame for NullableArray32<T> {
+    fn encode_frame(&self, frame: &mut Frame) {
+        match &self.0 {
+            Some(data) => {
+                frame.put(&length_prefix::<i32>(data.len()));
+                encode_frame_elements(frame, data);
+            }
+            None => frame.put(&-1i32),
         }
-
-        let r = (0..size).map(|_| T::decode(bytes, offset)).collect();
-        r
     }
 }
 
-impl<T> Offset for Vec<T> {
-    fn size(&self) -> usize {
-        self.len()
+impl<T: EncodeFrame> EncodeFrame for CompactArray<T> {
+    fn encode_frame(&self, frame: &mut Frame) {
+        frame.put(&UVarint(self.0.len() as u64 + 1));
+        encode_frame_elements(frame, &self.0);
     }
 }
 
-impl<T> Size for Vec<T>
-where
-    T: Size,
-{
-    fn size_in_bytes(&self) -> usize {
-        let size_prefix = std::mem::size_of::<i32>();
-        let elements_size: usize = self.iter().map(|e| e.size_in_bytes()).sum();
+impl<T: EncodeFrame> EncodeFrame for CompactNullableArray<T> {
+    fn encode_frame(&self, frame: &mut Frame) {
+        match &self.0 {
+            Some(data) => {
+                frame.put(&UVarint(data.len() as u64 + 1));
+                encode_frame_elements(frame, data);
+            }
+            None => frame.put(&0u8),
+        }
+    }
+}
 
-        size_prefix + elements_size
+impl<T: EncodeFrame> EncodeFrame for VarintArray<T> {
+    fn encode_frame(&self, frame: &mut Frame) {
+        frame.put(&Varint(self.0.len() as i64));
+        encode_frame_elements(frame, &self.0);
     }
 }
 
@@ -218,10 +310,8 @@ mod tests {
 
     #[test]
     fn test_cvec_encode() {
-        let data = CVec {
-            data: vec![0x01 as u8, 0x02 as u8, 0x03 as u8],
-        };
-        let encoded = data.encode();
+        let data = CompactArray(vec![0x01_u8, 0x02_u8, 0x03_u8]);
+        let encoded = data.to_bytes();
 
         let expected: Vec<u8> = vec![4, 1, 2, 3];
 
@@ -230,58 +320,93 @@ mod tests {
 
     #[test]
     fn test_cvec_decode() {
-        let bytes: &[u8] = &[4, 1, 2, 3];
+        let bytes = Bytes::from_static(&[4, 1, 2, 3]);
         let mut offset = 0;
 
-        let decoded: CVec<u8> = CVec::decode(bytes, &mut offset);
-
-        let expected = CVec {
-            data: vec![1, 2, 3],
-        };
+        let decoded: CompactArray<u8> = CompactArray::decode(&bytes, &mut offset);
 
-        assert_eq!(decoded.data, expected.data);
+        assert_eq!(decoded.0, vec![1, 2, 3]);
         assert_eq!(offset, bytes.len());
     }
 
+    #[test]
+    fn test_counts_beyond_the_input_fail() {
+        let bytes = Bytes::from_static(&[0x7f, 0xff, 0xff, 0xff, 1, 2]);
+        assert!(Array32::<i64>::try_decode(&bytes, &mut 0).is_err());
+
+        let bytes = Bytes::from_static(&[0xff, 0xff, 0xff, 0xff, 0x0f, 1]);
+        assert!(CompactArray::<u8>::try_decode(&bytes, &mut 0).is_err());
+
+        // An element cut short fails too, while a whole array decodes.
+        let bytes = Bytes::from_static(&[3, 0, 0, 0, 1, 0, 0]);
+        assert!(CompactArray::<i32>::try_decode(&bytes, &mut 0).is_err());
+        let bytes = Bytes::from_static(&[2, 0, 0, 0, 1]);
+        let decoded = CompactArray::<i32>::try_decode(&bytes, &mut 0).unwrap();
+        assert_eq!(decoded.0, [1]);
+    }
+
     #[test]
     fn test_cvec_size_in_bytes() {
-        let data = CVec {
-            data: vec![1, 2, 3],
-        };
+        let data = CompactArray(vec![1, 2, 3]);
 
         assert_eq!(data.size_in_bytes(), 13);
     }
 
     #[test]
     fn test_empty_cvec_encode_decode() {
-        let data = CVec::<u8> { data: Vec::new() };
-        let encoded = data.encode();
+        let data = CompactArray::<u8>(Vec::new());
+        let encoded = data.to_bytes();
 
-        let expected: Vec<u8> = vec![0];
+        let expected: Vec<u8> = vec![1];
 
         assert_eq!(encoded, expected);
 
         let mut offset = 0;
-        let decoded: CVec<u8> = CVec::decode(&encoded, &mut offset);
+        let decoded: CompactArray<u8> = CompactArray::decode(&encoded, &mut offset);
 
-        assert_eq!(decoded.data, Vec::<u8>::new());
-        assert_eq!(offset, encoded.len() + 1);
+        assert_eq!(decoded.0, Vec::<u8>::new());
+        assert_eq!(offset, encoded.len());
     }
 
     #[test]
     fn test_empty_cvec_size_in_bytes() {
-        let data = CVec::<u8> { data: Vec::new() };
+        let data = CompactArray::<u8>(Vec::new());
 
         assert_eq!(data.size_in_bytes(), 1);
     }
 
+    #[test]
+    fn test_compact_nullable_array_null_and_empty() {
+        let null = CompactNullableArray::<u8>(None);
+        let empty = CompactNullableArray::<u8>(Some(vec![]));
+
+        assert_eq!(null.to_bytes(), vec![0]);
+        assert_eq!(empty.to_bytes(), vec![1]);
+        assert_eq!(null.size_in_bytes(), 1);
+        assert_eq!(empty.size_in_bytes(), 1);
+
+        let mut offset = 0;
+        assert_eq!(
+            CompactNullableArray::<u8>::decode(&Bytes::from_static(&[0]), &mut offset),
+            null
+        );
+        assert_eq!(offset, 1);
+
+        let mut offset = 0;
+        assert_eq!(
+            CompactNullableArray::<u8>::decode(&Bytes::from_static(&[1]), &mut offset),
+            empty
+        );
+        assert_eq!(offset, 1);
+    }
+
     #[test]
     fn test_vec_encode() {
-        let data = vec![1 as u8, 2 as u8, 3 as u8];
-        let encoded = data.encode();
+        let data = Array32(vec![1_u8, 2_u8, 3_u8]);
+        let encoded = data.to_bytes();
 
         let mut expected: Vec<u8> = vec![];
-        expected.extend_from_slice(&3i32.encode());
+        expected.extend_from_slice(&3i32.to_be_bytes());
         expected.extend_from_slice(&[1, 2, 3]);
 
         assert_eq!(encoded, expected);
@@ -289,43 +414,40 @@ mod tests {
 
     #[test]
     fn test_empty_vec_encode() {
-        let data: Vec<u8> = Vec::new();
-        let encoded = data.encode();
+        let data: Array32<u8> = Array32(Vec::new());
+        let encoded = data.to_bytes();
 
-        let expected: Vec<u8> = vec![255];
+        let expected: Vec<u8> = vec![0, 0, 0, 0];
 
         assert_eq!(encoded, expected);
     }
 
     #[test]
     fn test_vec_decode() {
-        let bytes: &[u8] = &[0, 0, 0, 3 as u8, 1 as u8, 2 as u8, 3 as u8];
+        let bytes = Bytes::from_static(&[0, 0, 0, 3_u8, 1_u8, 2_u8, 3_u8]);
         let mut offset = 0;
 
-        let decoded: Vec<u8> = Vec::decode(bytes, &mut offset);
-
-        let expected = vec![1, 2, 3];
+        let decoded: Array32<u8> = Array32::decode(&bytes, &mut offset);
 
-        assert_eq!(decoded, expected);
+        assert_eq!(decoded.0, vec![1, 2, 3]);
         assert_eq!(offset, bytes.len());
     }
 
     #[test]
-    fn test_empty_vec_decode() {
-        let bytes: &[u8] = &[255, 255, 255, 255];
+    fn test_null_vec_decode() {
+        let bytes = Bytes::from_static(&[255, 255, 255, 255]);
         let mut offset = 0;
 
-        let decoded: Vec<u8> = Vec::decode(bytes, &mut offset);
+        let decoded: NullableArray32<u8> = NullableArray32::decode(&bytes, &mut offset);
 
-        let expected: Vec<u8> = Vec::new();
-
-        assert_eq!(decoded, expected);
-        assert_eq!(offset, bytes.len() + 1);
+        assert_eq!(decoded.0, None);
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(offset, bytes.len());
     }
 
     #[test]
     fn test_vec_size_in_bytes() {
-        let data = vec![1, 2, 3];
+        let data = Array32(vec![1, 2, 3]);
 
         let expected_size = std::mem::size_of::<i32>() + (3 * std::mem::size_of::<i32>());
         assert_eq!(data.size_in_bytes(), expected_size);
@@ -333,7 +455,7 @@ mod tests {
 
     #[test]
     fn test_empty_vec_size_in_bytes() {
-        let data: Vec<u8> = Vec::new();
+        let data: Array32<u8> = Array32(Vec::new());
 
         let expected_size = std::mem::size_of::<i32>();
         assert_eq!(data.size_in_bytes(), expected_size);
@@ -1,48 +1,339 @@
-use std::vec;
+use std::ops::Deref;
 
-use crate::{Decode, Encode, Offset, Size};
+use bytes::{BufMut, Bytes, BytesMut};
 
-use super::{unsigned_varint_bytes_wide, uvarint::UVarint};
+use crate::{Decode, Encode, Size};
 
-#[derive(Debug)]
-pub struct ByteBuf(pub Vec<u8>, pub UVarint);
+use super::{
+    decode_signed_varint, decode_unsigned_varint, encode_signed_varint, encode_unsigned_varint,
+    length_prefix, signed_varint_bytes_wide, take, unsigned_varint_bytes_wide,
+};
+
+/// Raw bytes without a length prefix, running to the end of the buffer being
+/// decoded, as used for the records payload of a batch.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ByteBuf(pub Bytes);
 
 impl Decode for ByteBuf {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let slice = &bytes[*offset..];
-        *offset = bytes.len();
-        Self(
-            Vec::from(slice),
-            UVarint::new(slice.len() as u64, unsigned_varint_bytes_wide(slice.len())),
-        )
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let slice = bytes.slice((*offset).min(bytes.len())..);
+        *offset = (*offset).max(bytes.len());
+        Self(slice)
     }
 }
 
 impl ByteBuf {
     pub fn empty() -> Self {
-        ByteBuf(vec![], UVarint(0, 1))
+        ByteBuf(Bytes::new())
     }
 }
 
 impl Encode for ByteBuf {
-    fn encode(&self) -> Vec<u8> {
-        let mut v = Vec::new();
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_slice(&self.0);
+    }
+}
+
+impl Size for ByteBuf {
+    fn size_in_bytes(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// `BYTES`: an `INT32` length followed by raw bytes. Never null.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Bytes32(pub Bytes);
+
+/// `NULLABLE_BYTES`: like [`Bytes32`], but a length of `-1` means null.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NullableBytes32(pub Option<Bytes>);
+
+/// `COMPACT_BYTES`: an unsigned varint holding `length + 1` followed by raw
+/// bytes. Never null.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompactBytes(pub Bytes);
 
-        v.extend(self.1.encode());
-        v.extend(self.0.clone());
+/// `COMPACT_NULLABLE_BYTES` (and `COMPACT_RECORDS`): like [`CompactBytes`],
+/// but a length prefix of `0` means null.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompactNullableBytes(pub Option<Bytes>);
 
-        v
+/// Bytes prefixed by a zigzag varint length where `-1` means null, as used by
+/// record keys, values and header values.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NullableVarintBytes(pub Option<Bytes>);
+
+/// Takes `len` bytes as a view into the decoded buffer, without copying.
+pub(crate) fn decode_bytes(bytes: &Bytes, offset: &mut usize, len: usize) -> Bytes {
+    let start = *offset;
+    match take(bytes, offset, len) {
+        Some(_) => bytes.slice(start..*offset),
+        None => Bytes::new(),
     }
 }
 
-impl Size for ByteBuf {
+impl Encode for Bytes32 {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_i32(length_prefix(self.0.len()));
+        buf.put_slice(&self.0);
+    }
+}
+
+impl Decode for Bytes32 {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let len = i32::decode(bytes, offset);
+        Self(decode_bytes(bytes, offset, len.max(0) as usize))
+    }
+}
+
+impl Size for Bytes32 {
+    fn size_in_bytes(&self) -> usize {
+        4 + self.0.len()
+    }
+}
+
+impl Encode for NullableBytes32 {
+    fn encode(&self, buf: &mut BytesMut) {
+        match &self.0 {
+            Some(data) => {
+                buf.put_i32(length_prefix(data.len()));
+                buf.put_slice(data);
+            }
+            None => buf.put_i32(-1),
+        }
+    }
+}
+
+impl Decode for NullableBytes32 {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let len = i32::decode(bytes, offset);
+
+        if len < 0 {
+            return Self(None);
+        }
+
+        Self(Some(decode_bytes(bytes, offset, len as usize)))
+    }
+}
+
+impl Size for NullableBytes32 {
+    fn size_in_bytes(&self) -> usize {
+        4 + self.0.as_ref().map_or(0, |data| data.len())
+    }
+}
+
+impl Encode for CompactBytes {
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_unsigned_varint(self.0.len() as u64 + 1, buf);
+        buf.put_slice(&self.0);
+    }
+}
+
+impl Decode for CompactBytes {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (len, _) = decode_unsigned_varint(bytes, offset);
+        Self(decode_bytes(bytes, offset, len.saturating_sub(1) as usize))
+    }
+}
+
+impl Size for CompactBytes {
+    fn size_in_bytes(&self) -> usize {
+        unsigned_varint_bytes_wide(self.0.len() + 1) + self.0.len()
+    }
+}
+
+impl Encode for CompactNullableBytes {
+    fn encode(&self, buf: &mut BytesMut) {
+        match &self.0 {
+            Some(data) => {
+                encode_unsigned_varint(data.len() as u64 + 1, buf);
+                buf.put_slice(data);
+            }
+            None => buf.put_u8(0),
+        }
+    }
+}
+
+impl Decode for CompactNullableBytes {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (len, _) = decode_unsigned_varint(bytes, offset);
+
+        if len == 0 {
+            return Self(None);
+        }
+
+        Self(Some(decode_bytes(bytes, offset, (len - 1) as usize)))
+    }
+}
+
+impl Size for CompactNullableBytes {
+    fn size_in_bytes(&self) -> usize {
+        match &self.0 {
+            Some(data) => unsigned_varint_bytes_wide(data.len() + 1) + data.len(),
+            None => 1,
+        }
+    }
+}
+
+impl Encode for NullableVarintBytes {
+    fn encode(&self, buf: &mut BytesMut) {
+        match &self.0 {
+            Some(data) => {
+                encode_signed_varint(data.len() as i64, buf);
+                buf.put_slice(data);
+            }
+            None => encode_signed_varint(-1, buf),
+        }
+    }
+}
+
+impl Decode for NullableVarintBytes {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (len, _) = decode_signed_varint(bytes, offset);
+
+        if len < 0 {
+            return Self(None);
+        }
+
+        Self(Some(decode_bytes(bytes, offset, len as usize)))
+    }
+}
+
+impl Size for NullableVarintBytes {
     fn size_in_bytes(&self) -> usize {
-        self.1 .1 + self.0.len()
+        match &self.0 {
+            Some(data) => signed_varint_bytes_wide(data.len() as i64) + data.len(),
+            None => 1,
+        }
     }
 }
 
-impl Offset for ByteBuf {
-    fn size(&self) -> usize {
-        self.1 .1 + self.0.len()
+macro_rules! impl_bytes_helpers {
+    ($name:ident) => {
+        impl Deref for $name {
+            type Target = Bytes;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        impl From<Bytes> for $name {
+            fn from(data: Bytes) -> Self {
+                Self(data)
+            }
+        }
+
+        impl From<Vec<u8>> for $name {
+            fn from(data: Vec<u8>) -> Self {
+                Self(Bytes::from(data))
+            }
+        }
+    };
+}
+
+macro_rules! impl_nullable_bytes_helpers {
+    ($name:ident) => {
+        impl $name {
+            pub fn null() -> Self {
+                Self(None)
+            }
+
+            pub fn as_deref(&self) -> Option<&[u8]> {
+                self.0.as_deref()
+            }
+        }
+
+        impl From<Option<Bytes>> for $name {
+            fn from(data: Option<Bytes>) -> Self {
+                Self(data)
+            }
+        }
+
+        impl From<Bytes> for $name {
+            fn from(data: Bytes) -> Self {
+                Self(Some(data))
+            }
+        }
+
+        impl From<Vec<u8>> for $name {
+            fn from(data: Vec<u8>) -> Self {
+                Self(Some(Bytes::from(data)))
+            }
+        }
+    };
+}
+
+impl_bytes_helpers!(Bytes32);
+impl_bytes_helpers!(CompactBytes);
+impl_nullable_bytes_helpers!(NullableBytes32);
+impl_nullable_bytes_helpers!(CompactNullableBytes);
+impl_nullable_bytes_helpers!(NullableVarintBytes);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nullable_bytes_null_and_empty() {
+        let cases = vec![
+            (
+                NullableBytes32(None).to_bytes(),
+                vec![0xff, 0xff, 0xff, 0xff],
+            ),
+            (
+                NullableBytes32(Some(Bytes::new())).to_bytes(),
+                vec![0, 0, 0, 0],
+            ),
+            (CompactNullableBytes(None).to_bytes(), vec![0]),
+            (CompactNullableBytes(Some(Bytes::new())).to_bytes(), vec![1]),
+            (NullableVarintBytes(None).to_bytes(), vec![1]),
+            (NullableVarintBytes(Some(Bytes::new())).to_bytes(), vec![0]),
+        ];
+
+        for (encoded, expected) in cases {
+            assert_eq!(encoded, expected);
+        }
+
+        let mut offset = 0;
+        assert_eq!(
+            NullableVarintBytes::decode(&Bytes::from_static(&[1]), &mut offset),
+            NullableVarintBytes(None)
+        );
+        let mut offset = 0;
+        assert_eq!(
+            CompactNullableBytes::decode(&Bytes::from_static(&[1]), &mut offset),
+            CompactNullableBytes(Some(Bytes::new()))
+        );
+    }
+
+    #[test]
+    fn test_decode_slices_without_copying() {
+        let buf = Bytes::from(vec![0, 0, 0, 2, 7, 8, 9]);
+
+        let mut offset = 0;
+        let value = Bytes32::decode(&buf, &mut offset);
+        assert_eq!(value.as_ptr(), buf[4..].as_ptr());
+
+        let rest = ByteBuf::decode(&buf, &mut offset);
+        assert_eq!(rest.0.as_ptr(), buf[6..].as_ptr());
+        assert_eq!(offset, buf.len());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let data = Bytes::from_static(&[1, 2, 3, 4]);
+
+        let compact = CompactBytes(data.clone());
+        let encoded = compact.to_bytes();
+        assert_eq!(encoded, vec![5, 1, 2, 3, 4]);
+        assert_eq!(compact.size_in_bytes(), encoded.len());
+        assert_eq!(CompactBytes::decode(&encoded, &mut 0), compact);
+
+        let bytes32 = Bytes32(data);
+        let encoded = bytes32.to_bytes();
+        assert_eq!(encoded, vec![0, 0, 0, 4, 1, 2, 3, 4]);
+        assert_eq!(bytes32.size_in_bytes(), encoded.len());
+        assert_eq!(Bytes32::decode(&encoded, &mut 0), bytes32);
     }
 }
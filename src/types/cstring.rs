@@ -1,160 +1,244 @@
-use crate::{types::varint::Varint, *};
+use std::ops::Deref;
 
-use super::uvarint::UVarint;
+use bytes::{BufMut, BytesMut};
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct CSignedString(pub String, pub usize);
+use crate::*;
 
-impl Decode for CSignedString {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let mut len = Varint::decode(bytes, offset);
+use super::{
+    decode_signed_varint, decode_unsigned_varint, encode_signed_varint, encode_unsigned_varint,
+    fail, signed_varint_bytes_wide, take, unsigned_varint_bytes_wide,
+};
 
-        if len.0 < 1 {
-            len.0 = 1;
+/// `COMPACT_STRING`: an unsigned varint holding `length + 1` followed by
+/// UTF-8 bytes. Never null.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+pub struct CompactString(pub String);
+
+/// `COMPACT_NULLABLE_STRING`: like [`CompactString`], but a length prefix
+/// of `0` means null.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+pub struct CompactNullableString(pub Option<String>);
+
+/// A string prefixed by a zigzag varint length, as used by record header keys.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+pub struct VarintString(pub String);
+
+fn decode_string(bytes: &[u8], offset: &mut usize, len: usize) -> String {
+    match take(bytes, offset, len).map(std::str::from_utf8) {
+        Some(Ok(value)) => value.to_string(),
+        Some(Err(_)) => {
+            fail(bytes, offset);
+            String::new()
         }
+        None => String::new(),
+    }
+}
 
-        let value = String::from_utf8(bytes[*offset..*offset + (len.0 as usize)].to_vec()).unwrap();
+impl Decode for CompactString {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (len, _) = decode_unsigned_varint(bytes, offset);
 
-        *offset += value.len();
+        Self(decode_string(bytes, offset, len.saturating_sub(1) as usize))
+    }
+}
 
-        Self(value, len.get_size())
+impl Encode for CompactString {
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_unsigned_varint(self.0.len() as u64 + 1, buf);
+        buf.put_slice(self.0.as_bytes());
     }
 }
 
-impl Encode for CSignedString {
-    fn encode(&self) -> Vec<u8> {
-        let mut v = Vec::new();
+impl Size for CompactString {
+    fn size_in_bytes(&self) -> usize {
+        unsigned_varint_bytes_wide(self.0.len() + 1) + self.0.len()
+    }
+}
 
-        // Calculate size with Varint encoding
-        let size = Varint::new((self.0.len() - 1) as i64);
-        v.extend_from_slice(&size.encode());
-        v.extend_from_slice(self.0.as_bytes());
+impl Deref for CompactString {
+    type Target = String;
 
-        v
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
 }
 
-impl Offset for CSignedString {
-    fn size(&self) -> usize {
-        self.1 + self.0.len()
+impl From<&str> for CompactString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
     }
 }
 
-impl Size for CSignedString {
-    fn size_in_bytes(&self) -> usize {
-        self.1 + self.0.len()
+impl From<String> for CompactString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl Decode for CompactNullableString {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (len, _) = decode_unsigned_varint(bytes, offset);
+
+        if len == 0 {
+            return Self(None);
+        }
+
+        Self(Some(decode_string(bytes, offset, (len - 1) as usize)))
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct CString(pub String, pub usize);
+impl Encode for CompactNullableString {
+    fn encode(&self, buf: &mut BytesMut) {
+        match &self.0 {
+            Some(value) => {
+                encode_unsigned_varint(value.len() as u64 + 1, buf);
+                buf.put_slice(value.as_bytes());
+            }
+            None => buf.put_u8(0),
+        }
+    }
+}
 
-impl Decode for CString {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let mut len = UVarint::decode(bytes, offset);
-        if len.0 < 1 {
-            len.0 = 1
+impl Size for CompactNullableString {
+    fn size_in_bytes(&self) -> usize {
+        match &self.0 {
+            Some(value) => unsigned_varint_bytes_wide(value.len() + 1) + value.len(),
+            None => 1,
         }
-        let value =
-            String::from_utf8(bytes[*offset..*offset + (len.0 - 1) as usize].to_vec()).unwrap();
-        *offset += value.len();
+    }
+}
+
+impl CompactNullableString {
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+impl From<Option<String>> for CompactNullableString {
+    fn from(value: Option<String>) -> Self {
+        Self(value)
+    }
+}
 
-        Self(value, len.1)
+impl From<&str> for CompactNullableString {
+    fn from(value: &str) -> Self {
+        Self(Some(value.to_string()))
     }
 }
 
-impl Encode for CString {
-    fn encode(&self) -> Vec<u8> {
-        let mut v = Vec::new();
+impl Decode for VarintString {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (len, _) = decode_signed_varint(bytes, offset);
 
-        let size = UVarint::new((self.0.len() - 1) as u64, self.1);
-        v.extend_from_slice(&size.encode());
-        v.extend_from_slice(self.0.as_bytes());
-        v
+        Self(decode_string(bytes, offset, len.max(0) as usize))
     }
 }
 
-impl Offset for CString {
-    fn size(&self) -> usize {
-        self.1 + self.0.len()
+impl Encode for VarintString {
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_signed_varint(self.0.len() as i64, buf);
+        buf.put_slice(self.0.as_bytes());
     }
 }
 
-impl Size for CString {
+impl Size for VarintString {
     fn size_in_bytes(&self) -> usize {
-        self.1 + self.0.len()
-    }
-}
-
-//#[cfg(test)]
-//mod tests {
-//    use super::*;
-//
-//    #[test]
-//    fn test_cstring_offset_size() {
-//        let test_cases = vec![
-//            ("".to_string(), 1, 1),
-//            ("hello".to_string(), 6, 1),
-//            ("world!".to_string(), 7, 1),
-//            ("A".repeat(128), 130, 2),
-//        ];
-//
-//        for (input, expected_size, bytes_wide) in test_cases {
-//            let cstring = CString(input, bytes_wide);
-//            assert_eq!(cstring.size(), expected_size);
-//            assert_eq!(cstring.size_in_bytes(), expected_size);
-//            assert_eq!(cstring.1, bytes_wide);
-//        }
-//    }
-//
-//    #[test]
-//    fn test_decode_encode_cstring() {
-//        let test_cases = vec![
-//            "hey que pasa chavalones".to_string(),
-//            "heyheyheyhey".to_string(),
-//            "Long string with spaces and special chars: !@#$%^&*()_+=-`~[]\\{}|;':\",./<>?"
-//                .to_string(),
-//        ];
-//
-//        for value in test_cases {
-//            let encoded = CString(value.clone(), value.len());
-//
-//            let encoded_value = CString::encode(&encoded);
-//
-//            let decoded = CString::decode(&encoded_value[..], &mut 0);
-//
-//            assert_eq!(decoded.0, value);
-//        }
-//    }
-//
-//    #[test]
-//    fn test_decode_from_byte_stream() {
-//        let test_cases = vec![
-//            (vec![0], "".to_string(), 1),
-//            (vec![5, 104, 101, 108, 108, 111], "hello".to_string(), 6),
-//            (
-//                vec![6, 119, 111, 114, 108, 100, 33],
-//                "world!".to_string(),
-//                7,
-//            ),
-//            (
-//                {
-//                    let mut v = vec![128, 1];
-//                    v.extend(vec![65; 128]);
-//                    v
-//                },
-//                "A".repeat(128),
-//                130,
-//            ),
-//        ];
-//
-//        for (bytes, expected_string, expected_size) in test_cases {
-//            let mut offset = 0;
-//            let decoded = CString::decode(&bytes, &mut offset);
-//
-//            assert_eq!(decoded.0, expected_string);
-//            assert_eq!(decoded.size(), expected_size);
-//            assert_eq!(decoded.size_in_bytes(), expected_size);
-//        }
-//    }
-//}
+        signed_varint_bytes_wide(self.0.len() as i64) + self.0.len()
+    }
+}
+
+impl Deref for VarintString {
+    type Target = String;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_string_size() {
+        let test_cases = vec![
+            ("".to_string(), 1),
+            ("hello".to_string(), 6),
+            ("world!".to_string(), 7),
+            ("A".repeat(128), 130),
+        ];
+
+        for (input, expected_size) in test_cases {
+            let string = CompactString(input);
+            assert_eq!(string.size_in_bytes(), expected_size);
+            assert_eq!(string.to_bytes().len(), expected_size);
+        }
+    }
+
+    #[test]
+    fn test_decode_from_byte_stream() {
+        let test_cases = vec![
+            (vec![1], "".to_string(), 1),
+            (vec![6, 104, 101, 108, 108, 111], "hello".to_string(), 6),
+            (
+                vec![7, 119, 111, 114, 108, 100, 33],
+                "world!".to_string(),
+                7,
+            ),
+            (
+                {
+                    let mut v = vec![129, 1];
+                    v.extend(vec![65; 128]);
+                    v
+                },
+                "A".repeat(128),
+                130,
+            ),
+        ];
+
+        for (bytes, expected_string, expected_size) in test_cases {
+            let mut offset = 0;
+            let decoded = CompactString::decode(&Bytes::from(bytes.clone()), &mut offset);
+
+            assert_eq!(decoded.0, expected_string);
+            assert_eq!(decoded.size_in_bytes(), expected_size);
+            assert_eq!(offset, expected_size);
+            assert_eq!(decoded.to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_compact_nullable_string_null_and_empty() {
+        let null = CompactNullableString(None);
+        let empty = CompactNullableString(Some(String::new()));
+
+        assert_eq!(null.to_bytes(), vec![0]);
+        assert_eq!(empty.to_bytes(), vec![1]);
+
+        let mut offset = 0;
+        assert_eq!(
+            CompactNullableString::decode(&Bytes::from_static(&[0]), &mut offset),
+            null
+        );
+        assert_eq!(offset, 1);
+
+        let mut offset = 0;
+        assert_eq!(
+            CompactNullableString::decode(&Bytes::from_static(&[1]), &mut offset),
+            empty
+        );
+        assert_eq!(offset, 1);
+    }
+
+    #[test]
+    fn test_varint_string_round_trip() {
+        let value = VarintString("header".to_string());
+        let encoded = value.to_bytes();
+
+        assert_eq!(encoded[0], 12);
+
+        let mut offset = 0;
+        assert_eq!(VarintString::decode(&encoded, &mut offset), value);
+        assert_eq!(offset, value.size_in_bytes());
+    }
+}
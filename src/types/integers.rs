@@ -1,24 +1,18 @@
+use bytes::{BufMut, BytesMut};
+
 use crate::*;
 
+use super::take_array;
+
 impl Encode for i8 {
-    fn encode(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_i8(*self);
     }
 }
 
 impl Decode for i8 {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let mut array = [0u8; 1];
-        array.copy_from_slice(&bytes[*offset..*offset + 1]);
-        *offset += 1;
-        let r = i8::from_be_bytes(array);
-        r
-    }
-}
-
-impl Offset for i8 {
-    fn size(&self) -> usize {
-        std::mem::size_of::<i8>()
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        i8::from_be_bytes(take_array(bytes, offset))
     }
 }
 
@@ -29,24 +23,14 @@ impl Size for i8 {
 }
 
 impl Encode for u8 {
-    fn encode(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u8(*self);
     }
 }
 
 impl Decode for u8 {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let mut array = [0u8; 1];
-        array.copy_from_slice(&bytes[*offset..*offset + 1]);
-        *offset += 1;
-        let r = u8::from_be_bytes(array);
-        r
-    }
-}
-
-impl Offset for u8 {
-    fn size(&self) -> usize {
-        std::mem::size_of::<u8>()
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        u8::from_be_bytes(take_array(bytes, offset))
     }
 }
 
@@ -57,24 +41,14 @@ impl Size for u8 {
 }
 
 impl Encode for i16 {
-    fn encode(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_i16(*self);
     }
 }
 
 impl Decode for i16 {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let mut array = [0u8; 2];
-        array.copy_from_slice(&bytes[*offset..*offset + 2]);
-        *offset += 2;
-        let r = i16::from_be_bytes(array);
-        r
-    }
-}
-
-impl Offset for i16 {
-    fn size(&self) -> usize {
-        std::mem::size_of::<i16>()
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        i16::from_be_bytes(take_array(bytes, offset))
     }
 }
 
@@ -85,24 +59,14 @@ impl Size for i16 {
 }
 
 impl Encode for i32 {
-    fn encode(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_i32(*self);
     }
 }
 
 impl Decode for i32 {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let mut array = [0u8; 4];
-        array.copy_from_slice(&bytes[*offset..*offset + 4]);
-        *offset += 4;
-        let r = i32::from_be_bytes(array);
-        r
-    }
-}
-
-impl Offset for i32 {
-    fn size(&self) -> usize {
-        std::mem::size_of::<i32>()
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        i32::from_be_bytes(take_array(bytes, offset))
     }
 }
 
@@ -113,24 +77,14 @@ impl Size for i32 {
 }
 
 impl Encode for i64 {
-    fn encode(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_i64(*self);
     }
 }
 
 impl Decode for i64 {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let mut array = [0u8; 8];
-        array.copy_from_slice(&bytes[*offset..*offset + 8]);
-        *offset += 8;
-        let r = i64::from_be_bytes(array);
-        r
-    }
-}
-
-impl Offset for i64 {
-    fn size(&self) -> usize {
-        std::mem::size_of::<i64>()
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        i64::from_be_bytes(take_array(bytes, offset))
     }
 }
 
@@ -141,29 +95,37 @@ impl Size for i64 {
 }
 
 impl Encode for u32 {
-    fn encode(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec() // Convert to big-endian bytes and return as Vec<u8>
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u32(*self);
     }
 }
 
 impl Decode for u32 {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let mut array = [0u8; 4];
-        array.copy_from_slice(&bytes[*offset..*offset + 4]);
-        *offset += 4; // Move the offset forward by 4 bytes
-        let r = u32::from_be_bytes(array); // Convert bytes to u32 (big-endian)
-        r
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        u32::from_be_bytes(take_array(bytes, offset))
     }
 }
 
-impl Offset for u32 {
-    fn size(&self) -> usize {
+impl Size for u32 {
+    fn size_in_bytes(&self) -> usize {
         std::mem::size_of::<u32>() // Size of u32 in bytes (always 4 bytes)
     }
 }
 
-impl Size for u32 {
+impl Encode for f64 {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_f64(*self);
+    }
+}
+
+impl Decode for f64 {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        f64::from_be_bytes(take_array(bytes, offset))
+    }
+}
+
+impl Size for f64 {
     fn size_in_bytes(&self) -> usize {
-        std::mem::size_of::<u32>() // Size of u32 in bytes (always 4 bytes)
+        std::mem::size_of::<f64>()
     }
 }
@@ -1,34 +1,115 @@
+use std::ops::Deref;
+
+use bytes::{BufMut, BytesMut};
+
 use crate::*;
 
-impl Encode for String {
-    fn encode(&self) -> Vec<u8> {
-        let mut v = Vec::new();
+use super::{fail, length_prefix, take};
+
+/// `STRING`: an `INT16` length followed by UTF-8 bytes. Never null.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+pub struct String16(pub String);
+
+/// `NULLABLE_STRING`: like [`String16`], but a length of `-1` means null.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+pub struct NullableString(pub Option<String>);
+
+fn decode_string(bytes: &[u8], offset: &mut usize, len: usize) -> String {
+    match take(bytes, offset, len).map(std::str::from_utf8) {
+        Some(Ok(value)) => value.to_string(),
+        Some(Err(_)) => {
+            fail(bytes, offset);
+            String::new()
+        }
+        None => String::new(),
+    }
+}
 
-        let size: i16 = self.len() as i16;
-        v.extend_from_slice(&size.encode());
-        v.extend_from_slice(self.as_bytes());
-        v
+impl Encode for String16 {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_i16(length_prefix(self.0.len()));
+        buf.put_slice(self.0.as_bytes());
     }
 }
 
-impl Decode for String {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
+impl Decode for String16 {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
         let len = i16::decode(bytes, offset);
-        let value = String::from_utf8(bytes[*offset..*offset + len as usize].to_vec()).unwrap();
-        *offset += len as usize;
-        value
+        Self(decode_string(bytes, offset, len.max(0) as usize))
+    }
+}
+
+impl Size for String16 {
+    fn size_in_bytes(&self) -> usize {
+        self.0.len() + 2
     }
 }
 
-impl Offset for String {
-    fn size(&self) -> usize {
-        self.len() + 2
+impl Deref for String16 {
+    type Target = String;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<&str> for String16 {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl From<String> for String16 {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl Encode for NullableString {
+    fn encode(&self, buf: &mut BytesMut) {
+        match &self.0 {
+            Some(value) => {
+                buf.put_i16(length_prefix(value.len()));
+                buf.put_slice(value.as_bytes());
+            }
+            None => buf.put_i16(-1),
+        }
     }
 }
 
-impl Size for String {
+impl Decode for NullableString {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let len = i16::decode(bytes, offset);
+
+        if len < 0 {
+            return Self(None);
+        }
+
+        Self(Some(decode_string(bytes, offset, len as usize)))
+    }
+}
+
+impl Size for NullableString {
     fn size_in_bytes(&self) -> usize {
-        self.len() + 2
+        2 + self.0.as_ref().map_or(0, |value| value.len())
+    }
+}
+
+impl NullableString {
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+impl From<Option<String>> for NullableString {
+    fn from(value: Option<String>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for NullableString {
+    fn from(value: &str) -> Self {
+        Self(Some(value.to_string()))
     }
 }
 
@@ -76,17 +157,17 @@ mod tests {
         ];
 
         for (input, expected_encoded) in test_cases {
-            let encoded = input.encode();
+            let encoded = String16(input.clone()).to_bytes();
             let mut offset = 0;
-            let decoded = String::decode(&encoded, &mut offset);
+            let decoded = String16::decode(&encoded, &mut offset);
 
-            assert_eq!(decoded, input);
+            assert_eq!(decoded.0, input);
             assert_eq!(encoded, expected_encoded);
         }
     }
 
     #[test]
-    fn test_string_offset_size() {
+    fn test_string_size() {
         let test_cases = vec![
             ("".to_string(), 2),
             ("hello".to_string(), 7),
@@ -96,9 +177,28 @@ mod tests {
         ];
 
         for (input, expected_size) in test_cases {
-            let string = input;
-            assert_eq!(string.size(), expected_size);
+            let string = String16(input);
             assert_eq!(string.size_in_bytes(), expected_size);
         }
     }
+
+    #[test]
+    fn test_nullable_string_null_and_empty() {
+        let null = NullableString(None);
+        let empty = NullableString(Some(String::new()));
+
+        assert_eq!(null.to_bytes(), vec![0xff, 0xff]);
+        assert_eq!(empty.to_bytes(), vec![0, 0]);
+
+        let mut offset = 0;
+        assert_eq!(NullableString::decode(&null.to_bytes(), &mut offset), null);
+        assert_eq!(offset, 2);
+
+        let mut offset = 0;
+        assert_eq!(
+            NullableString::decode(&empty.to_bytes(), &mut offset),
+            empty
+        );
+        assert_eq!(offset, 2);
+    }
 }
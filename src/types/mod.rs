@@ -1,13 +1,69 @@
+use ::bytes::BufMut;
+use anyhow::{bail, Error};
+
 pub mod array;
 pub mod bytes;
 pub mod cstring;
 pub mod integers;
 pub mod kafkastring;
+pub mod nullable;
 pub mod record;
+pub mod records;
 pub mod uuid;
 pub mod uvarint;
 pub mod varint;
 
+/// Takes the next `len` bytes. Decoding never panics on truncated or
+/// malformed input: it [`fail`]s instead, leaving `offset` past the end of
+/// `bytes` so that every later read comes back empty, and
+/// [`check_decoded`] reports it once the value is decoded.
+pub fn take<'a>(bytes: &'a [u8], offset: &mut usize, len: usize) -> Option<&'a [u8]> {
+    match bytes.get(*offset..).and_then(|rest| rest.get(..len)) {
+        Some(taken) => {
+            *offset += len;
+            Some(taken)
+        }
+        None => {
+            fail(bytes, offset);
+            None
+        }
+    }
+}
+
+/// Takes the next `N` bytes, or zeroes if there are not that many.
+pub fn take_array<const N: usize>(bytes: &[u8], offset: &mut usize) -> [u8; N] {
+    let mut array = [0; N];
+    if let Some(taken) = take(bytes, offset, N) {
+        array.copy_from_slice(taken);
+    }
+    array
+}
+
+/// Marks the input as malformed, see [`take`].
+pub fn fail(bytes: &[u8], offset: &mut usize) {
+    *offset = bytes.len() + 1;
+}
+
+/// Checks an element count read from the input. Every element takes at
+/// least a byte, so a count beyond the bytes left is malformed and is not
+/// allocated for.
+pub fn checked_count(bytes: &[u8], offset: &mut usize, count: usize) -> usize {
+    if count > bytes.len().saturating_sub(*offset) {
+        fail(bytes, offset);
+        0
+    } else {
+        count
+    }
+}
+
+/// Fails if decoding up to `offset` ran into truncated or malformed input.
+pub fn check_decoded(bytes: &[u8], offset: usize) -> Result<(), Error> {
+    if offset > bytes.len() {
+        bail!("truncated or malformed data");
+    }
+    Ok(())
+}
+
 pub fn decode_unsigned_varint(data: &[u8], offset: &mut usize) -> (u64, usize) {
     let mut value = 0u64;
     let mut shift = 0;
@@ -27,65 +83,53 @@ pub fn decode_unsigned_varint(data: &[u8], offset: &mut usize) -> (u64, usize) {
         }
 
         if shift >= 64 {
-            panic!("Varint is too large to decode properly");
+            break;
         }
     }
 
-    panic!("Varint is not able to be parsed");
+    fail(data, offset);
+    (0, bytes_read)
 }
 
 pub fn decode_signed_varint(data: &[u8], offset: &mut usize) -> (i64, usize) {
     let (zigzag_value, bytes_read) = decode_unsigned_varint(data, offset);
 
-    let original_value = zigzag_value >> 1;
-
-    let decoded_value = if zigzag_value & 1 == 1 {
-        -(original_value as i64)
-    } else {
-        original_value as i64
-    };
+    let decoded_value = (zigzag_value >> 1) as i64 ^ -((zigzag_value & 1) as i64);
 
     (decoded_value, bytes_read)
 }
 
-pub fn encode_unsigned_varint(mut value: u64) -> Vec<u8> {
-    let mut result = Vec::new();
-
+pub fn encode_unsigned_varint(mut value: u64, buf: &mut impl BufMut) {
     while value >= 0x80 {
-        result.push(((value & 0x7F) | 0x80) as u8);
+        buf.put_u8(((value & 0x7F) | 0x80) as u8);
         value >>= 7;
     }
 
-    result.push(value as u8);
-    result
+    buf.put_u8(value as u8);
 }
 
-pub fn encode_signed_varint(value: i64) -> Vec<u8> {
+pub fn encode_signed_varint(value: i64, buf: &mut impl BufMut) {
     let zigzag_value = if value < 0 { !(value << 1) } else { value << 1 };
 
-    encode_unsigned_varint(zigzag_value as u64)
+    encode_unsigned_varint(zigzag_value as u64, buf)
 }
 
-pub fn signed_varint_bytes_wide(value: usize) -> usize {
-    let zigzag_value = if value == 0 {
-        0
-    } else {
-        (value << 1) ^ ((value >> (usize::BITS - 1)) as usize)
-    };
-
-    let mut bytes = 0;
-    let mut temp_value = zigzag_value;
-
-    while temp_value > 0 {
-        bytes += 1;
-        temp_value >>= 7;
-    }
+/// Converts a length to the type of its fixed-width prefix. Lengths that do
+/// not fit would wrap, possibly to a negative value read back as null, so
+/// they are rejected outright.
+pub fn length_prefix<T: TryFrom<usize>>(len: usize) -> T {
+    T::try_from(len).unwrap_or_else(|_| {
+        panic!(
+            "length {len} does not fit in a {} prefix",
+            std::any::type_name::<T>()
+        )
+    })
+}
 
-    if value == 0 {
-        bytes = 1;
-    }
+pub fn signed_varint_bytes_wide(value: i64) -> usize {
+    let zigzag_value = ((value << 1) ^ (value >> 63)) as u64;
 
-    bytes
+    unsigned_varint_bytes_wide(zigzag_value as usize)
 }
 
 pub fn unsigned_varint_bytes_wide(value: usize) -> usize {
@@ -128,4 +172,24 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    #[should_panic(expected = "does not fit in a i16 prefix")]
+    fn test_length_prefix_rejects_overflow() {
+        length_prefix::<i16>(i16::MAX as usize + 1);
+    }
+
+    #[test]
+    fn test_signed_varint_round_trip() {
+        for value in [0i64, 1, -1, 63, -64, 64, 300, -300, i64::MAX, i64::MIN] {
+            let mut encoded = Vec::new();
+            encode_signed_varint(value, &mut encoded);
+            let mut offset = 0;
+            let (decoded, bytes_read) = decode_signed_varint(&encoded, &mut offset);
+
+            assert_eq!(decoded, value);
+            assert_eq!(bytes_read, encoded.len());
+            assert_eq!(signed_varint_bytes_wide(value), encoded.len());
+        }
+    }
 }
@@ -0,0 +1,41 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::*;
+
+/// A nullable struct field in a flexible version: an `INT8` of `-1` for null
+/// or `1` followed by the struct.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NullableStruct<T>(pub Option<T>);
+
+impl<T> Default for NullableStruct<T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<T: Encode> Encode for NullableStruct<T> {
+    fn encode(&self, buf: &mut BytesMut) {
+        match &self.0 {
+            Some(value) => {
+                buf.put_i8(1);
+                value.encode(buf);
+            }
+            None => buf.put_i8(-1),
+        }
+    }
+}
+
+impl<T: Decode> Decode for NullableStruct<T> {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        match i8::decode(bytes, offset) {
+            -1 => Self(None),
+            _ => Self(Some(T::decode(bytes, offset))),
+        }
+    }
+}
+
+impl<T: Size> Size for NullableStruct<T> {
+    fn size_in_bytes(&self) -> usize {
+        1 + self.0.as_ref().map_or(0, Size::size_in_bytes)
+    }
+}
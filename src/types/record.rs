@@ -1,46 +1,119 @@
+use bytes::{Bytes, BytesMut};
+
 use crate::{
     kafka::log::{
-        partition_record::PartitionRecord, topic_log::TopicRecord, FeatureLevelRecord, RecordValue,
-        UnknownRecord,
+        acl_record::{AccessControlEntryRecord, RemoveAccessControlEntryRecord},
+        config_record::ConfigRecord,
+        partition_record::PartitionRecord,
+        producer_ids_record::ProducerIdsRecord,
+        quota_record::ClientQuotaRecord,
+        scram_record::{RemoveUserScramCredentialRecord, UserScramCredentialRecord},
+        topic_log::{RemoveTopicRecord, TopicRecord},
+        FeatureLevelRecord, RecordValue, UnknownRecord,
     },
-    Decode, Encode, Offset, Size,
+    Decode, Encode, Size,
 };
 
+/// A metadata record value: the record type id and version, followed by the
+/// record itself.
 #[derive(Debug)]
 pub struct GenericRecord {
     pub r_record: RecordValue,
 }
 
+impl RecordValue {
+    /// The metadata record type id and the version this broker writes.
+    pub fn record_type(&self) -> (u8, u8) {
+        match self {
+            RecordValue::Topic(_) => (2, 0),
+            RecordValue::Partition(_) => (3, 1),
+            RecordValue::Config(_) => (4, 0),
+            RecordValue::RemoveTopic(_) => (9, 0),
+            RecordValue::FeatureLevel(_) => (12, 0),
+            RecordValue::ClientQuota(_) => (14, 0),
+            RecordValue::UserScramCredential(_) => (11, 0),
+            RecordValue::ProducerIds(_) => (15, 0),
+            RecordValue::RemoveUserScramCredential(_) => (22, 0),
+            RecordValue::AccessControlEntry(_) => (23, 0),
+            RecordValue::RemoveAccessControlEntry(_) => (24, 0),
+            RecordValue::Unknown(record) => (record.record_type, record.version),
+        }
+    }
+}
+
 impl Encode for GenericRecord {
-    fn encode(&self) -> Vec<u8> {
-        self.r_record.encode()
+    fn encode(&self, buf: &mut BytesMut) {
+        let (record_type, version) = self.r_record.record_type();
+        record_type.encode(buf);
+        version.encode(buf);
+
+        match &self.r_record {
+            RecordValue::Topic(record) => record.encode(buf),
+            RecordValue::Partition(record) => record.encode(buf),
+            RecordValue::Config(record) => record.encode(buf),
+            RecordValue::RemoveTopic(record) => record.encode(buf),
+            RecordValue::FeatureLevel(record) => record.encode(buf),
+            RecordValue::ClientQuota(record) => record.encode(buf),
+            RecordValue::ProducerIds(record) => record.encode(buf),
+            RecordValue::UserScramCredential(record) => record.encode(buf),
+            RecordValue::RemoveUserScramCredential(record) => record.encode(buf),
+            RecordValue::AccessControlEntry(record) => record.encode(buf),
+            RecordValue::RemoveAccessControlEntry(record) => record.encode(buf),
+            RecordValue::Unknown(_) => {}
+        }
     }
 }
 
 impl Decode for GenericRecord {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let t = u8::decode(bytes, offset);
-        let _v = u8::decode(bytes, offset);
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let record_type = u8::decode(bytes, offset);
+        let version = u8::decode(bytes, offset);
 
-        let r_record = match t {
+        let r_record = match record_type {
             12 => RecordValue::FeatureLevel(FeatureLevelRecord::decode(bytes, offset)),
             2 => RecordValue::Topic(TopicRecord::decode(bytes, offset)),
             3 => RecordValue::Partition(PartitionRecord::decode(bytes, offset)),
-            _ => RecordValue::Unknown(UnknownRecord {}),
+            4 => RecordValue::Config(ConfigRecord::decode(bytes, offset)),
+            9 => RecordValue::RemoveTopic(RemoveTopicRecord::decode(bytes, offset)),
+            11 => {
+                RecordValue::UserScramCredential(UserScramCredentialRecord::decode(bytes, offset))
+            }
+            14 => RecordValue::ClientQuota(ClientQuotaRecord::decode(bytes, offset)),
+            15 => RecordValue::ProducerIds(ProducerIdsRecord::decode(bytes, offset)),
+            22 => RecordValue::RemoveUserScramCredential(RemoveUserScramCredentialRecord::decode(
+                bytes, offset,
+            )),
+            23 => RecordValue::AccessControlEntry(AccessControlEntryRecord::decode(bytes, offset)),
+            24 => RecordValue::RemoveAccessControlEntry(RemoveAccessControlEntryRecord::decode(
+                bytes, offset,
+            )),
+            _ => RecordValue::Unknown(UnknownRecord {
+                record_type,
+                version,
+            }),
         };
 
         Self { r_record }
     }
 }
 
-impl Offset for GenericRecord {
-    fn size(&self) -> usize {
-        self.r_record.size_in_bytes()
-    }
-}
-
 impl Size for GenericRecord {
     fn size_in_bytes(&self) -> usize {
-        self.r_record.size_in_bytes()
+        let record = match &self.r_record {
+            RecordValue::Topic(record) => record.size_in_bytes(),
+            RecordValue::Partition(record) => record.size_in_bytes(),
+            RecordValue::Config(record) => record.size_in_bytes(),
+            RecordValue::RemoveTopic(record) => record.size_in_bytes(),
+            RecordValue::FeatureLevel(record) => record.size_in_bytes(),
+            RecordValue::ClientQuota(record) => record.size_in_bytes(),
+            RecordValue::ProducerIds(record) => record.size_in_bytes(),
+            RecordValue::UserScramCredential(record) => record.size_in_bytes(),
+            RecordValue::RemoveUserScramCredential(record) => record.size_in_bytes(),
+            RecordValue::AccessControlEntry(record) => record.size_in_bytes(),
+            RecordValue::RemoveAccessControlEntry(record) => record.size_in_bytes(),
+            RecordValue::Unknown(_) => 0,
+        };
+
+        2 + record
     }
 }
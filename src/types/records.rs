@@ -0,0 +1,108 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::{FileRegion, Frame},
+    Decode, EncodeFrame, Size,
+};
+
+use super::{
+    bytes::decode_bytes, decode_unsigned_varint, unsigned_varint_bytes_wide, uvarint::UVarint,
+};
+
+/// `COMPACT_RECORDS`: record batches prefixed by an unsigned varint holding
+/// `length + 1`. The batches are either held in memory or left on disk as a
+/// [`FileRegion`] to be spliced into the response, so they can only be
+/// encoded into a [`Frame`]. A length prefix of 0 is null, which is kept
+/// apart from empty records.
+#[derive(Debug, Clone)]
+pub enum CompactRecords {
+    Null,
+    Memory(Bytes),
+    File(FileRegion),
+}
+
+impl CompactRecords {
+    pub fn empty() -> Self {
+        CompactRecords::Memory(Bytes::new())
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            CompactRecords::Null => 0,
+            CompactRecords::Memory(bytes) => bytes.len(),
+            CompactRecords::File(region) => region.len as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl EncodeFrame for CompactRecords {
+    /// Writes the length prefix, followed by the batches themselves or a
+    /// reference to their file region.
+    fn encode_frame(&self, frame: &mut Frame) {
+        let prefix = UVarint(self.len() as u64 + 1);
+        match self {
+            CompactRecords::Null => frame.put(&UVarint(0)),
+            CompactRecords::Memory(bytes) => {
+                frame.put(&prefix);
+                frame.put_bytes(bytes.clone());
+            }
+            CompactRecords::File(region) => {
+                frame.put(&prefix);
+                frame.put_region(region.clone());
+            }
+        }
+    }
+}
+
+impl Decode for CompactRecords {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        let (len, _) = decode_unsigned_varint(bytes, offset);
+        if len == 0 {
+            return CompactRecords::Null;
+        }
+        CompactRecords::Memory(decode_bytes(bytes, offset, len as usize - 1))
+    }
+}
+
+impl Size for CompactRecords {
+    fn size_in_bytes(&self) -> usize {
+        match self {
+            CompactRecords::Null => 1,
+            _ => unsigned_varint_bytes_wide(self.len() + 1) + self.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(records: &CompactRecords) -> (Bytes, CompactRecords) {
+        let mut frame = Frame::default();
+        records.encode_frame(&mut frame);
+        let encoded = frame.into_bytes().unwrap();
+        assert_eq!(encoded.len(), records.size_in_bytes());
+        let decoded = CompactRecords::decode(&encoded, &mut 0);
+        (encoded, decoded)
+    }
+
+    #[test]
+    fn test_null_and_empty_records_round_trip() {
+        let (encoded, decoded) = round_trip(&CompactRecords::Null);
+        assert_eq!(&encoded[..], [0]);
+        assert!(matches!(decoded, CompactRecords::Null));
+
+        let (encoded, decoded) = round_trip(&CompactRecords::empty());
+        assert_eq!(&encoded[..], [1]);
+        assert!(matches!(decoded, CompactRecords::Memory(b) if b.is_empty()));
+
+        let records = CompactRecords::Memory(Bytes::from_static(b"batch"));
+        let (encoded, decoded) = round_trip(&records);
+        assert_eq!(&encoded[..], b"\x06batch");
+        assert!(matches!(decoded, CompactRecords::Memory(b) if b == "batch"));
+    }
+}
@@ -1,29 +1,30 @@
 use crate::*;
 
+use std::fmt;
+
+use bytes::{BufMut, BytesMut};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+use super::take_array;
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub struct UUID(pub [u8; 16]);
 
 impl Decode for UUID {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
-        let mut array = [0x00; 16];
-        array.copy_from_slice(&bytes[*offset..*offset + 16]);
-        let uuid = UUID(array);
-        *offset += 16;
-        uuid
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
+        UUID(take_array(bytes, offset))
     }
 }
 
-impl UUID {
-    pub fn to_string(&self) -> String {
-        Uuid::from_bytes(self.0).to_string()
+impl fmt::Display for UUID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Uuid::from_bytes(self.0))
     }
 }
 
 impl Encode for UUID {
-    fn encode(&self) -> Vec<u8> {
-        Vec::from(self.0)
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_slice(&self.0)
     }
 }
 
@@ -32,9 +33,3 @@ impl Size for UUID {
         16
     }
 }
-
-impl Offset for UUID {
-    fn size(&self) -> usize {
-        16
-    }
-}
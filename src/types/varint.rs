@@ -1,41 +1,32 @@
+use bytes::BytesMut;
+
 use crate::*;
 
-use super::{decode_signed_varint, encode_signed_varint};
+use super::{decode_signed_varint, encode_signed_varint, signed_varint_bytes_wide};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Varint(pub i64);
 
 impl Varint {
     pub fn get_size(&self) -> usize {
-        let v = encode_signed_varint(self.0);
-
-        v.len()
-    }
-    pub fn new(value: i64) -> Self {
-        Self(value + 1)
+        signed_varint_bytes_wide(self.0)
     }
 }
 
 impl Encode for Varint {
-    fn encode(&self) -> Vec<u8> {
-        encode_signed_varint(self.0)
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_signed_varint(self.0, buf)
     }
 }
 
 impl Decode for Varint {
-    fn decode(bytes: &[u8], offset: &mut usize) -> Self {
+    fn decode(bytes: &Bytes, offset: &mut usize) -> Self {
         let (value, _) = decode_signed_varint(bytes, offset);
 
         Varint(value)
     }
 }
 
-impl Offset for Varint {
-    fn size(&self) -> usize {
-        self.get_size()
-    }
-}
-
 impl Size for Varint {
     fn size_in_bytes(&self) -> usize {
         self.get_size()